@@ -2,28 +2,225 @@ use crate::storage;
 use crate::models::NoteContent;
 
 #[tauri::command]
-pub fn init_storage(app: tauri::AppHandle) -> Result<(), String> {
-    storage::init_storage(&app)
+pub fn init_storage(app: tauri::AppHandle) -> Result<crate::models::IndexRepairReport, String> {
+    let result = storage::init_storage(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
-pub fn list_notes(app: tauri::AppHandle) -> Result<Vec<crate::models::NoteMeta>, String> {
-    storage::list_notes(&app)
+pub fn verify_and_repair_index(app: tauri::AppHandle) -> Result<crate::models::IndexRepairReport, String> {
+    let result = storage::verify_and_repair_index(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
-pub fn read_note(app: tauri::AppHandle, note_id: String) -> Result<NoteContent, String> {
-    storage::read_note(&app, &note_id)
+pub fn reconcile_notes(app: tauri::AppHandle) -> Result<crate::models::ReconcileReport, String> {
+    let result = storage::reconcile_notes(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn list_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    include_archived: Option<bool>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_notes(&app, include_archived.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn list_archived_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_archived_notes(&app)
+}
+
+#[tauri::command]
+pub fn list_notes_sorted(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    sort: Option<crate::models::SortBy>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_notes_sorted(&app, sort)
+}
+
+#[tauri::command]
+pub fn list_notes_paged(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<crate::models::NotePage, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_notes_paged(&app, offset, limit)
+}
+
+#[tauri::command]
+pub fn list_notes_with_preview(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    preview_len: usize,
+) -> Result<Vec<crate::models::NotePreview>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_notes_with_preview(&app, preview_len)
+}
+
+#[tauri::command]
+pub fn backfill_note_previews(app: tauri::AppHandle) -> Result<usize, String> {
+    let result = storage::backfill_note_previews(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn reindex_tags_and_links(app: tauri::AppHandle) -> Result<usize, String> {
+    let changed = storage::reindex_tags_and_links(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(changed)
+}
+
+#[tauri::command]
+pub fn reorder_notes(app: tauri::AppHandle, ordered_ids: Vec<String>) -> Result<(), String> {
+    storage::reorder_notes(&app, &ordered_ids)?;
+    storage::emit_index_changed(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn read_note(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    load_version_count: Option<bool>,
+) -> Result<NoteContent, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::read_note(&app, &note_id, load_version_count.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn read_note_full(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    load_version_count: Option<bool>,
+) -> Result<crate::models::NoteFull, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::read_note_full(&app, &note_id, load_version_count.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn most_accessed_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    limit: usize,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::most_accessed_notes(&app, limit)
+}
+
+#[tauri::command]
+pub fn note_stats(app: tauri::AppHandle, note_id: String) -> Result<crate::models::NoteStats, String> {
+    storage::note_stats(&app, &note_id)
+}
+
+#[tauri::command]
+pub fn note_outline(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+) -> Result<Vec<crate::models::OutlineHeading>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::note_outline(&app, &note_id)
 }
 
 #[tauri::command]
 pub fn save_note(
     app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
     note_id: Option<String>,
     title: String,
     body: String,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::save_note(&app, note_id.as_deref(), &title, &body)
+    storage::ensure_unlocked(&app, &lock_state)?;
+    let meta = storage::save_note(&app, note_id.as_deref(), &title, &body)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn save_note_ex(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: Option<String>,
+    title: String,
+    body: String,
+) -> Result<crate::models::SaveResult, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    let result = storage::save_note_ex(&app, note_id.as_deref(), &title, &body)?;
+    storage::emit_note_updated(&app, &result.meta.id);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn append_to_note(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    text: String,
+    with_timestamp: bool,
+) -> Result<crate::models::NoteMeta, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    let meta = storage::append_to_note(&app, &note_id, &text, with_timestamp)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn toggle_task(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    line_index: usize,
+) -> Result<crate::models::NoteMeta, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    let meta = storage::toggle_task(&app, &note_id, line_index)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn find_and_replace(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    find: String,
+    replace: String,
+    case_sensitive: bool,
+    note_ids: Option<Vec<String>>,
+    dry_run: Option<bool>,
+) -> Result<crate::models::FindReplaceResult, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    let dry_run = dry_run.unwrap_or(false);
+    let result = storage::find_and_replace(&app, &find, &replace, case_sensitive, note_ids.as_deref(), dry_run)?;
+    if !dry_run && result.notes_changed > 0 {
+        storage::emit_index_changed(&app);
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn open_tasks(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+) -> Result<Vec<crate::models::OpenTask>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::open_tasks(&app)
 }
 
 #[tauri::command]
@@ -32,7 +229,59 @@ pub fn toggle_important(
     note_id: String,
     important: bool,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::toggle_important(&app, &note_id, important)
+    let meta = storage::toggle_important(&app, &note_id, important)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn archive_note(app: tauri::AppHandle, note_id: String) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::archive_note(&app, &note_id)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn unarchive_note(app: tauri::AppHandle, note_id: String) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::unarchive_note(&app, &note_id)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn batch_archive_notes(app: tauri::AppHandle, note_ids: Vec<String>) -> Result<Vec<crate::models::NoteMeta>, String> {
+    let result = storage::batch_archive_notes(&app, &note_ids)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn set_note_color(app: tauri::AppHandle, note_id: String, color: Option<String>) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::set_note_color(&app, &note_id, color.as_deref())?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn batch_set_color(
+    app: tauri::AppHandle,
+    note_ids: Vec<String>,
+    color: Option<String>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    let result = storage::batch_set_color(&app, &note_ids, color.as_deref())?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn toggle_pinned(
+    app: tauri::AppHandle,
+    note_id: String,
+    pinned: bool,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::toggle_pinned(&app, &note_id, pinned)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
@@ -41,7 +290,32 @@ pub fn attach_images(
     note_id: String,
     file_paths: Vec<String>,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::attach_images(&app, &note_id, &file_paths)
+    let meta = storage::attach_images(&app, &note_id, &file_paths)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn attach_files(
+    app: tauri::AppHandle,
+    note_id: String,
+    file_paths: Vec<String>,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::attach_files(&app, &note_id, &file_paths)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn dedupe_attachments(app: tauri::AppHandle, note_id: String) -> Result<crate::models::DedupeSummary, String> {
+    let summary = storage::dedupe_attachments(&app, &note_id)?;
+    storage::emit_note_updated(&app, &note_id);
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn get_thumbnail(app: tauri::AppHandle, relative_path: String, max_dim: u32) -> Result<Option<String>, String> {
+    storage::get_thumbnail(&app, &relative_path, max_dim)
 }
 
 #[tauri::command]
@@ -51,12 +325,132 @@ pub fn attach_image_from_clipboard(
     base64_data: String,
     suggested_name: String,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::attach_image_from_clipboard(&app, &note_id, &base64_data, &suggested_name)
+    let meta = storage::attach_image_from_clipboard(&app, &note_id, &base64_data, &suggested_name)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn attach_image_from_data_url(
+    app: tauri::AppHandle,
+    note_id: String,
+    data_url: String,
+    suggested_name: String,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::attach_image_from_data_url(&app, &note_id, &data_url, &suggested_name)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
 pub fn delete_note(app: tauri::AppHandle, note_id: String) -> Result<(), String> {
-    storage::delete_note(&app, &note_id)
+    storage::delete_note(&app, &note_id)?;
+    storage::emit_note_deleted(&app, &note_id);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_trashed_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_trashed_notes(&app)
+}
+
+#[tauri::command]
+pub fn restore_note(app: tauri::AppHandle, note_id: String) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::restore_note(&app, &note_id)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn empty_trash(app: tauri::AppHandle) -> Result<usize, String> {
+    let result = storage::empty_trash(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn get_storage_root(app: tauri::AppHandle) -> Result<String, String> {
+    storage::get_storage_root(&app)
+}
+
+#[tauri::command]
+pub fn set_storage_root(app: tauri::AppHandle, path: Option<String>, migrate: bool) -> Result<String, String> {
+    let result = storage::set_storage_root(&app, path.as_deref(), migrate)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn storage_usage(app: tauri::AppHandle) -> Result<crate::models::StorageUsage, String> {
+    storage::storage_usage(&app)
+}
+
+#[tauri::command]
+pub fn cleanup_orphans(app: tauri::AppHandle, dry_run: bool) -> Result<crate::models::OrphanReport, String> {
+    storage::cleanup_orphans(&app, dry_run)
+}
+
+#[tauri::command]
+pub fn lock(lock_state: tauri::State<'_, storage::LockState>) -> Result<(), String> {
+    storage::lock(&lock_state)
+}
+
+#[tauri::command]
+pub fn is_locked(app: tauri::AppHandle, lock_state: tauri::State<'_, storage::LockState>) -> Result<bool, String> {
+    storage::is_locked(&app, &lock_state)
+}
+
+#[tauri::command]
+pub fn unlock(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    passphrase: String,
+) -> Result<(), String> {
+    storage::unlock(&app, &lock_state, &passphrase)
+}
+
+#[tauri::command]
+pub fn set_lock_passphrase(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    current: Option<String>,
+    new: Option<String>,
+) -> Result<(), String> {
+    storage::set_lock_passphrase(&app, &lock_state, current.as_deref(), new.as_deref())
+}
+
+#[tauri::command]
+pub fn set_auto_lock_minutes(app: tauri::AppHandle, minutes: u32) -> Result<(), String> {
+    storage::set_auto_lock_minutes(&app, minutes)
+}
+
+#[tauri::command]
+pub fn get_settings(app: tauri::AppHandle) -> Result<crate::models::Settings, String> {
+    storage::get_settings(&app)
+}
+
+#[tauri::command]
+pub fn update_settings(
+    app: tauri::AppHandle,
+    settings: crate::models::Settings,
+) -> Result<crate::models::Settings, String> {
+    storage::update_settings(&app, settings)
+}
+
+#[tauri::command]
+pub fn migrate_note_extension(app: tauri::AppHandle, new_ext: String) -> Result<usize, String> {
+    let renamed = storage::migrate_note_extension(&app, &new_ext)?;
+    storage::emit_index_changed(&app);
+    Ok(renamed)
+}
+
+#[tauri::command]
+pub fn set_trash_retention_days(app: tauri::AppHandle, days: u32) -> Result<(), String> {
+    storage::set_trash_retention_days(&app, days)
 }
 
 #[tauri::command]
@@ -67,9 +461,34 @@ pub fn resolve_image_path(app: tauri::AppHandle, relative_path: String) -> Resul
         .map_err(|_| "Invalid path".into())
 }
 
+#[tauri::command]
+pub fn resolve_image_asset_url(app: tauri::AppHandle, relative_path: String) -> Result<String, String> {
+    storage::resolve_image_asset_url(&app, &relative_path)
+}
+
 #[tauri::command]
 pub fn update_note_title(app: tauri::AppHandle, note_id: String, new_title: String) -> Result<crate::models::NoteMeta, String> {
-    storage::update_note_title(&app, &note_id, &new_title)
+    let meta = storage::update_note_title(&app, &note_id, &new_title)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn set_note_aliases(
+    app: tauri::AppHandle,
+    note_id: String,
+    aliases: Vec<String>,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::set_note_aliases(&app, &note_id, &aliases)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn rename_note_and_relink(app: tauri::AppHandle, note_id: String, new_title: String) -> Result<usize, String> {
+    let result = storage::rename_note_and_relink(&app, &note_id, &new_title)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
@@ -78,58 +497,189 @@ pub fn list_tags(app: tauri::AppHandle) -> Result<Vec<String>, String> {
 }
 
 #[tauri::command]
-pub fn notes_by_tag(app: tauri::AppHandle, tag: String) -> Result<Vec<crate::models::NoteMeta>, String> {
+pub fn list_tags_with_counts(app: tauri::AppHandle) -> Result<Vec<crate::models::TagCount>, String> {
+    storage::list_tags_with_counts(&app)
+}
+
+#[tauri::command]
+pub fn tag_summary(app: tauri::AppHandle) -> Result<Vec<crate::models::TagSummary>, String> {
+    storage::tag_summary(&app)
+}
+
+#[tauri::command]
+pub fn notes_by_tag(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    tag: String,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
     storage::notes_by_tag(&app, &tag)
 }
 
+#[tauri::command]
+pub fn notes_by_tag_prefix(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    prefix: String,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::notes_by_tag_prefix(&app, &prefix)
+}
+
 #[tauri::command]
 pub fn add_tag_to_notes(app: tauri::AppHandle, note_ids: Vec<String>, tag: String) -> Result<Vec<crate::models::NoteMeta>, String> {
-    storage::add_tag_to_notes(&app, &note_ids, &tag)
+    let result = storage::add_tag_to_notes(&app, &note_ids, &tag)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn get_tag_meta(app: tauri::AppHandle) -> Result<std::collections::HashMap<String, crate::models::TagMeta>, String> {
+    storage::get_tag_meta(&app)
+}
+
+#[tauri::command]
+pub fn set_tag_color(app: tauri::AppHandle, tag: String, color: Option<String>) -> Result<std::collections::HashMap<String, crate::models::TagMeta>, String> {
+    storage::set_tag_color(&app, &tag, color.as_deref())
+}
+
+#[tauri::command]
+pub fn set_tag_description(app: tauri::AppHandle, tag: String, description: Option<String>) -> Result<std::collections::HashMap<String, crate::models::TagMeta>, String> {
+    storage::set_tag_description(&app, &tag, description.as_deref())
+}
+
+#[tauri::command]
+pub fn list_tags_detailed(app: tauri::AppHandle) -> Result<Vec<crate::models::TagDetail>, String> {
+    storage::list_tags_detailed(&app)
+}
+
+#[tauri::command]
+pub fn rename_tag(app: tauri::AppHandle, old: String, new: String) -> Result<Vec<crate::models::NoteMeta>, String> {
+    let result = storage::rename_tag(&app, &old, &new)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
 pub fn remove_tag_from_note(app: tauri::AppHandle, note_id: String, tag: String) -> Result<crate::models::NoteMeta, String> {
-    storage::remove_tag_from_note(&app, &note_id, &tag)
+    let meta = storage::remove_tag_from_note(&app, &note_id, &tag)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
 pub fn batch_delete_notes(app: tauri::AppHandle, note_ids: Vec<String>) -> Result<(), String> {
-    storage::batch_delete_notes(&app, &note_ids)
+    storage::batch_delete_notes(&app, &note_ids)?;
+    for id in &note_ids {
+        storage::emit_note_deleted(&app, id);
+    }
+    Ok(())
 }
 
 #[tauri::command]
 pub fn batch_toggle_important(app: tauri::AppHandle, note_ids: Vec<String>, important: bool) -> Result<Vec<crate::models::NoteMeta>, String> {
-    storage::batch_toggle_important(&app, &note_ids, important)
+    let result = storage::batch_toggle_important(&app, &note_ids, important)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn batch_toggle_pinned(app: tauri::AppHandle, note_ids: Vec<String>, pinned: bool) -> Result<Vec<crate::models::NoteMeta>, String> {
+    let result = storage::batch_toggle_pinned(&app, &note_ids, pinned)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
-pub fn duplicate_note(app: tauri::AppHandle, note_id: String) -> Result<crate::models::NoteMeta, String> {
-    storage::duplicate_note(&app, &note_id)
+pub fn duplicate_note(app: tauri::AppHandle, note_id: String) -> Result<crate::models::DuplicateResult, String> {
+    let result = storage::duplicate_note(&app, &note_id)?;
+    storage::emit_note_updated(&app, &result.meta.id);
+    Ok(result)
 }
 
 #[tauri::command]
-pub fn merge_notes(app: tauri::AppHandle, note_ids: Vec<String>) -> Result<crate::models::NoteMeta, String> {
-    storage::merge_notes(&app, &note_ids)
+pub fn merge_notes(
+    app: tauri::AppHandle,
+    note_ids: Vec<String>,
+    options: Option<crate::models::MergeOptions>,
+) -> Result<crate::models::NoteMeta, String> {
+    let result = storage::merge_notes(&app, &note_ids, &options.unwrap_or_default())?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
-pub fn export_note(app: tauri::AppHandle, note_id: String) -> Result<String, String> {
+pub fn export_note(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+) -> Result<String, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
     storage::export_note(&app, &note_id)
 }
 
 #[tauri::command]
 pub fn get_or_create_daily_note(app: tauri::AppHandle) -> Result<crate::models::NoteMeta, String> {
-    storage::get_or_create_daily_note(&app)
+    let meta = storage::get_or_create_daily_note(&app)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn get_or_create_periodic_note(
+    app: tauri::AppHandle,
+    period: crate::models::Period,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::get_or_create_periodic_note(&app, period)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
-pub fn get_backlinks(app: tauri::AppHandle, note_id: String) -> Result<Vec<crate::models::NoteMeta>, String> {
+pub fn get_linked_and_backlinks(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+) -> Result<crate::models::NoteConnections, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::get_linked_and_backlinks(&app, &note_id)
+}
+
+#[tauri::command]
+pub fn link_graph(app: tauri::AppHandle, include_tag_edges: bool) -> Result<crate::models::LinkGraph, String> {
+    storage::link_graph(&app, include_tag_edges)
+}
+
+#[tauri::command]
+pub fn unlinked_mentions(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+) -> Result<Vec<crate::models::UnlinkedMention>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::unlinked_mentions(&app, &note_id)
+}
+
+#[tauri::command]
+pub fn find_duplicate_titles(app: tauri::AppHandle) -> Result<Vec<crate::models::DuplicateTitleGroup>, String> {
+    storage::find_duplicate_titles(&app)
+}
+
+#[tauri::command]
+pub fn get_backlinks(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
     storage::get_backlinks(&app, &note_id)
 }
 
 #[tauri::command]
 pub fn remove_attachment(app: tauri::AppHandle, note_id: String, relative_path: String) -> Result<crate::models::NoteMeta, String> {
-    storage::remove_attachment(&app, &note_id, &relative_path)
+    let meta = storage::remove_attachment(&app, &note_id, &relative_path)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
@@ -139,12 +689,138 @@ pub fn rename_attachment(
     relative_path: String,
     new_name: String,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::rename_attachment(&app, &note_id, &relative_path, &new_name)
+    let meta = storage::rename_attachment(&app, &note_id, &relative_path, &new_name)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
-pub fn search_notes(app: tauri::AppHandle, query: String) -> Result<Vec<crate::models::NoteMeta>, String> {
-    storage::search_notes(&app, &query)
+pub fn search_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    query: String,
+    include_archived: Option<bool>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::search_notes(&app, &query, include_archived.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn quick_switch(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<crate::models::RankedNote>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::quick_switch(&app, &query, limit)
+}
+
+#[tauri::command]
+pub fn recent_notes(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    limit: usize,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::recent_notes(&app, limit)
+}
+
+#[tauri::command]
+pub fn list_saved_searches(app: tauri::AppHandle) -> Result<Vec<crate::models::SavedSearch>, String> {
+    storage::list_saved_searches(&app)
+}
+
+#[tauri::command]
+pub fn create_saved_search(app: tauri::AppHandle, name: String, query: String) -> Result<crate::models::SavedSearch, String> {
+    storage::create_saved_search(&app, &name, &query)
+}
+
+#[tauri::command]
+pub fn delete_saved_search(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    storage::delete_saved_search(&app, &id)
+}
+
+#[tauri::command]
+pub fn run_saved_search(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    id: String,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::run_saved_search(&app, &id)
+}
+
+#[tauri::command]
+pub fn list_favorites(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_favorites(&app)
+}
+
+#[tauri::command]
+pub fn add_favorite(app: tauri::AppHandle, note_id: String) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::add_favorite(&app, &note_id)
+}
+
+#[tauri::command]
+pub fn remove_favorite(app: tauri::AppHandle, note_id: String) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::remove_favorite(&app, &note_id)
+}
+
+#[tauri::command]
+pub fn reorder_favorites(app: tauri::AppHandle, ordered_ids: Vec<String>) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::reorder_favorites(&app, &ordered_ids)
+}
+
+#[tauri::command]
+pub fn search_notes_ranked(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    query: String,
+    include_archived: Option<bool>,
+) -> Result<Vec<crate::models::RankedNote>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::search_notes_ranked(&app, &query, include_archived.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn search_notes_paged(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    query: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<crate::models::NotePage, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::search_notes_paged(&app, &query, offset, limit)
+}
+
+#[tauri::command]
+pub fn search_notes_with_snippets(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    query: String,
+) -> Result<Vec<crate::models::SearchHit>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::search_notes_with_snippets(&app, &query)
+}
+
+#[tauri::command]
+pub fn rebuild_search_index(app: tauri::AppHandle) -> Result<usize, String> {
+    storage::rebuild_search_index(&app)
+}
+
+#[tauri::command]
+pub fn search_notes_regex(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    pattern: String,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::search_notes_regex(&app, &pattern)
 }
 
 #[tauri::command]
@@ -155,19 +831,54 @@ pub fn list_note_versions(app: tauri::AppHandle, note_id: String) -> Result<Vec<
 #[tauri::command]
 pub fn get_note_version(
     app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
     note_id: String,
     saved_at: String,
 ) -> Result<crate::models::NoteVersionContent, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
     storage::get_note_version(&app, &note_id, &saved_at)
 }
 
+#[tauri::command]
+pub fn pin_note_version(
+    app: tauri::AppHandle,
+    note_id: String,
+    saved_at: String,
+    pinned: bool,
+) -> Result<crate::models::NoteVersionItem, String> {
+    storage::pin_note_version(&app, &note_id, &saved_at, pinned)
+}
+
+#[tauri::command]
+pub fn create_note_snapshot(
+    app: tauri::AppHandle,
+    note_id: String,
+    label: Option<String>,
+) -> Result<crate::models::NoteVersionItem, String> {
+    storage::create_note_snapshot(&app, &note_id, label)
+}
+
+#[tauri::command]
+pub fn diff_note_versions(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    from_saved_at: String,
+    to_saved_at: String,
+) -> Result<Vec<crate::models::DiffLine>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::diff_note_versions(&app, &note_id, &from_saved_at, &to_saved_at)
+}
+
 #[tauri::command]
 pub fn restore_note_version(
     app: tauri::AppHandle,
     note_id: String,
     saved_at: String,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::restore_note_version(&app, &note_id, &saved_at)
+    let meta = storage::restore_note_version(&app, &note_id, &saved_at)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
@@ -175,13 +886,21 @@ pub fn list_templates(app: tauri::AppHandle) -> Result<Vec<crate::models::NoteTe
     storage::list_templates(&app)
 }
 
+#[tauri::command]
+pub fn list_templates_with_usage(app: tauri::AppHandle) -> Result<Vec<crate::models::TemplateInfo>, String> {
+    storage::list_templates_with_usage(&app)
+}
+
 #[tauri::command]
 pub fn create_note_from_template(
     app: tauri::AppHandle,
     template_id: String,
     title_override: Option<String>,
+    vars: Option<std::collections::HashMap<String, String>>,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::create_note_from_template(&app, &template_id, title_override.as_deref())
+    let meta = storage::create_note_from_template(&app, &template_id, title_override.as_deref(), &vars.unwrap_or_default())?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
 }
 
 #[tauri::command]
@@ -193,6 +912,22 @@ pub fn save_custom_template(
     storage::save_custom_template(&app, &name, &body)
 }
 
+#[tauri::command]
+pub fn duplicate_template(app: tauri::AppHandle, template_id: String) -> Result<crate::models::NoteTemplate, String> {
+    storage::duplicate_template(&app, &template_id)
+}
+
+#[tauri::command]
+pub fn update_custom_template(
+    app: tauri::AppHandle,
+    template_id: String,
+    name: String,
+    body: String,
+    default_title_pattern: Option<String>,
+) -> Result<crate::models::NoteTemplate, String> {
+    storage::update_custom_template(&app, &template_id, &name, &body, default_title_pattern.as_deref())
+}
+
 #[tauri::command]
 pub fn delete_custom_template(app: tauri::AppHandle, template_id: String) -> Result<(), String> {
     storage::delete_custom_template(&app, &template_id)
@@ -204,8 +939,25 @@ pub fn list_notebooks(app: tauri::AppHandle) -> Result<Vec<crate::models::Notebo
 }
 
 #[tauri::command]
-pub fn create_notebook(app: tauri::AppHandle, name: String) -> Result<crate::models::Notebook, String> {
-    storage::create_notebook(&app, &name)
+pub fn create_notebook(
+    app: tauri::AppHandle,
+    name: String,
+    parent_id: Option<String>,
+) -> Result<crate::models::Notebook, String> {
+    let result = storage::create_notebook(&app, &name, parent_id.as_deref())?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn move_notebook(
+    app: tauri::AppHandle,
+    notebook_id: String,
+    new_parent: Option<String>,
+) -> Result<crate::models::Notebook, String> {
+    let result = storage::move_notebook(&app, &notebook_id, new_parent.as_deref())?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
@@ -214,7 +966,55 @@ pub fn move_note_to_notebook(
     note_id: String,
     notebook_id: Option<String>,
 ) -> Result<crate::models::NoteMeta, String> {
-    storage::move_note_to_notebook(&app, &note_id, notebook_id.as_deref())
+    let meta = storage::move_note_to_notebook(&app, &note_id, notebook_id.as_deref())?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn list_notes_grouped_by_notebook(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+) -> Result<Vec<crate::models::NotebookNotes>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::list_notes_grouped_by_notebook(&app)
+}
+
+#[tauri::command]
+pub fn notes_by_notebook(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    notebook_id: Option<String>,
+) -> Result<Vec<crate::models::NoteMeta>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::notes_by_notebook(&app, notebook_id.as_deref())
+}
+
+#[tauri::command]
+pub fn notebook_counts(app: tauri::AppHandle) -> Result<Vec<crate::models::NotebookCount>, String> {
+    storage::notebook_counts(&app)
+}
+
+#[tauri::command]
+pub fn set_note_order(
+    app: tauri::AppHandle,
+    note_id: String,
+    order: Option<u32>,
+) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::set_note_order(&app, &note_id, order)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn reorder_notes_in_notebook(
+    app: tauri::AppHandle,
+    notebook_id: Option<String>,
+    ordered_ids: Vec<String>,
+) -> Result<(), String> {
+    storage::reorder_notes_in_notebook(&app, notebook_id.as_deref(), &ordered_ids)?;
+    storage::emit_index_changed(&app);
+    Ok(())
 }
 
 #[tauri::command]
@@ -222,8 +1022,22 @@ pub fn archive_notebook(
     app: tauri::AppHandle,
     notebook_id: String,
     archived: bool,
+    cascade: bool,
 ) -> Result<crate::models::Notebook, String> {
-    storage::archive_notebook(&app, &notebook_id, archived)
+    let result = storage::archive_notebook(&app, &notebook_id, archived, cascade)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn delete_notebook(
+    app: tauri::AppHandle,
+    notebook_id: String,
+    strategy: crate::models::NotebookDeleteStrategy,
+) -> Result<usize, String> {
+    let result = storage::delete_notebook(&app, &notebook_id, strategy)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 #[tauri::command]
@@ -232,14 +1046,80 @@ pub fn update_notebook_name(
     notebook_id: String,
     new_name: String,
 ) -> Result<crate::models::Notebook, String> {
-    storage::update_notebook_name(&app, &notebook_id, &new_name)
+    let result = storage::update_notebook_name(&app, &notebook_id, &new_name)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
 
 // --- Export & Sync ---
 
 #[tauri::command]
-pub fn export_note_as_markdown(app: tauri::AppHandle, note_id: String) -> Result<String, String> {
-    storage::export_note_as_markdown(&app, &note_id)
+pub fn export_note_as_markdown(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    inline_images: Option<bool>,
+) -> Result<String, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_note_as_markdown(&app, &note_id, inline_images.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn export_note_as_pdf(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    target_path: String,
+) -> Result<(), String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_note_as_pdf(&app, &note_id, &target_path)
+}
+
+#[tauri::command]
+pub fn export_notes_to_folder(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_ids: Vec<String>,
+    target_dir: String,
+) -> Result<Vec<String>, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_notes_to_folder(&app, &note_ids, &target_dir)
+}
+
+#[tauri::command]
+pub fn import_markdown_folder(app: tauri::AppHandle, source_dir: String) -> Result<crate::models::MarkdownImportResult, String> {
+    let result = storage::import_markdown_folder(&app, &source_dir)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn import_markdown_file(app: tauri::AppHandle, path: String) -> Result<crate::models::NoteMeta, String> {
+    let meta = storage::import_markdown_file(&app, &path)?;
+    storage::emit_note_updated(&app, &meta.id);
+    Ok(meta)
+}
+
+#[tauri::command]
+pub fn export_notebook_as_markdown(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    notebook_id: String,
+    include_frontmatter: bool,
+) -> Result<String, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_notebook_as_markdown(&app, &notebook_id, include_frontmatter)
+}
+
+#[tauri::command]
+pub fn export_note_as_html(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    note_id: String,
+    inline_images: Option<bool>,
+) -> Result<String, String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_note_as_html(&app, &note_id, inline_images.unwrap_or(false))
 }
 
 #[tauri::command]
@@ -247,22 +1127,60 @@ pub fn write_text_file(path: String, content: String) -> Result<(), String> {
     storage::write_text_file(&path, &content)
 }
 
+#[tauri::command]
+pub fn export_tags_as_csv(app: tauri::AppHandle, target_path: String) -> Result<usize, String> {
+    storage::export_tags_as_csv(&app, &target_path)
+}
+
 #[tauri::command]
 pub fn get_sync_folder(app: tauri::AppHandle) -> Result<Option<String>, String> {
     storage::get_sync_folder(&app)
 }
 
+#[tauri::command]
+pub fn sync_now(app: tauri::AppHandle) -> Result<crate::models::SyncReport, String> {
+    let result = storage::sync_now(&app)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
+}
+
 #[tauri::command]
 pub fn set_sync_folder(app: tauri::AppHandle, path: Option<String>) -> Result<(), String> {
     storage::set_sync_folder(&app, path)
 }
 
 #[tauri::command]
-pub fn export_backup(app: tauri::AppHandle, target_dir: String) -> Result<(), String> {
+pub fn export_backup(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    target_dir: String,
+) -> Result<(), String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
     storage::export_backup(&app, &target_dir)
 }
 
 #[tauri::command]
-pub fn import_backup(app: tauri::AppHandle, source_dir: String) -> Result<(), String> {
-    storage::import_backup(&app, &source_dir)
+pub fn export_backup_zip(
+    app: tauri::AppHandle,
+    lock_state: tauri::State<'_, storage::LockState>,
+    target_file: String,
+) -> Result<(), String> {
+    storage::ensure_unlocked(&app, &lock_state)?;
+    storage::export_backup_zip(&app, &target_file)
+}
+
+#[tauri::command]
+pub fn verify_backup(source_dir: String) -> Result<crate::models::BackupVerification, String> {
+    storage::verify_backup(&source_dir)
+}
+
+#[tauri::command]
+pub fn import_backup(
+    app: tauri::AppHandle,
+    source_dir: String,
+    mode: crate::models::ImportMode,
+) -> Result<crate::models::ImportSummary, String> {
+    let result = storage::import_backup(&app, &source_dir, mode)?;
+    storage::emit_index_changed(&app);
+    Ok(result)
 }
@@ -1,5 +1,5 @@
 mod commands;
-mod models;
+pub mod models;
 pub mod storage;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -7,48 +7,155 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(storage::LockState::default())
+        .setup(|app| {
+            storage::start_sync_watcher(app.handle());
+            if let Ok(root) = storage::storage_root(app.handle()) {
+                let _ = storage::allow_asset_access(app.handle(), &root);
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::init_storage,
+            commands::verify_and_repair_index,
+            commands::reconcile_notes,
             commands::list_notes,
+            commands::list_archived_notes,
+            commands::list_notes_sorted,
+            commands::list_notes_paged,
+            commands::list_notes_with_preview,
+            commands::backfill_note_previews,
+            commands::reindex_tags_and_links,
+            commands::reorder_notes,
             commands::read_note,
+            commands::read_note_full,
+            commands::most_accessed_notes,
+            commands::note_stats,
+            commands::note_outline,
             commands::save_note,
+            commands::save_note_ex,
+            commands::append_to_note,
+            commands::toggle_task,
+            commands::open_tasks,
+            commands::find_and_replace,
             commands::toggle_important,
+            commands::archive_note,
+            commands::unarchive_note,
+            commands::batch_archive_notes,
+            commands::set_note_color,
+            commands::batch_set_color,
+            commands::toggle_pinned,
             commands::attach_images,
+            commands::attach_files,
+            commands::dedupe_attachments,
+            commands::get_thumbnail,
             commands::attach_image_from_clipboard,
+            commands::attach_image_from_data_url,
             commands::delete_note,
+            commands::list_trashed_notes,
+            commands::restore_note,
+            commands::empty_trash,
+            commands::set_trash_retention_days,
+            commands::get_storage_root,
+            commands::set_storage_root,
+            commands::storage_usage,
+            commands::cleanup_orphans,
+            commands::lock,
+            commands::is_locked,
+            commands::unlock,
+            commands::set_lock_passphrase,
+            commands::set_auto_lock_minutes,
+            commands::get_settings,
+            commands::update_settings,
+            commands::migrate_note_extension,
             commands::resolve_image_path,
+            commands::resolve_image_asset_url,
             commands::update_note_title,
+            commands::rename_note_and_relink,
+            commands::set_note_aliases,
             commands::list_tags,
+            commands::list_tags_with_counts,
+            commands::tag_summary,
             commands::notes_by_tag,
+            commands::notes_by_tag_prefix,
             commands::add_tag_to_notes,
             commands::remove_tag_from_note,
+            commands::rename_tag,
+            commands::get_tag_meta,
+            commands::set_tag_color,
+            commands::set_tag_description,
+            commands::list_tags_detailed,
             commands::batch_delete_notes,
             commands::batch_toggle_important,
+            commands::batch_toggle_pinned,
             commands::duplicate_note,
             commands::merge_notes,
             commands::export_note,
             commands::get_or_create_daily_note,
+            commands::get_or_create_periodic_note,
             commands::get_backlinks,
+            commands::find_duplicate_titles,
+            commands::get_linked_and_backlinks,
+            commands::unlinked_mentions,
+            commands::link_graph,
             commands::remove_attachment,
             commands::rename_attachment,
             commands::search_notes,
+            commands::search_notes_ranked,
+            commands::search_notes_paged,
+            commands::quick_switch,
+            commands::recent_notes,
+            commands::list_saved_searches,
+            commands::create_saved_search,
+            commands::delete_saved_search,
+            commands::run_saved_search,
+            commands::list_favorites,
+            commands::add_favorite,
+            commands::remove_favorite,
+            commands::reorder_favorites,
+            commands::search_notes_with_snippets,
+            commands::search_notes_regex,
+            commands::rebuild_search_index,
             commands::list_note_versions,
             commands::get_note_version,
             commands::restore_note_version,
+            commands::diff_note_versions,
+            commands::pin_note_version,
+            commands::create_note_snapshot,
             commands::list_notebooks,
             commands::create_notebook,
+            commands::move_notebook,
             commands::move_note_to_notebook,
+            commands::list_notes_grouped_by_notebook,
+            commands::notes_by_notebook,
+            commands::notebook_counts,
+            commands::set_note_order,
+            commands::reorder_notes_in_notebook,
             commands::archive_notebook,
+            commands::delete_notebook,
             commands::update_notebook_name,
             commands::list_templates,
+            commands::list_templates_with_usage,
             commands::create_note_from_template,
             commands::save_custom_template,
+            commands::duplicate_template,
+            commands::update_custom_template,
             commands::delete_custom_template,
             commands::export_note_as_markdown,
+            commands::export_note_as_html,
+            commands::export_note_as_pdf,
+            commands::export_notes_to_folder,
+            commands::export_notebook_as_markdown,
+            commands::import_markdown_folder,
+            commands::import_markdown_file,
             commands::write_text_file,
+            commands::export_tags_as_csv,
             commands::get_sync_folder,
             commands::set_sync_folder,
+            commands::sync_now,
             commands::export_backup,
+            commands::export_backup_zip,
+            commands::verify_backup,
             commands::import_backup,
         ])
         .run(tauri::generate_context!())
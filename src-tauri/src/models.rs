@@ -1,5 +1,28 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
+/// Attachment type, derived from the file extension. Entries saved before non-image attachments
+/// existed have no kind and are images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentKind {
+    #[default]
+    Image,
+    Audio,
+    Pdf,
+    Other,
+}
+
+/// Outcome of dedupe_attachments.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupeSummary {
+    #[serde(rename = "filesRemoved")]
+    pub files_removed: usize,
+    #[serde(rename = "bytesSaved")]
+    pub bytes_saved: u64,
+}
+
+/// An attached file: images live under images/<noteId>/, other files under attachments/<noteId>/.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageRef {
     pub name: String,
@@ -9,6 +32,8 @@ pub struct ImageRef {
     /// File size in bytes, if known.
     #[serde(default)]
     pub size: Option<u64>,
+    #[serde(default)]
+    pub kind: AttachmentKind,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +55,69 @@ pub struct NoteMeta {
     pub is_daily: bool,
     #[serde(default, rename = "notebookId")]
     pub notebook_id: Option<String>,
+    /// Number of times the note has been opened via read_note.
+    #[serde(default, rename = "readCount")]
+    pub read_count: u32,
+    /// Id of the template this note was created from, if any.
+    #[serde(default, rename = "createdFromTemplateId")]
+    pub created_from_template_id: Option<String>,
+    /// Manual position among pinned notes in the same notebook (lower first); None = not pinned.
+    #[serde(default, rename = "pinOrder")]
+    pub pin_order: Option<u32>,
+    /// When the note was moved to the trash (RFC3339); None for live notes.
+    #[serde(default, rename = "deletedAt", skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    /// Pinned notes are listed first in the sidebar.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Manual sidebar position set by drag-reorder (lower first); None = sort by updated_at.
+    #[serde(default)]
+    pub order: Option<u32>,
+    /// Alternate names; [[Alias]] links resolve to this note.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Set on periodic (daily/weekly/monthly) notes. Daily notes also keep is_daily for older readers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<Period>,
+    /// Archived notes are hidden from list_notes and search_notes unless explicitly included.
+    #[serde(default)]
+    pub archived: bool,
+    /// Last metadata-only change (star, pin, archive, color, tags, aliases, notebook). Unlike updated_at it moves
+    /// regardless of the bumpUpdatedOnMetadataChange setting; None until the first such change.
+    #[serde(default, rename = "metadataUpdatedAt", skip_serializing_if = "Option::is_none")]
+    pub metadata_updated_at: Option<String>,
+    /// Start of the body (see note_preview), refreshed whenever the body is written so lists can show
+    /// it without reading files. Not searched. None for notes not saved since it was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// Color label: a palette name (see NOTE_COLOR_PALETTE) or `#rgb`/`#rrggbb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Span of time a periodic note covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// Order for list_notes_sorted. Ties fall back to updated_at (newest first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortBy {
+    /// Newest edit first.
+    UpdatedAt,
+    /// Newest note first.
+    CreatedAt,
+    /// A–Z, ignoring case and accents.
+    TitleAsc,
+    /// Z–A, ignoring case and accents.
+    TitleDesc,
+    /// Starred notes first.
+    Important,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,28 +128,454 @@ pub struct Notebook {
     pub archived: bool,
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    /// Parent notebook for nested notebooks (None = top level).
+    #[serde(default, rename = "parentId")]
+    pub parent_id: Option<String>,
+}
+
+/// Notes belonging to one notebook (notebook_id None = unfiled), in display order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotebookNotes {
+    #[serde(rename = "notebookId")]
+    pub notebook_id: Option<String>,
+    pub notes: Vec<NoteMeta>,
+}
+
+/// What happens to a notebook's notes when it is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotebookDeleteStrategy {
+    /// Keep the notes, unfiled.
+    MoveToUnfiled,
+    /// Delete the notes along with the notebook (they go to the trash).
+    DeleteNotes,
+}
+
+/// Result of duplicate_note: the new note plus attachments that could not be copied ("name: error").
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateResult {
+    pub meta: NoteMeta,
+    pub failed: Vec<String>,
+}
+
+/// Order of the source notes in a merged note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeOrder {
+    /// Oldest updated_at first.
+    #[default]
+    ByUpdated,
+    /// Oldest created_at first.
+    ByCreated,
+    /// As passed to merge_notes.
+    ByProvidedOrder,
+}
+
+/// Options for merge_notes; the defaults match the original behaviour.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MergeOptions {
+    #[serde(default)]
+    pub order: MergeOrder,
+    /// Written before each source's body; `{{title}}` becomes that note's title. None means "## {{title}}\n\n".
+    #[serde(default)]
+    pub separator: Option<String>,
+    /// Leave the sources untouched and write the merge to a new note.
+    #[serde(default, rename = "keepSources")]
+    pub keep_sources: bool,
+}
+
+/// Result of save_note_ex: the saved meta, whether the save created the note and whether another note
+/// already has the same title (ignoring case), which makes [[links]] to it ambiguous.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveResult {
+    pub meta: NoteMeta,
+    pub created: bool,
+    #[serde(default, rename = "duplicateTitle")]
+    pub duplicate_title: bool,
+}
+
+/// Notes sharing a title, ignoring case. `title` is the oldest note's spelling.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateTitleGroup {
+    pub title: String,
+    pub notes: Vec<NoteMeta>,
+}
+
+/// One window of a note list plus the size of the full (filtered) list, for virtual scrolling.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotePage {
+    pub items: Vec<NoteMeta>,
+    pub total: usize,
+}
+
+/// A note with the start of its body, for sidebar previews.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotePreview {
+    pub meta: NoteMeta,
+    pub preview: String,
 }
 
+/// Note counts for one notebook (notebook_id None = unfiled).
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotebookCount {
+    #[serde(rename = "notebookId")]
+    pub notebook_id: Option<String>,
+    pub total: usize,
+    pub important: usize,
+}
+
+/// Color and description for a tag; meta/tag_meta.json maps tag -> TagMeta.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagMeta {
+    /// CSS hex color (`#rgb` or `#rrggbb`); empty when only a description is set.
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A tag and the number of notes carrying it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Per-tag totals for the tag sidebar (see tag_summary).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagSummary {
+    pub tag: String,
+    pub count: usize,
+    /// Latest updated_at (RFC3339) among notes with this tag.
+    #[serde(rename = "lastUpdated")]
+    pub last_updated: String,
+    /// True if any note with this tag is starred.
+    #[serde(rename = "hasImportant")]
+    pub has_important: bool,
+}
+
+/// A tag with its note count and metadata (for list_tags_detailed).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagDetail {
+    pub tag: String,
+    pub count: usize,
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Disk usage of one note's attachments and version history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteStorageUsage {
+    #[serde(rename = "noteId")]
+    pub note_id: String,
+    #[serde(rename = "imageBytes")]
+    pub image_bytes: u64,
+    #[serde(rename = "versionBytes")]
+    pub version_bytes: u64,
+}
+
+/// Result of storage_usage. `images_bytes` covers images/ and attachments/; `total_bytes` also
+/// includes meta/ and the trash.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageUsage {
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    #[serde(rename = "notesBytes")]
+    pub notes_bytes: u64,
+    #[serde(rename = "imagesBytes")]
+    pub images_bytes: u64,
+    #[serde(rename = "versionsBytes")]
+    pub versions_bytes: u64,
+    /// Notes not in the trash, heaviest first.
+    #[serde(rename = "perNote")]
+    pub per_note: Vec<NoteStorageUsage>,
+}
+
+/// Result of cleanup_orphans: storage-relative paths with no owning note or ImageRef, and their size.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrphanReport {
+    pub paths: Vec<String>,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+}
+
+/// One file listed in a backup's manifest.json.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the backup root, with '/' separators.
+    pub path: String,
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+/// manifest.json written at the root of every backup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Result of verify_backup: files listed in the manifest that are missing or don't match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackupVerification {
+    pub missing: Vec<String>,
+    pub corrupt: Vec<String>,
+}
+
+/// Outcome of sync_now.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Files copied from local storage to the sync folder.
+    pub pushed: usize,
+    /// Files copied from the sync folder into local storage.
+    pub pulled: usize,
+    /// Relative paths changed on both sides since the last sync. The local copy was kept and
+    /// the sync folder's version saved locally as `conflicts/<path>.conflict`.
+    pub conflicts: Vec<String>,
+    /// Notes trashed or removed here because the other side trashed or deleted them.
+    #[serde(default)]
+    pub deleted: usize,
+}
+
+/// Result of reconcile_notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    /// Entries added for note files that were not in the index.
+    pub imported: Vec<NoteMeta>,
+    /// Ids of indexed notes whose .txt file no longer exists.
+    pub missing: Vec<String>,
+}
+
+/// Where verify_and_repair_index got the index from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexSource {
+    /// index.json was missing or parsed fine; nothing was changed.
+    Intact,
+    /// index.json was unreadable and the leftover index.json.tmp was used instead.
+    TempFile,
+    /// Neither file parsed, so a minimal index was rebuilt from notes/*.txt.
+    Rebuilt,
+}
+
+/// Outcome of the startup index check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexRepairReport {
+    pub source: IndexSource,
+    /// Storage-relative path the corrupt index.json was copied to, if it was corrupt.
+    #[serde(rename = "corruptBackup")]
+    pub corrupt_backup: Option<String>,
+    /// Notes in the recovered index (0 when intact).
+    #[serde(rename = "notesRecovered")]
+    pub notes_recovered: usize,
+}
+
+/// How import_backup combines a backup with the current notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    /// Overwrite current notes/, meta/ and images/ with the backup.
+    Replace,
+    /// Union notes by id (newer updated_at wins) and notebooks; keep everything else.
+    Merge,
+}
+
+/// Outcome of import_backup, counted per note.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Outcome of import_markdown_folder: notes created, plus "file: error" for files that could not be read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarkdownImportResult {
+    pub created: Vec<NoteMeta>,
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct IndexFile {
     pub notes: Vec<NoteMeta>,
     #[serde(default)]
     pub notebooks: Vec<Notebook>,
+    /// Soft-deleted notes; their files live under trash/<note_id>/.
+    #[serde(default)]
+    pub trashed: Vec<NoteMeta>,
+    /// Notes deleted for good, so sync doesn't bring them back (see Tombstone).
+    #[serde(default)]
+    pub tombstones: Vec<Tombstone>,
+    /// Format version of index.json; 0 for indexes written before it existed. See storage::migrate_index.
+    #[serde(default, rename = "schemaVersion")]
+    pub schema_version: u32,
 }
 
-impl Default for IndexFile {
-    fn default() -> Self {
-        Self {
-            notes: vec![],
-            notebooks: vec![],
-        }
-    }
+/// A note removed for good (trash emptied or purged, merged into another note). Sync compares
+/// deleted_at with the other side's updated_at to tell a deletion from a note not seen yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub id: String,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoteContent {
     pub meta: NoteMeta,
     pub body: String,
+    /// Number of version snapshots; only populated when requested (0 otherwise).
+    #[serde(default, rename = "versionsCount")]
+    pub versions_count: usize,
+}
+
+/// Everything the editor shows when a note is opened (read_note_full), in one call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteFull {
+    pub meta: NoteMeta,
+    pub body: String,
+    /// Number of version snapshots; only populated when requested (0 otherwise).
+    #[serde(default, rename = "versionsCount")]
+    pub versions_count: usize,
+    /// The note's notebook; None when unfiled or when notebook_id points at a deleted notebook.
+    pub notebook: Option<Notebook>,
+    pub backlinks: Vec<NoteMeta>,
+}
+
+/// Persisted inverted index (meta/search_index.json): lowercased word token -> ids of notes whose title or body contain it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    pub tokens: BTreeMap<String, Vec<String>>,
+    /// Note id -> (mtime in ns, length) of the body file its postings were taken from. A note whose
+    /// body no longer matches (edited outside the app) is searched by reading it instead.
+    #[serde(default)]
+    pub stamps: BTreeMap<String, (u64, u64)>,
+}
+
+/// A search result with a body excerpt explaining why it matched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub meta: NoteMeta,
+    pub snippet: String,
+    /// Byte ranges [start, end) of matched terms within `snippet`.
+    #[serde(rename = "matchRanges")]
+    pub match_ranges: Vec<(usize, usize)>,
+}
+
+/// A named search query ("smart folder") stored in meta/saved_searches.json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// A search result with its relevance score (higher is better; 0 for operator-only queries).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedNote {
+    pub meta: NoteMeta,
+    pub score: u32,
+}
+
+/// A note that mentions another note's title in plain text (not inside `[[...]]`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnlinkedMention {
+    pub meta: NoteMeta,
+    /// Byte offsets of each mention within the body.
+    pub offsets: Vec<usize>,
+}
+
+/// Outgoing links and backlinks of one note, for the connections panel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteConnections {
+    /// Notes this note links to, by title.
+    pub outgoing: Vec<NoteMeta>,
+    /// Notes linking to this note that aren't already in `outgoing`, newest first.
+    pub incoming: Vec<NoteMeta>,
+}
+
+/// A note in the link graph.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub title: String,
+}
+
+/// Why two notes are connected in the link graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphEdgeKind {
+    /// `from` links to `to` via [[...]].
+    Link,
+    /// The notes share at least one tag (undirected; `from` < `to`).
+    Tag,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: GraphEdgeKind,
+}
+
+/// All notes and the connections between them, for the graph view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Outcome of find_and_replace (or what it would do, for a dry run).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FindReplaceResult {
+    #[serde(rename = "notesChanged")]
+    pub notes_changed: usize,
+    #[serde(rename = "totalReplacements")]
+    pub total_replacements: usize,
+}
+
+/// An unchecked task line, for the agenda (see open_tasks).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenTask {
+    #[serde(rename = "noteId")]
+    pub note_id: String,
+    #[serde(rename = "noteTitle")]
+    pub note_title: String,
+    /// 0-based line index, as taken by toggle_task.
+    #[serde(rename = "lineIndex")]
+    pub line_index: usize,
+    /// Task text after the checkbox.
+    pub text: String,
+    /// Due date (YYYY-MM-DD) from an `@due(...)` or `📅` marker.
+    pub due: Option<String>,
+}
+
+/// A Markdown heading in a note body, for the outline panel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutlineHeading {
+    /// 1 for `#` through 6 for `######`.
+    pub level: u8,
+    pub text: String,
+    /// 1-based line number in the body.
+    pub line: usize,
+}
+
+/// Reading statistics for a note body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteStats {
+    pub words: usize,
+    pub chars: usize,
+    /// Estimated at ~200 words per minute, rounded up.
+    #[serde(rename = "readingMinutes")]
+    pub reading_minutes: u32,
+    #[serde(rename = "taskTotal")]
+    pub task_total: usize,
+    #[serde(rename = "taskDone")]
+    pub task_done: usize,
 }
 
 /// Single version entry in the edit timeline (for listing).
@@ -72,6 +586,12 @@ pub struct NoteVersionItem {
     pub title: String,
     #[serde(rename = "bodyPreview")]
     pub body_preview: String,
+    /// Pinned versions are never pruned.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Label given to a manual snapshot.
+    #[serde(default)]
+    pub label: Option<String>,
 }
 
 /// Full content of a past version (for preview/restore).
@@ -83,13 +603,35 @@ pub struct NoteVersionContent {
     pub body: String,
 }
 
-/// Stored version file format (saved_at, title, body).
+/// Stored version file format (saved_at, title, body, pinned, optional label).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VersionSnapshot {
     #[serde(rename = "savedAt")]
     pub saved_at: String,
     pub title: String,
     pub body: String,
+    /// Protected from pruning when true.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Set for snapshots taken manually via create_note_snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Kind of a line in a version diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+/// One line of a diff between two note versions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,3 +645,109 @@ pub struct NoteTemplate {
     #[serde(default, rename = "isCustom")]
     pub is_custom: bool,
 }
+
+/// Template plus usage info (for the templates picker).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    #[serde(flatten)]
+    pub template: NoteTemplate,
+    #[serde(rename = "isBuiltin")]
+    pub is_builtin: bool,
+    /// Number of notes in the index created from this template.
+    #[serde(rename = "notesCreated")]
+    pub notes_created: usize,
+}
+
+/// Payload of the `note-updated` and `note-deleted` events emitted after a command changes a note.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteEvent {
+    pub id: String,
+}
+
+/// Payload of the `backup-progress` event emitted while exporting or importing a backup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupProgress {
+    /// "export" or "import"
+    pub operation: String,
+    /// Cumulative bytes copied so far for this operation.
+    #[serde(rename = "bytesCopied")]
+    pub bytes_copied: u64,
+}
+
+/// User preferences stored in meta/settings.json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Days a trashed note is kept before being purged on startup; 0 disables auto-purge.
+    #[serde(default = "default_trash_retention_days", rename = "trashRetentionDays")]
+    pub trash_retention_days: u32,
+    /// Version snapshots kept per note (pinned versions excluded). Must be at least 1; clamped to 1000.
+    #[serde(default = "default_max_versions_per_note", rename = "maxVersionsPerNote")]
+    pub max_versions_per_note: i64,
+    /// Minutes of inactivity before the app locks again; 0 disables auto-lock.
+    #[serde(default, rename = "autoLockMinutes")]
+    pub auto_lock_minutes: u32,
+    /// Use the UTC date for daily notes and {{date}} instead of the system's local date.
+    #[serde(default, rename = "useUtcDates")]
+    pub use_utc_dates: bool,
+    /// Template rendered into new daily notes; None keeps the plain "# daily" body.
+    #[serde(default, rename = "dailyNoteTemplateId")]
+    pub daily_note_template_id: Option<String>,
+    /// chrono strftime pattern for daily note titles; None means "%Y-%m-%d".
+    #[serde(default, rename = "dailyNoteTitleFormat")]
+    pub daily_note_title_format: Option<String>,
+    /// Lowercase tags when notes are saved (tags are always NFC-normalized). Matching ignores case either way.
+    #[serde(default = "default_lowercase_tags", rename = "lowercaseTags")]
+    pub lowercase_tags: bool,
+    /// Whether metadata-only changes (star, pin, archive, color, tags, aliases, moving to a notebook) also bump
+    /// updated_at. Content edits (title, body, attachments) always do.
+    #[serde(default = "default_bump_updated_on_metadata_change", rename = "bumpUpdatedOnMetadataChange")]
+    pub bump_updated_on_metadata_change: bool,
+    /// Extension of note files under notes/, without the dot. Changed only through `migrate_note_extension`,
+    /// which renames the existing files.
+    #[serde(default = "default_note_file_extension", rename = "noteFileExtension")]
+    pub note_file_extension: String,
+    /// Also save a copy of each pasted image outside app storage, in `clipboard_images_folder`.
+    #[serde(default, rename = "saveClipboardImagesToFolder")]
+    pub save_clipboard_images_to_folder: bool,
+    /// Absolute folder for those copies; None means ~/Images.
+    #[serde(default, rename = "clipboardImagesFolder")]
+    pub clipboard_images_folder: Option<String>,
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+fn default_max_versions_per_note() -> i64 {
+    30
+}
+
+fn default_lowercase_tags() -> bool {
+    true
+}
+
+fn default_bump_updated_on_metadata_change() -> bool {
+    true
+}
+
+fn default_note_file_extension() -> String {
+    "txt".to_string()
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            trash_retention_days: default_trash_retention_days(),
+            max_versions_per_note: default_max_versions_per_note(),
+            auto_lock_minutes: 0,
+            use_utc_dates: false,
+            daily_note_template_id: None,
+            daily_note_title_format: None,
+            lowercase_tags: default_lowercase_tags(),
+            bump_updated_on_metadata_change: default_bump_updated_on_metadata_change(),
+            note_file_extension: default_note_file_extension(),
+            save_clipboard_images_to_folder: false,
+            clipboard_images_folder: None,
+        }
+    }
+}
@@ -1,30 +1,33 @@
-use crate::models::{ImageRef, IndexFile, NoteMeta, NoteTemplate, Notebook, NoteVersionContent, NoteVersionItem, VersionSnapshot};
+use crate::models::{AttachmentKind, BackupManifest, BackupProgress, BackupVerification, DedupeSummary, DiffKind, DiffLine, DuplicateResult, DuplicateTitleGroup, FindReplaceResult, GraphEdge, GraphEdgeKind, GraphNode, ImageRef, ImportMode, ImportSummary, IndexFile, IndexRepairReport, IndexSource, LinkGraph, ManifestEntry, MarkdownImportResult, MergeOptions, MergeOrder, NoteConnections, NoteEvent, NoteFull, NoteMeta, NotePage, NotePreview, NoteStats, NoteStorageUsage, OutlineHeading, NoteTemplate, Notebook, OpenTask, NotebookCount, NotebookDeleteStrategy, NotebookNotes, OrphanReport, Period, RankedNote, ReconcileReport, SaveResult, SavedSearch, SearchHit, SearchIndex, Settings, SortBy, StorageUsage, SyncReport, TagCount, Tombstone, TagDetail, TagMeta, TagSummary, TemplateInfo, UnlinkedMention, NoteVersionContent, NoteVersionItem, VersionSnapshot};
 use chrono::Utc;
 use serde_json;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use tauri::Manager;
+use tauri::{Emitter, Manager};
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 
-/// Extract #tag tokens from text (alphanumeric + underscore after #).
-fn extract_tags_from_body(body: &str) -> Vec<String> {
+/// Extract #tag tokens from text (alphanumerics, `_`, `-` and `/` after #). `/` nests tags
+/// (`#project/alpha`); empty segments are dropped, so `#a//b/` becomes `a/b`.
+pub fn extract_tags_from_body(body: &str) -> Vec<String> {
     let mut tags: HashSet<String> = HashSet::new();
     let mut chars = body.chars().peekable();
     while let Some(c) = chars.next() {
         if c == '#' {
             let mut tag = String::new();
             while let Some(&p) = chars.peek() {
-                if p.is_alphanumeric() || p == '_' || p == '-' {
+                if is_tag_char(p) {
                     tag.push(chars.next().unwrap());
                 } else {
                     break;
                 }
             }
+            let tag = clean_nested_tag(&tag);
             if !tag.is_empty() {
                 tags.insert(tag);
             }
@@ -35,6 +38,70 @@ fn extract_tags_from_body(body: &str) -> Vec<String> {
     v
 }
 
+/// Combining marks count as tag characters so a decomposed `e` + U+0301 stays inside the tag.
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || c == '_'
+        || c == '-'
+        || c == '/'
+        || unicode_normalization::char::is_combining_mark(c)
+}
+
+/// Drop empty `/` segments from a nested tag (`/a//b/` -> `a/b`).
+fn clean_nested_tag(tag: &str) -> String {
+    tag.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("/")
+}
+
+/// True when `tag` is `prefix` or nested under it (`project` matches `project/alpha` but not
+/// `projectx`). Compared with tag_key; a leading `#` and stray slashes on the prefix are ignored.
+pub fn tag_matches_prefix(tag: &str, prefix: &str) -> bool {
+    let prefix = tag_key(&clean_nested_tag(prefix.trim().trim_start_matches('#')));
+    if prefix.is_empty() {
+        return false;
+    }
+    let tag = tag_key(tag);
+    tag == prefix || tag.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Canonical form of a stored tag: Unicode NFC (so a precomposed `é` and `e` + combining accent are
+/// the same tag), then lowercased when `lowercase` is set (the `lowercaseTags` setting, on by default).
+pub fn normalize_tag(tag: &str, lowercase: bool) -> String {
+    let tag: String = tag.nfc().collect();
+    if lowercase {
+        tag.to_lowercase().nfc().collect()
+    } else {
+        tag
+    }
+}
+
+/// Key used to compare tags: NFC + lowercase, regardless of the lowercaseTags setting.
+pub fn tag_key(tag: &str) -> String {
+    normalize_tag(tag, true)
+}
+
+/// Rewrite inline `#old` tags to `#new` using the same token rules as extract_tags_from_body,
+/// so `#old` inside `#older` is left alone. Tags are compared with tag_key, so `#Old` is rewritten too.
+pub fn replace_inline_tag(body: &str, old: &str, new: &str) -> String {
+    let old = tag_key(old);
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if c == '#' {
+            let mut tag = String::new();
+            while let Some(&p) = chars.peek() {
+                if is_tag_char(p) {
+                    tag.push(chars.next().unwrap());
+                } else {
+                    break;
+                }
+            }
+            out.push_str(if tag_key(&clean_nested_tag(&tag)) == old { new } else { &tag });
+        }
+    }
+    out
+}
+
 /// Smart tags: derive a slug from the title (e.g. "Project Alpha" -> "project-alpha").
 fn extract_tags_from_title(title: &str) -> Vec<String> {
     let slug: String = title
@@ -52,39 +119,121 @@ fn extract_tags_from_title(title: &str) -> Vec<String> {
     }
 }
 
-/// Extract [[Title]] from text and resolve to note ids using index (title match, case-insensitive).
-fn extract_links_from_body(body: &str, notes: &[NoteMeta], exclude_id: &str) -> Vec<String> {
-    let mut ids: HashSet<String> = HashSet::new();
-    let mut chars = body.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c == '[' && chars.peek() == Some(&'[') {
-            chars.next();
-            let mut title = String::new();
-            while let Some(&p) = chars.peek() {
-                if p == ']' {
-                    chars.next();
-                    if chars.peek() == Some(&']') {
-                        chars.next();
-                        break;
-                    }
-                    title.push(']');
-                    title.push(chars.next().unwrap());
-                } else {
-                    title.push(chars.next().unwrap());
-                }
+/// A `[[...]]` wikilink in a body: byte range of the whole link and the raw text between the brackets.
+pub struct WikilinkSpan<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub inner: &'a str,
+    /// False for a trailing `[[` with no closing `]]` (inner runs to the end of the body).
+    pub closed: bool,
+}
+
+/// Find `[[...]]` spans. A link ends at the first `]]`, so a single `]` inside is part of the title.
+pub fn wikilink_spans(body: &str) -> Vec<WikilinkSpan<'_>> {
+    let mut spans = vec![];
+    let mut pos = 0;
+    while let Some(rel) = body[pos..].find("[[") {
+        let start = pos + rel;
+        let inner_start = start + 2;
+        match body[inner_start..].find("]]") {
+            Some(len) => {
+                let end = inner_start + len + 2;
+                spans.push(WikilinkSpan {
+                    start,
+                    end,
+                    inner: &body[inner_start..inner_start + len],
+                    closed: true,
+                });
+                pos = end;
             }
-            let title = title.trim();
-            if !title.is_empty() {
-                let lower = title.to_lowercase();
-                for n in notes {
-                    if n.id != exclude_id && n.title.to_lowercase() == lower {
-                        ids.insert(n.id.clone());
-                        break;
-                    }
-                }
+            None => {
+                spans.push(WikilinkSpan {
+                    start,
+                    end: body.len(),
+                    inner: &body[inner_start..],
+                    closed: false,
+                });
+                break;
             }
         }
     }
+    spans
+}
+
+/// Rewrite every closed `[[old_title]]` (case-insensitive, surrounding spaces ignored) to `[[new_title]]`.
+pub fn relink_title(body: &str, old_title: &str, new_title: &str) -> String {
+    let old_lower = old_title.trim().to_lowercase();
+    let mut out = String::with_capacity(body.len());
+    let mut last = 0;
+    for span in wikilink_spans(body) {
+        if span.closed && span.inner.trim().to_lowercase() == old_lower {
+            out.push_str(&body[last..span.start]);
+            out.push_str("[[");
+            out.push_str(new_title);
+            out.push_str("]]");
+            last = span.end;
+        }
+    }
+    out.push_str(&body[last..]);
+    out
+}
+
+/// Resolve a [[link]] target to a note id (case-insensitive). An exact title match wins; otherwise
+/// the note claiming it as an alias, with the oldest note (by created_at, then id) winning collisions.
+pub fn resolve_link_title<'a>(notes: &'a [NoteMeta], title: &str, exclude_id: &str) -> Option<&'a str> {
+    let lower = title.trim().to_lowercase();
+    if lower.is_empty() {
+        return None;
+    }
+    if let Some(n) = notes
+        .iter()
+        .find(|n| n.id != exclude_id && n.title.to_lowercase() == lower)
+    {
+        return Some(&n.id);
+    }
+    notes
+        .iter()
+        .filter(|n| n.id != exclude_id && n.aliases.iter().any(|a| a.to_lowercase() == lower))
+        .min_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)))
+        .map(|n| n.id.as_str())
+}
+
+/// Whether a note other than `exclude_id` already has `title`, compared the way links resolve titles.
+pub fn has_duplicate_title(notes: &[NoteMeta], title: &str, exclude_id: &str) -> bool {
+    let lower = title.trim().to_lowercase();
+    notes
+        .iter()
+        .any(|n| n.id != exclude_id && n.title.trim().to_lowercase() == lower)
+}
+
+/// Groups of two or more notes whose titles match ignoring case, sorted by title; notes within a group
+/// are oldest first.
+pub fn duplicate_title_groups(notes: &[NoteMeta]) -> Vec<DuplicateTitleGroup> {
+    let mut by_title: HashMap<String, Vec<&NoteMeta>> = HashMap::new();
+    for n in notes {
+        by_title.entry(n.title.trim().to_lowercase()).or_default().push(n);
+    }
+    let mut groups: Vec<(String, DuplicateTitleGroup)> = by_title
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|(key, mut group)| {
+            group.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+            let title = group[0].title.clone();
+            (key, DuplicateTitleGroup { title, notes: group.into_iter().cloned().collect() })
+        })
+        .collect();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    groups.into_iter().map(|(_, g)| g).collect()
+}
+
+/// Extract [[Title]] from text and resolve to note ids using index (title or alias match, case-insensitive).
+fn extract_links_from_body(body: &str, notes: &[NoteMeta], exclude_id: &str) -> Vec<String> {
+    let mut ids: HashSet<String> = HashSet::new();
+    for span in wikilink_spans(body) {
+        if let Some(id) = resolve_link_title(notes, span.inner, exclude_id) {
+            ids.insert(id.to_string());
+        }
+    }
     let mut v: Vec<String> = ids.into_iter().collect();
     v.sort();
     v
@@ -106,6 +255,16 @@ pub fn sanitize_filename(name: &str) -> String {
         .collect()
 }
 
+/// Trim surrounding whitespace from a note title, falling back to "Untitled" when nothing is left.
+pub fn normalize_title(title: &str) -> String {
+    let trimmed = title.trim();
+    if trimmed.is_empty() {
+        "Untitled".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
 /// Validate that a note id is a single path component (no directory traversal).
 pub fn validate_note_id(id: &str) -> Result<(), String> {
     if id.is_empty() {
@@ -120,15 +279,52 @@ pub fn validate_note_id(id: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Get the app storage root: ~/Library/Application Support/LocalPrivateNotes
-pub fn storage_root(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+/// Default storage root: ~/Library/Application Support/LocalPrivateNotes
+fn default_storage_root(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
     let app_data: PathBuf = app_handle
         .path()
         .app_data_dir()
         .map_err(|e| e.to_string())?;
     let parent = app_data.parent().ok_or("No parent for app data dir")?;
-    let root = parent.join("LocalPrivateNotes");
-    Ok(root)
+    Ok(parent.join("LocalPrivateNotes"))
+}
+
+/// Where the storage root override lives: settings.json in the app data dir, outside the storage root
+/// so it stays put when the root is moved.
+fn root_settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
+    Ok(app_data.join("settings.json"))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct RootSettings {
+    #[serde(default, rename = "storageRoot", skip_serializing_if = "Option::is_none")]
+    storage_root: Option<String>,
+}
+
+fn read_root_settings(app_handle: &tauri::AppHandle) -> RootSettings {
+    root_settings_path(app_handle)
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Get the app storage root: the configured override, else ~/Library/Application Support/LocalPrivateNotes.
+/// An override that isn't an existing absolute directory (e.g. an unplugged drive) is an error rather
+/// than a silent switch to the default location, where new notes would land apart from the real ones.
+pub fn storage_root(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    if let Some(custom) = read_root_settings(app_handle).storage_root {
+        let path = PathBuf::from(&custom);
+        if path.is_absolute() && path.is_dir() {
+            return Ok(path);
+        }
+        return Err(format!(
+            "Storage folder {} is not available; reconnect it or choose another storage location",
+            custom
+        ));
+    }
+    default_storage_root(app_handle)
 }
 
 fn notes_dir(root: &Path) -> PathBuf {
@@ -143,6 +339,22 @@ fn index_path(root: &Path) -> PathBuf {
     meta_dir(root).join("index.json")
 }
 
+fn search_index_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("search_index.json")
+}
+
+fn saved_searches_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("saved_searches.json")
+}
+
+fn favorites_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("favorites.json")
+}
+
+fn tag_meta_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("tag_meta.json")
+}
+
 fn templates_path(root: &Path) -> PathBuf {
     meta_dir(root).join("templates.json")
 }
@@ -151,44 +363,415 @@ fn images_dir(root: &Path, note_id: &str) -> PathBuf {
     root.join("images").join(sanitize_filename(note_id))
 }
 
+fn attachments_dir(root: &Path, note_id: &str) -> PathBuf {
+    root.join("attachments").join(sanitize_filename(note_id))
+}
+
 fn versions_dir(root: &Path, note_id: &str) -> PathBuf {
     root.join("versions").join(sanitize_filename(note_id))
 }
 
-/// Max number of version snapshots to keep per note.
+/// Default number of version snapshots to keep per note (overridable via settings).
 const MAX_VERSIONS_PER_NOTE: usize = 30;
 
+/// Upper bound for the configurable per-note version limit.
+const MAX_VERSIONS_PER_NOTE_LIMIT: usize = 1000;
+
 /// Sanitize timestamp for use as filename (replace ':' with '-').
 fn version_filename(saved_at: &str) -> String {
     format!("{}.json", saved_at.replace(':', "-"))
 }
 
-/// Ensure all directories exist and index.json exists.
-pub fn init_storage(app_handle: &tauri::AppHandle) -> Result<(), String> {
+/// Write a version snapshot into the note's versions dir (named by its saved_at).
+fn write_version_snapshot(v_dir: &Path, snapshot: &VersionSnapshot) -> Result<(), String> {
+    fs::create_dir_all(v_dir).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string())?;
+    atomic_write(&v_dir.join(version_filename(&snapshot.saved_at)), json.as_bytes())
+}
+
+/// Record `body` (the note's current body) as a labeled version before a bulk edit rewrites it.
+fn snapshot_before_edit(root: &Path, settings: &Settings, n: &NoteMeta, body: &str, label: String) -> Result<(), String> {
+    let v_dir = versions_dir(root, &n.id);
+    write_version_snapshot(
+        &v_dir,
+        &VersionSnapshot {
+            saved_at: n.updated_at.clone(),
+            title: n.title.clone(),
+            body: body.to_string(),
+            pinned: false,
+            label: Some(label),
+        },
+    )?;
+    prune_versions(&v_dir, max_versions_per_note(settings));
+    Ok(())
+}
+
+/// A body rewrite that touches several notes, kept so it can be undone.
+struct BodyEdit {
+    note_id: String,
+    old_body: String,
+    new_body: String,
+}
+
+/// Write every edit's new body. If one fails, the bodies already written are put back and the error
+/// is returned, so the notes are left as they were.
+fn write_body_edits(root: &Path, ext: &str, edits: &[BodyEdit]) -> Result<(), String> {
+    for (i, edit) in edits.iter().enumerate() {
+        if let Err(e) = atomic_write(&note_path_with_ext(root, &edit.note_id, ext), edit.new_body.as_bytes()) {
+            restore_body_edits(root, ext, &edits[..i]);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Put the old bodies of `edits` back (best-effort; used while failing).
+fn restore_body_edits(root: &Path, ext: &str, edits: &[BodyEdit]) {
+    for edit in edits {
+        let _ = atomic_write(&note_path_with_ext(root, &edit.note_id, ext), edit.old_body.as_bytes());
+    }
+}
+
+/// List entry for a snapshot, with the body cut to a short preview.
+fn version_item(snapshot: VersionSnapshot) -> NoteVersionItem {
+    let preview_len = 150;
+    let body_preview = if snapshot.body.chars().count() <= preview_len {
+        snapshot.body
+    } else {
+        format!("{}…", snapshot.body.chars().take(preview_len).collect::<String>())
+    };
+    NoteVersionItem {
+        saved_at: snapshot.saved_at,
+        title: snapshot.title,
+        body_preview,
+        pinned: snapshot.pinned,
+        label: snapshot.label,
+    }
+}
+
+/// Keep only the newest `max` unpinned snapshots in a versions dir. Pinned snapshots are never removed
+/// and don't count toward the limit.
+pub fn prune_versions(v_dir: &Path, max: usize) {
+    let Ok(entries) = fs::read_dir(v_dir) else {
+        return;
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|s| s.ends_with(".json"))
+        .collect();
+    names.sort_by(|a, b| b.cmp(a));
+    let mut kept = 0;
+    for name in names {
+        let path = v_dir.join(&name);
+        let pinned = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<VersionSnapshot>(&s).ok())
+            .is_some_and(|v| v.pinned);
+        if pinned {
+            continue;
+        }
+        kept += 1;
+        if kept > max {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Ensure all directories exist and index.json exists, repairing a corrupt index first
+/// (see verify_and_repair_index).
+pub fn init_storage(app_handle: &tauri::AppHandle) -> Result<IndexRepairReport, String> {
     let root = storage_root(app_handle)?;
     fs::create_dir_all(notes_dir(&root)).map_err(|e| e.to_string())?;
     fs::create_dir_all(meta_dir(&root)).map_err(|e| e.to_string())?;
     fs::create_dir_all(root.join("images")).map_err(|e| e.to_string())?;
 
+    let _guard = lock_index();
+    let report = repair_index(&root)?;
     let idx = index_path(&root);
     if !idx.exists() {
-        let empty = IndexFile::default();
-        write_index(&root, &empty)?;
+        let empty = IndexFile {
+            schema_version: INDEX_SCHEMA_VERSION,
+            ..Default::default()
+        };
+        write_index(&root, &empty, false)?;
+    }
+    // Also warms the index cache so the first command doesn't pay for the parse.
+    upgrade_index_file(&root)?;
+    let retention_days = read_settings(&root)?.trash_retention_days;
+    if retention_days > 0 {
+        purge_expired_trash(&root, retention_days)?;
+    }
+    Ok(report)
+}
+
+/// Check that index.json parses and recover it if not, so a crash mid-write doesn't leave the app unusable.
+pub fn verify_and_repair_index(app_handle: &tauri::AppHandle) -> Result<IndexRepairReport, String> {
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    repair_index(&root)
+}
+
+/// If index.json doesn't parse, copy it to `index.json.corrupt-<timestamp>` and replace it with
+/// index.json.tmp when that parses, otherwise with a minimal index rebuilt from notes/*.txt.
+/// A missing index.json is left alone (init_storage creates it).
+pub fn repair_index(root: &Path) -> Result<IndexRepairReport, String> {
+    let path = index_path(root);
+    let intact = IndexRepairReport {
+        source: IndexSource::Intact,
+        corrupt_backup: None,
+        notes_recovered: 0,
+    };
+    if !path.exists() {
+        return Ok(intact);
+    }
+    let raw = fs::read(&path).map_err(|e| e.to_string())?;
+    if serde_json::from_slice::<IndexFile>(&raw).is_ok() {
+        return Ok(intact);
+    }
+    let backup_name = format!("index.json.corrupt-{}", Utc::now().format("%Y%m%d-%H%M%S"));
+    fs::write(meta_dir(root).join(&backup_name), &raw).map_err(|e| e.to_string())?;
+    let from_temp = fs::read(path.with_extension("json.tmp"))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<IndexFile>(&b).ok());
+    let (source, index) = match from_temp {
+        Some(index) => (IndexSource::TempFile, index),
+        None => (IndexSource::Rebuilt, rebuild_index_from_notes(root)?),
+    };
+    invalidate_index_cache();
+    write_index(root, &index, true)?;
+    Ok(IndexRepairReport {
+        source,
+        corrupt_backup: Some(format!("meta/{}", backup_name)),
+        notes_recovered: index.notes.len(),
+    })
+}
+
+/// Minimal index with one note per note file in notes/: the file stem is the id and the title, and both
+/// timestamps come from the file's mtime. Notebooks, tags and attachments are not recoverable this way.
+fn rebuild_index_from_notes(root: &Path) -> Result<IndexFile, String> {
+    let mut index = IndexFile {
+        schema_version: INDEX_SCHEMA_VERSION,
+        ..Default::default()
+    };
+    for (id, path) in note_files(root, &note_extension(root)?) {
+        index.notes.push(stray_note_meta(&id, &id, &path));
+    }
+    Ok(index)
+}
+
+/// `(stem, path)` of every notes/*.<ext> whose stem is a valid note id, sorted by stem.
+fn note_files(root: &Path, ext: &str) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(notes_dir(root)) else {
+        return vec![];
+    };
+    let mut files: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(ext))
+        .filter_map(|p| {
+            let id = p.file_stem()?.to_str()?.to_string();
+            validate_note_id(&id).ok()?;
+            Some((id, p))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Modification time of `path` as RFC3339, or now if it can't be read.
+fn file_mtime_rfc3339(path: &Path) -> String {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(chrono::DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now())
+        .to_rfc3339()
+}
+
+/// Index entry for a note file found on disk without one; created and updated are both the file's mtime.
+fn stray_note_meta(id: &str, title: &str, path: &Path) -> NoteMeta {
+    let modified = file_mtime_rfc3339(path);
+    NoteMeta {
+        id: id.to_string(),
+        title: title.to_string(),
+        created_at: modified.clone(),
+        updated_at: modified,
+        important: false,
+        filename: path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string(),
+        images: vec![],
+        tags: vec![],
+        links_to: vec![],
+        is_daily: false,
+        notebook_id: None,
+        read_count: 0,
+        created_from_template_id: None,
+        pin_order: None,
+        deleted_at: None,
+        pinned: false,
+        order: None,
+        aliases: vec![],
+        period: None,
+        archived: false,
+        metadata_updated_at: None,
+        preview: None,
+        color: None,
     }
-    Ok(())
 }
 
-/// Atomic write: write to temp file then rename.
-pub fn write_index(root: &Path, index: &IndexFile) -> Result<(), String> {
+/// Upper bound for the serialized index; anything larger almost certainly means runaway growth from a bug.
+pub const MAX_INDEX_SIZE_BYTES: usize = 50 * 1024 * 1024;
+
+/// Atomic write (see atomic_write); the temp file is index.json.tmp, which repair_index falls back to.
+/// Refuses to write an index larger than MAX_INDEX_SIZE_BYTES unless `force` is set (recovery only).
+pub fn write_index(root: &Path, index: &IndexFile, force: bool) -> Result<(), String> {
     let path = index_path(root);
-    let temp_path = path.with_extension("json.tmp");
     let json = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    if !force && json.len() > MAX_INDEX_SIZE_BYTES {
+        return Err("Index file would exceed maximum allowed size".into());
+    }
+    atomic_write(&path, json.as_bytes())?;
+    let checked_at = std::time::SystemTime::now();
+    cache_index(&path, index_stamp(json.as_bytes()), file_stamp(&path), checked_at, index.clone());
+    Ok(())
+}
+
+/// Write `bytes` to `<path>.tmp`, fsync it, then rename it over `path`, so a crash leaves either the
+/// old or the new contents, never a truncated file.
+pub fn atomic_write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let mut temp_name = path.file_name().ok_or("Invalid path")?.to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
     let mut f = fs::File::create(&temp_path).map_err(|e| e.to_string())?;
-    f.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+    f.write_all(bytes).map_err(|e| e.to_string())?;
     f.sync_all().map_err(|e| e.to_string())?;
     drop(f);
-    fs::rename(&temp_path, &path).map_err(|e| e.to_string())?;
-    Ok(())
+    fs::rename(&temp_path, path).map_err(|e| e.to_string())
+}
+
+/// The last index read or written, with the stamp of the bytes it was parsed from and the file's
+/// (mtime, length) as of `checked_at`.
+struct CachedIndex {
+    path: PathBuf,
+    stamp: (u64, u64),
+    file: Option<(u64, u64)>,
+    checked_at: std::time::SystemTime,
+    index: IndexFile,
+}
+
+impl CachedIndex {
+    /// True if `path` still has the (mtime, length) seen when the cached contents were last confirmed,
+    /// and that mtime trailed the check by more than INDEX_MTIME_SLACK, so no rewrite can hide behind it.
+    fn is_fresh(&self, path: &Path, file: Option<(u64, u64)>) -> bool {
+        let Some((mtime, _)) = self.file else {
+            return false;
+        };
+        let checked_at = self.checked_at.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        self.path == path
+            && self.file == file
+            && std::time::Duration::from_nanos(mtime) + INDEX_MTIME_SLACK < checked_at
+    }
+}
+
+/// Parsed index.json kept between commands so reads skip reading and parsing it. It is process-wide
+/// rather than Tauri state because read_index/write_index only see the storage root. A read serves it
+/// after a stat when is_fresh holds; otherwise it reads and hashes the file, so changes made behind our
+/// back (sync, backup restore, manual edits) are picked up; invalidate_index_cache drops it outright.
+static INDEX_CACHE: std::sync::RwLock<Option<CachedIndex>> = std::sync::RwLock::new(None);
+
+/// How far index.json's mtime must trail the moment it was checked before (mtime, length) alone is
+/// trusted. A rewrite in the same timestamp tick as the check keeps both; 2 s covers FAT's granularity.
+const INDEX_MTIME_SLACK: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Length and hash of index.json's contents. Hashing is far cheaper than the parse it saves, and unlike
+/// mtime it catches an edit that keeps the length within the filesystem's timestamp granularity.
+fn index_stamp(bytes: &[u8]) -> (u64, u64) {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    (bytes.len() as u64, hasher.finish())
+}
+
+/// Cache `index`, parsed from bytes with `stamp`; `file` is index.json's stamp taken at `checked_at`,
+/// before those bytes were read (or after they were written).
+fn cache_index(
+    path: &Path,
+    stamp: (u64, u64),
+    file: Option<(u64, u64)>,
+    checked_at: std::time::SystemTime,
+    index: IndexFile,
+) {
+    if let Ok(mut cache) = INDEX_CACHE.write() {
+        *cache = Some(CachedIndex {
+            path: path.to_path_buf(),
+            stamp,
+            file,
+            checked_at,
+            index,
+        });
+    }
+}
+
+/// Forget the cached index; the next read_index parses index.json again.
+pub fn invalidate_index_cache() {
+    if let Ok(mut cache) = INDEX_CACHE.write() {
+        *cache = None;
+    }
+}
+
+/// Serializes index read-modify-write cycles: commands run on several threads, and without this an
+/// autosave and a tag edit could both read the index and the later write would drop the other's change.
+/// Process-wide for the same reason as INDEX_CACHE. Re-entrant, so a mutation that calls another
+/// (import -> save_note) doesn't deadlock on itself.
+struct IndexLock {
+    /// Owning thread and how many guards it holds.
+    owner: std::sync::Mutex<Option<(std::thread::ThreadId, usize)>>,
+    released: std::sync::Condvar,
+}
+
+static INDEX_LOCK: IndexLock = IndexLock {
+    owner: std::sync::Mutex::new(None),
+    released: std::sync::Condvar::new(),
+};
+
+/// Held for the whole read_index .. write_index cycle of a mutation; released on drop.
+pub struct IndexGuard {
+    /// Guards must be dropped on the thread that took them.
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+/// Block until no other thread holds the index lock, then take it (or nest, if this thread holds it).
+pub fn lock_index() -> IndexGuard {
+    let me = std::thread::current().id();
+    let mut owner = INDEX_LOCK.owner.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        match owner.as_mut() {
+            None => {
+                *owner = Some((me, 1));
+                break;
+            }
+            Some((thread, depth)) if *thread == me => {
+                *depth += 1;
+                break;
+            }
+            Some(_) => owner = INDEX_LOCK.released.wait(owner).unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+    IndexGuard {
+        _not_send: std::marker::PhantomData,
+    }
+}
+
+impl Drop for IndexGuard {
+    fn drop(&mut self) {
+        let mut owner = INDEX_LOCK.owner.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, depth)) = owner.as_mut() {
+            *depth -= 1;
+            if *depth == 0 {
+                *owner = None;
+                INDEX_LOCK.released.notify_one();
+            }
+        }
+    }
 }
 
 pub fn read_index(root: &Path) -> Result<IndexFile, String> {
@@ -196,112 +779,562 @@ pub fn read_index(root: &Path) -> Result<IndexFile, String> {
     if !path.exists() {
         return Ok(IndexFile::default());
     }
-    let mut f = fs::File::open(&path).map_err(|e| e.to_string())?;
-    let mut s = String::new();
-    f.read_to_string(&mut s).map_err(|e| e.to_string())?;
-    serde_json::from_str(&s).map_err(|e| e.to_string())
+    // Stat before reading: a write landing in between then shows up as a changed stamp next time.
+    let checked_at = std::time::SystemTime::now();
+    let file = file_stamp(&path);
+    if let Ok(cache) = INDEX_CACHE.read() {
+        if let Some(cached) = cache.as_ref().filter(|c| c.is_fresh(&path, file)) {
+            return Ok(cached.index.clone());
+        }
+    }
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+    let stamp = index_stamp(&bytes);
+    if let Ok(mut cache) = INDEX_CACHE.write() {
+        if let Some(cached) = cache.as_mut().filter(|c| c.path == path && c.stamp == stamp) {
+            cached.file = file;
+            cached.checked_at = checked_at;
+            return Ok(cached.index.clone());
+        }
+    }
+    let mut index: IndexFile = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    if index.schema_version < INDEX_SCHEMA_VERSION {
+        // An index from an older version that init_storage hasn't saved back yet (e.g. a restored backup).
+        index = migrate_index(index, read_settings(root)?.lowercase_tags);
+    }
+    cache_index(&path, stamp, file, checked_at, index.clone());
+    Ok(index)
+}
+
+// --- Index schema migrations ---
+
+/// Current IndexFile::schema_version. Bump it with each new migrate_vN_to_vN+1 step.
+pub const INDEX_SCHEMA_VERSION: u32 = 1;
+
+/// Bring an index up to INDEX_SCHEMA_VERSION, one version at a time. An index from a newer version is
+/// returned unchanged.
+pub fn migrate_index(mut index: IndexFile, lowercase_tags: bool) -> IndexFile {
+    if index.schema_version == 0 {
+        index = migrate_v0_to_v1(index, lowercase_tags);
+    }
+    index
+}
+
+/// v1: tags are NFC-normalized (and lowercased when `lowercase_tags`), deduplicated and sorted, as
+/// saves have done since tag normalization. Indexes from before then can hold `Work` and `work` side by side.
+pub fn migrate_v0_to_v1(mut index: IndexFile, lowercase_tags: bool) -> IndexFile {
+    for n in index.notes.iter_mut().chain(index.trashed.iter_mut()) {
+        let mut tags: Vec<String> = vec![];
+        for t in &n.tags {
+            let t = normalize_tag(t, lowercase_tags);
+            if !tags.iter().any(|existing| tag_key(existing) == tag_key(&t)) {
+                tags.push(t);
+            }
+        }
+        tags.sort();
+        n.tags = tags;
+    }
+    index.schema_version = 1;
+    index
+}
+
+/// Migrate index.json on disk if it predates INDEX_SCHEMA_VERSION, so later reads don't redo it.
+/// Either way the result ends up in the index cache.
+fn upgrade_index_file(root: &Path) -> Result<(), String> {
+    let path = index_path(root);
+    let checked_at = std::time::SystemTime::now();
+    let file = file_stamp(&path);
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+    let index: IndexFile = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    if index.schema_version >= INDEX_SCHEMA_VERSION {
+        cache_index(&path, index_stamp(&bytes), file, checked_at, index);
+        return Ok(());
+    }
+    let index = migrate_index(index, read_settings(root)?.lowercase_tags);
+    write_index(root, &index, false)
+}
+
+/// Path of a note's file. Reads the extension from the settings; loops over many notes resolve it once
+/// with note_extension and use note_path_with_ext.
+fn note_path(root: &Path, note_id: &str) -> Result<PathBuf, String> {
+    Ok(note_path_with_ext(root, note_id, &note_extension(root)?))
+}
+
+fn note_path_with_ext(root: &Path, note_id: &str, ext: &str) -> PathBuf {
+    notes_dir(root).join(note_filename(&sanitize_filename(note_id), ext))
+}
+
+fn note_filename(note_id: &str, ext: &str) -> String {
+    format!("{}.{}", note_id, ext)
+}
+
+/// Extension of note files from the settings. An invalid stored value is an error rather than a
+/// fallback to "txt", which would point every note path at files that aren't there.
+fn note_extension(root: &Path) -> Result<String, String> {
+    validate_note_extension(&read_settings(root)?.note_file_extension)
+        .map_err(|e| format!("Invalid noteFileExtension in settings: {}", e))
+}
+
+/// Normalize a note file extension: a leading dot is dropped and it is lowercased. It must be 1–16
+/// ASCII letters or digits so it can't change the directory or clash with the .tmp files of atomic writes.
+pub fn validate_note_extension(ext: &str) -> Result<String, String> {
+    let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+    if ext.is_empty() || ext.len() > 16 || !ext.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("Note file extension must be 1-16 letters or digits".into());
+    }
+    if ext == "tmp" {
+        return Err("Note file extension cannot be tmp".into());
+    }
+    Ok(ext)
 }
 
-fn note_path(root: &Path, note_id: &str) -> PathBuf {
-    notes_dir(root).join(format!("{}.txt", sanitize_filename(note_id)))
+/// Note order used by every list (sidebar, notebooks). Precedence: `pinned` notes first; then notes
+/// with a `pin_order` (ascending); then notes with a manual `order` (ascending); then updated_at desc.
+pub fn compare_sidebar_order(a: &NoteMeta, b: &NoteMeta) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    // Some(_) before None, lower values first.
+    let manual = |x: Option<u32>, y: Option<u32>| match (x, y) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    b.pinned
+        .cmp(&a.pinned)
+        .then_with(|| manual(a.pin_order, b.pin_order))
+        .then_with(|| manual(a.order, b.order))
+        .then_with(|| b.updated_at.cmp(&a.updated_at))
 }
 
-/// List all notes from index.
-pub fn list_notes(app_handle: &tauri::AppHandle) -> Result<Vec<NoteMeta>, String> {
+/// List notes from index in sidebar order (see compare_sidebar_order). Archived notes are left out
+/// unless `include_archived` is set.
+pub fn list_notes(app_handle: &tauri::AppHandle, include_archived: bool) -> Result<Vec<NoteMeta>, String> {
     let root = storage_root(app_handle)?;
     let index = read_index(&root)?;
-    Ok(index.notes)
+    let mut notes: Vec<NoteMeta> = index.notes.into_iter().filter(|n| include_archived || !n.archived).collect();
+    notes.sort_by(compare_sidebar_order);
+    Ok(notes)
 }
 
-/// Read note body and metadata.
-pub fn read_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<crate::models::NoteContent, String> {
-    validate_note_id(note_id)?;
+/// Archived notes in sidebar order.
+pub fn list_archived_notes(app_handle: &tauri::AppHandle) -> Result<Vec<NoteMeta>, String> {
     let root = storage_root(app_handle)?;
     let index = read_index(&root)?;
-    let meta = index
-        .notes
-        .into_iter()
-        .find(|n| n.id == note_id)
-        .ok_or_else(|| "Note not found".to_string())?;
-    let path = note_path(&root, note_id);
-    let body = if path.exists() {
-        fs::read_to_string(&path).map_err(|e| e.to_string())?
-    } else {
-        String::new()
+    let mut notes: Vec<NoteMeta> = index.notes.into_iter().filter(|n| n.archived).collect();
+    notes.sort_by(compare_sidebar_order);
+    Ok(notes)
+}
+
+/// Title sort key: accents stripped (NFD without combining marks) and lowercased, so "Émile" sorts
+/// with "emile" and before "Zoe".
+fn title_sort_key(title: &str) -> String {
+    title
+        .nfd()
+        .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Compare two notes for `sort`; ties are broken by updated_at desc, then id, so the order is total.
+pub fn compare_notes_by(sort: SortBy, a: &NoteMeta, b: &NoteMeta) -> std::cmp::Ordering {
+    let primary = match sort {
+        SortBy::UpdatedAt => std::cmp::Ordering::Equal,
+        SortBy::CreatedAt => b.created_at.cmp(&a.created_at),
+        SortBy::TitleAsc => title_sort_key(&a.title).cmp(&title_sort_key(&b.title)),
+        SortBy::TitleDesc => title_sort_key(&b.title).cmp(&title_sort_key(&a.title)),
+        SortBy::Important => b.important.cmp(&a.important),
     };
-    Ok(crate::models::NoteContent { meta, body })
+    primary
+        .then_with(|| b.updated_at.cmp(&a.updated_at))
+        .then_with(|| a.id.cmp(&b.id))
 }
 
-/// Create or update a note. If note_id is None, create new.
+/// List unarchived notes ordered by `sort`; None keeps the sidebar order of list_notes.
+pub fn list_notes_sorted(app_handle: &tauri::AppHandle, sort: Option<SortBy>) -> Result<Vec<NoteMeta>, String> {
+    let Some(sort) = sort else {
+        return list_notes(app_handle, false);
+    };
+    let root = storage_root(app_handle)?;
+    let mut notes: Vec<NoteMeta> = read_index(&root)?.notes.into_iter().filter(|n| !n.archived).collect();
+    notes.sort_by(|a, b| compare_notes_by(sort, a, b));
+    Ok(notes)
+}
+
+/// Window of an already filtered and sorted list: skip `offset` notes (default 0), then take at most
+/// `limit` (default all). `total` is the length before windowing.
+pub fn page_notes(notes: Vec<NoteMeta>, offset: Option<usize>, limit: Option<usize>) -> NotePage {
+    let total = notes.len();
+    let items = notes
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    NotePage { items, total }
+}
+
+/// list_notes, windowed by page_notes.
+pub fn list_notes_paged(
+    app_handle: &tauri::AppHandle,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<NotePage, String> {
+    Ok(page_notes(list_notes(app_handle, false)?, offset, limit))
+}
+
+/// First `len` characters of `body` with runs of whitespace (including newlines) collapsed to one space.
+pub fn preview_text(body: &str, len: usize) -> String {
+    let mut out = String::new();
+    let mut count = 0;
+    for word in body.split_whitespace() {
+        if count >= len {
+            break;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+            count += 1;
+        }
+        for c in word.chars() {
+            if count >= len {
+                break;
+            }
+            out.push(c);
+            count += 1;
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// Characters of body text cached in NoteMeta.preview.
+pub const NOTE_PREVIEW_CHARS: usize = 160;
+
+/// Preview of a body: a leading `# heading` line is dropped (it usually repeats the title), then
+/// preview_text.
+pub fn note_preview(body: &str, len: usize) -> String {
+    let trimmed = body.trim_start();
+    let rest = match trimmed.split_once('\n') {
+        Some((first, rest)) if first.starts_with("# ") => rest,
+        None if trimmed.starts_with("# ") => "",
+        _ => trimmed,
+    };
+    preview_text(rest, len)
+}
+
+/// Read at most the first `chars` characters' worth of bytes (4 per char) of a file, cut back to the
+/// last complete UTF-8 character.
+fn read_text_prefix(path: &Path, chars: usize) -> String {
+    let Ok(f) = fs::File::open(path) else {
+        return String::new();
+    };
+    let mut buf = vec![];
+    if f.take(chars as u64 * 4).read_to_end(&mut buf).is_err() {
+        return String::new();
+    }
+    match String::from_utf8(buf) {
+        Ok(s) => s,
+        Err(e) => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut buf = e.into_bytes();
+            buf.truncate(valid);
+            String::from_utf8(buf).unwrap_or_default()
+        }
+    }
+}
+
+/// list_notes with the first `preview_len` characters of each body (see note_preview). Notes saved
+/// since previews were cached answer from NoteMeta.preview when it is long enough; the rest cost a
+/// file read each, so for large libraries page the list (list_notes_paged) or run backfill_note_previews.
+pub fn list_notes_with_preview(app_handle: &tauri::AppHandle, preview_len: usize) -> Result<Vec<NotePreview>, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    Ok(list_notes(app_handle, false)?
+        .into_iter()
+        .map(|meta| {
+            let preview = match &meta.preview {
+                Some(cached) if preview_len <= NOTE_PREVIEW_CHARS => preview_text(cached, preview_len),
+                _ => {
+                    // A heading line can take up to a line's worth of the prefix, so read a little extra.
+                    let prefix = read_text_prefix(&note_path_with_ext(&root, &meta.id, &ext), preview_len + 200);
+                    note_preview(&prefix, preview_len)
+                }
+            };
+            NotePreview { meta, preview }
+        })
+        .collect())
+}
+
+/// Fill NoteMeta.preview for notes saved before previews were cached. Returns how many were filled.
+pub fn backfill_note_previews(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let mut filled = 0;
+    for n in index.notes.iter_mut().filter(|n| n.preview.is_none()) {
+        let body = fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)).unwrap_or_default();
+        n.preview = Some(note_preview(&body, NOTE_PREVIEW_CHARS));
+        filled += 1;
+    }
+    if filled > 0 {
+        write_index(&root, &index, false)?;
+    }
+    Ok(filled)
+}
+
+/// Re-derive every note's tags and links from its title and body (as a save would) and write the
+/// index once. Use after the extraction rules change or when the index has drifted from the files.
+/// Tags that exist only in the index (frontmatter, added by hand) are kept, as they are on a save, so
+/// this adds missing body tags but can't tell a stale `#tag` from one added by hand.
+/// Notes whose file can't be read are left alone. Returns how many notes changed.
+pub fn reindex_tags_and_links(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let lowercase_tags = read_settings(&root)?.lowercase_tags;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let mut derived: Vec<(usize, Vec<String>, Vec<String>)> = vec![];
+    for (pos, n) in index.notes.iter().enumerate() {
+        let Ok(body) = fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)) else {
+            continue;
+        };
+        let tags = tags_after_edit(&n.title, &n.tags, &body, &body, lowercase_tags);
+        let links_to = extract_links_from_body(&body, &index.notes, &n.id);
+        if tags != n.tags || links_to != n.links_to {
+            derived.push((pos, tags, links_to));
+        }
+    }
+    for (pos, tags, links_to) in &derived {
+        let n = &mut index.notes[*pos];
+        n.tags = tags.clone();
+        n.links_to = links_to.clone();
+    }
+    if !derived.is_empty() {
+        write_index(&root, &index, false)?;
+    }
+    Ok(derived.len())
+}
+
+/// Set manual sidebar order from a drag-reorder: ordered_ids get 1, 2, 3…; every other note loses its
+/// manual order and falls back to updated_at. Single index write.
+pub fn reorder_notes(app_handle: &tauri::AppHandle, ordered_ids: &[String]) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    for id in ordered_ids {
+        if !index.notes.iter().any(|n| n.id == *id) {
+            return Err("Note not found".into());
+        }
+    }
+    for n in index.notes.iter_mut() {
+        n.order = ordered_ids.iter().position(|id| *id == n.id).map(|i| i as u32 + 1);
+    }
+    write_index(&root, &index, false)?;
+    Ok(())
+}
+
+/// Number of saved version snapshots for a note (0 if it has no versions directory).
+fn count_versions(root: &Path, note_id: &str) -> usize {
+    match fs::read_dir(versions_dir(root, note_id)) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("json"))
+            .count(),
+        Err(_) => 0,
+    }
+}
+
+/// Read note body and metadata. The versions directory is only scanned when `load_version_count` is set.
+pub fn read_note(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    load_version_count: bool,
+) -> Result<crate::models::NoteContent, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let meta = {
+        let _guard = lock_index();
+        let mut index = read_index(&root)?;
+        let n = index
+            .notes
+            .iter_mut()
+            .find(|n| n.id == note_id)
+            .ok_or_else(|| "Note not found".to_string())?;
+        n.read_count = n.read_count.saturating_add(1);
+        let meta = n.clone();
+        // Best-effort: failing to bump the counter should not prevent opening the note.
+        let _ = write_index(&root, &index, false);
+        meta
+    };
+    load_note_content(&root, meta, load_version_count)
+}
+
+/// A note's meta and body for internal use (duplicate, export): unlike read_note, this doesn't count as
+/// the user opening the note, so it neither bumps read_count nor writes the index.
+pub fn read_note_uncounted(app_handle: &tauri::AppHandle, note_id: &str) -> Result<crate::models::NoteContent, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let meta = read_index(&root)?
+        .notes
+        .into_iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    load_note_content(&root, meta, false)
+}
+
+fn load_note_content(root: &Path, meta: NoteMeta, load_version_count: bool) -> Result<crate::models::NoteContent, String> {
+    let note_id = meta.id.as_str();
+    let path = note_path(root, note_id)?;
+    let body = if path.exists() {
+        fs::read_to_string(&path).map_err(|e| e.to_string())?
+    } else {
+        String::new()
+    };
+    let versions_count = if load_version_count {
+        count_versions(root, note_id)
+    } else {
+        0
+    };
+    Ok(crate::models::NoteContent {
+        meta,
+        body,
+        versions_count,
+    })
+}
+
+/// Tags a note gets on save: body #tags plus the title slug, normalized and sorted.
+pub fn note_tags(title: &str, body: &str, lowercase_tags: bool) -> Vec<String> {
+    let body_tags = extract_tags_from_body(body);
+    let title_tags = extract_tags_from_title(title);
+    let mut tags: HashSet<String> = HashSet::new();
+    for t in body_tags.into_iter().chain(title_tags) {
+        tags.insert(normalize_tag(&t, lowercase_tags));
+    }
+    let mut tags: Vec<String> = tags.into_iter().collect();
+    tags.sort();
+    tags
+}
+
+/// Create or update a note. If note_id is None, create new. Saving an existing note with its current
+/// title and body is a no-op: nothing is written, no version is recorded, and the stored meta is returned.
+/// A leading frontmatter block (as export_note_as_markdown writes) is stripped from the body; its tags
+/// are added to the note's and its `created:` is used when the note is created. Tags that don't come
+/// from the previous body are kept across saves.
 pub fn save_note(
     app_handle: &tauri::AppHandle,
     note_id: Option<&str>,
     title: &str,
     body: &str,
 ) -> Result<NoteMeta, String> {
+    save_note_ex(app_handle, note_id, title, body).map(|r| r.meta)
+}
+
+/// Tags for a note being re-saved: `tags` (from the new body and frontmatter) plus those of `previous`
+/// that weren't derived from the old body (`previous_derived`), i.e. frontmatter or manually added ones.
+pub fn carry_over_tags(previous: &[String], previous_derived: &[String], mut tags: Vec<String>) -> Vec<String> {
+    for t in previous {
+        if !previous_derived.contains(t) && !tags.iter().any(|existing| tag_key(existing) == tag_key(t)) {
+            tags.push(t.clone());
+        }
+    }
+    tags.sort();
+    tags
+}
+
+/// A note's tags after its body changed from `old_body` to `new_body` under the same title: the new
+/// body's tags plus whatever carry_over_tags keeps from `tags`.
+pub fn tags_after_edit(title: &str, tags: &[String], old_body: &str, new_body: &str, lowercase_tags: bool) -> Vec<String> {
+    let previous_derived = note_tags(title, old_body, lowercase_tags);
+    carry_over_tags(tags, &previous_derived, note_tags(title, new_body, lowercase_tags))
+}
+
+/// save_note, also reporting whether the note was created (no id, or an id not yet in the index)
+/// rather than updated.
+pub fn save_note_ex(
+    app_handle: &tauri::AppHandle,
+    note_id: Option<&str>,
+    title: &str,
+    body: &str,
+) -> Result<SaveResult, String> {
+    save_note_with(app_handle, note_id, title, body, |_| {})
+}
+
+/// save_note_ex, with `on_create` applied to the meta of a note it creates before the index write, so
+/// fields save_note doesn't derive (a template id, imported tags, copied attachments) land in the same
+/// write as the note itself. Not called when an existing note is updated.
+fn save_note_with(
+    app_handle: &tauri::AppHandle,
+    note_id: Option<&str>,
+    title: &str,
+    body: &str,
+    on_create: impl FnOnce(&mut NoteMeta),
+) -> Result<SaveResult, String> {
+    let title = normalize_title(title);
+    let title = title.as_str();
+    // A pasted or re-imported export keeps its metadata out of the stored body (see strip_note_frontmatter).
+    let (fm, body) = strip_note_frontmatter(body);
+    let body = body.as_str();
     let root = storage_root(app_handle)?;
     let now = Utc::now().to_rfc3339();
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
 
-    let body_tags = extract_tags_from_body(body);
-    let title_tags = extract_tags_from_title(title);
-    let mut tags: HashSet<String> = body_tags.into_iter().collect();
-    for t in title_tags {
-        tags.insert(t);
+    let lowercase_tags = read_settings(&root)?.lowercase_tags;
+    let mut tags = note_tags(title, body, lowercase_tags);
+    for t in &fm.tags {
+        let t = normalize_tag(t, lowercase_tags);
+        if !tags.iter().any(|existing| tag_key(existing) == tag_key(&t)) {
+            tags.push(t);
+        }
     }
-    let mut tags: Vec<String> = tags.into_iter().collect();
     tags.sort();
+    let created_at = fm.created_at.unwrap_or_else(|| now.clone());
     let links_to = extract_links_from_body(body, &index.notes, note_id.unwrap_or(""));
+    let preview = Some(note_preview(body, NOTE_PREVIEW_CHARS));
+    let duplicate_title = has_duplicate_title(&index.notes, title, note_id.unwrap_or(""));
 
-    let (id, meta) = if let Some(id) = note_id {
+    let (id, meta, created) = if let Some(id) = note_id {
         validate_note_id(id)?;
         let pos = index.notes.iter().position(|n| n.id == id);
         match pos {
             Some(i) => {
                 let n = index.notes.get_mut(i).unwrap();
+                let path = note_path(&root, id)?;
+                let current_body = fs::read_to_string(&path).ok();
+                // Autosave often sends unchanged content: skip the writes and the version snapshot.
+                if n.title == title && current_body.as_deref() == Some(body) {
+                    return Ok(SaveResult {
+                        meta: n.clone(),
+                        created: false,
+                        duplicate_title,
+                    });
+                }
                 // Save current content as a version before overwriting (if note already has body on disk)
-                let path = note_path(&root, id);
-                if path.exists() {
-                    if let Ok(current_body) = fs::read_to_string(&path) {
-                        let v_dir = versions_dir(&root, id);
-                        let _ = fs::create_dir_all(&v_dir);
-                        let snapshot = VersionSnapshot {
-                            saved_at: n.updated_at.clone(),
-                            title: n.title.clone(),
-                            body: current_body,
-                        };
-                        let v_name = version_filename(&snapshot.saved_at);
-                        let v_path = v_dir.join(&v_name);
-                        if let Ok(json) = serde_json::to_string_pretty(&snapshot) {
-                            let _ = fs::write(&v_path, json);
-                        }
-                        // Keep only the last MAX_VERSIONS_PER_NOTE
-                        if let Ok(entries) = fs::read_dir(&v_dir) {
-                            let mut names: Vec<String> = entries
-                                .filter_map(|e| e.ok())
-                                .filter_map(|e| e.file_name().into_string().ok())
-                                .filter(|s| s.ends_with(".json"))
-                                .collect();
-                            names.sort_by(|a, b| b.cmp(a));
-                            for name in names.into_iter().skip(MAX_VERSIONS_PER_NOTE) {
-                                let _ = fs::remove_file(v_dir.join(&name));
-                            }
-                        }
-                    }
+                if let Some(current_body) = &current_body {
+                    let snapshot = VersionSnapshot {
+                        saved_at: n.updated_at.clone(),
+                        title: n.title.clone(),
+                        body: current_body.clone(),
+                        pinned: false,
+                        label: None,
+                    };
+                    let v_dir = versions_dir(&root, id);
+                    let _ = write_version_snapshot(&v_dir, &snapshot);
+                    prune_versions(&v_dir, max_versions_per_note(&read_settings(&root)?));
                 }
+                let derived = note_tags(&n.title, current_body.as_deref().unwrap_or(""), lowercase_tags);
+                let tags = carry_over_tags(&n.tags, &derived, tags.clone());
                 n.title = title.to_string();
                 n.updated_at = now.clone();
-                n.tags = tags.clone();
+                n.tags = tags;
                 n.links_to = links_to.clone();
-                (id.to_string(), n.clone())
+                n.preview = preview.clone();
+                (id.to_string(), n.clone(), false)
             }
             None => {
                 let id = id.to_string();
-                let filename = format!("{}.txt", id);
-                let meta = NoteMeta {
+                let filename = note_filename(&id, &note_extension(&root)?);
+                let mut meta = NoteMeta {
                     id: id.clone(),
                     title: title.to_string(),
-                    created_at: now.clone(),
+                    created_at: created_at.clone(),
                     updated_at: now.clone(),
                     important: false,
                     filename: filename.clone(),
@@ -310,18 +1343,31 @@ pub fn save_note(
                     links_to: links_to.clone(),
                     is_daily: false,
                     notebook_id: None,
+                    read_count: 0,
+                    created_from_template_id: None,
+                    pin_order: None,
+                    deleted_at: None,
+                    pinned: false,
+                    order: None,
+                    aliases: vec![],
+                    period: None,
+                    archived: false,
+                    metadata_updated_at: None,
+                    preview: preview.clone(),
+                    color: None,
                 };
+                on_create(&mut meta);
                 index.notes.push(meta.clone());
-                (id, meta)
+                (id, meta, true)
             }
         }
     } else {
         let id = Uuid::new_v4().to_string();
-        let filename = format!("{}.txt", id);
-        let meta = NoteMeta {
+        let filename = note_filename(&id, &note_extension(&root)?);
+        let mut meta = NoteMeta {
             id: id.clone(),
             title: title.to_string(),
-            created_at: now.clone(),
+            created_at,
             updated_at: now,
             important: false,
             filename,
@@ -330,1004 +1376,6793 @@ pub fn save_note(
             links_to,
             is_daily: false,
             notebook_id: None,
+            read_count: 0,
+            created_from_template_id: None,
+            pin_order: None,
+            deleted_at: None,
+            pinned: false,
+            order: None,
+            aliases: vec![],
+            period: None,
+            archived: false,
+            metadata_updated_at: None,
+            preview,
+            color: None,
         };
+        on_create(&mut meta);
         index.notes.push(meta.clone());
-        (id, meta)
+        (id, meta, true)
     };
 
-    let path = note_path(&root, &id);
-    fs::write(&path, body).map_err(|e| e.to_string())?;
-    write_index(&root, &index)?;
-    Ok(meta)
+    atomic_write(&note_path(&root, &id)?, body.as_bytes())?;
+    write_index(&root, &index, false)?;
+    update_search_index(&root, |sidx| search_index_add_note(sidx, &id, &meta.title, body));
+    Ok(SaveResult {
+        meta,
+        created,
+        duplicate_title,
+    })
 }
 
-/// Toggle important flag.
-pub fn toggle_important(app_handle: &tauri::AppHandle, note_id: &str, important: bool) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    n.important = important;
-    n.updated_at = Utc::now().to_rfc3339();
-    let meta = n.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
+/// Longest title title_from_body takes from a body line.
+const MAX_DERIVED_TITLE_CHARS: usize = 100;
+
+/// Title for a note that only exists as a body: its first non-empty line without leading `#`s,
+/// cut to MAX_DERIVED_TITLE_CHARS, or `fallback` if the body is blank.
+pub fn title_from_body(body: &str, fallback: &str) -> String {
+    body.lines()
+        .map(|l| l.trim_start_matches('#').trim())
+        .find(|l| !l.is_empty())
+        .map(|l| l.chars().take(MAX_DERIVED_TITLE_CHARS).collect::<String>().trim_end().to_string())
+        .unwrap_or_else(|| normalize_title(fallback))
 }
 
-/// Copy image files into images/<noteId>/ and update note metadata.
-pub fn attach_images(
-    app_handle: &tauri::AppHandle,
-    note_id: &str,
-    file_paths: &[String],
-) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    let root = storage_root(app_handle)?;
-    let img_dir = images_dir(&root, note_id);
-    fs::create_dir_all(&img_dir).map_err(|e| e.to_string())?;
+/// Note files with no index entry, matched by id. Files whose stem sanitize_filename would change are
+/// skipped: note_path could never address them by that id, so importing one would re-import it on
+/// every run.
+pub fn stray_note_files(root: &Path, index: &IndexFile) -> Result<Vec<(String, PathBuf)>, String> {
+    let known: HashSet<&str> = index.notes.iter().map(|n| n.id.as_str()).collect();
+    Ok(note_files(root, &note_extension(root)?)
+        .into_iter()
+        .filter(|(id, _)| !known.contains(id.as_str()) && sanitize_filename(id) == *id)
+        .collect())
+}
 
+/// Bring the index in line with notes/: add an entry for every .txt file that has none (title from
+/// its first line, tags and links from its body, timestamps from the file's mtime), and report
+/// index entries whose file is gone. Missing notes are only reported, never removed.
+pub fn reconcile_notes(app_handle: &tauri::AppHandle) -> Result<ReconcileReport, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
-    let note = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    let added_at = Utc::now().to_rfc3339();
-
-    for path_str in file_paths {
-        let src = Path::new(path_str);
-        if !src.is_file() {
+    let lowercase_tags = read_settings(&root)?.lowercase_tags;
+    let mut bodies: Vec<(String, String)> = vec![];
+    for (id, path) in stray_note_files(&root, &index)? {
+        // Not valid UTF-8: not something we wrote or can edit, so leave it alone.
+        let Ok(body) = fs::read_to_string(&path) else {
             continue;
-        }
-        let stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
-        let ext = src.extension().and_then(|e| e.to_str()).unwrap_or("");
-        let safe_name = sanitize_filename(stem);
-        let stored_name = if ext.is_empty() {
-            format!("{}-{}", chrono::Utc::now().timestamp_millis(), safe_name)
-        } else {
-            format!("{}-{}.{}", chrono::Utc::now().timestamp_millis(), safe_name, ext)
         };
-        let dest = img_dir.join(&stored_name);
-        fs::copy(src, &dest).map_err(|e| e.to_string())?;
-        let size = fs::metadata(&dest).ok().map(|m| m.len());
-        let relative_path = format!("images/{}/{}", note_id, stored_name);
-        note.images.push(ImageRef {
-            name: src.file_name().and_then(|n| n.to_str()).unwrap_or("file").to_string(),
-            path: relative_path,
-            added_at: added_at.clone(),
-            size,
+        let mut meta = stray_note_meta(&id, &title_from_body(&body, &id), &path);
+        meta.tags = note_tags(&meta.title, &body, lowercase_tags);
+        meta.preview = Some(note_preview(&body, NOTE_PREVIEW_CHARS));
+        index.notes.push(meta);
+        bodies.push((id, body));
+    }
+    // Links are resolved once every stray note is in the index, so they can point at each other.
+    let mut imported = vec![];
+    for (id, body) in &bodies {
+        let links_to = extract_links_from_body(body, &index.notes, id);
+        if let Some(n) = index.notes.iter_mut().find(|n| n.id == *id) {
+            n.links_to = links_to;
+            imported.push(n.clone());
+        }
+    }
+    let missing: Vec<String> = index
+        .notes
+        .iter()
+        .filter(|n| !note_path_with_ext(&root, &n.id, &ext).exists())
+        .map(|n| n.id.clone())
+        .collect();
+    if !imported.is_empty() {
+        write_index(&root, &index, false)?;
+        update_search_index(&root, |sidx| {
+            for (meta, (_, body)) in imported.iter().zip(&bodies) {
+                search_index_add_note(sidx, &meta.id, &meta.title, body);
+            }
         });
     }
-    note.updated_at = Utc::now().to_rfc3339();
-    let meta = note.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
+    Ok(ReconcileReport { imported, missing })
 }
 
-/// Attach a single image from clipboard (base64-encoded bytes) to a note.
-pub fn attach_image_from_clipboard(
+/// Text append_to_note writes after a body whose last byte is `tail` (None = empty file): a blank line
+/// separates it from existing content, then an optional `## <timestamp>` heading, then `text` ending
+/// in a single newline.
+pub fn append_chunk(tail: Option<u8>, text: &str, timestamp: Option<&str>) -> String {
+    let mut out = String::new();
+    match tail {
+        None => {}
+        Some(b'\n') => out.push('\n'),
+        Some(_) => out.push_str("\n\n"),
+    }
+    if let Some(ts) = timestamp {
+        out.push_str("## ");
+        out.push_str(ts);
+        out.push('\n');
+    }
+    out.push_str(text.trim_end_matches(['\r', '\n']));
+    out.push('\n');
+    out
+}
+
+/// Append text to the end of a note (quick capture), optionally under a `## HH:MM` heading in the
+/// user's time zone. Tags, links and the search index are then re-derived from the
+/// full body. No version snapshot is taken: earlier content is left untouched.
+pub fn append_to_note(
     app_handle: &tauri::AppHandle,
     note_id: &str,
-    base64_data: &str,
-    suggested_name: &str,
+    text: &str,
+    with_timestamp: bool,
 ) -> Result<NoteMeta, String> {
     validate_note_id(note_id)?;
-    let data = BASE64
-        .decode(base64_data.trim())
-        .map_err(|e| format!("Invalid base64 image: {}", e))?;
-    if data.is_empty() {
-        return Err("Image data is empty".into());
+    if text.trim().is_empty() {
+        return Err("Nothing to append".into());
     }
     let root = storage_root(app_handle)?;
-    let img_dir = images_dir(&root, note_id);
-    fs::create_dir_all(&img_dir).map_err(|e| e.to_string())?;
+    let settings = read_settings(&root)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let pos = index.notes.iter().position(|n| n.id == note_id).ok_or("Note not found")?;
+    let old_body = match fs::read_to_string(note_path(&root, note_id)?) {
+        Ok(body) => body,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.to_string()),
+    };
+    let timestamp = with_timestamp.then(|| user_now(&settings).format("%H:%M").to_string());
+    let body = format!("{}{}", old_body, append_chunk(old_body.as_bytes().last().copied(), text, timestamp.as_deref()));
+    write_edited_body(&root, &mut index, pos, &old_body, &body, settings.lowercase_tags)
+}
 
-    let path = Path::new(suggested_name);
-    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("paste");
-    let ext = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .filter(|e| !e.is_empty())
-        .unwrap_or("png");
-    let safe_stem = sanitize_filename(stem);
-    let stored_name = format!(
-        "{}-{}.{}",
-        chrono::Utc::now().timestamp_millis(),
-        safe_stem,
-        ext.to_lowercase()
-    );
-    let dest = img_dir.join(&stored_name);
-    fs::write(&dest, &data).map_err(|e| e.to_string())?;
-    // Also save a copy to the default user Images folder (~/Images)
-    if let Some(default_dir) = default_images_folder() {
-        let _ = fs::create_dir_all(&default_dir);
-        let timestamp = Utc::now().format("%Y-%m-%d-%H%M%S");
-        let default_name = format!("paste-{}.{}", timestamp, ext.to_lowercase());
-        let default_path = default_dir.join(&default_name);
-        let _ = fs::write(&default_path, &data);
+/// Write a body edited in place (append, task toggle) for `index.notes[pos]` and re-derive what comes
+/// from it: tags (see tags_after_edit), links, preview, updated_at and the search index. No version
+/// snapshot is taken.
+fn write_edited_body(
+    root: &Path,
+    index: &mut IndexFile,
+    pos: usize,
+    old_body: &str,
+    body: &str,
+    lowercase_tags: bool,
+) -> Result<NoteMeta, String> {
+    let note_id = index.notes[pos].id.clone();
+    atomic_write(&note_path(root, &note_id)?, body.as_bytes())?;
+    let links_to = extract_links_from_body(body, &index.notes, &note_id);
+    let n = &mut index.notes[pos];
+    n.tags = tags_after_edit(&n.title, &n.tags, old_body, body, lowercase_tags);
+    n.links_to = links_to;
+    n.preview = Some(note_preview(body, NOTE_PREVIEW_CHARS));
+    n.updated_at = Utc::now().to_rfc3339();
+    let meta = n.clone();
+    write_index(root, index, false)?;
+    update_search_index(root, |sidx| search_index_add_note(sidx, &note_id, &meta.title, body));
+    Ok(meta)
+}
+
+/// Flip the checkbox of the task on line `line_index` (0-based) of `body`: `[ ]` becomes `[x]` and
+/// `[x]`/`[X]` becomes `[ ]`. Line endings are preserved. Errors if that line is not a task line
+/// (same rules as the task filters).
+pub fn toggle_task_line(body: &str, line_index: usize) -> Result<String, String> {
+    let mut out = String::with_capacity(body.len());
+    let mut found = false;
+    for (i, raw) in body.split_inclusive('\n').enumerate() {
+        if i != line_index {
+            out.push_str(raw);
+            continue;
+        }
+        let checked = task_line_state(raw).ok_or("Line is not a task")?;
+        // task_line_state matched "- [" or "* [" right after the indentation.
+        let box_at = raw.len() - raw.trim_start().len() + 3;
+        out.push_str(&raw[..box_at]);
+        out.push(if checked { ' ' } else { 'x' });
+        out.push_str(&raw[box_at + 1..]);
+        found = true;
     }
-    let size = data.len() as u64;
-    let relative_path = format!("images/{}/{}", note_id, stored_name);
-    let display_name = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("paste")
-        .to_string();
+    if !found {
+        return Err("Line is out of range".into());
+    }
+    Ok(out)
+}
 
-    let mut index = read_index(&root)?;
-    let note = index
-        .notes
-        .iter_mut()
-        .find(|n| n.id == note_id)
-        .ok_or("Note not found")?;
-    let added_at = Utc::now().to_rfc3339();
-    note.images.push(ImageRef {
-        name: display_name,
-        path: relative_path,
-        added_at,
-        size: Some(size),
+/// Due date in a task line: `@due(2024-06-01)` or `📅 2024-06-01`, returned as YYYY-MM-DD.
+/// The first marker holding a valid date wins; invalid dates are ignored.
+pub fn parse_task_due(line: &str) -> Option<String> {
+    let valid = |s: &str| chrono::NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").ok().map(|d| d.format("%Y-%m-%d").to_string());
+    let from_due = line.match_indices("@due(").filter_map(|(i, m)| {
+        let rest = &line[i + m.len()..];
+        valid(&rest[..rest.find(')')?])
     });
-    note.updated_at = Utc::now().to_rfc3339();
-    let meta = note.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
+    let from_calendar = line.match_indices('\u{1F4C5}').filter_map(|(i, m)| {
+        let rest = line[i + m.len()..].trim_start();
+        valid(rest.get(..10)?)
+    });
+    from_due.chain(from_calendar).next()
 }
 
-/// Default folder for saving clipboard-pasted images: ~/Images (or $USERPROFILE/Images on Windows).
-fn default_images_folder() -> Option<PathBuf> {
-    let home = env::var_os("HOME")
-        .or_else(|| env::var_os("USERPROFILE"))
-        .map(PathBuf::from)?;
-    Some(home.join("Images"))
+/// Unchecked tasks in a body as (line index, text after the checkbox).
+fn open_task_lines(body: &str) -> impl Iterator<Item = (usize, &str)> {
+    body.lines()
+        .enumerate()
+        .filter(|(_, line)| task_line_state(line) == Some(false))
+        .map(|(i, line)| (i, line.trim()[5..].trim()))
 }
 
-/// Delete a note: remove from index, delete .txt, image folder, and versions.
-pub fn delete_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<(), String> {
+/// Unchecked tasks across all unarchived notes, soonest due first; tasks without a due date follow,
+/// most recently updated note first. Reads every note body, so this is meant for an agenda view rather
+/// than per keystroke.
+pub fn open_tasks(app_handle: &tauri::AppHandle) -> Result<Vec<OpenTask>, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let mut notes = read_index(&root)?.notes;
+    notes.retain(|n| !n.archived);
+    notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    let mut tasks = vec![];
+    for n in &notes {
+        let Ok(body) = fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)) else {
+            continue;
+        };
+        for (line_index, text) in open_task_lines(&body) {
+            tasks.push(OpenTask {
+                note_id: n.id.clone(),
+                note_title: n.title.clone(),
+                line_index,
+                text: text.to_string(),
+                due: parse_task_due(text),
+            });
+        }
+    }
+    // Stable sort keeps note recency and line order within the same due date.
+    tasks.sort_by(|a, b| match (&a.due, &b.due) {
+        (Some(x), Some(y)) => x.cmp(y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    Ok(tasks)
+}
+
+/// Check or uncheck one task in a note (see toggle_task_line) without the UI sending the whole body.
+pub fn toggle_task(app_handle: &tauri::AppHandle, note_id: &str, line_index: usize) -> Result<NoteMeta, String> {
     validate_note_id(note_id)?;
     let root = storage_root(app_handle)?;
+    let settings = read_settings(&root)?;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
     let pos = index.notes.iter().position(|n| n.id == note_id).ok_or("Note not found")?;
-    index.notes.remove(pos);
-    write_index(&root, &index)?;
-    let path = note_path(&root, note_id);
-    let _ = fs::remove_file(&path);
-    let img_dir = images_dir(&root, note_id);
-    let _ = fs::remove_dir_all(&img_dir);
-    let v_dir = versions_dir(&root, note_id);
-    let _ = fs::remove_dir_all(&v_dir);
-    Ok(())
+    let old_body = fs::read_to_string(note_path(&root, note_id)?).map_err(|e| e.to_string())?;
+    let body = toggle_task_line(&old_body, line_index)?;
+    write_edited_body(&root, &mut index, pos, &old_body, &body, settings.lowercase_tags)
 }
 
-/// Resolve full filesystem path for an image (relative path under storage root).
-pub fn resolve_image_path(app_handle: &tauri::AppHandle, relative_path: &str) -> Result<PathBuf, String> {
-    if relative_path.contains("..") || relative_path.starts_with('/') {
-        return Err("Invalid path".into());
+/// Replace every occurrence of `find` (literal text, not a pattern) in `body`, returning the new body and the
+/// number of replacements. `replace` is inserted as is; `$` has no special meaning.
+pub fn replace_text(body: &str, find: &str, replace: &str, case_sensitive: bool) -> Result<(String, usize), String> {
+    if find.is_empty() {
+        return Err("Search text cannot be empty".into());
     }
-    let root = storage_root(app_handle)?;
-    let full = root.join(relative_path);
-    if !full.starts_with(&root) {
-        return Err("Invalid path".into());
+    let re = regex::RegexBuilder::new(&regex::escape(find))
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let count = re.find_iter(body).count();
+    if count == 0 {
+        return Ok((body.to_string(), 0));
     }
-    Ok(full)
+    Ok((re.replace_all(body, regex::NoExpand(replace)).into_owned(), count))
 }
 
-/// Update only the title of a note (for sidebar inline edit).
-pub fn update_note_title(app_handle: &tauri::AppHandle, note_id: &str, new_title: &str) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
+/// Replace `find` with `replace` in the bodies of `note_ids` (unknown ids are ignored), or of every note
+/// when None. Each changed note first gets a labeled version snapshot so the edit can be undone from its
+/// history; then its body is written and tags, links, preview and updated_at are re-derived. If a body
+/// can't be written, the ones already written are put back and the index is left alone. The index and
+/// the search index are each written once. With `dry_run` only the counts are computed.
+pub fn find_and_replace(
+    app_handle: &tauri::AppHandle,
+    find: &str,
+    replace: &str,
+    case_sensitive: bool,
+    note_ids: Option<&[String]>,
+    dry_run: bool,
+) -> Result<FindReplaceResult, String> {
     let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let settings = read_settings(&root)?;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
-    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    n.title = new_title.to_string();
-    n.updated_at = Utc::now().to_rfc3339();
-    let meta = n.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
-}
-
-/// List all unique tags across notes, sorted.
-pub fn list_tags(app_handle: &tauri::AppHandle) -> Result<Vec<String>, String> {
-    let root = storage_root(app_handle)?;
-    let index = read_index(&root)?;
-    let mut tags: HashSet<String> = HashSet::new();
-    for n in &index.notes {
-        for t in &n.tags {
-            tags.insert(t.clone());
+    let mut result = FindReplaceResult::default();
+    let mut edits: Vec<(usize, String, String)> = vec![];
+    for (pos, n) in index.notes.iter().enumerate() {
+        if note_ids.is_some_and(|ids| !ids.contains(&n.id)) {
+            continue;
+        }
+        let Ok(body) = fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)) else {
+            continue;
+        };
+        let (new_body, count) = replace_text(&body, find, replace, case_sensitive)?;
+        if count == 0 || new_body == body {
+            continue;
+        }
+        result.notes_changed += 1;
+        result.total_replacements += count;
+        if dry_run {
+            continue;
         }
+        let snapshot = VersionSnapshot {
+            saved_at: n.updated_at.clone(),
+            title: n.title.clone(),
+            body: body.clone(),
+            pinned: false,
+            label: Some(format!("Before replacing \"{}\"", find)),
+        };
+        let v_dir = versions_dir(&root, &n.id);
+        write_version_snapshot(&v_dir, &snapshot)?;
+        prune_versions(&v_dir, max_versions_per_note(&settings));
+        edits.push((pos, body, new_body));
     }
-    let mut v: Vec<String> = tags.into_iter().collect();
-    v.sort();
-    Ok(v)
+    if edits.is_empty() {
+        return Ok(result);
+    }
+    for (i, (pos, _, new_body)) in edits.iter().enumerate() {
+        if let Err(e) = atomic_write(&note_path_with_ext(&root, &index.notes[*pos].id, &ext), new_body.as_bytes()) {
+            for (pos, old_body, _) in &edits[..i] {
+                let _ = atomic_write(&note_path_with_ext(&root, &index.notes[*pos].id, &ext), old_body.as_bytes());
+            }
+            return Err(e);
+        }
+    }
+    let now = Utc::now().to_rfc3339();
+    for (pos, old_body, body) in &edits {
+        let links_to = extract_links_from_body(body, &index.notes, &index.notes[*pos].id);
+        let n = &mut index.notes[*pos];
+        n.tags = tags_after_edit(&n.title, &n.tags, old_body, body, settings.lowercase_tags);
+        n.links_to = links_to;
+        n.preview = Some(note_preview(body, NOTE_PREVIEW_CHARS));
+        n.updated_at = now.clone();
+    }
+    write_index(&root, &index, false)?;
+    update_search_index(&root, |sidx| {
+        for (pos, _, body) in &edits {
+            let n = &index.notes[*pos];
+            search_index_add_note(sidx, &n.id, &n.title, body);
+        }
+    });
+    Ok(result)
 }
 
-/// List notes that have the given tag.
-pub fn notes_by_tag(app_handle: &tauri::AppHandle, tag: &str) -> Result<Vec<NoteMeta>, String> {
+/// Most frequently opened notes (by read_count), highest first; ties, including notes never opened, go by updated_at.
+pub fn most_accessed_notes(app_handle: &tauri::AppHandle, limit: usize) -> Result<Vec<NoteMeta>, String> {
     let root = storage_root(app_handle)?;
     let index = read_index(&root)?;
-    Ok(index
-        .notes
-        .into_iter()
-        .filter(|n| n.tags.iter().any(|t| t == tag))
-        .collect())
+    let mut notes = index.notes;
+    notes.sort_by(|a, b| b.read_count.cmp(&a.read_count).then_with(|| b.updated_at.cmp(&a.updated_at)));
+    notes.truncate(limit);
+    Ok(notes)
 }
 
-/// Add a tag to multiple notes (merge with existing).
-pub fn add_tag_to_notes(
-    app_handle: &tauri::AppHandle,
-    note_ids: &[String],
-    tag: &str,
-) -> Result<Vec<NoteMeta>, String> {
-    if note_ids.is_empty() || tag.trim().is_empty() {
-        return Ok(vec![]);
-    }
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let tag = tag.trim().to_string();
-    let mut updated = vec![];
-    for n in index.notes.iter_mut() {
-        if note_ids.contains(&n.id) && !n.tags.contains(&tag) {
-            n.tags.push(tag.clone());
-            n.updated_at = Utc::now().to_rfc3339();
-            updated.push(n.clone());
-        }
-    }
-    write_index(&root, &index)?;
-    Ok(updated)
+/// Toggle important flag.
+pub fn toggle_important(app_handle: &tauri::AppHandle, note_id: &str, important: bool) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    set_important(&storage_root(app_handle)?, note_id, important)
 }
 
-/// Remove a tag from a note.
-pub fn remove_tag_from_note(app_handle: &tauri::AppHandle, note_id: &str, tag: &str) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
+/// toggle_important for the storage root `root`.
+pub fn set_important(root: &Path, note_id: &str, important: bool) -> Result<NoteMeta, String> {
+    let bump = read_settings(root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
     let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    n.tags.retain(|t| t != tag);
-    n.updated_at = Utc::now().to_rfc3339();
+    n.important = important;
+    touch_metadata(n, &Utc::now().to_rfc3339(), bump);
     let meta = n.clone();
-    write_index(&root, &index)?;
+    write_index(root, &index, false)?;
     Ok(meta)
 }
 
-/// Delete multiple notes in one index write.
-pub fn batch_delete_notes(app_handle: &tauri::AppHandle, note_ids: &[String]) -> Result<(), String> {
-    if note_ids.is_empty() {
-        return Ok(());
-    }
-    for id in note_ids {
-        validate_note_id(id)?;
-    }
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let ids_set: HashSet<&str> = note_ids.iter().map(|s| s.as_str()).collect();
-    index.notes.retain(|n| !ids_set.contains(n.id.as_str()));
-    write_index(&root, &index)?;
-    for id in note_ids {
-        let path = note_path(&root, id);
-        let _ = fs::remove_file(&path);
-        let img_dir = images_dir(&root, id);
-        let _ = fs::remove_dir_all(&img_dir);
-        let v_dir = versions_dir(&root, id);
-        let _ = fs::remove_dir_all(&v_dir);
+/// Stamp a metadata-only change (star, pin, archive, color, tags, aliases, notebook) on `n`: metadata_updated_at
+/// always moves to `now`, updated_at only when `bump` (the bumpUpdatedOnMetadataChange setting) is on.
+fn touch_metadata(n: &mut NoteMeta, now: &str, bump: bool) {
+    n.metadata_updated_at = Some(now.to_string());
+    if bump {
+        n.updated_at = now.to_string();
     }
-    Ok(())
 }
 
-/// Set important flag on multiple notes.
-pub fn batch_toggle_important(
+/// Apply a metadata-only change to the given notes in one index write (see touch_metadata). Unknown ids
+/// are an error so a stale selection isn't silently ignored.
+fn update_notes_metadata(
     app_handle: &tauri::AppHandle,
     note_ids: &[String],
-    important: bool,
+    f: impl Fn(&mut NoteMeta),
 ) -> Result<Vec<NoteMeta>, String> {
-    if note_ids.is_empty() {
-        return Ok(vec![]);
+    for id in note_ids {
+        validate_note_id(id)?;
     }
     let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
+    if let Some(missing) = note_ids.iter().find(|id| !index.notes.iter().any(|n| n.id == **id)) {
+        return Err(format!("Note not found: {}", missing));
+    }
     let now = Utc::now().to_rfc3339();
-    let ids_set: HashSet<&str> = note_ids.iter().map(|s| s.as_str()).collect();
     let mut updated = vec![];
-    for n in index.notes.iter_mut() {
-        if ids_set.contains(n.id.as_str()) {
-            n.important = important;
-            n.updated_at = now.clone();
-            updated.push(n.clone());
-        }
+    for n in index.notes.iter_mut().filter(|n| note_ids.contains(&n.id)) {
+        f(n);
+        touch_metadata(n, &now, bump);
+        updated.push(n.clone());
     }
-    write_index(&root, &index)?;
+    write_index(&root, &index, false)?;
     Ok(updated)
 }
 
-/// Duplicate a note (new id, same title + " (copy)", same body and images).
-pub fn duplicate_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteMeta, String> {
-    let content = read_note(app_handle, note_id)?;
-    let new_title = format!("{} (copy)", content.meta.title.trim());
-    let meta = save_note(app_handle, None, &new_title, &content.body)?;
-    if !content.meta.images.is_empty() {
-        let root = storage_root(app_handle)?;
-        let src_dir = images_dir(&root, note_id);
-        let dest_dir = images_dir(&root, &meta.id);
-        if src_dir.exists() {
-            fs::create_dir_all(&dest_dir).map_err(|e| e.to_string())?;
-            for entry in fs::read_dir(&src_dir).map_err(|e| e.to_string())? {
-                let entry = entry.map_err(|e| e.to_string())?;
-                let path = entry.path();
-                if path.is_file() {
-                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
-                    let dest = dest_dir.join(name);
-                    fs::copy(&path, &dest).map_err(|e| e.to_string())?;
-                }
-            }
-            let mut index = read_index(&root)?;
-            let note = index.notes.iter_mut().find(|n| n.id == meta.id).ok_or("Note not found")?;
-            let added_at = Utc::now().to_rfc3339();
-            for img in &content.meta.images {
-                let stored_name = Path::new(&img.path).file_name().and_then(|n| n.to_str()).unwrap_or("image");
-                let relative_path = format!("images/{}/{}", meta.id, stored_name);
-                note.images.push(ImageRef {
-                    name: img.name.clone(),
-                    path: relative_path,
-                    added_at: added_at.clone(),
-                    size: img.size,
-                });
-            }
-            note.updated_at = Utc::now().to_rfc3339();
-            write_index(&root, &index)?;
-        }
-    }
-    read_note(app_handle, &meta.id).map(|c| c.meta)
+fn set_notes_archived(app_handle: &tauri::AppHandle, note_ids: &[String], archived: bool) -> Result<Vec<NoteMeta>, String> {
+    update_notes_metadata(app_handle, note_ids, |n| n.archived = archived)
 }
 
-/// Merge multiple notes: concatenate bodies (oldest first by updated_at), delete others, return merged note meta.
-pub fn merge_notes(app_handle: &tauri::AppHandle, note_ids: &[String]) -> Result<NoteMeta, String> {
-    if note_ids.is_empty() {
-        return Err("No notes to merge".into());
-    }
-    if note_ids.len() == 1 {
-        let root = storage_root(app_handle)?;
-        let index = read_index(&root)?;
-        return index
-            .notes
-            .into_iter()
-            .find(|n| n.id == note_ids[0])
-            .ok_or_else(|| "Note not found".to_string());
-    }
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let mut to_merge: Vec<(String, String, String)> = vec![];
-    for id in note_ids {
-        let meta = index.notes.iter().find(|n| n.id == *id).ok_or("Note not found")?;
-        let body = fs::read_to_string(note_path(&root, id)).unwrap_or_default();
-        to_merge.push((meta.updated_at.clone(), meta.title.clone(), body));
-    }
-    to_merge.sort_by(|a, b| a.0.cmp(&b.0));
-    let merged_title = to_merge[0].1.clone();
-    let merged_body: String = to_merge
-        .iter()
-        .map(|(_, t, b)| format!("## {}\n\n{}\n\n", t, b))
-        .collect();
-    let keep_id = note_ids[0].clone();
-    let remove_ids: Vec<&str> = note_ids[1..].iter().map(|s| s.as_str()).collect();
-    fs::write(note_path(&root, &keep_id), merged_body.trim()).map_err(|e| e.to_string())?;
-    let n = index.notes.iter_mut().find(|n| n.id == keep_id).ok_or("Note not found")?;
-    n.title = merged_title;
-    n.updated_at = Utc::now().to_rfc3339();
-    let meta = n.clone();
-    index.notes.retain(|n| !remove_ids.contains(&n.id.as_str()));
-    write_index(&root, &index)?;
-    for id in &note_ids[1..] {
-        let _ = fs::remove_file(note_path(&root, id));
-        let _ = fs::remove_dir_all(images_dir(&root, id));
-    }
-    Ok(meta)
+/// Hide a note from the main list and search without deleting it.
+pub fn archive_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteMeta, String> {
+    let mut updated = set_notes_archived(app_handle, &[note_id.to_string()], true)?;
+    updated.pop().ok_or_else(|| "Note not found".to_string())
 }
 
-/// Export note as plain text (title + body).
-pub fn export_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<String, String> {
-    let content = read_note(app_handle, note_id)?;
-    Ok(format!("{}\n\n{}\n", content.meta.title, content.body))
+pub fn unarchive_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteMeta, String> {
+    let mut updated = set_notes_archived(app_handle, &[note_id.to_string()], false)?;
+    updated.pop().ok_or_else(|| "Note not found".to_string())
 }
 
-/// Export note as Markdown: YAML frontmatter (optional) + # title + body. [[Title]] left as-is for compatibility.
-pub fn export_note_as_markdown(app_handle: &tauri::AppHandle, note_id: &str) -> Result<String, String> {
-    let content = read_note(app_handle, note_id)?;
-    let mut md = String::new();
-    if !content.meta.tags.is_empty() || content.meta.created_at != content.meta.updated_at {
-        md.push_str("---\n");
-        if !content.meta.tags.is_empty() {
-            md.push_str("tags:\n");
-            for t in &content.meta.tags {
-                md.push_str(&format!("  - {}\n", t));
-            }
-        }
-        md.push_str(&format!("created: {}\n", content.meta.created_at));
-        md.push_str(&format!("updated: {}\n", content.meta.updated_at));
-        md.push_str("---\n\n");
-    }
-    md.push_str(&format!("# {}\n\n", content.meta.title));
-    md.push_str(&content.body);
-    if !content.body.ends_with('\n') {
-        md.push('\n');
+/// Archive multiple notes in one index write.
+pub fn batch_archive_notes(app_handle: &tauri::AppHandle, note_ids: &[String]) -> Result<Vec<NoteMeta>, String> {
+    if note_ids.is_empty() {
+        return Ok(vec![]);
     }
-    Ok(md)
+    set_notes_archived(app_handle, note_ids, true)
 }
 
-/// Write text to a file at the given path (e.g. user-chosen save path from dialog).
-pub fn write_text_file(path: &str, content: &str) -> Result<(), String> {
-    let p = Path::new(path);
-    if p.exists() && p.is_dir() {
-        return Err("Path is a directory".into());
+/// Named note colors accepted besides hex values; the UI maps them to theme-aware shades.
+pub const NOTE_COLOR_PALETTE: &[&str] = &["red", "orange", "yellow", "green", "teal", "blue", "purple", "pink", "gray"];
+
+/// Normalize a note color: a palette name or `#rgb`/`#rrggbb`, lowercased. None or blank clears it.
+pub fn normalize_note_color(color: Option<&str>) -> Result<Option<String>, String> {
+    let color = color.map(str::trim).unwrap_or("").to_lowercase();
+    if color.is_empty() {
+        return Ok(None);
     }
-    if let Some(parent) = p.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    if NOTE_COLOR_PALETTE.contains(&color.as_str()) {
+        return Ok(Some(color));
     }
-    fs::write(p, content).map_err(|e| e.to_string())?;
-    Ok(())
+    validate_tag_color(&color).map_err(|_| format!("Note color must be #rgb, #rrggbb or one of: {}", NOTE_COLOR_PALETTE.join(", ")))?;
+    Ok(Some(color))
 }
 
-/// Built-in note templates.
-fn builtin_templates() -> Vec<NoteTemplate> {
-    vec![
-        NoteTemplate {
-            id: "daily-journal".to_string(),
-            name: "Daily journal".to_string(),
-            body: "# Daily Journal — {{date}}\n\n## What happened today\n- \n\n## Thoughts & reflections\n- \n\n## Tomorrow\n- \n".to_string(),
-            default_title_pattern: Some("Journal {{date}}".to_string()),
-            is_custom: false,
-        },
-        NoteTemplate {
-            id: "meeting-notes".to_string(),
-            name: "Meeting notes".to_string(),
-            body: "# Meeting: {{title}}\n\n**Date:** {{date}}\n**Attendees:** \n**Agenda:**\n- \n\n**Notes:**\n- \n\n**Action items:**\n- [ ] \n- [ ] \n".to_string(),
-            default_title_pattern: Some("Meeting {{date}}".to_string()),
-            is_custom: false,
-        },
-        NoteTemplate {
-            id: "project-planning".to_string(),
-            name: "Project planning".to_string(),
-            body: "# Project: {{title}}\n\n## Overview\n- **Goal:** \n- **Timeline:** \n\n## Tasks\n- [ ] \n- [ ] \n\n## Notes\n- \n".to_string(),
-            default_title_pattern: Some("Project".to_string()),
-            is_custom: false,
-        },
-    ]
+/// Color-code a note (see normalize_note_color); None clears it.
+pub fn set_note_color(app_handle: &tauri::AppHandle, note_id: &str, color: Option<&str>) -> Result<NoteMeta, String> {
+    let color = normalize_note_color(color)?;
+    let mut updated = update_notes_metadata(app_handle, &[note_id.to_string()], |n| n.color = color.clone())?;
+    updated.pop().ok_or_else(|| "Note not found".to_string())
 }
 
-fn read_custom_templates(root: &Path) -> Result<Vec<NoteTemplate>, String> {
-    let path = templates_path(root);
-    if !path.exists() {
+/// set_note_color for several notes in one index write.
+pub fn batch_set_color(app_handle: &tauri::AppHandle, note_ids: &[String], color: Option<&str>) -> Result<Vec<NoteMeta>, String> {
+    let color = normalize_note_color(color)?;
+    if note_ids.is_empty() {
         return Ok(vec![]);
     }
-    let mut f = fs::File::open(&path).map_err(|e| e.to_string())?;
-    let mut s = String::new();
-    f.read_to_string(&mut s).map_err(|e| e.to_string())?;
-    serde_json::from_str(&s).map_err(|e| e.to_string())
+    update_notes_metadata(app_handle, note_ids, |n| n.color = color.clone())
 }
 
-fn write_custom_templates(root: &Path, templates: &[NoteTemplate]) -> Result<(), String> {
-    let path = templates_path(root);
-    let temp_path = path.with_extension("json.tmp");
-    let json = serde_json::to_string_pretty(templates).map_err(|e| e.to_string())?;
-    let mut f = fs::File::create(&temp_path).map_err(|e| e.to_string())?;
-    f.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
-    f.sync_all().map_err(|e| e.to_string())?;
-    drop(f);
-    fs::rename(&temp_path, &path).map_err(|e| e.to_string())?;
-    Ok(())
+/// Image extensions recognized for attachments (lowercase, without the dot).
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "gif", "bmp"];
+
+/// Detect an image MIME type from the file's magic bytes (PNG, JPEG, WebP, GIF, BMP).
+pub fn detect_image_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.starts_with(b"BM") && data.len() >= 14 {
+        Some("image/bmp")
+    } else {
+        None
+    }
 }
 
-/// Replace {{date}} and {{title}} in template body/title.
-fn apply_template_placeholders(body: &str, title: &str) -> (String, String) {
-    let now = Utc::now();
-    let date = now.format("%Y-%m-%d").to_string();
-    let body_out = body
-        .replace("{{date}}", &date)
-        .replace("{{title}}", title);
-    let title_out = title
-        .replace("{{date}}", &date)
-        .replace("{{title}}", title);
-    (body_out, title_out)
+/// File extension used when storing an image of the given MIME type.
+pub fn image_extension_for_mime(mime: &str) -> Option<&'static str> {
+    match mime {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/webp" => Some("webp"),
+        "image/gif" => Some("gif"),
+        "image/bmp" => Some("bmp"),
+        _ => None,
+    }
 }
 
-/// List all templates (built-in + custom).
-pub fn list_templates(app_handle: &tauri::AppHandle) -> Result<Vec<NoteTemplate>, String> {
-    let root = storage_root(app_handle)?;
-    let custom = read_custom_templates(&root)?;
-    let mut out = builtin_templates();
-    out.extend(custom);
-    Ok(out)
+/// True if the extension (case-insensitive) is one of the recognized image formats.
+pub fn is_image_extension(ext: &str) -> bool {
+    let ext = ext.to_lowercase();
+    IMAGE_EXTENSIONS.contains(&ext.as_str())
 }
 
-/// Create a new note from a template. title_override: if provided, use it; else use template default with placeholders.
-pub fn create_note_from_template(
-    app_handle: &tauri::AppHandle,
-    template_id: &str,
-    title_override: Option<&str>,
-) -> Result<NoteMeta, String> {
+/// Sniff the first bytes of a file and return the matching image extension, if any.
+fn sniff_image_extension(path: &Path) -> Option<&'static str> {
+    let mut buf = [0u8; 16];
+    let mut f = fs::File::open(path).ok()?;
+    let n = f.read(&mut buf).ok()?;
+    detect_image_mime(&buf[..n]).and_then(image_extension_for_mime)
+}
+
+/// Pin or unpin a note at the top of the sidebar (independent of the important flag).
+pub fn toggle_pinned(app_handle: &tauri::AppHandle, note_id: &str, pinned: bool) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
     let root = storage_root(app_handle)?;
-    let builtin = builtin_templates();
-    let custom = read_custom_templates(&root)?;
-    let template = builtin
-        .into_iter()
-        .chain(custom.into_iter())
-        .find(|t| t.id == template_id)
-        .ok_or_else(|| "Template not found".to_string())?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.pinned = pinned;
+    touch_metadata(n, &Utc::now().to_rfc3339(), bump);
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
 
-    let default_title = template
-        .default_title_pattern
-        .as_deref()
-        .unwrap_or("Untitled");
-    let title_input = title_override.unwrap_or(default_title).trim();
-    let title_input = if title_input.is_empty() { "Untitled" } else { title_input };
-    let (body, title) = apply_template_placeholders(&template.body, title_input);
-    save_note(app_handle, None, &title, &body)
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "wav", "ogg", "oga", "m4a", "aac", "flac", "opus"];
+
+/// Attachment kind for a file extension (case-insensitive).
+pub fn attachment_kind(ext: &str) -> AttachmentKind {
+    let ext = ext.to_lowercase();
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        AttachmentKind::Image
+    } else if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+        AttachmentKind::Audio
+    } else if ext == "pdf" {
+        AttachmentKind::Pdf
+    } else {
+        AttachmentKind::Other
+    }
 }
 
-/// Save a custom template (creates new with id custom-<uuid>).
-pub fn save_custom_template(
+/// True for a storage-relative path under images/ or attachments/ with no traversal.
+pub fn is_attachment_path(relative_path: &str) -> bool {
+    !relative_path.contains("..")
+        && !relative_path.starts_with('/')
+        && (relative_path.starts_with("images/") || relative_path.starts_with("attachments/"))
+}
+
+/// Copy files into <subdir>/<noteId>/ as "<millis>-<name>.<ext>" and add them to the note's attachments.
+/// `stored_ext` picks the extension to store under from the source path and its original extension.
+fn copy_attachments(
     app_handle: &tauri::AppHandle,
-    name: &str,
-    body: &str,
-) -> Result<NoteTemplate, String> {
+    note_id: &str,
+    file_paths: &[String],
+    subdir: &str,
+    stored_ext: impl Fn(&Path, &str) -> String,
+) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
     let root = storage_root(app_handle)?;
-    let mut custom = read_custom_templates(&root)?;
-    let id = format!("custom-{}", Uuid::new_v4());
-    let t = NoteTemplate {
-        id: id.clone(),
-        name: name.to_string(),
-        body: body.to_string(),
-        default_title_pattern: Some(name.to_string()),
-        is_custom: true,
+    // Held from the first copy until the index lists it, so cleanup_orphans never sees it unowned.
+    let _guard = lock_index();
+    let dir = root.join(subdir).join(sanitize_filename(note_id));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut index = read_index(&root)?;
+    let note = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    let added_at = Utc::now().to_rfc3339();
+
+    for path_str in file_paths {
+        let src = Path::new(path_str);
+        if !src.is_file() {
+            continue;
+        }
+        let stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
+        let ext = stored_ext(src, src.extension().and_then(|e| e.to_str()).unwrap_or(""));
+        let safe_name = sanitize_filename(stem);
+        let (hash, size) = hash_file(src)?;
+        // A file with identical content already in the folder is reused instead of copied again.
+        let stored_name = match find_identical_file(&dir, &hash, size) {
+            Some(existing) => existing,
+            None => {
+                let stored_name = if ext.is_empty() {
+                    format!("{}-{}", chrono::Utc::now().timestamp_millis(), safe_name)
+                } else {
+                    format!("{}-{}.{}", chrono::Utc::now().timestamp_millis(), safe_name, ext)
+                };
+                fs::copy(src, dir.join(&stored_name)).map_err(|e| e.to_string())?;
+                stored_name
+            }
+        };
+        let relative_path = format!("{}/{}/{}", subdir, note_id, stored_name);
+        if note.images.iter().any(|img| img.path == relative_path) {
+            continue;
+        }
+        let size = Some(size);
+        let ext = Path::new(&stored_name).extension().and_then(|e| e.to_str()).unwrap_or("").to_string();
+        note.images.push(ImageRef {
+            name: src.file_name().and_then(|n| n.to_str()).unwrap_or("file").to_string(),
+            path: relative_path,
+            added_at: added_at.clone(),
+            size,
+            kind: attachment_kind(&ext),
+        });
+    }
+    note.updated_at = Utc::now().to_rfc3339();
+    let meta = note.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Copy image files into images/<noteId>/ and update note metadata.
+pub fn attach_images(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    file_paths: &[String],
+) -> Result<NoteMeta, String> {
+    // Recognized image extensions are stored lowercase; otherwise fall back to sniffing the content.
+    copy_attachments(app_handle, note_id, file_paths, "images", |src, ext| {
+        if is_image_extension(ext) {
+            ext.to_lowercase()
+        } else {
+            sniff_image_extension(src).map(String::from).unwrap_or_else(|| ext.to_string())
+        }
+    })
+}
+
+/// Copy arbitrary files (PDFs, audio, anything) into attachments/<noteId>/; the kind comes from the extension.
+pub fn attach_files(app_handle: &tauri::AppHandle, note_id: &str, file_paths: &[String]) -> Result<NoteMeta, String> {
+    copy_attachments(app_handle, note_id, file_paths, "attachments", |src, ext| {
+        if attachment_kind(ext) == AttachmentKind::Other {
+            sniff_image_extension(src).map(String::from).unwrap_or_else(|| ext.to_lowercase())
+        } else {
+            ext.to_lowercase()
+        }
+    })
+}
+
+/// Largest decoded image accepted by attach_image_from_data_url.
+pub const MAX_DATA_URL_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Split a `data:<mime>;base64,<data>` URL into the file extension for its MIME type and the base64
+/// payload. Only base64 data URLs of the image types in image_extension_for_mime are accepted, and the
+/// payload must decode to at most MAX_DATA_URL_IMAGE_BYTES.
+pub fn parse_image_data_url(data_url: &str) -> Result<(&'static str, &str), String> {
+    let data_url = data_url.trim();
+    let rest = data_url
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        .map(|_| &data_url[5..])
+        .ok_or("Not a data URL")?;
+    let (header, payload) = rest.split_once(',').ok_or("Not a data URL")?;
+    let mut params = header.split(';').map(|p| p.trim().to_ascii_lowercase());
+    let mime = params.next().unwrap_or_default();
+    if !params.any(|p| p == "base64") {
+        return Err("Data URL is not base64-encoded".into());
+    }
+    let ext = image_extension_for_mime(&mime).ok_or_else(|| format!("Unsupported image type: {}", mime))?;
+    if payload.trim().len() / 4 * 3 > MAX_DATA_URL_IMAGE_BYTES {
+        return Err("Image is too large".into());
+    }
+    Ok((ext, payload))
+}
+
+/// Attach an image given as a `data:` URL (see parse_image_data_url), stored like a clipboard paste.
+/// The MIME type's extension replaces any extension on `suggested_name`.
+pub fn attach_image_from_data_url(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    data_url: &str,
+    suggested_name: &str,
+) -> Result<NoteMeta, String> {
+    let (ext, payload) = parse_image_data_url(data_url)?;
+    let stem = Path::new(suggested_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or("image");
+    attach_image_from_clipboard(app_handle, note_id, payload, &format!("{}.{}", stem, ext))
+}
+
+/// Attach a single image from clipboard (base64-encoded bytes) to a note.
+pub fn attach_image_from_clipboard(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    base64_data: &str,
+    suggested_name: &str,
+) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    let data = BASE64
+        .decode(base64_data.trim())
+        .map_err(|e| format!("Invalid base64 image: {}", e))?;
+    if data.is_empty() {
+        return Err("Image data is empty".into());
+    }
+    let root = storage_root(app_handle)?;
+    // Held from the write until the index lists the image, so cleanup_orphans never sees it unowned.
+    let _guard = lock_index();
+    let img_dir = images_dir(&root, note_id);
+    fs::create_dir_all(&img_dir).map_err(|e| e.to_string())?;
+
+    let path = Path::new(suggested_name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("paste");
+    // Trust the bytes over the suggested name: clipboard data is PNG, JPEG, WebP, GIF or BMP.
+    let ext = detect_image_mime(&data)
+        .and_then(image_extension_for_mime)
+        .or_else(|| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .filter(|e| is_image_extension(e))
+        })
+        .unwrap_or("png");
+    let safe_stem = sanitize_filename(stem);
+    let size = data.len() as u64;
+    let hash = {
+        use sha2::{Digest, Sha256};
+        to_hex(&Sha256::digest(&data))
     };
-    custom.push(t.clone());
-    write_custom_templates(&root, &custom)?;
-    Ok(t)
+    // Re-pasting the same image reuses the stored file.
+    let stored_name = match find_identical_file(&img_dir, &hash, size) {
+        Some(existing) => existing,
+        None => {
+            let stored_name = format!(
+                "{}-{}.{}",
+                chrono::Utc::now().timestamp_millis(),
+                safe_stem,
+                ext.to_lowercase()
+            );
+            fs::write(img_dir.join(&stored_name), &data).map_err(|e| e.to_string())?;
+            stored_name
+        }
+    };
+    // Only when the user opted in: a copy outside app storage is a side effect they should ask for.
+    if let Some(default_dir) = clipboard_images_folder(&read_settings(&root)?) {
+        let _ = fs::create_dir_all(&default_dir);
+        let timestamp = Utc::now().format("%Y-%m-%d-%H%M%S");
+        let default_name = format!("paste-{}.{}", timestamp, ext.to_lowercase());
+        let default_path = default_dir.join(&default_name);
+        let _ = fs::write(&default_path, &data);
+    }
+    let relative_path = format!("images/{}/{}", note_id, stored_name);
+    let display_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("paste")
+        .to_string();
+
+    let mut index = read_index(&root)?;
+    let note = index
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or("Note not found")?;
+    let added_at = Utc::now().to_rfc3339();
+    if !note.images.iter().any(|img| img.path == relative_path) {
+        note.images.push(ImageRef {
+            name: display_name,
+            path: relative_path,
+            added_at,
+            size: Some(size),
+            kind: AttachmentKind::Image,
+        });
+    }
+    note.updated_at = Utc::now().to_rfc3339();
+    let meta = note.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
 }
 
-/// Delete a custom template by id.
-pub fn delete_custom_template(app_handle: &tauri::AppHandle, template_id: &str) -> Result<(), String> {
-    if !template_id.starts_with("custom-") {
-        return Err("Can only delete custom templates".into());
+/// Default folder for saving clipboard-pasted images: ~/Images (or $USERPROFILE/Images on Windows).
+/// Where pasted images are also copied, or None when saveClipboardImagesToFolder is off.
+fn clipboard_images_folder(settings: &Settings) -> Option<PathBuf> {
+    if !settings.save_clipboard_images_to_folder {
+        return None;
     }
+    settings
+        .clipboard_images_folder
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(default_images_folder)
+}
+
+fn default_images_folder() -> Option<PathBuf> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)?;
+    Some(home.join("Images"))
+}
+
+/// Delete a note: move it to the trash (restorable until the trash is emptied or auto-purged).
+pub fn delete_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<(), String> {
+    validate_note_id(note_id)?;
     let root = storage_root(app_handle)?;
-    let mut custom = read_custom_templates(&root)?;
-    let len_before = custom.len();
-    custom.retain(|t| t.id != template_id);
-    if custom.len() == len_before {
-        return Err("Template not found".into());
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let pos = index.notes.iter().position(|n| n.id == note_id).ok_or("Note not found")?;
+    move_note_files_to_trash(&root, note_id, &ext)?;
+    let mut meta = index.notes.remove(pos);
+    meta.deleted_at = Some(Utc::now().to_rfc3339());
+    index.trashed.push(meta);
+    if let Err(e) = write_index(&root, &index, false) {
+        let _ = restore_note_files_from_trash(&root, note_id, &ext);
+        return Err(e);
     }
-    write_custom_templates(&root, &custom)?;
+    update_search_index(&root, |sidx| search_index_remove_note(sidx, note_id));
     Ok(())
 }
 
-/// Get or create today's daily note (YYYY-MM-DD), tag #daily.
-pub fn get_or_create_daily_note(app_handle: &tauri::AppHandle) -> Result<NoteMeta, String> {
-    let today = Utc::now().format("%Y-%m-%d").to_string();
+fn trash_dir(root: &Path, note_id: &str) -> PathBuf {
+    root.join("trash").join(sanitize_filename(note_id))
+}
+
+/// (live path, trash path) pairs for a note's body, images, attachments, and versions.
+fn note_trash_moves(root: &Path, note_id: &str, ext: &str) -> [(PathBuf, PathBuf); 4] {
+    let dir = trash_dir(root, note_id);
+    [
+        (note_path_with_ext(root, note_id, ext), dir.join("note.txt")),
+        (images_dir(root, note_id), dir.join("images")),
+        (attachments_dir(root, note_id), dir.join("attachments")),
+        (versions_dir(root, note_id), dir.join("versions")),
+    ]
+}
+
+/// Rename each `(from, to)` pair, skipping sources that don't exist. On the first failure the
+/// renames already done are undone, so either every present part moved or none did.
+fn rename_all_or_nothing(moves: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+    for (i, (from, to)) in moves.iter().enumerate() {
+        if !from.exists() {
+            continue;
+        }
+        let result = match to.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::rename(from, to)),
+            None => fs::rename(from, to),
+        };
+        if let Err(e) = result {
+            for (done_from, done_to) in moves[..i].iter().rev() {
+                if done_to.exists() {
+                    let _ = fs::rename(done_to, done_from);
+                }
+            }
+            return Err(format!("Cannot move {}: {}", from.display(), e));
+        }
+    }
+    Ok(())
+}
+
+/// Move a note's body, images, attachments, and versions into trash/<note_id>/. Missing parts are
+/// skipped; if any present part cannot be moved, nothing is moved and the error is returned.
+fn move_note_files_to_trash(root: &Path, note_id: &str, ext: &str) -> Result<(), String> {
+    rename_all_or_nothing(&note_trash_moves(root, note_id, ext))
+}
+
+/// Move a trashed note's files back to notes/, images/, attachments/, and versions/ (all or nothing).
+fn restore_note_files_from_trash(root: &Path, note_id: &str, ext: &str) -> Result<(), String> {
+    let moves: Vec<(PathBuf, PathBuf)> =
+        note_trash_moves(root, note_id, ext).into_iter().map(|(live, trashed)| (trashed, live)).collect();
+    rename_all_or_nothing(&moves)?;
+    let _ = fs::remove_dir_all(trash_dir(root, note_id));
+    Ok(())
+}
+
+/// True if a note trashed at `deleted_at` (RFC3339) is older than `retention_days` at `now`.
+/// Unparseable timestamps are never considered expired.
+pub fn is_trash_expired(deleted_at: &str, now: chrono::DateTime<Utc>, retention_days: u32) -> bool {
+    match chrono::DateTime::parse_from_rfc3339(deleted_at) {
+        Ok(dt) => now.signed_duration_since(dt.with_timezone(&Utc)) > chrono::Duration::days(retention_days as i64),
+        Err(_) => false,
+    }
+}
+
+/// Permanently delete trashed notes older than retention_days.
+fn purge_expired_trash(root: &Path, retention_days: u32) -> Result<(), String> {
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
+    let now = Utc::now();
+    let (expired, kept): (Vec<NoteMeta>, Vec<NoteMeta>) = index
+        .trashed
+        .into_iter()
+        .partition(|n| n.deleted_at.as_deref().is_some_and(|d| is_trash_expired(d, now, retention_days)));
+    if expired.is_empty() {
+        return Ok(());
+    }
+    index.trashed = kept;
+    add_tombstones(&mut index, expired.iter().map(|n| n.id.as_str()), &now.to_rfc3339());
+    write_index(root, &index, false)?;
+    for n in &expired {
+        let _ = fs::remove_dir_all(trash_dir(root, &n.id));
+    }
+    Ok(())
+}
+
+/// Record that `ids` were deleted for good at `now`, so sync removes them on the other side too
+/// instead of copying them back. Replaces any older tombstone for the same id.
+fn add_tombstones<'a>(index: &mut IndexFile, ids: impl IntoIterator<Item = &'a str>, now: &str) {
+    for id in ids {
+        index.tombstones.retain(|t| t.id != id);
+        index.tombstones.push(Tombstone {
+            id: id.to_string(),
+            deleted_at: now.to_string(),
+        });
+    }
+}
+
+/// Notes currently in the trash, most recently deleted first.
+pub fn list_trashed_notes(app_handle: &tauri::AppHandle) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let mut notes = index.trashed;
+    notes.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    Ok(notes)
+}
+
+/// Restore a trashed note: put its metadata back into the index and move its files back.
+/// If its notebook was removed in the meantime, the note comes back unfiled.
+pub fn restore_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
     let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
-    if let Some(n) = index.notes.iter().find(|n| n.is_daily && n.title == today) {
-        return Ok(n.clone());
+    let pos = index.trashed.iter().position(|n| n.id == note_id).ok_or("Note not found in trash")?;
+    restore_note_files_from_trash(&root, note_id, &ext)?;
+    let mut meta = index.trashed.remove(pos);
+    meta.deleted_at = None;
+    if let Some(nb) = meta.notebook_id.as_deref() {
+        if !index.notebooks.iter().any(|b| b.id == nb) {
+            meta.notebook_id = None;
+        }
     }
-    let id = Uuid::new_v4().to_string();
-    let filename = format!("{}.txt", id);
-    let now = Utc::now().to_rfc3339();
-    let meta = NoteMeta {
-        id: id.clone(),
-        title: today.clone(),
-        created_at: now.clone(),
-        updated_at: now,
-        important: false,
-        filename: filename.clone(),
-        images: vec![],
-        tags: vec!["daily".to_string()],
-        links_to: vec![],
-        is_daily: true,
-        notebook_id: None,
-    };
     index.notes.push(meta.clone());
-    write_index(&root, &index)?;
-    let path = note_path(&root, &id);
-    fs::write(&path, "# daily\n").map_err(|e| e.to_string())?;
+    if let Err(e) = write_index(&root, &index, false) {
+        let _ = move_note_files_to_trash(&root, note_id, &ext);
+        return Err(e);
+    }
+    let body = fs::read_to_string(note_path_with_ext(&root, note_id, &ext)).unwrap_or_default();
+    update_search_index(&root, |sidx| search_index_add_note(sidx, note_id, &meta.title, &body));
     Ok(meta)
 }
 
-/// Notes that link to this note (backlinks).
-pub fn get_backlinks(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<NoteMeta>, String> {
-    validate_note_id(note_id)?;
+/// Permanently delete everything in the trash. Returns the number of notes purged.
+pub fn empty_trash(app_handle: &tauri::AppHandle) -> Result<usize, String> {
     let root = storage_root(app_handle)?;
-    let index = read_index(&root)?;
-    Ok(index
-        .notes
-        .into_iter()
-        .filter(|n| n.links_to.contains(&note_id.to_string()))
-        .collect())
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let trashed = std::mem::take(&mut index.trashed);
+    add_tombstones(&mut index, trashed.iter().map(|n| n.id.as_str()), &Utc::now().to_rfc3339());
+    write_index(&root, &index, false)?;
+    for n in &trashed {
+        let _ = fs::remove_dir_all(trash_dir(&root, &n.id));
+    }
+    Ok(trashed.len())
 }
 
-/// Remove one attachment from a note.
-pub fn remove_attachment(
-    app_handle: &tauri::AppHandle,
-    note_id: &str,
-    relative_path: &str,
-) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    if relative_path.contains("..") || relative_path.starts_with('/') {
+/// Resolve full filesystem path for an image (relative path under storage root).
+pub fn resolve_image_path(app_handle: &tauri::AppHandle, relative_path: &str) -> Result<PathBuf, String> {
+    if !is_attachment_path(relative_path) {
         return Err("Invalid path".into());
     }
     let root = storage_root(app_handle)?;
     let full = root.join(relative_path);
-    if full.exists() {
-        let _ = fs::remove_file(&full);
+    if !full.starts_with(&root) {
+        return Err("Invalid path".into());
+    }
+    Ok(full)
+}
+
+/// URL the webview loads `path` from through the asset protocol; the same string `convertFileSrc(path)`
+/// from @tauri-apps/api builds (the whole path encoded as with encodeURIComponent).
+pub fn asset_url(path: &Path) -> String {
+    let mut out = String::from(if cfg!(any(windows, target_os = "android")) {
+        "http://asset.localhost/"
+    } else {
+        "asset://localhost/"
+    });
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
     }
+    out
+}
+
+/// Asset-protocol URL for an image or attachment, with the same path checks as resolve_image_path.
+pub fn resolve_image_asset_url(app_handle: &tauri::AppHandle, relative_path: &str) -> Result<String, String> {
+    resolve_image_path(app_handle, relative_path).map(|p| asset_url(&p))
+}
+
+/// Update only the title of a note (for sidebar inline edit).
+pub fn update_note_title(app_handle: &tauri::AppHandle, note_id: &str, new_title: &str) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
     let mut index = read_index(&root)?;
     let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    n.images.retain(|img| img.path != relative_path);
+    n.title = normalize_title(new_title);
     n.updated_at = Utc::now().to_rfc3339();
     let meta = n.clone();
-    write_index(&root, &index)?;
+    write_index(&root, &index, false)?;
+    let body = fs::read_to_string(note_path(&root, note_id)?).unwrap_or_default();
+    update_search_index(&root, |sidx| search_index_add_note(sidx, note_id, &meta.title, &body));
     Ok(meta)
 }
 
-/// Rename an attachment (display name only; file on disk is unchanged).
-pub fn rename_attachment(
-    app_handle: &tauri::AppHandle,
-    note_id: &str,
-    relative_path: &str,
-    new_name: &str,
-) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    if relative_path.contains("..") || relative_path.starts_with('/') {
-        return Err("Invalid path".into());
+/// Rename a note and rewrite `[[Old Title]]` links in every other note to the new title, refreshing their
+/// links_to. Returns how many other notes were rewritten.
+pub fn rename_note_and_relink(app_handle: &tauri::AppHandle, note_id: &str, new_title: &str) -> Result<usize, String> {
+    rename_note_and_relink_in(&storage_root(app_handle)?, note_id, new_title)
+}
+
+/// rename_note_and_relink for the storage root `root`. Links are only rewritten when `[[Old Title]]`
+/// resolves to this note (see resolve_link_title); if another note owns that title, its links are left
+/// alone. Like rename_tag_in, all bodies are read first, each rewritten one is snapshotted, and a failed
+/// write or index update puts them back.
+pub fn rename_note_and_relink_in(root: &Path, note_id: &str, new_title: &str) -> Result<usize, String> {
+    validate_note_id(note_id)?;
+    let new_title = normalize_title(new_title);
+    if new_title.contains("]]") {
+        return Err("Title cannot contain ]] when updating links".into());
+    }
+    let ext = note_extension(root)?;
+    let settings = read_settings(root)?;
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
+    let old_title = index.notes.iter().find(|n| n.id == note_id).ok_or("Note not found")?.title.clone();
+    let owns_links = resolve_link_title(&index.notes, &old_title, "") == Some(note_id);
+
+    let mut edits: Vec<BodyEdit> = vec![];
+    for n in index.notes.iter().filter(|n| owns_links && n.id != note_id) {
+        let body = match fs::read_to_string(note_path_with_ext(root, &n.id, &ext)) {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Cannot read \"{}\": {}", n.title, e)),
+        };
+        let new_body = relink_title(&body, &old_title, &new_title);
+        if new_body != body {
+            edits.push(BodyEdit {
+                note_id: n.id.clone(),
+                old_body: body,
+                new_body,
+            });
+        }
+    }
+    for edit in &edits {
+        let n = index.notes.iter().find(|n| n.id == edit.note_id).ok_or("Note not found")?;
+        let label = format!("Before relinking [[{}]] to [[{}]]", old_title, new_title);
+        snapshot_before_edit(root, &settings, n, &edit.old_body, label)?;
+    }
+    write_body_edits(root, &ext, &edits)?;
+
+    let now = Utc::now().to_rfc3339();
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.title = new_title;
+    n.updated_at = now.clone();
+    let renamed = n.clone();
+    for edit in &edits {
+        let links_to = extract_links_from_body(&edit.new_body, &index.notes, &edit.note_id);
+        if let Some(n) = index.notes.iter_mut().find(|n| n.id == edit.note_id) {
+            n.links_to = links_to;
+            n.preview = Some(note_preview(&edit.new_body, NOTE_PREVIEW_CHARS));
+            n.updated_at = now.clone();
+        }
+    }
+    if let Err(e) = write_index(root, &index, false) {
+        restore_body_edits(root, &ext, &edits);
+        return Err(e);
+    }
+
+    let renamed_body = fs::read_to_string(note_path_with_ext(root, note_id, &ext)).unwrap_or_default();
+    update_search_index(root, |sidx| {
+        search_index_add_note(sidx, note_id, &renamed.title, &renamed_body);
+        for edit in &edits {
+            if let Some(n) = index.notes.iter().find(|n| n.id == edit.note_id) {
+                search_index_add_note(sidx, &n.id, &n.title, &edit.new_body);
+            }
+        }
+    });
+    Ok(edits.len())
+}
+
+/// Replace a note's aliases (trimmed, empty and case-insensitive duplicates dropped). Other notes'
+/// links_to pick up the new aliases the next time they are saved.
+pub fn set_note_aliases(app_handle: &tauri::AppHandle, note_id: &str, aliases: &[String]) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    let mut cleaned: Vec<String> = vec![];
+    for a in aliases {
+        let a = a.trim();
+        if a.is_empty() {
+            continue;
+        }
+        if a.contains("[[") || a.contains("]]") {
+            return Err("Alias cannot contain [[ or ]]".into());
+        }
+        if !cleaned.iter().any(|c| c.to_lowercase() == a.to_lowercase()) {
+            cleaned.push(a.to_string());
+        }
+    }
+    let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.aliases = cleaned;
+    touch_metadata(n, &Utc::now().to_rfc3339(), bump);
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Unique tags across notes with the number of notes carrying each. Variants that share a tag_key
+/// (`Work`, `work`, NFD `café`) are collapsed into one: the spelling used by the most notes, ties
+/// going to the smallest. A note with several variants counts once. Sorted by key.
+pub fn count_tags(notes: &[NoteMeta]) -> Vec<(String, usize)> {
+    let mut variants: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for n in notes {
+        let mut seen: HashSet<String> = HashSet::new();
+        for t in &n.tags {
+            let key = tag_key(t);
+            *variants.entry(key.clone()).or_default().entry(t.nfc().collect()).or_insert(0) += 1;
+            if seen.insert(key.clone()) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut keyed: Vec<(String, String, usize)> = variants
+        .into_iter()
+        .filter_map(|(key, spellings)| {
+            let canonical = spellings
+                .into_iter()
+                .max_by(|(a, ca), (b, cb)| ca.cmp(cb).then_with(|| b.cmp(a)))
+                .map(|(t, _)| t)?;
+            let count = counts.get(&key).copied().unwrap_or(0);
+            Some((key, canonical, count))
+        })
+        .collect();
+    keyed.sort();
+    keyed.into_iter().map(|(_, t, count)| (t, count)).collect()
+}
+
+/// Unique tag names from count_tags (case and Unicode variants collapsed), sorted by key.
+pub fn collapse_tag_variants(notes: &[NoteMeta]) -> Vec<String> {
+    count_tags(notes).into_iter().map(|(t, _)| t).collect()
+}
+
+/// List all unique tags across notes (case and Unicode variants collapsed), sorted.
+pub fn list_tags(app_handle: &tauri::AppHandle) -> Result<Vec<String>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(collapse_tag_variants(&index.notes))
+}
+
+/// Tag usage from count_tags, most used first, then by name.
+pub fn tag_usage_counts(notes: &[NoteMeta]) -> Vec<TagCount> {
+    let mut counts: Vec<TagCount> = count_tags(notes)
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    counts
+}
+
+/// Tags with the number of notes using each, most used first (for the tag cloud).
+pub fn list_tags_with_counts(app_handle: &tauri::AppHandle) -> Result<Vec<TagCount>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(tag_usage_counts(&index.notes))
+}
+
+/// Count, most recent update and starred flag per tag in one pass over `notes`. Variants are grouped
+/// by tag_key and shown with their most common spelling, like count_tags; sorted by tag key.
+pub fn summarize_tags(notes: &[NoteMeta]) -> Vec<TagSummary> {
+    struct Acc {
+        spellings: HashMap<String, usize>,
+        count: usize,
+        last_updated: String,
+        has_important: bool,
+    }
+    let mut by_key: HashMap<String, Acc> = HashMap::new();
+    for n in notes {
+        let mut seen: HashSet<String> = HashSet::new();
+        for t in &n.tags {
+            let key = tag_key(t);
+            let acc = by_key.entry(key.clone()).or_insert_with(|| Acc {
+                spellings: HashMap::new(),
+                count: 0,
+                last_updated: String::new(),
+                has_important: false,
+            });
+            *acc.spellings.entry(t.nfc().collect()).or_insert(0) += 1;
+            if !seen.insert(key) {
+                continue;
+            }
+            acc.count += 1;
+            acc.has_important |= n.important;
+            if n.updated_at > acc.last_updated {
+                acc.last_updated = n.updated_at.clone();
+            }
+        }
+    }
+    let mut keyed: Vec<(String, TagSummary)> = by_key
+        .into_iter()
+        .filter_map(|(key, acc)| {
+            let tag = acc
+                .spellings
+                .into_iter()
+                .max_by(|(a, ca), (b, cb)| ca.cmp(cb).then_with(|| b.cmp(a)))
+                .map(|(t, _)| t)?;
+            Some((key, TagSummary { tag, count: acc.count, last_updated: acc.last_updated, has_important: acc.has_important }))
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    keyed.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Tags with note count, last update and whether any note is starred (for sorting the tag sidebar by recency).
+pub fn tag_summary(app_handle: &tauri::AppHandle) -> Result<Vec<TagSummary>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(summarize_tags(&index.notes))
+}
+
+/// List notes that have the given tag or a tag nested under it (`project` -> `project/alpha`).
+pub fn notes_by_tag_prefix(app_handle: &tauri::AppHandle, prefix: &str) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(index
+        .notes
+        .into_iter()
+        .filter(|n| n.tags.iter().any(|t| tag_matches_prefix(t, prefix)))
+        .collect())
+}
+
+/// List notes that have the given tag, ignoring case and Unicode normalization differences.
+pub fn notes_by_tag(app_handle: &tauri::AppHandle, tag: &str) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let key = tag_key(tag);
+    Ok(index
+        .notes
+        .into_iter()
+        .filter(|n| n.tags.iter().any(|t| tag_key(t) == key))
+        .collect())
+}
+
+/// Add a tag to multiple notes (merge with existing).
+pub fn add_tag_to_notes(
+    app_handle: &tauri::AppHandle,
+    note_ids: &[String],
+    tag: &str,
+) -> Result<Vec<NoteMeta>, String> {
+    if note_ids.is_empty() || tag.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    add_tag(&storage_root(app_handle)?, note_ids, tag)
+}
+
+/// add_tag_to_notes for the storage root `root`.
+pub fn add_tag(root: &Path, note_ids: &[String], tag: &str) -> Result<Vec<NoteMeta>, String> {
+    let bump = read_settings(root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
+    let tag = normalize_tag(tag.trim(), read_settings(root)?.lowercase_tags);
+    let key = tag_key(&tag);
+    let mut updated = vec![];
+    for n in index.notes.iter_mut() {
+        if note_ids.contains(&n.id) && !n.tags.iter().any(|t| tag_key(t) == key) {
+            n.tags.push(tag.clone());
+            touch_metadata(n, &Utc::now().to_rfc3339(), bump);
+            updated.push(n.clone());
+        }
+    }
+    write_index(root, &index, false)?;
+    Ok(updated)
+}
+
+/// Rename a tag on every note (metadata and inline `#tag` in bodies). Returns the affected notes.
+/// The tag's color/description move to the new name unless it already has its own.
+pub fn rename_tag(app_handle: &tauri::AppHandle, old: &str, new: &str) -> Result<Vec<NoteMeta>, String> {
+    rename_tag_in(&storage_root(app_handle)?, old, new)
+}
+
+/// rename_tag for the storage root `root`. Every affected body is read before any is written (an
+/// unreadable one fails the rename) and gets a version snapshot first; if a write or the index
+/// update fails, the bodies already rewritten are put back.
+pub fn rename_tag_in(root: &Path, old: &str, new: &str) -> Result<Vec<NoteMeta>, String> {
+    let old = old.trim().trim_start_matches('#');
+    let new = new.trim().trim_start_matches('#');
+    if old.is_empty() || new.is_empty() {
+        return Err("Tag cannot be empty".into());
+    }
+    if !new.chars().all(is_tag_char) {
+        return Err("Invalid tag name".into());
+    }
+    let ext = note_extension(root)?;
+    let settings = read_settings(root)?;
+    let new = normalize_tag(new, settings.lowercase_tags);
+    let new = new.as_str();
+    if normalize_tag(old, false) == new {
+        return Ok(vec![]);
+    }
+    let old_key = tag_key(old);
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
+    let mut edits: Vec<BodyEdit> = vec![];
+    for n in index.notes.iter().filter(|n| n.tags.iter().any(|t| tag_key(t) == old_key)) {
+        let body = match fs::read_to_string(note_path_with_ext(root, &n.id, &ext)) {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Cannot read \"{}\": {}", n.title, e)),
+        };
+        let new_body = replace_inline_tag(&body, old, new);
+        if new_body != body {
+            edits.push(BodyEdit {
+                note_id: n.id.clone(),
+                old_body: body,
+                new_body,
+            });
+        }
+    }
+    for edit in &edits {
+        let n = index.notes.iter().find(|n| n.id == edit.note_id).ok_or("Note not found")?;
+        snapshot_before_edit(root, &settings, n, &edit.old_body, format!("Before renaming #{} to #{}", old, new))?;
+    }
+    write_body_edits(root, &ext, &edits)?;
+    let now = Utc::now().to_rfc3339();
+    let mut updated = vec![];
+    for n in index.notes.iter_mut() {
+        if !n.tags.iter().any(|t| tag_key(t) == old_key) {
+            continue;
+        }
+        if let Some(edit) = edits.iter().find(|e| e.note_id == n.id) {
+            n.preview = Some(note_preview(&edit.new_body, NOTE_PREVIEW_CHARS));
+        }
+        n.tags.retain(|t| tag_key(t) != old_key);
+        if !n.tags.iter().any(|t| t == new) {
+            n.tags.push(new.to_string());
+        }
+        n.tags.sort();
+        n.updated_at = now.clone();
+        updated.push(n.clone());
+    }
+    if !updated.is_empty() {
+        if let Err(e) = write_index(root, &index, false) {
+            restore_body_edits(root, &ext, &edits);
+            return Err(e);
+        }
+    }
+    if !edits.is_empty() {
+        update_search_index(root, |sidx| {
+            for edit in &edits {
+                if let Some(n) = index.notes.iter().find(|n| n.id == edit.note_id) {
+                    search_index_add_note(sidx, &n.id, &n.title, &edit.new_body);
+                }
+            }
+        });
+    }
+    let mut meta = read_tag_meta(root)?;
+    let new_key = tag_key(new);
+    if new_key != old_key && !meta.contains_key(&new_key) {
+        if let Some(m) = meta.remove(&old_key) {
+            meta.insert(new_key, m);
+            write_tag_meta(root, &meta)?;
+        }
+    }
+    Ok(updated)
+}
+
+/// Remove a tag from a note.
+pub fn remove_tag_from_note(app_handle: &tauri::AppHandle, note_id: &str, tag: &str) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    let key = tag_key(tag);
+    n.tags.retain(|t| tag_key(t) != key);
+    touch_metadata(n, &Utc::now().to_rfc3339(), bump);
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+fn read_tag_meta(root: &Path) -> Result<HashMap<String, TagMeta>, String> {
+    let path = tag_meta_path(root);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let s = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&s).map_err(|e| e.to_string())
+}
+
+fn write_tag_meta(root: &Path, meta: &HashMap<String, TagMeta>) -> Result<(), String> {
+    let path = tag_meta_path(root);
+    let json = serde_json::to_string_pretty(meta).map_err(|e| e.to_string())?;
+    atomic_write(&path, json.as_bytes())
+}
+
+/// Check a tag color: `#` followed by 3 or 6 hex digits.
+pub fn validate_tag_color(color: &str) -> Result<(), String> {
+    let hex = color.strip_prefix('#').ok_or("Tag color must start with #")?;
+    if (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err("Tag color must be #rgb or #rrggbb".into())
+    }
+}
+
+/// Update one tag's entry in meta/tag_meta.json (keyed by tag_key); entries left with neither a
+/// color nor a description are removed.
+fn update_tag_meta(
+    app_handle: &tauri::AppHandle,
+    tag: &str,
+    f: impl FnOnce(&mut TagMeta),
+) -> Result<HashMap<String, TagMeta>, String> {
+    let key = tag_key(tag.trim().trim_start_matches('#'));
+    if key.is_empty() {
+        return Err("Tag cannot be empty".into());
+    }
+    let root = storage_root(app_handle)?;
+    let mut all = read_tag_meta(&root)?;
+    let entry = all.entry(key.clone()).or_default();
+    f(entry);
+    if entry.color.is_empty() && entry.description.is_none() {
+        all.remove(&key);
+    }
+    write_tag_meta(&root, &all)?;
+    Ok(all)
+}
+
+/// Colors and descriptions for all tags, keyed by normalized tag.
+pub fn get_tag_meta(app_handle: &tauri::AppHandle) -> Result<HashMap<String, TagMeta>, String> {
+    let root = storage_root(app_handle)?;
+    read_tag_meta(&root)
+}
+
+/// Set a tag's color (`#rgb`/`#rrggbb`); None or an empty string clears it.
+pub fn set_tag_color(
+    app_handle: &tauri::AppHandle,
+    tag: &str,
+    color: Option<&str>,
+) -> Result<HashMap<String, TagMeta>, String> {
+    let color = color.map(str::trim).unwrap_or("").to_lowercase();
+    if !color.is_empty() {
+        validate_tag_color(&color)?;
+    }
+    update_tag_meta(app_handle, tag, |m| m.color = color)
+}
+
+/// Set a tag's description; None or a blank string clears it.
+pub fn set_tag_description(
+    app_handle: &tauri::AppHandle,
+    tag: &str,
+    description: Option<&str>,
+) -> Result<HashMap<String, TagMeta>, String> {
+    let description = description.map(str::trim).filter(|d| !d.is_empty()).map(String::from);
+    update_tag_meta(app_handle, tag, |m| m.description = description)
+}
+
+/// Tags as in list_tags, with note counts and any color/description from meta/tag_meta.json.
+pub fn tag_details(notes: &[NoteMeta], meta: &HashMap<String, TagMeta>) -> Vec<TagDetail> {
+    count_tags(notes)
+        .into_iter()
+        .map(|(tag, count)| {
+            let m = meta.get(&tag_key(&tag));
+            TagDetail {
+                color: m.map(|m| m.color.clone()).filter(|c| !c.is_empty()),
+                description: m.and_then(|m| m.description.clone()),
+                tag,
+                count,
+            }
+        })
+        .collect()
+}
+
+/// list_tags with counts, colors and descriptions; list_tags itself keeps returning plain names.
+pub fn list_tags_detailed(app_handle: &tauri::AppHandle) -> Result<Vec<TagDetail>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let meta = read_tag_meta(&root)?;
+    Ok(tag_details(&index.notes, &meta))
+}
+
+/// Move multiple notes to the trash in one index write.
+pub fn batch_delete_notes(app_handle: &tauri::AppHandle, note_ids: &[String]) -> Result<(), String> {
+    if note_ids.is_empty() {
+        return Ok(());
+    }
+    for id in note_ids {
+        validate_note_id(id)?;
+    }
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    // Only live notes move; an id that is already trashed must not have its trash/ files touched.
+    let live: Vec<String> = note_ids.iter().filter(|id| index.notes.iter().any(|n| &n.id == *id)).cloned().collect();
+    move_index_entries_to_trash(&mut index, &live);
+    trash_note_files(&root, &live, &ext)?;
+    if let Err(e) = write_index(&root, &index, false) {
+        untrash_note_files(&root, &live, &ext);
+        return Err(e);
+    }
+    drop_from_search_index(&root, &live);
+    Ok(())
+}
+
+/// Move the given notes from index.notes to index.trashed (in memory), stamping deleted_at.
+fn move_index_entries_to_trash(index: &mut IndexFile, note_ids: &[String]) {
+    let ids_set: HashSet<&str> = note_ids.iter().map(|s| s.as_str()).collect();
+    let now = Utc::now().to_rfc3339();
+    let (removed, kept): (Vec<NoteMeta>, Vec<NoteMeta>) =
+        std::mem::take(&mut index.notes).into_iter().partition(|n| ids_set.contains(n.id.as_str()));
+    index.notes = kept;
+    for mut meta in removed {
+        meta.deleted_at = Some(now.clone());
+        index.trashed.push(meta);
+    }
+}
+
+/// Move trashed notes' files into trash/, all or nothing: if one note's files cannot be moved,
+/// the notes already moved are put back and the error is returned. Call before the index write.
+fn trash_note_files(root: &Path, note_ids: &[String], ext: &str) -> Result<(), String> {
+    for (i, id) in note_ids.iter().enumerate() {
+        if let Err(e) = move_note_files_to_trash(root, id, ext) {
+            untrash_note_files(root, &note_ids[..i], ext);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Undo trash_note_files after a failed index write (best-effort; the index still lists the notes as live).
+fn untrash_note_files(root: &Path, note_ids: &[String], ext: &str) {
+    for id in note_ids {
+        let _ = restore_note_files_from_trash(root, id, ext);
+    }
+}
+
+/// Drop trashed notes from the search index. Call after the index write.
+fn drop_from_search_index(root: &Path, note_ids: &[String]) {
+    update_search_index(root, |sidx| {
+        for id in note_ids {
+            search_index_remove_note(sidx, id);
+        }
+    });
+}
+
+/// Set important flag on multiple notes.
+pub fn batch_toggle_important(
+    app_handle: &tauri::AppHandle,
+    note_ids: &[String],
+    important: bool,
+) -> Result<Vec<NoteMeta>, String> {
+    if note_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let now = Utc::now().to_rfc3339();
+    let ids_set: HashSet<&str> = note_ids.iter().map(|s| s.as_str()).collect();
+    let mut updated = vec![];
+    for n in index.notes.iter_mut() {
+        if ids_set.contains(n.id.as_str()) {
+            n.important = important;
+            touch_metadata(n, &now, bump);
+            updated.push(n.clone());
+        }
+    }
+    write_index(&root, &index, false)?;
+    Ok(updated)
+}
+
+/// Set pinned flag on multiple notes.
+pub fn batch_toggle_pinned(
+    app_handle: &tauri::AppHandle,
+    note_ids: &[String],
+    pinned: bool,
+) -> Result<Vec<NoteMeta>, String> {
+    if note_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let now = Utc::now().to_rfc3339();
+    let ids_set: HashSet<&str> = note_ids.iter().map(|s| s.as_str()).collect();
+    let mut updated = vec![];
+    for n in index.notes.iter_mut() {
+        if ids_set.contains(n.id.as_str()) {
+            n.pinned = pinned;
+            touch_metadata(n, &now, bump);
+            updated.push(n.clone());
+        }
+    }
+    write_index(&root, &index, false)?;
+    Ok(updated)
+}
+
+/// Duplicate a note (new id, same title + " (copy)", same body and attachments). Attachments are
+/// copied before the note is created and body references point at the copies. A file that can't be
+/// copied (missing, unreadable, disk full) is skipped and reported in `failed` instead of aborting.
+pub fn duplicate_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<DuplicateResult, String> {
+    let content = read_note_uncounted(app_handle, note_id)?;
+    let root = storage_root(app_handle)?;
+    // The copies live under an id the index doesn't know yet; holding the lock until the note is
+    // written keeps cleanup_orphans from deleting them in between.
+    let _guard = lock_index();
+    let new_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let mut body = content.body;
+    let mut copies = vec![];
+    let mut failed = vec![];
+    for img in &content.meta.images {
+        match copy_attachment_to(&root, img, &new_id) {
+            Ok(Some(copy)) => {
+                body = body.replace(&img.path, &copy.path);
+                copies.push(ImageRef {
+                    added_at: now.clone(),
+                    ..copy
+                });
+            }
+            Ok(None) => failed.push(format!("{}: file not found", img.name)),
+            Err(e) => failed.push(format!("{}: {}", img.name, e)),
+        }
+    }
+    let new_title = format!("{} (copy)", content.meta.title.trim());
+    let meta = save_note_with(app_handle, Some(&new_id), &new_title, &body, |n| n.images = copies)?.meta;
+    Ok(DuplicateResult { meta, failed })
+}
+
+/// Link or copy one attachment into `note_id`'s folder of the same kind (`images/` or `attachments/`),
+/// renaming it on a clash. None if the ref isn't a stored attachment or its file is missing.
+fn copy_attachment_to(root: &Path, img: &ImageRef, note_id: &str) -> Result<Option<ImageRef>, String> {
+    let Some((subdir, rest)) = img.path.split_once('/') else { return Ok(None) };
+    let src = root.join(&img.path);
+    if !is_attachment_path(&img.path) || !src.is_file() {
+        return Ok(None);
+    }
+    let file = Path::new(rest);
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
+    let ext = file.extension().and_then(|e| e.to_str()).map(|e| format!(".{}", e)).unwrap_or_default();
+    let dest_dir = root.join(subdir).join(sanitize_filename(note_id));
+    fs::create_dir_all(&dest_dir).map_err(|e| e.to_string())?;
+    let stored = dedupe_filename(stem, &ext, |n| dest_dir.join(n).exists());
+    let dest = dest_dir.join(&stored);
+    // Attachments are never modified in place, so the copy can share the original's data.
+    if fs::hard_link(&src, &dest).is_err() {
+        fs::copy(&src, &dest).map_err(|e| e.to_string())?;
+    }
+    Ok(Some(ImageRef {
+        name: img.name.clone(),
+        path: format!("{}/{}/{}", subdir, note_id, stored),
+        added_at: img.added_at.clone(),
+        size: img.size,
+        kind: img.kind,
+    }))
+}
+
+/// Put merge sources in the requested order (stable, so equal timestamps keep the provided order).
+pub fn sort_merge_sources(notes: &mut [NoteMeta], order: MergeOrder) {
+    match order {
+        MergeOrder::ByUpdated => notes.sort_by(|a, b| a.updated_at.cmp(&b.updated_at)),
+        MergeOrder::ByCreated => notes.sort_by(|a, b| a.created_at.cmp(&b.created_at)),
+        MergeOrder::ByProvidedOrder => {}
+    }
+}
+
+/// Concatenate (title, body) parts, each preceded by `separator` with `{{title}}` filled in
+/// (default `## {{title}}\n\n`). Parts are separated by a blank line; the result is trimmed.
+pub fn merge_note_bodies(parts: &[(String, String)], separator: Option<&str>) -> String {
+    let separator = separator.unwrap_or("## {{title}}\n\n");
+    let merged: String = parts
+        .iter()
+        .map(|(title, body)| format!("{}{}\n\n", separator.replace("{{title}}", title), body))
+        .collect();
+    merged.trim().to_string()
+}
+
+/// Merge multiple notes into the first id (or, with `keep_sources`, into a new note). Bodies are
+/// concatenated in `options.order` under `options.separator`, the title comes from the first source in
+/// that order, and the tags and attachments of every source are carried over (attachment files are
+/// linked into the merged note's folders and body references rewritten). Without `keep_sources` the
+/// other notes are deleted. Returns the merged note meta.
+pub fn merge_notes(app_handle: &tauri::AppHandle, note_ids: &[String], options: &MergeOptions) -> Result<NoteMeta, String> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let note_ids: Vec<&String> = note_ids.iter().filter(|id| seen.insert(id.as_str())).collect();
+    if note_ids.is_empty() {
+        return Err("No notes to merge".into());
+    }
+    if note_ids.len() == 1 {
+        let root = storage_root(app_handle)?;
+        let index = read_index(&root)?;
+        return index
+            .notes
+            .into_iter()
+            .find(|n| n.id == *note_ids[0])
+            .ok_or_else(|| "Note not found".to_string());
+    }
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
+    let index = read_index(&root)?;
+    let mut sources: Vec<NoteMeta> = vec![];
+    for id in &note_ids {
+        sources.push(index.notes.iter().find(|n| n.id == **id).cloned().ok_or("Note not found")?);
+    }
+    sort_merge_sources(&mut sources, options.order);
+    let target_id = if options.keep_sources {
+        Uuid::new_v4().to_string()
+    } else {
+        note_ids[0].clone()
+    };
+
+    let mut images: Vec<ImageRef> = vec![];
+    let mut parts: Vec<(String, String)> = vec![];
+    for src in &sources {
+        let mut body = fs::read_to_string(note_path_with_ext(&root, &src.id, &ext)).unwrap_or_default();
+        for img in &src.images {
+            if src.id == target_id {
+                images.push(img.clone());
+            } else if let Some(copy) = copy_attachment_to(&root, img, &target_id)? {
+                body = body.replace(&img.path, &copy.path);
+                images.push(copy);
+            }
+        }
+        parts.push((src.title.clone(), body));
+    }
+    let merged_body = merge_note_bodies(&parts, options.separator.as_deref());
+    // save_note records a version of the kept note and re-derives tags and links from the merged body.
+    let saved = save_note(app_handle, Some(&target_id), &sources[0].title, &merged_body)?;
+
+    let lowercase_tags = read_settings(&root)?.lowercase_tags;
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == saved.id).ok_or("Note not found")?;
+    for t in sources.iter().flat_map(|s| s.tags.iter()) {
+        let t = normalize_tag(t, lowercase_tags);
+        if !n.tags.iter().any(|existing| tag_key(existing) == tag_key(&t)) {
+            n.tags.push(t);
+        }
+    }
+    n.tags.sort();
+    n.images = images;
+    let meta = n.clone();
+    let remove_ids: Vec<&str> = if options.keep_sources {
+        vec![]
+    } else {
+        note_ids[1..].iter().map(|s| s.as_str()).collect()
+    };
+    index.notes.retain(|n| !remove_ids.contains(&n.id.as_str()));
+    add_tombstones(&mut index, remove_ids.iter().copied(), &Utc::now().to_rfc3339());
+    write_index(&root, &index, false)?;
+    for id in &remove_ids {
+        let _ = fs::remove_file(note_path_with_ext(&root, id, &ext));
+        let _ = fs::remove_dir_all(images_dir(&root, id));
+        let _ = fs::remove_dir_all(attachments_dir(&root, id));
+    }
+    if !remove_ids.is_empty() {
+        update_search_index(&root, |sidx| {
+            for id in &remove_ids {
+                search_index_remove_note(sidx, id);
+            }
+        });
+    }
+    Ok(meta)
+}
+
+/// Export note as plain text (title + body).
+pub fn export_note(app_handle: &tauri::AppHandle, note_id: &str) -> Result<String, String> {
+    let content = read_note_uncounted(app_handle, note_id)?;
+    Ok(format!("{}\n\n{}\n", content.meta.title, content.body))
+}
+
+/// Export note as Markdown: YAML frontmatter (optional) + # title + body. [[Title]] left as-is for compatibility.
+/// With `inline_images`, the note's images are embedded as `data:` URIs (see inline_image_data_url).
+pub fn export_note_as_markdown(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    inline_images: bool,
+) -> Result<String, String> {
+    let content = read_note_uncounted(app_handle, note_id)?;
+    let mut body = content.body;
+    if inline_images {
+        for img in content.meta.images.iter().filter(|i| i.kind == AttachmentKind::Image) {
+            if !body.contains(&img.path) {
+                continue;
+            }
+            if let Some(url) = resolve_image_path(app_handle, &img.path).ok().and_then(|p| inline_image_data_url(&p)) {
+                body = body.replace(&img.path, &url);
+            }
+        }
+    }
+    Ok(note_to_markdown(&content.meta, &body))
+}
+
+/// Largest image embedded by the inline-images exports; bigger ones keep their path so a single photo
+/// doesn't turn the export into tens of megabytes of base64.
+pub const MAX_INLINE_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// `data:<mime>;base64,...` URI for an image, or None if it isn't a recognized image format or is
+/// larger than MAX_INLINE_IMAGE_BYTES.
+pub fn image_data_url(data: &[u8]) -> Option<String> {
+    if data.len() > MAX_INLINE_IMAGE_BYTES {
+        return None;
+    }
+    let mime = detect_image_mime(data)?;
+    Some(format!("data:{};base64,{}", mime, BASE64.encode(data)))
+}
+
+/// image_data_url for a file, checking the size before reading it.
+fn inline_image_data_url(path: &Path) -> Option<String> {
+    let len = fs::metadata(path).ok()?.len();
+    if len > MAX_INLINE_IMAGE_BYTES as u64 {
+        return None;
+    }
+    image_data_url(&fs::read(path).ok()?)
+}
+
+/// YAML frontmatter for an export (tags, created, updated), or "" when there is nothing to add.
+fn markdown_frontmatter(tags: &[String], created_at: &str, updated_at: &str) -> String {
+    let mut md = String::new();
+    if !tags.is_empty() || created_at != updated_at {
+        md.push_str("---\n");
+        if !tags.is_empty() {
+            md.push_str("tags:\n");
+            for t in tags {
+                md.push_str(&format!("  - {}\n", t));
+            }
+        }
+        md.push_str(&format!("created: {}\n", created_at));
+        md.push_str(&format!("updated: {}\n", updated_at));
+        md.push_str("---\n\n");
+    }
+    md
+}
+
+/// Markdown export of one note: frontmatter + # title + body.
+pub fn note_to_markdown(meta: &NoteMeta, body: &str) -> String {
+    let mut md = markdown_frontmatter(&meta.tags, &meta.created_at, &meta.updated_at);
+    md.push_str(&format!("# {}\n\n", meta.title));
+    md.push_str(body);
+    if !body.ends_with('\n') {
+        md.push('\n');
+    }
+    md
+}
+
+/// GitHub-style heading anchor (lowercase, punctuation dropped, spaces to '-'). Repeats of the same
+/// heading get "-1", "-2"… like GitHub does; `used` tracks anchors handed out so far.
+pub fn heading_anchor(heading: &str, used: &mut HashMap<String, usize>) -> String {
+    let slug: String = heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect();
+    match used.get_mut(&slug) {
+        Some(count) => {
+            *count += 1;
+            format!("{}-{}", slug, count)
+        }
+        None => {
+            used.insert(slug.clone(), 0);
+            slug
+        }
+    }
+}
+
+/// One Markdown document for a notebook: optional frontmatter (all tags, notebook created, latest note
+/// update), `# Name`, a table of contents, then each note under `## Title` separated by `---`.
+/// `notes` should already be in the desired order.
+pub fn render_notebook_markdown(notebook: &Notebook, notes: &[(NoteMeta, String)], include_frontmatter: bool) -> String {
+    let mut md = String::new();
+    if include_frontmatter {
+        let tags: BTreeSet<String> = notes.iter().flat_map(|(m, _)| m.tags.iter().cloned()).collect();
+        let tags: Vec<String> = tags.into_iter().collect();
+        let updated = notes
+            .iter()
+            .map(|(m, _)| m.updated_at.as_str())
+            .max()
+            .unwrap_or(&notebook.created_at);
+        md.push_str(&markdown_frontmatter(&tags, &notebook.created_at, updated));
+    }
+    let mut used: HashMap<String, usize> = HashMap::new();
+    heading_anchor(&notebook.name, &mut used);
+    md.push_str(&format!("# {}\n\n", notebook.name));
+    if notes.is_empty() {
+        return md;
+    }
+    heading_anchor("Contents", &mut used);
+    let anchors: Vec<String> = notes.iter().map(|(m, _)| heading_anchor(&m.title, &mut used)).collect();
+    md.push_str("## Contents\n\n");
+    for ((meta, _), anchor) in notes.iter().zip(&anchors) {
+        md.push_str(&format!("- [{}](#{})\n", meta.title, anchor));
+    }
+    for (i, (meta, body)) in notes.iter().enumerate() {
+        md.push_str(if i == 0 { "\n" } else { "\n---\n\n" });
+        md.push_str(&format!("## {}\n\n", meta.title));
+        md.push_str(body);
+        if !body.ends_with('\n') {
+            md.push('\n');
+        }
+    }
+    md
+}
+
+/// Export all notes in a notebook (oldest first) as one combined Markdown document.
+pub fn export_notebook_as_markdown(
+    app_handle: &tauri::AppHandle,
+    notebook_id: &str,
+    include_frontmatter: bool,
+) -> Result<String, String> {
+    validate_notebook_id(notebook_id)?;
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let index = read_index(&root)?;
+    let notebook = index
+        .notebooks
+        .iter()
+        .find(|nb| nb.id == notebook_id)
+        .ok_or("Notebook not found")?;
+    let mut members: Vec<&NoteMeta> = index
+        .notes
+        .iter()
+        .filter(|n| n.notebook_id.as_deref() == Some(notebook_id))
+        .collect();
+    members.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+    let notes: Vec<(NoteMeta, String)> = members
+        .into_iter()
+        .map(|m| {
+            let body = fs::read_to_string(note_path_with_ext(&root, &m.id, &ext)).unwrap_or_default();
+            (m.clone(), body)
+        })
+        .collect();
+    Ok(render_notebook_markdown(notebook, &notes, include_frontmatter))
+}
+
+/// `<base><ext>`, or `<base> (2)<ext>`, `<base> (3)<ext>`… for the first name `taken` rejects.
+pub fn dedupe_filename(base: &str, ext: &str, taken: impl Fn(&str) -> bool) -> String {
+    let name = format!("{}{}", base, ext);
+    if !taken(&name) {
+        return name;
+    }
+    let mut i = 2;
+    loop {
+        let name = format!("{} ({}){}", base, i, ext);
+        if !taken(&name) {
+            return name;
+        }
+        i += 1;
+    }
+}
+
+/// Export notes as `<title>.md` files into target_dir, copying their images into `images/` next to them
+/// (body paths are rewritten to match). Existing files are never overwritten; names get a numeric suffix.
+/// Returns the paths of the written Markdown files.
+pub fn export_notes_to_folder(
+    app_handle: &tauri::AppHandle,
+    note_ids: &[String],
+    target_dir: &str,
+) -> Result<Vec<String>, String> {
+    for id in note_ids {
+        validate_note_id(id)?;
+    }
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let index = read_index(&root)?;
+    let target = PathBuf::from(target_dir);
+    fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+    let images_target = target.join("images");
+    let mut used: HashSet<String> = HashSet::new();
+    let mut used_images: HashSet<String> = HashSet::new();
+    let mut written = vec![];
+    for id in note_ids {
+        let meta = index.notes.iter().find(|n| n.id == *id).ok_or("Note not found")?;
+        let mut body = fs::read_to_string(note_path_with_ext(&root, id, &ext)).unwrap_or_default();
+        for img in &meta.images {
+            let src = root.join(&img.path);
+            if !src.is_file() {
+                continue;
+            }
+            let file_name = Path::new(&img.path)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(sanitize_filename)
+                .unwrap_or_else(|| sanitize_filename(&img.name));
+            let (stem, ext) = match file_name.rfind('.') {
+                Some(dot) if dot > 0 => (&file_name[..dot], &file_name[dot..]),
+                _ => (file_name.as_str(), ""),
+            };
+            let name = dedupe_filename(stem, ext, |n| {
+                used_images.contains(&n.to_lowercase()) || images_target.join(n).exists()
+            });
+            fs::create_dir_all(&images_target).map_err(|e| e.to_string())?;
+            fs::copy(&src, images_target.join(&name)).map_err(|e| e.to_string())?;
+            body = body.replace(&img.path, &format!("images/{}", name));
+            used_images.insert(name.to_lowercase());
+        }
+        let base = sanitize_filename(&meta.title);
+        let base = if base.is_empty() { "Untitled".to_string() } else { base };
+        let name = dedupe_filename(&base, ".md", |n| used.contains(&n.to_lowercase()) || target.join(n).exists());
+        let path = target.join(&name);
+        fs::write(&path, note_to_markdown(meta, &body)).map_err(|e| e.to_string())?;
+        used.insert(name.to_lowercase());
+        written.push(path.to_string_lossy().to_string());
+    }
+    Ok(written)
+}
+
+/// A Markdown file split into what import needs. `created_at` is only set for a valid RFC3339 `created:`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownImport {
+    pub title: String,
+    pub body: String,
+    pub tags: Vec<String>,
+    pub created_at: Option<String>,
+}
+
+/// Split a leading `---` YAML block off `text`. Returns (frontmatter lines, rest) or None if there is none.
+fn split_frontmatter(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn unquote_yaml(value: &str) -> &str {
+    let v = value.trim();
+    if v.len() >= 2 && ((v.starts_with('"') && v.ends_with('"')) || (v.starts_with('\'') && v.ends_with('\''))) {
+        &v[1..v.len() - 1]
+    } else {
+        v
+    }
+}
+
+/// Frontmatter tag as a note tag: optional leading '#', then tag characters only.
+fn frontmatter_tag(value: &str) -> Option<String> {
+    let t = unquote_yaml(value).trim_start_matches('#');
+    let t = clean_nested_tag(t);
+    if !t.is_empty() && t.chars().all(is_tag_char) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Fields read from a frontmatter block (see parse_frontmatter).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frontmatter {
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: Option<String>,
+}
+
+/// Read the keys export_note_as_markdown writes (`tags:` block list, `created:`, `updated:`) plus
+/// `title:` and inline `tags: [a, b]` from the lines between the `---` markers; anything else is ignored.
+fn parse_frontmatter(block: &str) -> Frontmatter {
+    let mut fm = Frontmatter::default();
+    let mut in_tags = false;
+    for line in block.lines() {
+        let trimmed = line.trim();
+        if in_tags {
+            if let Some(item) = trimmed.strip_prefix("- ") {
+                fm.tags.extend(frontmatter_tag(item));
+                continue;
+            }
+            in_tags = false;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match key.trim() {
+            "title" if !unquote_yaml(value).is_empty() => fm.title = Some(unquote_yaml(value).to_string()),
+            "tags" if value.is_empty() => in_tags = true,
+            "tags" => {
+                let list = value.trim_start_matches('[').trim_end_matches(']');
+                fm.tags.extend(list.split(',').filter_map(frontmatter_tag));
+            }
+            "created" => {
+                let value = unquote_yaml(value);
+                if chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+                    fm.created_at = Some(value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    fm
+}
+
+/// True if a `---` block is metadata rather than body text that happens to sit between two rules:
+/// every non-blank line is `key: value` or a `- item`, and at least one key is one we write or read.
+fn is_note_frontmatter(block: &str) -> bool {
+    let mut known_key = false;
+    for line in block.lines().filter(|l| !l.trim().is_empty()) {
+        if line.trim_start().starts_with("- ") {
+            continue;
+        }
+        let Some((key, _)) = line.split_once(':') else { return false };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return false;
+        }
+        known_key |= matches!(key, "title" | "tags" | "created" | "updated");
+    }
+    known_key
+}
+
+/// Split a leading frontmatter block off a body being saved, if is_note_frontmatter accepts it.
+/// Blank lines after the block are dropped too. Keys we don't read (with their list items) stay at the
+/// top of the body in a `---` block of their own; it has no key we know, so later saves leave it be.
+pub fn strip_note_frontmatter(body: &str) -> (Frontmatter, String) {
+    match split_frontmatter(body) {
+        Some((block, rest)) if is_note_frontmatter(block) => {
+            let rest = rest.trim_start_matches(['\r', '\n']);
+            let unknown = unknown_frontmatter_lines(block);
+            let body = if unknown.is_empty() { rest.to_string() } else { format!("---\n{}---\n{}", unknown, rest) };
+            (parse_frontmatter(block), body)
+        }
+        _ => (Frontmatter::default(), body.to_string()),
+    }
+}
+
+/// Lines of a frontmatter block whose key is not one is_note_frontmatter knows, each with the
+/// `- item` / indented lines that follow it.
+fn unknown_frontmatter_lines(block: &str) -> String {
+    let mut out = String::new();
+    let mut keep = false;
+    for line in block.split_inclusive('\n') {
+        let continues = line.starts_with([' ', '\t']) || line.trim_start().starts_with("- ") || line.trim().is_empty();
+        if !continues {
+            let key = line.split_once(':').map(|(k, _)| k.trim()).unwrap_or("");
+            keep = !matches!(key, "title" | "tags" | "created" | "updated");
+        }
+        if keep {
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Parse a Markdown/text file for import. Understands the frontmatter export_note_as_markdown writes
+/// (see parse_frontmatter). Title order: frontmatter `title:`, then a leading `# Title` line (stripped
+/// from the body, as the exporter writes it), then, for files without frontmatter, the first heading
+/// anywhere (left in the body), then `fallback_title` (the file name).
+pub fn parse_markdown_import(text: &str, fallback_title: &str) -> MarkdownImport {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let (fm, body, has_frontmatter) = match split_frontmatter(text) {
+        Some((frontmatter, rest)) => (parse_frontmatter(frontmatter), rest.trim_start_matches(['\r', '\n']), true),
+        None => (Frontmatter::default(), text, false),
+    };
+    let Frontmatter {
+        mut title,
+        mut tags,
+        created_at,
+    } = fm;
+    let first_line = body.lines().next().unwrap_or("");
+    let body = match first_line.strip_prefix("# ").map(str::trim) {
+        Some(heading) if !heading.is_empty() && !matches!(title.as_deref(), Some(t) if t != heading) => {
+            title = Some(heading.to_string());
+            body[first_line.len()..].trim_start_matches(['\r', '\n'])
+        }
+        _ => body,
+    };
+    if title.is_none() && !has_frontmatter {
+        title = body
+            .lines()
+            .filter_map(|l| l.trim_start_matches('#').strip_prefix(' ').filter(|_| l.starts_with('#')))
+            .map(str::trim)
+            .find(|h| !h.is_empty())
+            .map(str::to_string);
+    }
+    let mut seen = HashSet::new();
+    tags.retain(|t| seen.insert(t.clone()));
+    MarkdownImport {
+        title: title.unwrap_or_else(|| fallback_title.to_string()),
+        body: body.to_string(),
+        tags,
+        created_at,
+    }
+}
+
+/// Create a note from a parsed import via save_note, adding the frontmatter tags (body #tags are
+/// picked up by save_note) and keeping the original created date in the same index write.
+fn create_note_from_import(app_handle: &tauri::AppHandle, import: &MarkdownImport) -> Result<NoteMeta, String> {
+    let lowercase_tags = read_settings(&storage_root(app_handle)?)?.lowercase_tags;
+    let on_create = |n: &mut NoteMeta| {
+        for t in &import.tags {
+            let t = normalize_tag(t, lowercase_tags);
+            if !n.tags.iter().any(|existing| tag_key(existing) == tag_key(&t)) {
+                n.tags.push(t);
+            }
+        }
+        n.tags.sort();
+        if let Some(created) = &import.created_at {
+            n.created_at = created.clone();
+        }
+    };
+    save_note_with(app_handle, None, &import.title, &import.body, on_create).map(|r| r.meta)
+}
+
+/// Import every `.md`/`.txt` file directly inside source_dir as a new note, in file name order.
+/// Files that cannot be read (e.g. not UTF-8) are skipped and listed in `failed`.
+pub fn import_markdown_folder(app_handle: &tauri::AppHandle, source_dir: &str) -> Result<MarkdownImportResult, String> {
+    let source = Path::new(source_dir);
+    if !source.is_dir() {
+        return Err("Source folder does not exist".into());
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(source)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("txt"))
+                    .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+    let mut result = MarkdownImportResult::default();
+    for path in files {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let text = match fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) => {
+                result.failed.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let import = parse_markdown_import(&text, &stem);
+        result.created.push(create_note_from_import(app_handle, &import)?);
+    }
+    Ok(result)
+}
+
+/// Import one Markdown/text file (e.g. dropped on the window) as a new note.
+pub fn import_markdown_file(app_handle: &tauri::AppHandle, path: &str) -> Result<NoteMeta, String> {
+    let path = Path::new(path);
+    if !path.is_file() {
+        return Err("File does not exist".into());
+    }
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    create_note_from_import(app_handle, &parse_markdown_import(&text, &stem))
+}
+
+/// Escape text for HTML element content and attribute values.
+pub fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// file:// URL for an absolute path (forward slashes; spaces and URL delimiters percent-encoded).
+pub fn file_url(path: &Path) -> String {
+    let mut p = path.to_string_lossy().replace('\\', "/");
+    if !p.starts_with('/') {
+        // Windows drive path (C:/...)
+        p.insert(0, '/');
+    }
+    let mut out = String::from("file://");
+    for c in p.chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '#' => out.push_str("%23"),
+            '?' => out.push_str("%3F"),
+            '%' => out.push_str("%25"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+const HTML_EXPORT_STYLE: &str = "body{font-family:-apple-system,BlinkMacSystemFont,\"Segoe UI\",sans-serif;\
+max-width:46rem;margin:2rem auto;padding:0 1rem;line-height:1.6;color:#222}\
+pre{background:#f5f5f5;padding:.75rem;overflow-x:auto}code{font-family:ui-monospace,monospace}\
+table{border-collapse:collapse}th,td{border:1px solid #ccc;padding:.25rem .5rem}\
+img{max-width:100%}blockquote{border-left:3px solid #ccc;margin-left:0;padding-left:1rem;color:#555}";
+
+/// Body with `[[Title]]` links reduced to their plain text, for rendered exports.
+fn flatten_wikilinks(body: &str) -> String {
+    let mut text = String::with_capacity(body.len());
+    let mut last = 0;
+    for span in wikilink_spans(body) {
+        if span.closed {
+            text.push_str(&body[last..span.start]);
+            text.push_str(span.inner.trim());
+            last = span.end;
+        }
+    }
+    text.push_str(&body[last..]);
+    text
+}
+
+/// Markdown extensions enabled for rendered exports (HTML, PDF).
+fn export_markdown_options() -> pulldown_cmark::Options {
+    use pulldown_cmark::Options;
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options
+}
+
+/// Render a note as a standalone HTML document (GFM tables, task lists, strikethrough, code fences).
+/// `[[Title]]` links become plain text; `images/...` URLs are passed through `image_url` for rewriting.
+pub fn render_note_html(title: &str, body: &str, image_url: impl Fn(&str) -> Option<String>) -> String {
+    use pulldown_cmark::{CowStr, Event, Parser, Tag};
+
+    let text = flatten_wikilinks(body);
+    let options = export_markdown_options();
+    let rewrite = |url: CowStr<'static>| -> CowStr<'static> {
+        if url.starts_with("images/") {
+            if let Some(abs) = image_url(&url) {
+                return abs.into();
+            }
+        }
+        url
+    };
+    let events = Parser::new_ext(&text, options).map(|event| match event {
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: rewrite(dest_url.into_static()),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: rewrite(dest_url.into_static()),
+            title,
+            id,
+        }),
+        other => other,
+    });
+    let mut content = String::new();
+    pulldown_cmark::html::push_html(&mut content, events);
+
+    let title = html_escape(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{HTML_EXPORT_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n{content}</body>\n</html>\n"
+    )
+}
+
+/// Export note as a standalone HTML document; image paths become absolute file:// URLs, or `data:` URIs
+/// with `inline_images` (files over MAX_INLINE_IMAGE_BYTES or not images still get file:// URLs).
+pub fn export_note_as_html(app_handle: &tauri::AppHandle, note_id: &str, inline_images: bool) -> Result<String, String> {
+    let content = read_note_uncounted(app_handle, note_id)?;
+    Ok(render_note_html(&content.meta.title, &content.body, |rel| {
+        let path = resolve_image_path(app_handle, rel).ok()?;
+        inline_images
+            .then(|| inline_image_data_url(&path))
+            .flatten()
+            .or_else(|| Some(file_url(&path)))
+    }))
+}
+
+/// List item marker in a PDF export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdfListMarker {
+    Bullet,
+    Number(u64),
+    Task(bool),
+}
+
+/// Block-level layout unit for the PDF export, flattened from the Markdown body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdfBlock {
+    Heading { level: u8, text: String },
+    /// `indent` is the blockquote depth.
+    Paragraph { text: String, indent: u8 },
+    ListItem { depth: u8, marker: PdfListMarker, text: String },
+    Code(String),
+    Image { url: String, alt: String },
+    Rule,
+}
+
+/// Flatten a note body into PDF blocks using the same Markdown dialect as the HTML export.
+/// Inline formatting is dropped; table rows become paragraphs with cells joined by " | ".
+pub fn markdown_pdf_blocks(body: &str) -> Vec<PdfBlock> {
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+    enum Pending {
+        None,
+        Heading(u8),
+        Paragraph,
+        Item(u8, PdfListMarker),
+    }
+
+    let text = flatten_wikilinks(body);
+    let mut blocks = vec![];
+    let mut pending = Pending::None;
+    let mut buf = String::new();
+    let mut lists: Vec<Option<u64>> = vec![];
+    let mut quote_depth: u8 = 0;
+    let mut code: Option<String> = None;
+    let mut image: Option<(String, String)> = None;
+
+    let flush = |pending: &mut Pending, buf: &mut String, blocks: &mut Vec<PdfBlock>, quote_depth: u8| {
+        let text = buf.trim().to_string();
+        buf.clear();
+        match std::mem::replace(pending, Pending::None) {
+            Pending::Heading(level) if !text.is_empty() => blocks.push(PdfBlock::Heading { level, text }),
+            Pending::Item(depth, marker) => blocks.push(PdfBlock::ListItem { depth, marker, text }),
+            Pending::Paragraph | Pending::None if !text.is_empty() => {
+                blocks.push(PdfBlock::Paragraph { text, indent: quote_depth })
+            }
+            _ => {}
+        }
+    };
+
+    for event in Parser::new_ext(&text, export_markdown_options()) {
+        if let Some((_, alt)) = image.as_mut() {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    let (url, alt) = image.take().unwrap();
+                    blocks.push(PdfBlock::Image { url, alt });
+                }
+                Event::Text(t) | Event::Code(t) => alt.push_str(&t),
+                _ => {}
+            }
+            continue;
+        }
+        if let Some(code_text) = code.as_mut() {
+            match event {
+                Event::End(TagEnd::CodeBlock) => blocks.push(PdfBlock::Code(code.take().unwrap())),
+                Event::Text(t) => code_text.push_str(&t),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                pending = Pending::Heading(level as u8);
+            }
+            // A loose list item wraps its text in a paragraph; keep it on the item.
+            Event::Start(Tag::Paragraph) if !(matches!(pending, Pending::Item(..)) && buf.trim().is_empty()) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                pending = Pending::Paragraph;
+            }
+            Event::Start(Tag::List(start)) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                let depth = lists.len().saturating_sub(1) as u8;
+                let marker = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        PdfListMarker::Number(*n - 1)
+                    }
+                    _ => PdfListMarker::Bullet,
+                };
+                pending = Pending::Item(depth, marker);
+            }
+            Event::TaskListMarker(checked) => {
+                if let Pending::Item(_, marker) = &mut pending {
+                    *marker = PdfListMarker::Task(checked);
+                }
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                quote_depth += 1;
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                quote_depth = quote_depth.saturating_sub(1);
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                code = Some(String::new());
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                image = Some((dest_url.to_string(), String::new()));
+            }
+            Event::Start(Tag::TableCell) if !buf.is_empty() => buf.push_str(" | "),
+            Event::End(TagEnd::Heading(_) | TagEnd::Paragraph | TagEnd::Item | TagEnd::TableHead | TagEnd::TableRow) => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth)
+            }
+            Event::Rule => {
+                flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+                blocks.push(PdfBlock::Rule);
+            }
+            Event::Text(t) | Event::Code(t) => buf.push_str(&t),
+            Event::SoftBreak => buf.push(' '),
+            Event::HardBreak => buf.push('\n'),
+            _ => {}
+        }
+    }
+    flush(&mut pending, &mut buf, &mut blocks, quote_depth);
+    blocks
+}
+
+/// Greedy word wrap to at most `max_chars` per line; words longer than a line are split.
+/// Explicit newlines are kept.
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while !word.is_empty() {
+                let sep = usize::from(len > 0);
+                if len + sep + word.len() <= max_chars {
+                    if sep == 1 {
+                        line.push(' ');
+                    }
+                    line.extend(word.iter());
+                    len += sep + word.len();
+                    break;
+                }
+                if len > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    len = 0;
+                    continue;
+                }
+                let rest = word.split_off(max_chars.min(word.len()));
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Width and height (px) of a JPEG plus its component count, read from the SOF segment.
+fn jpeg_dimensions(data: &[u8]) -> Option<(usize, usize, u8)> {
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            i += 1;
+            continue;
+        }
+        let marker = data[i + 1];
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as usize;
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as usize;
+            return Some((width, height, data[i + 9]));
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+/// Load a PNG or JPEG as a PDF image. JPEGs are embedded as-is; PNGs are decoded to 8-bit
+/// RGB/grey with any alpha flattened onto white. Other formats return None.
+fn pdf_image(path: &Path) -> Option<printpdf::ImageXObject> {
+    use printpdf::{ColorBits, ColorSpace, ImageFilter, ImageXObject, Px};
+
+    let data = fs::read(path).ok()?;
+    let (width, height, color_space, image_data, image_filter) = match detect_image_mime(&data)? {
+        "image/jpeg" => {
+            let (w, h, components) = jpeg_dimensions(&data)?;
+            let color_space = match components {
+                1 => ColorSpace::Greyscale,
+                3 => ColorSpace::Rgb,
+                4 => ColorSpace::Cmyk,
+                _ => return None,
+            };
+            (w, h, color_space, data, Some(ImageFilter::DCT))
+        }
+        "image/png" => {
+            let mut decoder = png::Decoder::new(data.as_slice());
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info().ok()?;
+            let mut buf = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buf).ok()?;
+            buf.truncate(info.buffer_size());
+            let over_white = |c: u8, a: u8| ((c as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+            let (color_space, pixels) = match info.color_type {
+                png::ColorType::Rgb => (ColorSpace::Rgb, buf),
+                png::ColorType::Grayscale => (ColorSpace::Greyscale, buf),
+                png::ColorType::Rgba => (
+                    ColorSpace::Rgb,
+                    buf.chunks_exact(4).flat_map(|p| [0, 1, 2].map(|i| over_white(p[i], p[3]))).collect(),
+                ),
+                png::ColorType::GrayscaleAlpha => {
+                    (ColorSpace::Greyscale, buf.chunks_exact(2).map(|p| over_white(p[0], p[1])).collect())
+                }
+                png::ColorType::Indexed => return None,
+            };
+            (info.width as usize, info.height as usize, color_space, pixels, None)
+        }
+        _ => return None,
+    };
+    Some(ImageXObject {
+        width: Px(width),
+        height: Px(height),
+        color_space,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data,
+        image_filter,
+        smask: None,
+        clipping_bbox: None,
+    })
+}
+
+const PDF_PAGE_WIDTH_MM: f32 = 210.0;
+const PDF_PAGE_HEIGHT_MM: f32 = 297.0;
+const PDF_MARGIN_MM: f32 = 20.0;
+const PT_TO_MM: f32 = 25.4 / 72.0;
+
+/// Page cursor for render_note_pdf: starts a new A4 page when the next line does not fit.
+struct PdfCursor {
+    doc: printpdf::PdfDocumentReference,
+    layer: printpdf::PdfLayerReference,
+    y: f32,
+}
+
+impl PdfCursor {
+    fn ensure_space(&mut self, height_mm: f32) {
+        if self.y - height_mm < PDF_MARGIN_MM {
+            let (page, layer) = self.doc.add_page(
+                printpdf::Mm(PDF_PAGE_WIDTH_MM),
+                printpdf::Mm(PDF_PAGE_HEIGHT_MM),
+                "Layer 1",
+            );
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PDF_PAGE_HEIGHT_MM - PDF_MARGIN_MM;
+        }
+    }
+
+    /// Characters per line at x (mm) for a font whose average advance is `char_width` em.
+    fn max_chars(size: f32, x: f32, char_width: f32) -> usize {
+        let width_pt = (PDF_PAGE_WIDTH_MM - PDF_MARGIN_MM - x) / PT_TO_MM;
+        (width_pt / (size * char_width)) as usize
+    }
+
+    /// Write word-wrapped text at x (mm).
+    fn text(&mut self, text: &str, font: &printpdf::IndirectFontRef, size: f32, x: f32, char_width: f32) {
+        let lines = wrap_text(text, Self::max_chars(size, x, char_width));
+        self.lines(lines, font, size, x);
+    }
+
+    /// Write lines as given, one per row, at x (mm).
+    fn lines(&mut self, lines: Vec<String>, font: &printpdf::IndirectFontRef, size: f32, x: f32) {
+        let line_height = size * 1.4 * PT_TO_MM;
+        for line in lines {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            self.layer.use_text(line, size, printpdf::Mm(x), printpdf::Mm(self.y + line_height * 0.25), font);
+        }
+    }
+
+    fn line(&self, from: (f32, f32), to: (f32, f32)) {
+        let point = |(x, y): (f32, f32)| (printpdf::Point::new(printpdf::Mm(x), printpdf::Mm(y)), false);
+        self.layer.add_line(printpdf::Line { points: vec![point(from), point(to)], is_closed: false });
+    }
+}
+
+/// Render a note to PDF bytes (A4, built-in Helvetica/Courier). Headings, paragraphs, lists, task
+/// checkboxes, blockquotes, code blocks and rules are laid out; PNG/JPEG images are embedded when
+/// `image_path` resolves their URL, other images are replaced by their alt text.
+pub fn render_note_pdf(title: &str, body: &str, image_path: impl Fn(&str) -> Option<PathBuf>) -> Result<Vec<u8>, String> {
+    use printpdf::{BuiltinFont, Image, ImageTransform, Mm, PdfDocument};
+
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PDF_PAGE_WIDTH_MM), Mm(PDF_PAGE_HEIGHT_MM), "Layer 1");
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| e.to_string())?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(|e| e.to_string())?;
+    let mono = doc.add_builtin_font(BuiltinFont::Courier).map_err(|e| e.to_string())?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let mut cur = PdfCursor { doc, layer, y: PDF_PAGE_HEIGHT_MM - PDF_MARGIN_MM };
+    let content_width = PDF_PAGE_WIDTH_MM - 2.0 * PDF_MARGIN_MM;
+
+    cur.text(title, &bold, 22.0, PDF_MARGIN_MM, 0.55);
+    cur.y -= 4.0;
+    for block in markdown_pdf_blocks(body) {
+        match block {
+            PdfBlock::Heading { level, text } => {
+                let size = match level {
+                    1 => 18.0,
+                    2 => 15.0,
+                    3 => 13.0,
+                    _ => 11.0,
+                };
+                cur.y -= 3.0;
+                cur.text(&text, &bold, size, PDF_MARGIN_MM, 0.55);
+                cur.y -= 1.0;
+            }
+            PdfBlock::Paragraph { text, indent } => {
+                cur.text(&text, &regular, 11.0, PDF_MARGIN_MM + 6.0 * indent as f32, 0.5);
+                cur.y -= 2.0;
+            }
+            PdfBlock::ListItem { depth, marker, text } => {
+                let x = PDF_MARGIN_MM + 6.0 * depth as f32;
+                cur.ensure_space(11.0 * 1.4 * PT_TO_MM);
+                let top = cur.y;
+                match marker {
+                    PdfListMarker::Bullet => cur.layer.use_text("\u{2022}", 11.0, Mm(x + 1.0), Mm(top - 4.0), &regular),
+                    PdfListMarker::Number(n) => cur.layer.use_text(format!("{}.", n), 11.0, Mm(x), Mm(top - 4.0), &regular),
+                    PdfListMarker::Task(checked) => {
+                        let (x0, y0, s) = (x + 0.5, top - 4.2, 3.2);
+                        cur.line((x0, y0), (x0 + s, y0));
+                        cur.line((x0 + s, y0), (x0 + s, y0 + s));
+                        cur.line((x0 + s, y0 + s), (x0, y0 + s));
+                        cur.line((x0, y0 + s), (x0, y0));
+                        if checked {
+                            cur.line((x0 + 0.6, y0 + 1.7), (x0 + 1.3, y0 + 0.6));
+                            cur.line((x0 + 1.3, y0 + 0.6), (x0 + 2.7, y0 + 2.7));
+                        }
+                    }
+                }
+                cur.text(&text, &regular, 11.0, x + 6.0, 0.5);
+                cur.y -= 1.0;
+            }
+            PdfBlock::Code(text) => {
+                // Keep indentation; long lines are cut rather than word-wrapped.
+                let max = PdfCursor::max_chars(9.5, PDF_MARGIN_MM + 4.0, 0.6).max(1);
+                let lines = text
+                    .trim_end_matches('\n')
+                    .lines()
+                    .flat_map(|l| {
+                        let chars: Vec<char> = l.chars().collect();
+                        if chars.is_empty() {
+                            vec![String::new()]
+                        } else {
+                            chars.chunks(max).map(|c| c.iter().collect()).collect()
+                        }
+                    })
+                    .collect();
+                cur.lines(lines, &mono, 9.5, PDF_MARGIN_MM + 4.0);
+                cur.y -= 2.0;
+            }
+            PdfBlock::Rule => {
+                cur.ensure_space(4.0);
+                cur.y -= 2.0;
+                cur.line((PDF_MARGIN_MM, cur.y), (PDF_MARGIN_MM + content_width, cur.y));
+                cur.y -= 2.0;
+            }
+            PdfBlock::Image { url, alt } => match image_path(&url).as_deref().and_then(pdf_image) {
+                Some(img) => {
+                    // Natural size at 96 dpi, scaled down to fit the content box.
+                    let (w_mm, h_mm) = (img.width.0 as f32 * 25.4 / 96.0, img.height.0 as f32 * 25.4 / 96.0);
+                    let max_h = PDF_PAGE_HEIGHT_MM - 2.0 * PDF_MARGIN_MM;
+                    let scale = (content_width / w_mm).min(max_h / h_mm).min(1.0);
+                    cur.ensure_space(h_mm * scale);
+                    cur.y -= h_mm * scale;
+                    Image::from(img).add_to_layer(
+                        cur.layer.clone(),
+                        ImageTransform {
+                            translate_x: Some(Mm(PDF_MARGIN_MM)),
+                            translate_y: Some(Mm(cur.y)),
+                            scale_x: Some(scale),
+                            scale_y: Some(scale),
+                            dpi: Some(96.0),
+                            ..Default::default()
+                        },
+                    );
+                    cur.y -= 2.0;
+                }
+                None => {
+                    let label = if alt.trim().is_empty() { url } else { alt };
+                    cur.text(&format!("[image: {}]", label), &regular, 11.0, PDF_MARGIN_MM, 0.5);
+                    cur.y -= 2.0;
+                }
+            },
+        }
+    }
+    cur.doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+/// Export a note as a PDF file at target_path; attached images are resolved from storage.
+pub fn export_note_as_pdf(app_handle: &tauri::AppHandle, note_id: &str, target_path: &str) -> Result<(), String> {
+    let content = read_note_uncounted(app_handle, note_id)?;
+    let pdf = render_note_pdf(&content.meta.title, &content.body, |url| {
+        if url.starts_with("images/") {
+            resolve_image_path(app_handle, url).ok()
+        } else {
+            None
+        }
+    })?;
+    fs::write(target_path, pdf).map_err(|e| e.to_string())
+}
+
+/// Write text to a file at the given path (e.g. user-chosen save path from dialog).
+pub fn write_text_file(path: &str, content: &str) -> Result<(), String> {
+    let p = Path::new(path);
+    if p.exists() && p.is_dir() {
+        return Err("Path is a directory".into());
+    }
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(p, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Quote a CSV field when it contains a comma, quote, or line break (RFC 4180).
+pub fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Export one CSV row per (note, tag) pair: note_id, note_title, tag, created_at, updated_at.
+/// Returns the number of data rows written (header excluded).
+pub fn export_tags_as_csv(app_handle: &tauri::AppHandle, target_path: &str) -> Result<usize, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let mut csv = String::from("note_id,note_title,tag,created_at,updated_at\n");
+    let mut rows = 0;
+    for n in &index.notes {
+        for t in &n.tags {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                csv_escape(&n.id),
+                csv_escape(&n.title),
+                csv_escape(t),
+                csv_escape(&n.created_at),
+                csv_escape(&n.updated_at)
+            ));
+            rows += 1;
+        }
+    }
+    write_text_file(target_path, &csv)?;
+    Ok(rows)
+}
+
+/// Built-in note templates.
+fn builtin_templates() -> Vec<NoteTemplate> {
+    vec![
+        NoteTemplate {
+            id: "daily-journal".to_string(),
+            name: "Daily journal".to_string(),
+            body: "# Daily Journal — {{date}}\n\n## What happened today\n- \n\n## Thoughts & reflections\n- \n\n## Tomorrow\n- \n".to_string(),
+            default_title_pattern: Some("Journal {{date}}".to_string()),
+            is_custom: false,
+        },
+        NoteTemplate {
+            id: "meeting-notes".to_string(),
+            name: "Meeting notes".to_string(),
+            body: "# Meeting: {{title}}\n\n**Date:** {{date}}\n**Attendees:** \n**Agenda:**\n- \n\n**Notes:**\n- \n\n**Action items:**\n- [ ] \n- [ ] \n".to_string(),
+            default_title_pattern: Some("Meeting {{date}}".to_string()),
+            is_custom: false,
+        },
+        NoteTemplate {
+            id: "project-planning".to_string(),
+            name: "Project planning".to_string(),
+            body: "# Project: {{title}}\n\n## Overview\n- **Goal:** \n- **Timeline:** \n\n## Tasks\n- [ ] \n- [ ] \n\n## Notes\n- \n".to_string(),
+            default_title_pattern: Some("Project".to_string()),
+            is_custom: false,
+        },
+    ]
+}
+
+fn read_custom_templates(root: &Path) -> Result<Vec<NoteTemplate>, String> {
+    let path = templates_path(root);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut f = fs::File::open(&path).map_err(|e| e.to_string())?;
+    let mut s = String::new();
+    f.read_to_string(&mut s).map_err(|e| e.to_string())?;
+    serde_json::from_str(&s).map_err(|e| e.to_string())
+}
+
+fn write_custom_templates(root: &Path, templates: &[NoteTemplate]) -> Result<(), String> {
+    let path = templates_path(root);
+    let json = serde_json::to_string_pretty(templates).map_err(|e| e.to_string())?;
+    atomic_write(&path, json.as_bytes())
+}
+
+/// Replace `{{name}}` tokens in `text`; tokens `lookup` does not know are left as written.
+fn replace_placeholders(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else { break };
+        let name = rest[start + 2..start + 2 + len].trim();
+        out.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + len + 4]),
+        }
+        rest = &rest[start + len + 4..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Fill template placeholders in the title, then the body: {{date}}, {{time}} (HH:MM), {{datetime}},
+/// {{weekday}}, {{year}}, {{uuid}} (one per note), {{title}} (body only, the finished title), plus any
+/// `vars`, which take precedence. `now` carries the offset whose calendar date and clock are used.
+/// Unknown tokens are left intact.
+pub fn apply_template_placeholders(
+    body: &str,
+    title: &str,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    vars: &HashMap<String, String>,
+) -> (String, String) {
+    let uuid = Uuid::new_v4().to_string();
+    let builtin = |name: &str| -> Option<String> {
+        if let Some(value) = vars.get(name) {
+            return Some(value.clone());
+        }
+        let format = match name {
+            "date" => "%Y-%m-%d",
+            "time" => "%H:%M",
+            "datetime" => "%Y-%m-%d %H:%M",
+            "weekday" => "%A",
+            "year" => "%Y",
+            "uuid" => return Some(uuid.clone()),
+            _ => return None,
+        };
+        Some(now.format(format).to_string())
+    };
+    let title_out = replace_placeholders(title, |name| if name == "title" { None } else { builtin(name) });
+    let body_out = replace_placeholders(body, |name| {
+        if name == "title" && !vars.contains_key(name) {
+            Some(title_out.clone())
+        } else {
+            builtin(name)
+        }
+    });
+    (body_out, title_out)
+}
+
+/// List all templates (built-in + custom).
+pub fn list_templates(app_handle: &tauri::AppHandle) -> Result<Vec<NoteTemplate>, String> {
+    let root = storage_root(app_handle)?;
+    let custom = read_custom_templates(&root)?;
+    let mut out = builtin_templates();
+    out.extend(custom);
+    Ok(out)
+}
+
+/// Create a new note from a template. title_override: if provided, use it; else use template default with placeholders.
+/// `vars` fill custom `{{name}}` tokens in the title and body.
+pub fn create_note_from_template(
+    app_handle: &tauri::AppHandle,
+    template_id: &str,
+    title_override: Option<&str>,
+    vars: &HashMap<String, String>,
+) -> Result<NoteMeta, String> {
+    let root = storage_root(app_handle)?;
+    let builtin = builtin_templates();
+    let custom = read_custom_templates(&root)?;
+    let template = builtin
+        .into_iter()
+        .chain(custom)
+        .find(|t| t.id == template_id)
+        .ok_or_else(|| "Template not found".to_string())?;
+
+    let default_title = template
+        .default_title_pattern
+        .as_deref()
+        .unwrap_or("Untitled");
+    let title_input = title_override.unwrap_or(default_title).trim();
+    let title_input = if title_input.is_empty() { "Untitled" } else { title_input };
+    let (body, title) = apply_template_placeholders(&template.body, title_input, user_now(&read_settings(&root)?), vars);
+    let template_id = template.id.clone();
+    save_note_with(app_handle, None, &title, &body, |n| n.created_from_template_id = Some(template_id)).map(|r| r.meta)
+}
+
+/// List all templates with how many existing notes were created from each.
+pub fn list_templates_with_usage(app_handle: &tauri::AppHandle) -> Result<Vec<TemplateInfo>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let templates = list_templates(app_handle)?;
+    Ok(templates
+        .into_iter()
+        .map(|t| {
+            let notes_created = index
+                .notes
+                .iter()
+                .filter(|n| n.created_from_template_id.as_deref() == Some(t.id.as_str()))
+                .count();
+            TemplateInfo {
+                is_builtin: !t.is_custom,
+                notes_created,
+                template: t,
+            }
+        })
+        .collect())
+}
+
+/// Save a custom template (creates new with id custom-<uuid>).
+pub fn save_custom_template(
+    app_handle: &tauri::AppHandle,
+    name: &str,
+    body: &str,
+) -> Result<NoteTemplate, String> {
+    add_custom_template(app_handle, name, body, Some(name))
+}
+
+/// Append a new custom template with the given title pattern.
+fn add_custom_template(
+    app_handle: &tauri::AppHandle,
+    name: &str,
+    body: &str,
+    default_title_pattern: Option<&str>,
+) -> Result<NoteTemplate, String> {
+    let root = storage_root(app_handle)?;
+    let mut custom = read_custom_templates(&root)?;
+    let id = format!("custom-{}", Uuid::new_v4());
+    let t = NoteTemplate {
+        id: id.clone(),
+        name: name.to_string(),
+        body: body.to_string(),
+        default_title_pattern: default_title_pattern.map(str::to_string),
+        is_custom: true,
+    };
+    custom.push(t.clone());
+    write_custom_templates(&root, &custom)?;
+    Ok(t)
+}
+
+/// Copy a built-in or custom template into a new custom template named "<name> (copy)".
+pub fn duplicate_template(app_handle: &tauri::AppHandle, template_id: &str) -> Result<NoteTemplate, String> {
+    let template = list_templates(app_handle)?
+        .into_iter()
+        .find(|t| t.id == template_id)
+        .ok_or_else(|| "Template not found".to_string())?;
+    add_custom_template(
+        app_handle,
+        &format!("{} (copy)", template.name),
+        &template.body,
+        template.default_title_pattern.as_deref(),
+    )
+}
+
+/// Replace a custom template's name, body and title pattern in place (same id).
+pub fn update_custom_template(
+    app_handle: &tauri::AppHandle,
+    template_id: &str,
+    name: &str,
+    body: &str,
+    default_title_pattern: Option<&str>,
+) -> Result<NoteTemplate, String> {
+    if !template_id.starts_with("custom-") {
+        return Err("Can only edit custom templates".into());
+    }
+    let root = storage_root(app_handle)?;
+    let mut custom = read_custom_templates(&root)?;
+    let t = custom
+        .iter_mut()
+        .find(|t| t.id == template_id)
+        .ok_or_else(|| "Template not found".to_string())?;
+    t.name = name.to_string();
+    t.body = body.to_string();
+    t.default_title_pattern = default_title_pattern.map(str::to_string);
+    let updated = t.clone();
+    write_custom_templates(&root, &custom)?;
+    Ok(updated)
+}
+
+/// Delete a custom template by id.
+pub fn delete_custom_template(app_handle: &tauri::AppHandle, template_id: &str) -> Result<(), String> {
+    if !template_id.starts_with("custom-") {
+        return Err("Can only delete custom templates".into());
+    }
+    let root = storage_root(app_handle)?;
+    let mut custom = read_custom_templates(&root)?;
+    let len_before = custom.len();
+    custom.retain(|t| t.id != template_id);
+    if custom.len() == len_before {
+        return Err("Template not found".into());
+    }
+    write_custom_templates(&root, &custom)?;
+    Ok(())
+}
+
+/// Title of the periodic note covering `date`: "2024-01-15", ISO week "2024-W03", or "2024-01".
+pub fn periodic_note_title(period: Period, date: chrono::NaiveDate) -> String {
+    use chrono::Datelike;
+    match period {
+        Period::Daily => date.format("%Y-%m-%d").to_string(),
+        Period::Weekly => {
+            let week = date.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+        Period::Monthly => date.format("%Y-%m").to_string(),
+    }
+}
+
+/// Tag given to periodic notes of this kind.
+fn period_tag(period: Period) -> &'static str {
+    match period {
+        Period::Daily => "daily",
+        Period::Weekly => "weekly",
+        Period::Monthly => "monthly",
+    }
+}
+
+/// Starting body for a new periodic note covering `date`.
+pub fn periodic_note_body(period: Period, date: chrono::NaiveDate) -> String {
+    use chrono::{Datelike, Duration};
+    match period {
+        Period::Daily => "# daily\n".to_string(),
+        Period::Weekly => {
+            let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+            let sunday = monday + Duration::days(6);
+            format!(
+                "# weekly\n\n{} – {}\n\n## Goals\n\n- [ ] \n\n## Notes\n\n## Review\n",
+                monday.format("%Y-%m-%d"),
+                sunday.format("%Y-%m-%d")
+            )
+        }
+        Period::Monthly => format!("# monthly\n\n{}\n\n## Goals\n\n- [ ] \n\n## Review\n", date.format("%B %Y")),
+    }
+}
+
+/// Period of a note; daily notes written before `period` existed only have is_daily.
+fn note_period(n: &NoteMeta) -> Option<Period> {
+    n.period.or(if n.is_daily { Some(Period::Daily) } else { None })
+}
+
+/// Current time in the zone dates are shown in: the system's local offset, or UTC if the user chose it.
+/// Stored timestamps (created_at, updated_at) stay RFC3339 UTC regardless.
+fn user_now(settings: &Settings) -> chrono::DateTime<chrono::FixedOffset> {
+    if settings.use_utc_dates {
+        Utc::now().fixed_offset()
+    } else {
+        chrono::Local::now().fixed_offset()
+    }
+}
+
+const DEFAULT_DAILY_TITLE_FORMAT: &str = "%Y-%m-%d";
+
+/// Check a daily note title pattern: valid chrono strftime, and the resulting title must survive
+/// sanitize_filename unchanged (no '/', ':', … that would be rewritten).
+pub fn validate_daily_title_format(format: &str) -> Result<(), String> {
+    use chrono::format::{Item, StrftimeItems};
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid date format: {}", format));
+    }
+    let sample = chrono::NaiveDate::from_ymd_opt(2024, 12, 31).unwrap().and_hms_opt(23, 59, 59).unwrap();
+    let title = sample.format(format).to_string();
+    if title.trim().is_empty() || sanitize_filename(&title) != title {
+        return Err(format!("Date format gives an unusable title: {:?}", title));
+    }
+    Ok(())
+}
+
+/// Title, starting body and template id (if one was used) for today's daily note, honoring the
+/// daily note settings. An unknown template id falls back to the plain body.
+fn daily_note_content(
+    settings: &Settings,
+    templates: &[NoteTemplate],
+    now: chrono::DateTime<chrono::FixedOffset>,
+) -> (String, String, Option<String>) {
+    let format = settings
+        .daily_note_title_format
+        .as_deref()
+        .filter(|f| validate_daily_title_format(f).is_ok())
+        .unwrap_or(DEFAULT_DAILY_TITLE_FORMAT);
+    let title = now.format(format).to_string();
+    let template = settings
+        .daily_note_template_id
+        .as_deref()
+        .and_then(|id| templates.iter().find(|t| t.id == id));
+    match template {
+        Some(t) => (title.clone(), apply_template_placeholders(&t.body, &title, now, &HashMap::new()).0, Some(t.id.clone())),
+        None => (title, periodic_note_body(Period::Daily, now.date_naive()), None),
+    }
+}
+
+/// Get or create today's daily note (YYYY-MM-DD unless dailyNoteTitleFormat is set), tag #daily.
+pub fn get_or_create_daily_note(app_handle: &tauri::AppHandle) -> Result<NoteMeta, String> {
+    get_or_create_periodic_note(app_handle, Period::Daily)
+}
+
+/// Get or create the daily, weekly or monthly note for today, matched by title and period.
+pub fn get_or_create_periodic_note(app_handle: &tauri::AppHandle, period: Period) -> Result<NoteMeta, String> {
+    let root = storage_root(app_handle)?;
+    let settings = read_settings(&root)?;
+    let now = user_now(&settings);
+    let (title, body, template_id) = match period {
+        Period::Daily => {
+            let mut templates = builtin_templates();
+            templates.extend(read_custom_templates(&root)?);
+            daily_note_content(&settings, &templates, now)
+        }
+        _ => (periodic_note_title(period, now.date_naive()), periodic_note_body(period, now.date_naive()), None),
+    };
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    if let Some(n) = index.notes.iter().find(|n| note_period(n) == Some(period) && n.title == title) {
+        return Ok(n.clone());
+    }
+    let id = Uuid::new_v4().to_string();
+    let filename = note_filename(&id, &note_extension(&root)?);
+    let now = Utc::now().to_rfc3339();
+    let meta = NoteMeta {
+        id: id.clone(),
+        title: title.clone(),
+        created_at: now.clone(),
+        updated_at: now,
+        important: false,
+        filename: filename.clone(),
+        images: vec![],
+        tags: vec![period_tag(period).to_string()],
+        links_to: vec![],
+        is_daily: period == Period::Daily,
+        notebook_id: None,
+        read_count: 0,
+        created_from_template_id: template_id,
+        pin_order: None,
+        deleted_at: None,
+        pinned: false,
+        order: None,
+        aliases: vec![],
+        period: Some(period),
+        archived: false,
+        metadata_updated_at: None,
+        preview: Some(note_preview(&body, NOTE_PREVIEW_CHARS)),
+        color: None,
+    };
+    index.notes.push(meta.clone());
+    write_index(&root, &index, false)?;
+    atomic_write(&note_path(&root, &id)?, body.as_bytes())?;
+    update_search_index(&root, |sidx| search_index_add_note(sidx, &id, &meta.title, &body));
+    Ok(meta)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offsets of plain-text mentions of `title` in `body`: case-insensitive, on word boundaries
+/// ("Cat" doesn't match "Category"), and skipping anything inside a `[[...]]` link.
+pub fn find_unlinked_mentions(body: &str, title: &str) -> Vec<usize> {
+    let title = title.trim();
+    if title.is_empty() {
+        return vec![];
+    }
+    let Ok(re) = regex::RegexBuilder::new(&regex::escape(title))
+        .case_insensitive(true)
+        .build()
+    else {
+        return vec![];
+    };
+    let links = wikilink_spans(body);
+    re.find_iter(body)
+        .filter(|m| {
+            let before_ok = !body[..m.start()].chars().next_back().is_some_and(is_word_char);
+            let after_ok = !body[m.end()..].chars().next().is_some_and(is_word_char);
+            before_ok && after_ok
+        })
+        .filter(|m| !links.iter().any(|l| m.start() < l.end && m.end() > l.start))
+        .map(|m| m.start())
+        .collect()
+}
+
+/// Other notes that mention this note's title without linking to it.
+pub fn unlinked_mentions(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<UnlinkedMention>, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let index = read_index(&root)?;
+    let title = index
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .map(|n| n.title.clone())
+        .ok_or("Note not found")?;
+    let mut out = vec![];
+    for n in index.notes {
+        if n.id == note_id {
+            continue;
+        }
+        let body = fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)).unwrap_or_default();
+        let offsets = find_unlinked_mentions(&body, &title);
+        if !offsets.is_empty() {
+            out.push(UnlinkedMention { meta: n, offsets });
+        }
+    }
+    out.sort_by(|a, b| b.meta.updated_at.cmp(&a.meta.updated_at));
+    Ok(out)
+}
+
+/// Notes that share a title with another note (ignoring case), grouped; see duplicate_title_groups.
+pub fn find_duplicate_titles(app_handle: &tauri::AppHandle) -> Result<Vec<DuplicateTitleGroup>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(duplicate_title_groups(&index.notes))
+}
+
+/// Build the link graph from notes' links_to. Edges to ids that aren't in `notes` (deleted) are dropped.
+/// With `include_tag_edges`, each pair of notes sharing a tag also gets one undirected Tag edge.
+pub fn build_link_graph(notes: &[NoteMeta], include_tag_edges: bool) -> LinkGraph {
+    let ids: HashSet<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+    let nodes = notes
+        .iter()
+        .map(|n| GraphNode {
+            id: n.id.clone(),
+            title: n.title.clone(),
+        })
+        .collect();
+    let mut edges = vec![];
+    for n in notes {
+        for to in &n.links_to {
+            if to != &n.id && ids.contains(to.as_str()) {
+                edges.push(GraphEdge {
+                    from: n.id.clone(),
+                    to: to.clone(),
+                    kind: GraphEdgeKind::Link,
+                });
+            }
+        }
+    }
+    if include_tag_edges {
+        let mut by_tag: HashMap<&str, Vec<&str>> = HashMap::new();
+        for n in notes {
+            for t in &n.tags {
+                by_tag.entry(t.as_str()).or_default().push(n.id.as_str());
+            }
+        }
+        let mut pairs: BTreeSet<(&str, &str)> = BTreeSet::new();
+        for members in by_tag.values() {
+            for (i, a) in members.iter().enumerate() {
+                for b in &members[i + 1..] {
+                    if a != b {
+                        pairs.insert(if a < b { (a, b) } else { (b, a) });
+                    }
+                }
+            }
+        }
+        edges.extend(pairs.into_iter().map(|(a, b)| GraphEdge {
+            from: a.to_string(),
+            to: b.to_string(),
+            kind: GraphEdgeKind::Tag,
+        }));
+    }
+    LinkGraph { nodes, edges }
+}
+
+/// The whole link graph in one index read (no body I/O; links_to is kept current on save).
+pub fn link_graph(app_handle: &tauri::AppHandle, include_tag_edges: bool) -> Result<LinkGraph, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(build_link_graph(&index.notes, include_tag_edges))
+}
+
+/// Resolve a note's outgoing links and backlinks from the index. Each note appears once: a note that
+/// links both ways is listed under `outgoing` only.
+pub fn build_connections(notes: &[NoteMeta], note_id: &str) -> Result<NoteConnections, String> {
+    let note = notes.iter().find(|n| n.id == note_id).ok_or("Note not found")?;
+    let mut outgoing: Vec<NoteMeta> = notes
+        .iter()
+        .filter(|n| n.id != note_id && note.links_to.contains(&n.id))
+        .cloned()
+        .collect();
+    outgoing.sort_by_key(|n| n.title.to_lowercase());
+    let mut incoming: Vec<NoteMeta> = notes
+        .iter()
+        .filter(|n| n.id != note_id && n.links_to.iter().any(|id| id == note_id))
+        .filter(|n| !note.links_to.contains(&n.id))
+        .cloned()
+        .collect();
+    incoming.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    Ok(NoteConnections { outgoing, incoming })
+}
+
+/// Outgoing links and backlinks for a note in one call.
+pub fn get_linked_and_backlinks(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteConnections, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    build_connections(&index.notes, note_id)
+}
+
+/// Notes that link to this note (backlinks).
+pub fn get_backlinks(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<NoteMeta>, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(index
+        .notes
+        .into_iter()
+        .filter(|n| n.links_to.contains(&note_id.to_string()))
+        .collect())
+}
+
+/// read_note plus the note's notebook and backlinks, so opening a note is a single round-trip.
+/// Counts as opening the note (read_count is bumped once).
+pub fn read_note_full(app_handle: &tauri::AppHandle, note_id: &str, load_version_count: bool) -> Result<NoteFull, String> {
+    let content = read_note(app_handle, note_id, load_version_count)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let notebook = content
+        .meta
+        .notebook_id
+        .as_deref()
+        .and_then(|nid| index.notebooks.iter().find(|nb| nb.id == nid).cloned());
+    let backlinks = index.notes.into_iter().filter(|n| n.links_to.iter().any(|l| l == note_id)).collect();
+    Ok(NoteFull {
+        meta: content.meta,
+        body: content.body,
+        versions_count: content.versions_count,
+        notebook,
+        backlinks,
+    })
+}
+
+/// Remove one attachment from a note.
+pub fn remove_attachment(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    relative_path: &str,
+) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    if !is_attachment_path(relative_path) {
+        return Err("Invalid path".into());
+    }
+    let root = storage_root(app_handle)?;
+    let full = root.join(relative_path);
+    if full.exists() {
+        let _ = fs::remove_file(&full);
+    }
+    remove_thumbnails(&full, None);
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.images.retain(|img| img.path != relative_path);
+    n.updated_at = Utc::now().to_rfc3339();
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Rename an attachment (display name only; file on disk is unchanged).
+pub fn rename_attachment(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    relative_path: &str,
+    new_name: &str,
+) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    if !is_attachment_path(relative_path) {
+        return Err("Invalid path".into());
+    }
+    let new_name = sanitize_filename(new_name.trim());
+    if new_name.is_empty() {
+        return Err("Name cannot be empty".into());
+    }
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    if let Some(img) = n.images.iter_mut().find(|img| img.path == relative_path) {
+        img.name = new_name;
+    } else {
+        return Err("Attachment not found".into());
+    }
+    n.updated_at = Utc::now().to_rfc3339();
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Stored name of a file directly in `dir` with the given size and SHA-256, if there is one.
+pub fn find_identical_file(dir: &Path, sha256: &str, size: u64) -> Option<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter(|e| e.metadata().map(|m| m.len() == size).unwrap_or(false))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .find(|name| hash_file(&dir.join(name)).map(|(h, _)| h == sha256).unwrap_or(false))
+}
+
+/// Collapse attachments of a note that have identical content: one file is kept per content hash,
+/// the others are deleted and every reference (ImageRef paths and the note body) is pointed at the kept file.
+pub fn dedupe_attachments(app_handle: &tauri::AppHandle, note_id: &str) -> Result<DedupeSummary, String> {
+    validate_note_id(note_id)?;
+    dedupe_note_attachments(&storage_root(app_handle)?, note_id)
+}
+
+/// dedupe_attachments for the storage root `root`.
+pub fn dedupe_note_attachments(root: &Path, note_id: &str) -> Result<DedupeSummary, String> {
+    let _guard = lock_index();
+    let mut index = read_index(root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    let mut summary = DedupeSummary::default();
+    let mut replaced: Vec<(String, String)> = vec![];
+    for subdir in ["images", "attachments"] {
+        let dir = root.join(subdir).join(sanitize_filename(note_id));
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        // Oldest (timestamp-prefixed) name first, so the earliest copy is the one kept.
+        names.sort();
+        let mut kept: HashMap<String, String> = HashMap::new();
+        for name in names {
+            let path = dir.join(&name);
+            let (hash, size) = hash_file(&path)?;
+            match kept.get(&hash) {
+                Some(keep) => {
+                    fs::remove_file(&path).map_err(|e| e.to_string())?;
+                    remove_thumbnails(&path, None);
+                    summary.files_removed += 1;
+                    summary.bytes_saved += size;
+                    replaced.push((
+                        format!("{}/{}/{}", subdir, note_id, name),
+                        format!("{}/{}/{}", subdir, note_id, keep),
+                    ));
+                }
+                None => {
+                    kept.insert(hash, name);
+                }
+            }
+        }
+    }
+    if replaced.is_empty() {
+        return Ok(summary);
+    }
+    for img in n.images.iter_mut() {
+        if let Some((_, keep)) = replaced.iter().find(|(old, _)| *old == img.path) {
+            img.path = keep.clone();
+        }
+    }
+    let mut seen = HashSet::new();
+    n.images.retain(|img| seen.insert(img.path.clone()));
+    n.updated_at = Utc::now().to_rfc3339();
+    let title = n.title.clone();
+    let path = note_path(root, note_id)?;
+    if let Ok(body) = fs::read_to_string(&path) {
+        let new_body = replaced.iter().fold(body.clone(), |b, (old, keep)| replace_link_target(&b, old, keep));
+        if new_body != body {
+            atomic_write(&path, new_body.as_bytes())?;
+            update_search_index(root, |sidx| search_index_add_note(sidx, note_id, &title, &new_body));
+            n.preview = Some(note_preview(&new_body, NOTE_PREVIEW_CHARS));
+        }
+    }
+    write_index(root, &index, false)?;
+    Ok(summary)
+}
+
+/// Replace `old` with `new` where it is a whole markdown link or image target: right after `(` or `<`
+/// and followed by `)`, `>` or whitespace (a link title). A longer path that merely starts with `old`
+/// (`a.png.bak` for `a.png`) is left alone.
+pub fn replace_link_target(body: &str, old: &str, new: &str) -> String {
+    if old.is_empty() {
+        return body.to_string();
+    }
+    let mut out = String::with_capacity(body.len());
+    let mut last = 0;
+    for (start, _) in body.match_indices(old) {
+        let end = start + old.len();
+        let opens = matches!(body[..start].chars().next_back(), Some('(' | '<'));
+        let closes = matches!(body[end..].chars().next(), Some(c) if c == ')' || c == '>' || c.is_whitespace());
+        if start >= last && opens && closes {
+            out.push_str(&body[last..start]);
+            out.push_str(new);
+            last = end;
+        }
+    }
+    out.push_str(&body[last..]);
+    out
+}
+
+/// Thumbnails are cached next to their source, in <dir>/.thumbs/.
+const THUMBS_DIR: &str = ".thumbs";
+const MAX_THUMBNAIL_DIM: u32 = 2048;
+
+/// Cache file name for a thumbnail of `stored_name`: keyed by the source mtime (ms) and max_dim, so an
+/// edited source never matches an old thumbnail. Sources that may be transparent stay PNG, the rest JPEG.
+pub fn thumbnail_name(stored_name: &str, mtime_ms: u128, max_dim: u32) -> String {
+    let ext = Path::new(stored_name).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let thumb_ext = if matches!(ext.as_str(), "jpg" | "jpeg" | "bmp") { "jpg" } else { "png" };
+    format!("{}.{}.{}.{}", stored_name, mtime_ms, max_dim, thumb_ext)
+}
+
+/// Write a thumbnail of `src` fitting in max_dim x max_dim to `dest` (format from dest's extension).
+/// Returns Ok(false) without writing when `src` is not a decodable raster image.
+pub fn write_thumbnail(src: &Path, dest: &Path, max_dim: u32) -> Result<bool, String> {
+    let Ok(img) = image::ImageReader::open(src)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+    else {
+        return Ok(false);
+    };
+    let thumb = img.thumbnail(max_dim, max_dim);
+    let thumb = if dest.extension().and_then(|e| e.to_str()) == Some("jpg") {
+        image::DynamicImage::ImageRgb8(thumb.to_rgb8())
+    } else {
+        thumb
+    };
+    thumb.save(dest).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Delete cached thumbnails of the source file, except those for the given source mtime (best-effort).
+fn remove_thumbnails(source: &Path, keep_mtime_ms: Option<u128>) {
+    let (Some(dir), Some(name)) = (source.parent(), source.file_name().and_then(|n| n.to_str())) else {
+        return;
+    };
+    let prefix = format!("{}.", name);
+    let keep = keep_mtime_ms.map(|m| format!("{}{}.", prefix, m));
+    let Ok(entries) = fs::read_dir(dir.join(THUMBS_DIR)) else { return };
+    for entry in entries.flatten() {
+        let thumb = entry.file_name().to_string_lossy().to_string();
+        if thumb.starts_with(&prefix) && !keep.as_ref().is_some_and(|k| thumb.starts_with(k.as_str())) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Resolved path of a thumbnail (at most max_dim px on each side) for an attached image, generated on first
+/// use and cached in .thumbs/. Images already small enough resolve to the original. Returns None for files
+/// that are not raster images (PDFs, audio, SVG, …).
+pub fn get_thumbnail(app_handle: &tauri::AppHandle, relative_path: &str, max_dim: u32) -> Result<Option<String>, String> {
+    if max_dim == 0 || max_dim > MAX_THUMBNAIL_DIM {
+        return Err(format!("Thumbnail size must be between 1 and {}", MAX_THUMBNAIL_DIM));
+    }
+    let src = resolve_image_path(app_handle, relative_path)?;
+    let ext = src.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if !src.is_file() || !is_image_extension(ext) {
+        return Ok(None);
+    }
+    if let Ok((w, h)) = image::image_dimensions(&src) {
+        if w <= max_dim && h <= max_dim {
+            return Ok(Some(src.to_string_lossy().to_string()));
+        }
+    }
+    let mtime_ms = fs::metadata(&src)
+        .and_then(|m| m.modified())
+        .map_err(|e| e.to_string())?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let stored_name = src.file_name().and_then(|n| n.to_str()).ok_or("Invalid path")?;
+    let name = thumbnail_name(stored_name, mtime_ms, max_dim);
+    let thumbs = src.parent().ok_or("Invalid path")?.join(THUMBS_DIR);
+    let dest = thumbs.join(&name);
+    if !dest.is_file() {
+        // Thumbnails of an older version of the source are stale now.
+        remove_thumbnails(&src, Some(mtime_ms));
+        fs::create_dir_all(&thumbs).map_err(|e| e.to_string())?;
+        if !write_thumbnail(&src, &dest, max_dim)? {
+            return Ok(None);
+        }
+    }
+    Ok(Some(dest.to_string_lossy().to_string()))
+}
+
+/// GFM task list line: Some(false) for "- [ ]", Some(true) for "- [x]"/"- [X]" (or "*"), None otherwise.
+fn task_line_state(line: &str) -> Option<bool> {
+    let t = line.trim();
+    if t.starts_with("- [ ]") || t.starts_with("* [ ]") {
+        Some(false)
+    } else if t.starts_with("- [x]") || t.starts_with("- [X]") || t.starts_with("* [x]") || t.starts_with("* [X]") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// GFM task list: detect lines like "- [ ]" or "- [x]" (or "*").
+fn body_has_task_lines(body: &str) -> (bool, bool) {
+    let mut has_unchecked = false;
+    let mut has_checked = false;
+    for line in body.lines() {
+        match task_line_state(line) {
+            Some(true) => has_checked = true,
+            Some(false) => has_unchecked = true,
+            None => {}
+        }
+        if has_unchecked && has_checked {
+            break;
+        }
+    }
+    (has_unchecked, has_checked)
+}
+
+/// Count task lines: (total, done).
+pub fn count_task_lines(body: &str) -> (usize, usize) {
+    body.lines()
+        .filter_map(task_line_state)
+        .fold((0, 0), |(total, done), checked| (total + 1, done + checked as usize))
+}
+
+/// Reading speed used for NoteStats::reading_minutes.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Word/char/task statistics for a note body.
+pub fn compute_note_stats(body: &str) -> NoteStats {
+    let words = body.split_whitespace().count();
+    let (task_total, task_done) = count_task_lines(body);
+    NoteStats {
+        words,
+        chars: body.chars().count(),
+        reading_minutes: words.div_ceil(WORDS_PER_MINUTE) as u32,
+        task_total,
+        task_done,
+    }
+}
+
+/// Reading stats for a note (word/char counts, reading time, tasks).
+pub fn note_stats(app_handle: &tauri::AppHandle, note_id: &str) -> Result<NoteStats, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    if !index.notes.iter().any(|n| n.id == note_id) {
+        return Err("Note not found".into());
+    }
+    // Read the body directly so computing stats doesn't count as opening the note.
+    let body = fs::read_to_string(note_path(&root, note_id)?).unwrap_or_default();
+    Ok(compute_note_stats(&body))
+}
+
+/// Opening or closing code fence: the fence character and run length, e.g. ('`', 3) for "```rust".
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let t = line.trim_start();
+    if line.len() - t.len() > 3 {
+        return None;
+    }
+    let c = t.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = t.chars().take_while(|x| *x == c).count();
+    (run >= 3).then_some((c, run))
+}
+
+/// ATX headings (`#` .. `######`) in a body, in order. Lines inside ``` or ~~~ fenced code blocks are
+/// skipped, as are `#tag` lines (no space after the hashes) and empty headings.
+pub fn parse_outline(body: &str) -> Vec<OutlineHeading> {
+    let mut out = vec![];
+    let mut fence: Option<(char, usize)> = None;
+    for (i, line) in body.lines().enumerate() {
+        if let Some((c, run)) = code_fence(line) {
+            match fence {
+                None => fence = Some((c, run)),
+                // A closing fence uses the same character, at least as many times, and nothing else.
+                Some((open_c, open_run)) if c == open_c && run >= open_run && line.trim().chars().all(|x| x == c) => {
+                    fence = None
+                }
+                Some(_) => {}
+            }
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+        let t = line.trim_start();
+        if line.len() - t.len() > 3 {
+            continue;
+        }
+        let level = t.chars().take_while(|c| *c == '#').count();
+        if level == 0 || level > 6 {
+            continue;
+        }
+        let rest = &t[level..];
+        if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+            continue;
+        }
+        // Optional closing sequence: "## Title ##".
+        let text = rest.trim();
+        let text = match text.trim_end_matches('#') {
+            stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+            _ => text,
+        };
+        if text.is_empty() {
+            continue;
+        }
+        out.push(OutlineHeading {
+            level: level as u8,
+            text: text.to_string(),
+            line: i + 1,
+        });
+    }
+    out
+}
+
+/// Heading outline of a note (see parse_outline).
+pub fn note_outline(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<OutlineHeading>, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    if !index.notes.iter().any(|n| n.id == note_id) {
+        return Err("Note not found".into());
+    }
+    // Like note_stats, read the body directly so the outline panel doesn't count as opening the note.
+    let body = fs::read_to_string(note_path(&root, note_id)?).unwrap_or_default();
+    Ok(parse_outline(&body))
+}
+
+/// One token of a search query; quoted phrases are never treated as operators or the OR keyword.
+/// `negated` is set for a leading `-` (e.g. `-draft`, `-tag:archive`, `-"old plan"`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryToken {
+    pub text: String,
+    pub quoted: bool,
+    pub negated: bool,
+}
+
+/// Split a query on whitespace, keeping `"quoted phrases"` together (an unclosed quote runs to the end).
+pub fn tokenize_query(query: &str) -> Vec<QueryToken> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut negated = false;
+        if c == '-' {
+            chars.next();
+            match chars.peek() {
+                Some(&p) if !p.is_whitespace() => negated = true,
+                // A lone "-" is just text.
+                _ => {
+                    tokens.push(QueryToken {
+                        text: "-".into(),
+                        quoted: false,
+                        negated: false,
+                    });
+                    continue;
+                }
+            }
+        }
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&p| p != '"').collect();
+            if !phrase.trim().is_empty() {
+                tokens.push(QueryToken {
+                    text: phrase.trim().to_string(),
+                    quoted: true,
+                    negated,
+                });
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&p) = chars.peek() {
+                if p.is_whitespace() {
+                    break;
+                }
+                word.push(p);
+                chars.next();
+            }
+            tokens.push(QueryToken {
+                text: word,
+                quoted: false,
+                negated,
+            });
+        }
+    }
+    tokens
+}
+
+/// Which note timestamp a date range operator compares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Updated,
+}
+
+/// A `created:`/`updated:` operator: the note's `YYYY-MM-DD` date prefix compared against `date`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateRange {
+    pub field: DateField,
+    /// One of ">=", "<=", ">", "<", "=".
+    pub op: &'static str,
+    pub date: String,
+}
+
+impl DateRange {
+    pub fn matches(&self, note_date: &str) -> bool {
+        let d = note_date.get(..10).unwrap_or(note_date);
+        match self.op {
+            ">=" => d >= self.date.as_str(),
+            "<=" => d <= self.date.as_str(),
+            ">" => d > self.date.as_str(),
+            "<" => d < self.date.as_str(),
+            _ => d == self.date,
+        }
+    }
+}
+
+/// Parse the value of a `created:`/`updated:` operator (`>=2024-01-01`, `<2024-06-01`, `2024-03-05`, …).
+/// None if the date isn't a valid YYYY-MM-DD.
+pub fn parse_date_range(field: DateField, value: &str) -> Option<DateRange> {
+    let op = [">=", "<=", ">", "<", "="].into_iter().find(|op| value.starts_with(op)).unwrap_or("=");
+    let date = value.strip_prefix(op).unwrap_or(value).trim();
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    if date.len() != 10 {
+        return None;
+    }
+    Some(DateRange {
+        field,
+        op,
+        date: date.to_string(),
+    })
+}
+
+/// Parsed search_notes query. Operators are global filters; free text forms OR-combined groups of ANDed terms.
+/// Negated forms are applied after the positive filters. If a tag is both required and excluded
+/// (`tag:x -tag:x`), the positive filter wins and the exclusion is dropped.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchQuery {
+    /// Matches the tag and any tag nested under it (`tag:project` finds `project/alpha`).
+    pub tag_filter: Option<String>,
+    pub starred_only: bool,
+    /// "today" | "week" | "month"
+    pub date_filter: Option<String>,
+    pub has_attachments_only: bool,
+    pub has_tasks_only: bool,
+    /// Some(true) = completed only, Some(false) = uncompleted only
+    pub task_filter: Option<bool>,
+    pub sort_by_access: bool,
+    /// Lowercased terms/phrases; a note matches if all terms of any one group match.
+    pub text_groups: Vec<Vec<String>>,
+    /// Notes whose title or body contains any of these are excluded.
+    pub excluded_text: Vec<String>,
+    /// Notes carrying any of these tags (or tags nested under them) are excluded.
+    pub excluded_tags: Vec<String>,
+    pub exclude_starred: bool,
+    pub exclude_attachments: bool,
+    pub exclude_tasks: bool,
+    /// Some(true) = exclude notes with completed tasks, Some(false) = exclude notes with open tasks
+    pub excluded_task_filter: Option<bool>,
+    /// `created:`/`updated:` operators; a note must satisfy all of them.
+    pub date_ranges: Vec<DateRange>,
+}
+
+impl SearchQuery {
+    pub fn has_text(&self) -> bool {
+        !self.text_groups.is_empty()
+    }
+
+    /// True if the note body is needed to evaluate the free-text part (positive or excluded).
+    pub fn needs_body_text(&self) -> bool {
+        self.has_text() || !self.excluded_text.is_empty()
+    }
+
+    /// Evaluate the free-text part for one note from precomputed index lookups (term -> matching ids).
+    /// Returns None when some term isn't in `lookups`, meaning the note text has to be read. Only
+    /// valid for notes search_index_stale_notes doesn't report.
+    pub fn matches_text_indexed(&self, note_id: &str, lookups: &HashMap<String, HashSet<String>>) -> Option<bool> {
+        let hit = |term: &String| lookups.get(term).map(|ids| ids.contains(note_id));
+        for term in &self.excluded_text {
+            if hit(term)? {
+                return Some(false);
+            }
+        }
+        if self.text_groups.is_empty() {
+            return Some(true);
+        }
+        let mut any = false;
+        for group in &self.text_groups {
+            let mut all = true;
+            for term in group {
+                all &= hit(term)?;
+            }
+            any |= all;
+        }
+        Some(any)
+    }
+
+    /// True if the (already lowercased) title or body satisfies the free-text part of the query.
+    pub fn matches_text(&self, title_lower: &str, body_lower: &str) -> bool {
+        if self
+            .excluded_text
+            .iter()
+            .any(|term| title_lower.contains(term.as_str()) || body_lower.contains(term.as_str()))
+        {
+            return false;
+        }
+        if self.text_groups.is_empty() {
+            return true;
+        }
+        self.text_groups.iter().any(|group| {
+            group
+                .iter()
+                .all(|term| title_lower.contains(term.as_str()) || body_lower.contains(term.as_str()))
+        })
+    }
+}
+
+/// Parse a search query: operators tag: is:starred date:today|week|month has:attachments has:tasks
+/// is:completed is:uncompleted sort:accessed, created:/updated: date ranges (`created:>=2024-01-01`),
+/// `"exact phrases"`, the `OR` keyword between text terms, and a leading `-` to exclude text, phrases,
+/// tags, and is:/has: forms.
+pub fn parse_search_query(query: &str) -> SearchQuery {
+    let mut q = SearchQuery::default();
+    let mut group: Vec<String> = vec![];
+    for token in tokenize_query(query) {
+        if token.negated {
+            let part_lower = token.text.to_lowercase();
+            if token.quoted {
+                q.excluded_text.push(part_lower);
+            } else if let Some(tag) = part_lower.strip_prefix("tag:") {
+                let tag = tag.trim();
+                if !tag.is_empty() {
+                    q.excluded_tags.push(tag_key(tag));
+                }
+            } else if part_lower == "is:starred" {
+                q.exclude_starred = true;
+            } else if part_lower == "has:attachments" {
+                q.exclude_attachments = true;
+            } else if part_lower == "has:tasks" {
+                q.exclude_tasks = true;
+            } else if part_lower == "is:completed" {
+                q.excluded_task_filter = Some(true);
+            } else if part_lower == "is:uncompleted" {
+                q.excluded_task_filter = Some(false);
+            } else {
+                q.excluded_text.push(part_lower);
+            }
+            continue;
+        }
+        if token.quoted {
+            group.push(token.text.to_lowercase());
+            continue;
+        }
+        if token.text == "OR" {
+            if !group.is_empty() {
+                q.text_groups.push(std::mem::take(&mut group));
+            }
+            continue;
+        }
+        let part_lower = token.text.to_lowercase();
+        if let Some(tag) = part_lower.strip_prefix("tag:") {
+            let tag = tag.trim();
+            if !tag.is_empty() {
+                q.tag_filter = Some(tag_key(tag));
+            }
+        } else if part_lower == "is:starred" {
+            q.starred_only = true;
+        } else if part_lower == "date:today" {
+            q.date_filter = Some("today".into());
+        } else if part_lower == "date:week" {
+            q.date_filter = Some("week".into());
+        } else if part_lower == "date:month" {
+            q.date_filter = Some("month".into());
+        } else if part_lower == "has:attachments" {
+            q.has_attachments_only = true;
+        } else if part_lower == "has:tasks" {
+            q.has_tasks_only = true;
+        } else if part_lower == "is:completed" {
+            q.task_filter = Some(true);
+        } else if part_lower == "is:uncompleted" {
+            q.task_filter = Some(false);
+        } else if part_lower == "sort:accessed" {
+            q.sort_by_access = true;
+        } else if let Some(value) = part_lower.strip_prefix("created:") {
+            // Invalid dates drop the operator rather than failing the search.
+            q.date_ranges.extend(parse_date_range(DateField::Created, value));
+        } else if let Some(value) = part_lower.strip_prefix("updated:") {
+            q.date_ranges.extend(parse_date_range(DateField::Updated, value));
+        } else {
+            group.push(part_lower);
+        }
+    }
+    if !group.is_empty() {
+        q.text_groups.push(group);
+    }
+    if let Some(ref tag) = q.tag_filter {
+        q.excluded_tags.retain(|t| t != tag);
+    }
+    q
+}
+
+/// Search notes: full-text (title + body) with `"phrases"` and `OR`, plus the operators handled by parse_search_query.
+/// Queries with free text are ordered by relevance_score, then updated_at; operator-only queries by
+/// updated_at (or read count with sort:accessed). Archived notes are skipped unless `include_archived` is set.
+pub fn search_notes(
+    app_handle: &tauri::AppHandle,
+    query: &str,
+    include_archived: bool,
+) -> Result<Vec<NoteMeta>, String> {
+    Ok(search_notes_ranked(app_handle, query, include_archived)?.into_iter().map(|r| r.meta).collect())
+}
+
+/// Weight of a term occurrence in the title relative to one in the body.
+const TITLE_MATCH_WEIGHT: u32 = 5;
+
+/// Relevance of a note for `terms` (lowercased): occurrences in the title count TITLE_MATCH_WEIGHT
+/// each, occurrences in the body count 1. Titles and bodies are matched case-insensitively.
+pub fn relevance_score(terms: &[String], title: &str, body: &str) -> u32 {
+    let title = title.to_lowercase();
+    let body = body.to_lowercase();
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut score = 0u32;
+    for term in terms.iter().filter(|t| !t.is_empty() && seen.insert(t.as_str())) {
+        let in_title = title.matches(term.as_str()).count() as u32;
+        let in_body = body.matches(term.as_str()).count() as u32;
+        score = score.saturating_add(in_title.saturating_mul(TITLE_MATCH_WEIGHT).saturating_add(in_body));
+    }
+    score
+}
+
+/// search_notes with each hit's relevance_score, in the same order.
+pub fn search_notes_ranked(
+    app_handle: &tauri::AppHandle,
+    query: &str,
+    include_archived: bool,
+) -> Result<Vec<RankedNote>, String> {
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let index = read_index(&root)?;
+    let q = query.trim();
+    if q.is_empty() {
+        return Ok(index
+            .notes
+            .into_iter()
+            .filter(|n| include_archived || !n.archived)
+            .map(|meta| RankedNote { meta, score: 0 })
+            .collect());
+    }
+    let now = Utc::now();
+    let today = now.format("%Y-%m-%d").to_string();
+    let week_start = (now - chrono::Duration::days(7)).format("%Y-%m-%d").to_string();
+    let month_start = (now - chrono::Duration::days(30)).format("%Y-%m-%d").to_string();
+    let parsed = parse_search_query(q);
+    // Resolve index-answerable terms once; other terms fall back to reading note bodies.
+    let mut lookups: HashMap<String, HashSet<String>> = HashMap::new();
+    // Notes whose body changed outside the app since they were indexed are read instead.
+    let mut stale: HashSet<String> = HashSet::new();
+    if parsed.needs_body_text() {
+        let sidx = load_or_build_search_index(&root, &ext, &index.notes);
+        stale = search_index_stale_notes(&root, &ext, &sidx, &index.notes);
+        for term in parsed.text_groups.iter().flatten().chain(parsed.excluded_text.iter()) {
+            if let Some(ids) = search_index_lookup(&sidx, term) {
+                lookups.insert(term.clone(), ids);
+            }
+        }
+    }
+    let matched: Vec<NoteMeta> = index.notes.into_iter().filter(|n| {
+        if n.archived && !include_archived {
+            return false;
+        }
+        if let Some(ref tag) = parsed.tag_filter {
+            if !n.tags.iter().any(|t| tag_matches_prefix(t, tag)) {
+                return false;
+            }
+        }
+        if parsed.starred_only && !n.important {
+            return false;
+        }
+        if let Some(ref date_kind) = parsed.date_filter {
+            let note_date: String = n.updated_at.chars().take(10).collect();
+            let ok = match date_kind.as_str() {
+                "today" => note_date == today,
+                "week" => note_date >= week_start,
+                "month" => note_date >= month_start,
+                _ => true,
+            };
+            if !ok {
+                return false;
+            }
+        }
+        if !parsed.date_ranges.iter().all(|r| match r.field {
+            DateField::Created => r.matches(&n.created_at),
+            DateField::Updated => r.matches(&n.updated_at),
+        }) {
+            return false;
+        }
+        if parsed.has_attachments_only && n.images.is_empty() {
+            return false;
+        }
+        if parsed.has_tasks_only
+            || parsed.task_filter.is_some()
+            || parsed.exclude_tasks
+            || parsed.excluded_task_filter.is_some()
+        {
+            let body_path = note_path_with_ext(&root, &n.id, &ext);
+            let body = fs::read_to_string(&body_path).unwrap_or_default();
+            let (has_unchecked, has_checked) = body_has_task_lines(&body);
+            if parsed.has_tasks_only && !has_unchecked && !has_checked {
+                return false;
+            }
+            if let Some(completed_only) = parsed.task_filter {
+                if completed_only && !has_checked {
+                    return false;
+                }
+                if !completed_only && !has_unchecked {
+                    return false;
+                }
+            }
+            if parsed.exclude_tasks && (has_unchecked || has_checked) {
+                return false;
+            }
+            match parsed.excluded_task_filter {
+                Some(true) if has_checked => return false,
+                Some(false) if has_unchecked => return false,
+                _ => {}
+            }
+        }
+        // Negated filters run after the positive ones.
+        if parsed
+            .excluded_tags
+            .iter()
+            .any(|tag| n.tags.iter().any(|t| tag_matches_prefix(t, tag)))
+        {
+            return false;
+        }
+        if parsed.exclude_starred && n.important {
+            return false;
+        }
+        if parsed.exclude_attachments && !n.images.is_empty() {
+            return false;
+        }
+        if !parsed.needs_body_text() {
+            return true;
+        }
+        if !stale.contains(&n.id) {
+            if let Some(matched) = parsed.matches_text_indexed(&n.id, &lookups) {
+                return matched;
+            }
+        }
+        let title_lower = n.title.to_lowercase();
+        let body_path = note_path_with_ext(&root, &n.id, &ext);
+        let body = fs::read_to_string(&body_path).unwrap_or_default().to_lowercase();
+        parsed.matches_text(&title_lower, &body)
+    }).collect();
+    let terms: Vec<String> = parsed.text_groups.iter().flatten().cloned().collect();
+    let mut out: Vec<RankedNote> = matched
+        .into_iter()
+        .map(|meta| {
+            let score = if terms.is_empty() {
+                0
+            } else {
+                let body = fs::read_to_string(note_path_with_ext(&root, &meta.id, &ext)).unwrap_or_default();
+                relevance_score(&terms, &meta.title, &body)
+            };
+            RankedNote { meta, score }
+        })
+        .collect();
+    if parsed.sort_by_access {
+        out.sort_by(|a, b| {
+            b.meta.read_count.cmp(&a.meta.read_count).then_with(|| b.meta.updated_at.cmp(&a.meta.updated_at))
+        });
+    } else {
+        out.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| b.meta.updated_at.cmp(&a.meta.updated_at)));
+    }
+    Ok(out)
+}
+
+/// fuzzy_score points: per matched character, extra when it starts a word, and extra when it directly
+/// follows the previous match. Each skipped character between two matches costs 1.
+const FUZZY_MATCH: i64 = 1;
+const FUZZY_WORD_START: i64 = 8;
+const FUZZY_CONSECUTIVE: i64 = 5;
+
+/// fzf-style subsequence score of `query` against `text`, or None if the query's characters (spaces
+/// ignored) don't all appear in order. Case-insensitive. Picks the best-scoring alignment, so
+/// `prjalp` prefers the word starts in "Project Alpha" over scattered letters.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let q: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(lower).collect();
+    if q.is_empty() {
+        return None;
+    }
+    let t: Vec<char> = text.chars().collect();
+    let t_lower: Vec<char> = t.iter().copied().map(lower).collect();
+    let word_start = |j: usize| {
+        j == 0 || !t[j - 1].is_alphanumeric() || (t[j - 1].is_lowercase() && t[j].is_uppercase())
+    };
+    const NONE: i64 = i64::MIN / 2;
+    // prev[j]: best score with the previous query character matched at text position j.
+    let mut prev: Vec<i64> = vec![NONE; t.len()];
+    for (i, &qc) in q.iter().enumerate() {
+        let mut cur = vec![NONE; t.len()];
+        // Best prev[k] + k over k < j - 1 (a gap of j - k - 1 skipped characters).
+        let mut best_gap = NONE;
+        for j in 0..t.len() {
+            if j >= 2 && prev[j - 2] > NONE {
+                best_gap = best_gap.max(prev[j - 2] + (j - 2) as i64);
+            }
+            if t_lower[j] != qc {
+                continue;
+            }
+            let base = if i == 0 {
+                0
+            } else {
+                let consecutive = if j >= 1 && prev[j - 1] > NONE { prev[j - 1] + FUZZY_CONSECUTIVE } else { NONE };
+                let gapped = if best_gap > NONE { best_gap - j as i64 + 1 } else { NONE };
+                consecutive.max(gapped)
+            };
+            if base > NONE {
+                cur[j] = base + FUZZY_MATCH + if word_start(j) { FUZZY_WORD_START } else { 0 };
+            }
+        }
+        prev = cur;
+    }
+    let best = prev.into_iter().max().filter(|&s| s > NONE)?;
+    Some(best.max(1) as u32)
+}
+
+/// The `limit` most recently updated notes, newest first (ties by id), using a bounded min-heap so
+/// only `limit` entries are ever kept instead of sorting every note.
+pub fn most_recent_notes(notes: &[NoteMeta], limit: usize) -> Vec<NoteMeta> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    if limit == 0 {
+        return vec![];
+    }
+    // Reverse makes the root the least recent note kept so far, which is the one to evict.
+    let mut heap: BinaryHeap<Reverse<(&str, Reverse<&str>, usize)>> = BinaryHeap::with_capacity(limit + 1);
+    for (i, n) in notes.iter().enumerate() {
+        heap.push(Reverse((n.updated_at.as_str(), Reverse(n.id.as_str()), i)));
+        if heap.len() > limit {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|Reverse((_, _, i))| notes[i].clone()).collect()
+}
+
+/// Recently updated notes for the Recents view (archived notes excluded).
+pub fn recent_notes(app_handle: &tauri::AppHandle, limit: usize) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let mut index = read_index(&root)?;
+    index.notes.retain(|n| !n.archived);
+    Ok(most_recent_notes(&index.notes, limit))
+}
+
+/// Command-palette jump: notes whose title fuzzy-matches `query` (see fuzzy_score), best first, then
+/// most recently updated. Titles come from the index, so no note bodies are read. An empty query
+/// returns the most recently updated notes with score 0.
+pub fn quick_switch(app_handle: &tauri::AppHandle, query: &str, limit: usize) -> Result<Vec<RankedNote>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let mut out: Vec<RankedNote> = if query.trim().is_empty() {
+        index.notes.into_iter().map(|meta| RankedNote { meta, score: 0 }).collect()
+    } else {
+        index
+            .notes
+            .into_iter()
+            .filter_map(|meta| fuzzy_score(query, &meta.title).map(|score| RankedNote { meta, score }))
+            .collect()
+    };
+    out.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| b.meta.updated_at.cmp(&a.meta.updated_at)));
+    out.truncate(limit);
+    Ok(out)
+}
+
+pub fn read_saved_searches(root: &Path) -> Result<Vec<SavedSearch>, String> {
+    let path = saved_searches_path(root);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let s = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&s).map_err(|e| e.to_string())
+}
+
+fn write_saved_searches(root: &Path, searches: &[SavedSearch]) -> Result<(), String> {
+    let path = saved_searches_path(root);
+    let json = serde_json::to_string_pretty(searches).map_err(|e| e.to_string())?;
+    atomic_write(&path, json.as_bytes())
+}
+
+/// Saved searches in creation order.
+pub fn list_saved_searches(app_handle: &tauri::AppHandle) -> Result<Vec<SavedSearch>, String> {
+    let root = storage_root(app_handle)?;
+    read_saved_searches(&root)
+}
+
+/// Save a named query. The query is stored as typed and parsed again on every run.
+pub fn create_saved_search(app_handle: &tauri::AppHandle, name: &str, query: &str) -> Result<SavedSearch, String> {
+    add_saved_search(&storage_root(app_handle)?, name, query)
+}
+
+/// create_saved_search for the storage root `root`. Holds the index lock across the read-modify-write
+/// so two concurrent creates can't drop each other's entry.
+pub fn add_saved_search(root: &Path, name: &str, query: &str) -> Result<SavedSearch, String> {
+    let name = name.trim();
+    let query = query.trim();
+    if name.is_empty() {
+        return Err("Saved search name cannot be empty".into());
+    }
+    if query.is_empty() {
+        return Err("Saved search query cannot be empty".into());
+    }
+    let _guard = lock_index();
+    let mut searches = read_saved_searches(root)?;
+    let search = SavedSearch {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        query: query.to_string(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    searches.push(search.clone());
+    write_saved_searches(root, &searches)?;
+    Ok(search)
+}
+
+pub fn delete_saved_search(app_handle: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    remove_saved_search(&storage_root(app_handle)?, id)
+}
+
+/// delete_saved_search for the storage root `root`, under the index lock like add_saved_search.
+pub fn remove_saved_search(root: &Path, id: &str) -> Result<(), String> {
+    let _guard = lock_index();
+    let mut searches = read_saved_searches(root)?;
+    let before = searches.len();
+    searches.retain(|s| s.id != id);
+    if searches.len() == before {
+        return Err("Saved search not found".into());
+    }
+    write_saved_searches(root, &searches)
+}
+
+/// Run a saved search through search_notes.
+pub fn run_saved_search(app_handle: &tauri::AppHandle, id: &str) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let search = read_saved_searches(&root)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or("Saved search not found")?;
+    search_notes(app_handle, &search.query, false)
+}
+
+// --- Favorites ---
+
+fn read_favorites(root: &Path) -> Result<Vec<String>, String> {
+    let path = favorites_path(root);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let s = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&s).map_err(|e| e.to_string())
+}
+
+fn write_favorites(root: &Path, ids: &[String]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(ids).map_err(|e| e.to_string())?;
+    atomic_write(&favorites_path(root), json.as_bytes())
+}
+
+/// Favorite ids resolved to notes in favorites order; ids of deleted (or trashed) notes and repeats
+/// are dropped.
+pub fn resolve_favorites(ids: &[String], notes: &[NoteMeta]) -> Vec<NoteMeta> {
+    let by_id: HashMap<&str, &NoteMeta> = notes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut seen = HashSet::new();
+    ids.iter()
+        .filter(|id| seen.insert(id.as_str()))
+        .filter_map(|id| by_id.get(id.as_str()).map(|n| (*n).clone()))
+        .collect()
+}
+
+/// New favorites order: `ordered` ids that are favorites first, then the remaining favorites in their
+/// current order. Ids in `ordered` that aren't favorites are ignored.
+pub fn reorder_favorite_ids(current: &[String], ordered: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = vec![];
+    for id in ordered.iter().chain(current) {
+        if current.contains(id) && !out.contains(id) {
+            out.push(id.clone());
+        }
+    }
+    out
+}
+
+/// Favorite notes in their manual order (separate from the per-note `important` flag).
+pub fn list_favorites(app_handle: &tauri::AppHandle) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(resolve_favorites(&read_favorites(&root)?, &index.notes))
+}
+
+/// Rewrite favorites.json with `f` applied to the list of ids that still resolve to notes, and return
+/// the resolved favorites.
+fn update_favorites(app_handle: &tauri::AppHandle, f: impl FnOnce(&mut Vec<String>)) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let index = read_index(&root)?;
+    let mut ids: Vec<String> = resolve_favorites(&read_favorites(&root)?, &index.notes)
+        .into_iter()
+        .map(|n| n.id)
+        .collect();
+    f(&mut ids);
+    write_favorites(&root, &ids)?;
+    Ok(resolve_favorites(&ids, &index.notes))
+}
+
+/// Add a note to the end of the favorites (no-op if it is already there).
+pub fn add_favorite(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<NoteMeta>, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    if !read_index(&root)?.notes.iter().any(|n| n.id == note_id) {
+        return Err("Note not found".into());
+    }
+    update_favorites(app_handle, |ids| {
+        if !ids.iter().any(|id| id == note_id) {
+            ids.push(note_id.to_string());
+        }
+    })
+}
+
+pub fn remove_favorite(app_handle: &tauri::AppHandle, note_id: &str) -> Result<Vec<NoteMeta>, String> {
+    update_favorites(app_handle, |ids| ids.retain(|id| id != note_id))
+}
+
+/// Reorder favorites from a drag in the top bar (see reorder_favorite_ids).
+pub fn reorder_favorites(app_handle: &tauri::AppHandle, ordered_ids: &[String]) -> Result<Vec<NoteMeta>, String> {
+    update_favorites(app_handle, |ids| *ids = reorder_favorite_ids(ids, ordered_ids))
+}
+
+// --- Full-text search index ---
+
+/// Lowercased word tokens (maximal runs of alphanumeric characters) in `text`.
+pub fn tokenize_for_index(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Replace a note's postings with the tokens of its current title and body.
+pub fn search_index_add_note(sidx: &mut SearchIndex, note_id: &str, title: &str, body: &str) {
+    search_index_remove_note(sidx, note_id);
+    let mut tokens = tokenize_for_index(title);
+    tokens.extend(tokenize_for_index(body));
+    for token in tokens {
+        let ids = sidx.tokens.entry(token).or_default();
+        if let Err(pos) = ids.binary_search_by(|id| id.as_str().cmp(note_id)) {
+            ids.insert(pos, note_id.to_string());
+        }
+    }
+}
+
+/// Drop every posting for a note (and any token left without notes), and its body stamp.
+pub fn search_index_remove_note(sidx: &mut SearchIndex, note_id: &str) {
+    sidx.tokens.retain(|_, ids| {
+        ids.retain(|id| id != note_id);
+        !ids.is_empty()
+    });
+    sidx.stamps.remove(note_id);
+}
+
+/// (mtime in ns since the epoch, length) of a file, or None if it can't be stat'ed.
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some((mtime.as_nanos() as u64, meta.len()))
+}
+
+/// Record the current body stamp of every note in `notes` that has none (search_index_add_note
+/// clears it), i.e. of the notes just (re)indexed.
+pub fn search_index_stamp_notes(root: &Path, ext: &str, sidx: &mut SearchIndex, notes: &[NoteMeta]) {
+    for n in notes {
+        if !sidx.stamps.contains_key(&n.id) {
+            if let Some(stamp) = file_stamp(&note_path_with_ext(root, &n.id, ext)) {
+                sidx.stamps.insert(n.id.clone(), stamp);
+            }
+        }
+    }
+}
+
+/// Ids in `notes` whose body file changed since the search index took their postings (or that were
+/// never stamped); their index entries can't be trusted.
+pub fn search_index_stale_notes(root: &Path, ext: &str, sidx: &SearchIndex, notes: &[NoteMeta]) -> HashSet<String> {
+    notes
+        .iter()
+        .filter(|n| {
+            let current = file_stamp(&note_path_with_ext(root, &n.id, ext));
+            current.is_none() || sidx.stamps.get(&n.id) != current.as_ref()
+        })
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+/// Ids of notes whose title/body contain `term` as a substring, or None if the index can't answer it exactly.
+/// A term made only of alphanumerics can only occur inside a single indexed token, so scanning the token keys
+/// is exact; anything else (phrases, punctuation) needs the note text.
+pub fn search_index_lookup(sidx: &SearchIndex, term: &str) -> Option<HashSet<String>> {
+    if term.is_empty() || !term.chars().all(|c| c.is_alphanumeric()) {
+        return None;
+    }
+    let term = term.to_lowercase();
+    let mut ids = HashSet::new();
+    for (token, posting) in &sidx.tokens {
+        if token.contains(term.as_str()) {
+            ids.extend(posting.iter().cloned());
+        }
+    }
+    Some(ids)
+}
+
+fn build_search_index(root: &Path, ext: &str, notes: &[NoteMeta]) -> SearchIndex {
+    let mut sidx = SearchIndex::default();
+    for n in notes {
+        let body = fs::read_to_string(note_path_with_ext(root, &n.id, ext)).unwrap_or_default();
+        search_index_add_note(&mut sidx, &n.id, &n.title, &body);
+    }
+    search_index_stamp_notes(root, ext, &mut sidx, notes);
+    sidx
+}
+
+fn write_search_index(root: &Path, sidx: &SearchIndex) -> Result<(), String> {
+    let path = search_index_path(root);
+    let json = serde_json::to_string(sidx).map_err(|e| e.to_string())?;
+    atomic_write(&path, json.as_bytes())
+}
+
+/// None if missing or unreadable, or written before body stamps existed (so its postings can't be
+/// checked against the files).
+fn read_search_index(root: &Path) -> Option<SearchIndex> {
+    let s = fs::read_to_string(search_index_path(root)).ok()?;
+    let sidx: SearchIndex = serde_json::from_str(&s).ok()?;
+    if sidx.stamps.is_empty() && !sidx.tokens.is_empty() {
+        return None;
+    }
+    Some(sidx)
+}
+
+/// Load the search index, rebuilding it from the notes on disk if it is missing or unreadable.
+fn load_or_build_search_index(root: &Path, ext: &str, notes: &[NoteMeta]) -> SearchIndex {
+    if let Some(sidx) = read_search_index(root) {
+        return sidx;
+    }
+    let sidx = build_search_index(root, ext, notes);
+    let _ = write_search_index(root, &sidx);
+    sidx
+}
+
+/// Apply an incremental change to the persisted search index. If there is no index yet, nothing is done
+/// (it is built on the next search); if the update can't be saved, the index is discarded so it gets rebuilt.
+/// Notes `f` (re)added are stamped with their body files as they are now, so call it after writing them.
+fn update_search_index(root: &Path, f: impl FnOnce(&mut SearchIndex)) {
+    let path = search_index_path(root);
+    if !path.exists() {
+        return;
+    }
+    match read_search_index(root) {
+        Some(mut sidx) => {
+            f(&mut sidx);
+            let (Ok(index), Ok(ext)) = (read_index(root), note_extension(root)) else {
+                let _ = fs::remove_file(&path);
+                return;
+            };
+            search_index_stamp_notes(root, &ext, &mut sidx, &index.notes);
+            if write_search_index(root, &sidx).is_err() {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        None => {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Rebuild meta/search_index.json from scratch (recovery). Returns the number of distinct tokens.
+pub fn rebuild_search_index(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let sidx = build_search_index(&root, &note_extension(&root)?, &index.notes);
+    write_search_index(&root, &sidx)?;
+    Ok(sidx.tokens.len())
+}
+
+/// Approximate snippet length (in characters) returned by search_notes_with_snippets.
+const SNIPPET_CHARS: usize = 200;
+
+/// Cut a ~`window`-char snippet of `body` around the first case-insensitive match of any term (or from the start
+/// if none match), and return it with the byte ranges of every term match inside the snippet.
+/// All offsets are on UTF-8 char boundaries of the returned snippet.
+pub fn build_snippet(body: &str, terms: &[String], window: usize) -> (String, Vec<(usize, usize)>) {
+    let alternatives: Vec<String> = terms
+        .iter()
+        .filter(|t| !t.is_empty())
+        .map(|t| regex::escape(t))
+        .collect();
+    let re = if alternatives.is_empty() {
+        None
+    } else {
+        regex::RegexBuilder::new(&alternatives.join("|"))
+            .case_insensitive(true)
+            .build()
+            .ok()
+    };
+    let first = re.as_ref().and_then(|re| re.find(body)).map(|m| m.start()).unwrap_or(0);
+    // Start about half a window before the match, on a char boundary.
+    let before: Vec<usize> = body[..first].char_indices().map(|(i, _)| i).collect();
+    let start = before
+        .len()
+        .checked_sub(window / 2)
+        .map(|i| before[i])
+        .unwrap_or(0);
+    let end = body[start..]
+        .char_indices()
+        .nth(window)
+        .map(|(i, _)| start + i)
+        .unwrap_or(body.len());
+    let snippet = body[start..end].to_string();
+    let ranges = match re {
+        Some(re) => re.find_iter(&snippet).map(|m| (m.start(), m.end())).collect(),
+        None => vec![],
+    };
+    (snippet, ranges)
+}
+
+/// search_notes, windowed by page_notes after filtering and sorting.
+pub fn search_notes_paged(
+    app_handle: &tauri::AppHandle,
+    query: &str,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<NotePage, String> {
+    Ok(page_notes(search_notes(app_handle, query, false)?, offset, limit))
+}
+
+/// Like search_notes, but each hit carries a body snippet around the first match and the matched byte ranges.
+pub fn search_notes_with_snippets(app_handle: &tauri::AppHandle, query: &str) -> Result<Vec<SearchHit>, String> {
+    let notes = search_notes(app_handle, query, false)?;
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let parsed = parse_search_query(query.trim());
+    let terms: Vec<String> = parsed.text_groups.into_iter().flatten().collect();
+    Ok(notes
+        .into_iter()
+        .map(|meta| {
+            let body = fs::read_to_string(note_path_with_ext(&root, &meta.id, &ext)).unwrap_or_default();
+            let (snippet, match_ranges) = build_snippet(&body, &terms, SNIPPET_CHARS);
+            SearchHit {
+                meta,
+                snippet,
+                match_ranges,
+            }
+        })
+        .collect())
+}
+
+/// Upper bound on the compiled size of a user-supplied search regex, to guard against pathological patterns.
+const MAX_SEARCH_REGEX_SIZE: usize = 1 << 20;
+
+/// Compile a user-supplied search pattern (case-insensitive), rejecting invalid or oversized patterns.
+pub fn build_search_regex(pattern: &str) -> Result<regex::Regex, String> {
+    if pattern.trim().is_empty() {
+        return Err("Pattern cannot be empty".into());
+    }
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(MAX_SEARCH_REGEX_SIZE)
+        .dfa_size_limit(MAX_SEARCH_REGEX_SIZE)
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e))
+}
+
+/// Search notes whose title or body matches a regular expression, newest first.
+/// Notes whose body can't be read are skipped.
+pub fn search_notes_regex(app_handle: &tauri::AppHandle, pattern: &str) -> Result<Vec<NoteMeta>, String> {
+    let re = build_search_regex(pattern)?;
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let index = read_index(&root)?;
+    let mut out: Vec<NoteMeta> = index
+        .notes
+        .into_iter()
+        .filter(|n| {
+            let body = match fs::read_to_string(note_path_with_ext(&root, &n.id, &ext)) {
+                Ok(b) => b,
+                Err(_) => return false,
+            };
+            re.is_match(&n.title) || re.is_match(&body)
+        })
+        .collect();
+    out.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    Ok(out)
+}
+
+fn validate_notebook_id(id: &str) -> Result<(), String> {
+    if id.is_empty() {
+        return Err("Notebook id cannot be empty".into());
+    }
+    if id.contains('/') || id.contains('\\') || id == "." || id == ".." {
+        return Err("Invalid notebook id".into());
+    }
+    if id.chars().any(|c| c.is_control()) {
+        return Err("Invalid notebook id".into());
+    }
+    Ok(())
+}
+
+/// Order notebooks for tree rendering: depth-first, so every parent comes before its children.
+/// Siblings are non-archived first, then by created_at (id breaks ties). Notebooks whose parent
+/// no longer exists are treated as top level.
+pub fn sort_notebooks_for_tree(mut notebooks: Vec<Notebook>) -> Vec<Notebook> {
+    notebooks.sort_by(|a, b| {
+        let a_archived = a.archived as u8;
+        let b_archived = b.archived as u8;
+        a_archived
+            .cmp(&b_archived)
+            .then_with(|| a.created_at.cmp(&b.created_at))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    let ids: HashSet<String> = notebooks.iter().map(|nb| nb.id.clone()).collect();
+    let parent_of = |nb: &Notebook| nb.parent_id.clone().filter(|p| ids.contains(p));
+    let mut out: Vec<Notebook> = Vec::with_capacity(notebooks.len());
+    let mut placed: HashSet<String> = HashSet::new();
+    // Explicit stack (children pushed in reverse) keeps sibling order without recursion.
+    let mut stack: Vec<&Notebook> = notebooks.iter().filter(|nb| parent_of(nb).is_none()).rev().collect();
+    while let Some(nb) = stack.pop() {
+        if !placed.insert(nb.id.clone()) {
+            continue;
+        }
+        out.push(nb.clone());
+        stack.extend(
+            notebooks
+                .iter()
+                .filter(|c| parent_of(c).as_deref() == Some(nb.id.as_str()))
+                .rev(),
+        );
+    }
+    // Anything left is part of a parent cycle in a hand-edited index; keep it rather than drop it.
+    for nb in notebooks.iter() {
+        if !placed.contains(&nb.id) {
+            out.push(nb.clone());
+        }
+    }
+    out
+}
+
+/// True if making `new_parent` the parent of `notebook_id` would create a cycle.
+pub fn notebook_creates_cycle(notebooks: &[Notebook], notebook_id: &str, new_parent: &str) -> bool {
+    let mut current = Some(new_parent.to_string());
+    let mut seen: HashSet<String> = HashSet::new();
+    while let Some(id) = current {
+        if id == notebook_id {
+            return true;
+        }
+        if !seen.insert(id.clone()) {
+            // Existing cycle elsewhere; not one we'd be creating.
+            return false;
+        }
+        current = notebooks
+            .iter()
+            .find(|nb| nb.id == id)
+            .and_then(|nb| nb.parent_id.clone());
+    }
+    false
+}
+
+/// Ids of all notebooks nested (at any depth) under `notebook_id`.
+pub fn notebook_descendants(notebooks: &[Notebook], notebook_id: &str) -> Vec<String> {
+    let mut out: Vec<String> = vec![];
+    let mut queue: Vec<String> = vec![notebook_id.to_string()];
+    while let Some(id) = queue.pop() {
+        for nb in notebooks.iter() {
+            if nb.parent_id.as_deref() == Some(id.as_str()) && nb.id != notebook_id && !out.contains(&nb.id) {
+                out.push(nb.id.clone());
+                queue.push(nb.id.clone());
+            }
+        }
+    }
+    out
+}
+
+/// List all notebooks in tree order (see sort_notebooks_for_tree).
+pub fn list_notebooks(app_handle: &tauri::AppHandle) -> Result<Vec<Notebook>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(sort_notebooks_for_tree(index.notebooks))
+}
+
+/// Create a new notebook, optionally nested under a parent. Name must be non-empty.
+pub fn create_notebook(
+    app_handle: &tauri::AppHandle,
+    name: &str,
+    parent_id: Option<&str>,
+) -> Result<Notebook, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Notebook name cannot be empty".into());
+    }
+    if let Some(pid) = parent_id {
+        validate_notebook_id(pid)?;
+    }
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    if let Some(pid) = parent_id {
+        if !index.notebooks.iter().any(|nb| nb.id == pid) {
+            return Err("Parent notebook not found".into());
+        }
+    }
+    let id = Uuid::new_v4().to_string();
+    validate_notebook_id(&id)?;
+    let now = Utc::now().to_rfc3339();
+    let notebook = Notebook {
+        id: id.clone(),
+        name: name.to_string(),
+        archived: false,
+        created_at: now.clone(),
+        parent_id: parent_id.map(String::from),
+    };
+    index.notebooks.push(notebook.clone());
+    write_index(&root, &index, false)?;
+    Ok(notebook)
+}
+
+/// Move a note into a notebook. Use None to move to unfiled.
+pub fn move_note_to_notebook(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    notebook_id: Option<&str>,
+) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    if let Some(nid) = notebook_id {
+        validate_notebook_id(nid)?;
+    }
+    let root = storage_root(app_handle)?;
+    let bump = read_settings(&root)?.bump_updated_on_metadata_change;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    if let Some(nid) = notebook_id {
+        if !index.notebooks.iter().any(|nb| nb.id == nid) {
+            return Err("Notebook not found".into());
+        }
+    }
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.notebook_id = notebook_id.map(String::from);
+    touch_metadata(n, &Utc::now().to_rfc3339(), bump);
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Notes grouped by notebook (unfiled first, then notebooks in list_notebooks order), each group sorted by compare_sidebar_order.
+pub fn list_notes_grouped_by_notebook(app_handle: &tauri::AppHandle) -> Result<Vec<NotebookNotes>, String> {
+    let notebooks = list_notebooks(app_handle)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let mut groups: Vec<NotebookNotes> = vec![NotebookNotes {
+        notebook_id: None,
+        notes: vec![],
+    }];
+    groups.extend(notebooks.into_iter().map(|nb| NotebookNotes {
+        notebook_id: Some(nb.id),
+        notes: vec![],
+    }));
+    for n in index.notes {
+        // Notes pointing at a notebook that no longer exists are shown as unfiled.
+        let pos = groups
+            .iter()
+            .position(|g| g.notebook_id.is_some() && g.notebook_id == n.notebook_id)
+            .unwrap_or(0);
+        groups[pos].notes.push(n);
+    }
+    for g in groups.iter_mut() {
+        g.notes.sort_by(compare_sidebar_order);
+    }
+    Ok(groups)
+}
+
+/// Notes in one notebook (None = unfiled), sorted by compare_sidebar_order. Notes pointing at a missing
+/// notebook count as unfiled, matching the grouped listing; archived notebooks still return their notes.
+pub fn filter_notes_by_notebook(notebooks: &[Notebook], notes: Vec<NoteMeta>, notebook_id: Option<&str>) -> Vec<NoteMeta> {
+    let known: HashSet<&str> = notebooks.iter().map(|nb| nb.id.as_str()).collect();
+    let mut out: Vec<NoteMeta> = notes
+        .into_iter()
+        .filter(|n| n.notebook_id.as_deref().filter(|id| known.contains(id)) == notebook_id)
+        .collect();
+    out.sort_by(compare_sidebar_order);
+    out
+}
+
+/// List the notes in one notebook (None = unfiled) in a single index read. Unknown notebook ids are an error.
+pub fn notes_by_notebook(app_handle: &tauri::AppHandle, notebook_id: Option<&str>) -> Result<Vec<NoteMeta>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    if let Some(id) = notebook_id {
+        if !index.notebooks.iter().any(|nb| nb.id == id) {
+            return Err("Notebook not found".into());
+        }
+    }
+    Ok(filter_notes_by_notebook(&index.notebooks, index.notes, notebook_id))
+}
+
+/// Count notes per notebook in one pass: unfiled first, then every notebook in tree order (including
+/// empty ones). Notes pointing at a missing notebook count as unfiled, matching the grouped listing.
+pub fn count_notes_by_notebook(notebooks: &[Notebook], notes: &[NoteMeta]) -> Vec<NotebookCount> {
+    let known: HashSet<&str> = notebooks.iter().map(|nb| nb.id.as_str()).collect();
+    let mut counts: HashMap<Option<&str>, (usize, usize)> = HashMap::new();
+    for n in notes {
+        let key = n.notebook_id.as_deref().filter(|id| known.contains(id));
+        let entry = counts.entry(key).or_default();
+        entry.0 += 1;
+        if n.important {
+            entry.1 += 1;
+        }
+    }
+    let mut ids: Vec<Option<String>> = vec![None];
+    ids.extend(sort_notebooks_for_tree(notebooks.to_vec()).into_iter().map(|nb| Some(nb.id)));
+    ids.into_iter()
+        .map(|id| {
+            let (total, important) = counts.get(&id.as_deref()).copied().unwrap_or_default();
+            NotebookCount {
+                notebook_id: id,
+                total,
+                important,
+            }
+        })
+        .collect()
+}
+
+/// Note counts per notebook (trashed notes live outside index.notes, so they're not counted).
+pub fn notebook_counts(app_handle: &tauri::AppHandle) -> Result<Vec<NotebookCount>, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    Ok(count_notes_by_notebook(&index.notebooks, &index.notes))
+}
+
+/// Pin a note at the given position within its notebook (None to unpin).
+pub fn set_note_order(app_handle: &tauri::AppHandle, note_id: &str, order: Option<u32>) -> Result<NoteMeta, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
+    n.pin_order = order;
+    let meta = n.clone();
+    write_index(&root, &index, false)?;
+    Ok(meta)
+}
+
+/// Assign pin_order 1, 2, 3… to the given notes in order. All ids must belong to the notebook (None = unfiled);
+/// notes in that notebook that aren't listed are unpinned.
+pub fn reorder_notes_in_notebook(
+    app_handle: &tauri::AppHandle,
+    notebook_id: Option<&str>,
+    ordered_ids: &[String],
+) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    for id in ordered_ids {
+        let n = index.notes.iter().find(|n| n.id == *id).ok_or("Note not found")?;
+        if n.notebook_id.as_deref() != notebook_id {
+            return Err("Note is not in this notebook".into());
+        }
+    }
+    for n in index.notes.iter_mut() {
+        if n.notebook_id.as_deref() != notebook_id {
+            continue;
+        }
+        n.pin_order = ordered_ids
+            .iter()
+            .position(|id| *id == n.id)
+            .map(|i| i as u32 + 1);
+    }
+    write_index(&root, &index, false)?;
+    Ok(())
+}
+
+/// Move a notebook under a new parent (None = top level). Rejects moves that would create a cycle.
+pub fn move_notebook(
+    app_handle: &tauri::AppHandle,
+    notebook_id: &str,
+    new_parent: Option<&str>,
+) -> Result<Notebook, String> {
+    validate_notebook_id(notebook_id)?;
+    if let Some(pid) = new_parent {
+        validate_notebook_id(pid)?;
+    }
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    if !index.notebooks.iter().any(|nb| nb.id == notebook_id) {
+        return Err("Notebook not found".into());
+    }
+    if let Some(pid) = new_parent {
+        if !index.notebooks.iter().any(|nb| nb.id == pid) {
+            return Err("Parent notebook not found".into());
+        }
+        if notebook_creates_cycle(&index.notebooks, notebook_id, pid) {
+            return Err("Cannot move a notebook into itself or one of its sub-notebooks".into());
+        }
+    }
+    let nb = index.notebooks.iter_mut().find(|n| n.id == notebook_id).ok_or("Notebook not found")?;
+    nb.parent_id = new_parent.map(String::from);
+    let notebook = nb.clone();
+    write_index(&root, &index, false)?;
+    Ok(notebook)
+}
+
+/// Archive or unarchive a notebook. With `cascade`, its sub-notebooks are updated too.
+pub fn archive_notebook(
+    app_handle: &tauri::AppHandle,
+    notebook_id: &str,
+    archived: bool,
+    cascade: bool,
+) -> Result<Notebook, String> {
+    validate_notebook_id(notebook_id)?;
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let descendants = if cascade {
+        notebook_descendants(&index.notebooks, notebook_id)
+    } else {
+        vec![]
+    };
+    for nb in index.notebooks.iter_mut() {
+        if descendants.contains(&nb.id) {
+            nb.archived = archived;
+        }
+    }
+    let nb = index.notebooks.iter_mut().find(|n| n.id == notebook_id).ok_or("Notebook not found")?;
+    nb.archived = archived;
+    let notebook = nb.clone();
+    write_index(&root, &index, false)?;
+    Ok(notebook)
+}
+
+/// Remove a notebook from the index (in memory) and apply the strategy to its member notes.
+/// Sub-notebooks move up to the deleted notebook's parent. Returns the ids of the affected notes.
+pub fn remove_notebook_from_index(
+    index: &mut IndexFile,
+    notebook_id: &str,
+    strategy: NotebookDeleteStrategy,
+) -> Result<Vec<String>, String> {
+    let pos = index
+        .notebooks
+        .iter()
+        .position(|nb| nb.id == notebook_id)
+        .ok_or("Notebook not found")?;
+    let removed = index.notebooks.remove(pos);
+    for nb in index.notebooks.iter_mut() {
+        if nb.parent_id.as_deref() == Some(notebook_id) {
+            nb.parent_id = removed.parent_id.clone();
+        }
+    }
+    let member_ids: Vec<String> = index
+        .notes
+        .iter()
+        .filter(|n| n.notebook_id.as_deref() == Some(notebook_id))
+        .map(|n| n.id.clone())
+        .collect();
+    match strategy {
+        NotebookDeleteStrategy::MoveToUnfiled => {
+            for n in index.notes.iter_mut() {
+                if n.notebook_id.as_deref() == Some(notebook_id) {
+                    n.notebook_id = None;
+                }
+            }
+        }
+        NotebookDeleteStrategy::DeleteNotes => move_index_entries_to_trash(index, &member_ids),
+    }
+    Ok(member_ids)
+}
+
+/// Delete a notebook, either moving its notes to unfiled or deleting them (to the trash).
+/// Returns how many notes were affected.
+pub fn delete_notebook(
+    app_handle: &tauri::AppHandle,
+    notebook_id: &str,
+    strategy: NotebookDeleteStrategy,
+) -> Result<usize, String> {
+    validate_notebook_id(notebook_id)?;
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let affected = remove_notebook_from_index(&mut index, notebook_id, strategy)?;
+    let trashing = strategy == NotebookDeleteStrategy::DeleteNotes;
+    if trashing {
+        trash_note_files(&root, &affected, &ext)?;
+    }
+    if let Err(e) = write_index(&root, &index, false) {
+        if trashing {
+            untrash_note_files(&root, &affected, &ext);
+        }
+        return Err(e);
+    }
+    if trashing {
+        drop_from_search_index(&root, &affected);
+    }
+    Ok(affected.len())
+}
+
+/// Rename a notebook.
+pub fn update_notebook_name(
+    app_handle: &tauri::AppHandle,
+    notebook_id: &str,
+    new_name: &str,
+) -> Result<Notebook, String> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err("Notebook name cannot be empty".into());
+    }
+    validate_notebook_id(notebook_id)?;
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    let nb = index.notebooks.iter_mut().find(|n| n.id == notebook_id).ok_or("Notebook not found")?;
+    nb.name = new_name.to_string();
+    let notebook = nb.clone();
+    write_index(&root, &index, false)?;
+    Ok(notebook)
+}
+
+// --- Settings ---
+
+fn settings_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("settings.json")
+}
+
+/// The stored settings, or the defaults if settings.json doesn't exist. An unreadable or corrupt file
+/// is an error: falling back to the defaults would silently switch the note extension (and every other
+/// setting) until the next save overwrote the user's file.
+fn read_settings(root: &Path) -> Result<Settings, String> {
+    let path = settings_path(root);
+    let s = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Settings::default()),
+        Err(e) => return Err(format!("Cannot read settings: {}", e)),
+    };
+    serde_json::from_str(&s).map_err(|e| format!("Settings are corrupt: {}", e))
+}
+
+fn write_settings(root: &Path, settings: &Settings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    atomic_write(&settings_path(root), json.as_bytes())
+}
+
+/// Effective per-note version limit: falls back to the default when the stored value is invalid
+/// (0 or negative) and is clamped to 1..=1000.
+pub fn max_versions_per_note(settings: &Settings) -> usize {
+    if settings.max_versions_per_note <= 0 {
+        return MAX_VERSIONS_PER_NOTE;
+    }
+    (settings.max_versions_per_note as usize).min(MAX_VERSIONS_PER_NOTE_LIMIT)
+}
+
+/// Validate settings before saving. `maxVersionsPerNote` of 0 or below is rejected; values above
+/// 1000 are clamped. A `clipboardImagesFolder` must be absolute; blank means the default.
+pub fn validate_settings(mut settings: Settings) -> Result<Settings, String> {
+    if settings.max_versions_per_note <= 0 {
+        return Err("maxVersionsPerNote must be at least 1".into());
+    }
+    settings.daily_note_title_format = settings.daily_note_title_format.filter(|f| !f.trim().is_empty());
+    if let Some(format) = &settings.daily_note_title_format {
+        validate_daily_title_format(format)?;
+    }
+    settings.daily_note_template_id = settings.daily_note_template_id.filter(|id| !id.trim().is_empty());
+    settings.clipboard_images_folder = settings
+        .clipboard_images_folder
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty());
+    if settings.clipboard_images_folder.as_deref().is_some_and(|f| !Path::new(f).is_absolute()) {
+        return Err("clipboardImagesFolder must be an absolute path".into());
+    }
+    settings.max_versions_per_note = settings
+        .max_versions_per_note
+        .min(MAX_VERSIONS_PER_NOTE_LIMIT as i64);
+    Ok(settings)
+}
+
+pub fn get_settings(app_handle: &tauri::AppHandle) -> Result<Settings, String> {
+    let root = storage_root(app_handle)?;
+    read_settings(&root)
+}
+
+/// Replace the settings. A lower version limit takes effect on each note's next save. The note file
+/// extension is kept as stored, since changing it means renaming files (see migrate_note_extension).
+pub fn update_settings(app_handle: &tauri::AppHandle, settings: Settings) -> Result<Settings, String> {
+    let root = storage_root(app_handle)?;
+    let mut settings = validate_settings(settings)?;
+    settings.note_file_extension = read_settings(&root)?.note_file_extension;
+    write_settings(&root, &settings)?;
+    Ok(settings)
+}
+
+/// Switch note files to `new_ext`: each notes/<id>.<old> is renamed to <id>.<new>, every note's `filename`
+/// is updated and the setting is saved. Nothing changes if one of the new names is already taken, and a
+/// failure part-way renames the files back. Returns the number of files renamed.
+pub fn migrate_note_extension(app_handle: &tauri::AppHandle, new_ext: &str) -> Result<usize, String> {
+    let root = storage_root(app_handle)?;
+    let new_ext = validate_note_extension(new_ext)?;
+    let _guard = lock_index();
+    let mut index = read_index(&root)?;
+    if new_ext == note_extension(&root)? {
+        return Ok(0);
+    }
+    let moves: Vec<(PathBuf, PathBuf)> = note_files(&root, &note_extension(&root)?)
+        .into_iter()
+        .map(|(id, path)| (path, notes_dir(&root).join(note_filename(&id, &new_ext))))
+        .collect();
+    if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+        return Err(format!("{} already exists", to.display()));
+    }
+    let undo = |done: &[(PathBuf, PathBuf)]| {
+        for (from, to) in done {
+            let _ = fs::rename(to, from);
+        }
+    };
+    for (i, (from, to)) in moves.iter().enumerate() {
+        if let Err(e) = fs::rename(from, to) {
+            undo(&moves[..i]);
+            return Err(e.to_string());
+        }
+    }
+    let mut settings = read_settings(&root)?;
+    settings.note_file_extension = new_ext.clone();
+    if let Err(e) = write_settings(&root, &settings) {
+        undo(&moves);
+        return Err(e);
+    }
+    for n in index.notes.iter_mut().chain(index.trashed.iter_mut()) {
+        n.filename = note_filename(&sanitize_filename(&n.id), &new_ext);
+    }
+    write_index(&root, &index, false)?;
+    Ok(moves.len())
+}
+
+/// Set how many days trashed notes are kept before being purged on startup (0 = keep forever).
+pub fn set_trash_retention_days(app_handle: &tauri::AppHandle, days: u32) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let mut settings = read_settings(&root)?;
+    settings.trash_retention_days = days;
+    write_settings(&root, &settings)
+}
+
+// --- Storage location ---
+
+/// Subdirectories moved when the storage root changes with `migrate`.
+const STORAGE_SUBDIRS: [&str; 6] = ["notes", "meta", "images", "attachments", "versions", "trash"];
+
+/// Check a new storage root against the current one: it must be absolute and not inside the current root.
+/// Both are compared as resolved paths (see resolve_path), so a symlink or `..` can't hide the nesting.
+pub fn validate_storage_root_change(current: &Path, new: &Path) -> Result<(), String> {
+    if !new.is_absolute() {
+        return Err("Storage location must be an absolute path".into());
+    }
+    let (current, new) = (resolve_path(current), resolve_path(new));
+    if new != current && new.starts_with(&current) {
+        return Err("Storage location cannot be inside the current storage folder".into());
+    }
+    Ok(())
+}
+
+/// `path` canonicalized as far as it exists, with the part that doesn't exist yet appended and its
+/// `.`/`..` folded.
+fn resolve_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut lexical = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            c => lexical.push(c),
+        }
+    }
+    let mut existing = lexical.as_path();
+    let mut missing: Vec<&std::ffi::OsStr> = vec![];
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return missing.iter().rev().fold(resolved, |p, part| p.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return lexical,
+        }
+    }
+}
+
+/// One storage subdirectory moved by migrate_storage_dirs: renamed in one step, or copied (when
+/// rename fails, e.g. across volumes) with the original still in place.
+struct MovedDir {
+    src: PathBuf,
+    dest: PathBuf,
+    renamed: bool,
+}
+
+/// Move the storage subdirectories from `current` to `new`, all or nothing. `commit` runs once every
+/// directory is in place (set_storage_root saves the new location there). If a move or `commit` fails,
+/// renamed directories are moved back and copies removed, leaving `current` as it was; originals that
+/// were copied are deleted only after `commit` succeeds.
+pub fn migrate_storage_dirs(current: &Path, new: &Path, commit: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    if let Some(dir) = STORAGE_SUBDIRS.iter().find(|d| current.join(d).exists() && new.join(d).exists()) {
+        return Err(format!("The new location already contains {}/", dir));
+    }
+    let mut moved: Vec<MovedDir> = vec![];
+    let mut result = Ok(());
+    for dir in STORAGE_SUBDIRS {
+        let (src, dest) = (current.join(dir), new.join(dir));
+        if !src.exists() {
+            continue;
+        }
+        if fs::rename(&src, &dest).is_ok() {
+            moved.push(MovedDir { src, dest, renamed: true });
+            continue;
+        }
+        let copied = copy_dir_all(&src, &dest, &mut |_| {});
+        moved.push(MovedDir { src, dest, renamed: false });
+        if let Err(e) = copied {
+            result = Err(e);
+            break;
+        }
+    }
+    if let Err(e) = result.and_then(|_| commit()) {
+        for m in moved.iter().rev() {
+            if m.renamed {
+                let _ = fs::rename(&m.dest, &m.src);
+            } else {
+                let _ = fs::remove_dir_all(&m.dest);
+            }
+        }
+        return Err(e);
+    }
+    // The new location is live now; an original that can't be removed is only a leftover copy.
+    for m in moved.iter().filter(|m| !m.renamed) {
+        let _ = fs::remove_dir_all(&m.src);
+    }
+    Ok(())
+}
+
+pub fn get_storage_root(app_handle: &tauri::AppHandle) -> Result<String, String> {
+    Ok(storage_root(app_handle)?.to_string_lossy().to_string())
+}
+
+/// Point storage at `path` (None = back to the default location). With `migrate`, the current notes/,
+/// meta/, images/, attachments/, versions/ and trash/ are moved there first, all or nothing (see
+/// migrate_storage_dirs); the move is refused if the new location already has any of them. Without
+/// `migrate`, this also works when the current location is missing. Returns the new root.
+pub fn set_storage_root(app_handle: &tauri::AppHandle, path: Option<&str>, migrate: bool) -> Result<String, String> {
+    let current = if migrate { Some(storage_root(app_handle)?) } else { storage_root(app_handle).ok() };
+    let new = match path {
+        Some(p) => PathBuf::from(p.trim()),
+        None => default_storage_root(app_handle)?,
+    };
+    match &current {
+        Some(current) => validate_storage_root_change(current, &new)?,
+        None if !new.is_absolute() => return Err("Storage location must be an absolute path".into()),
+        None => {}
+    }
+    let settings = RootSettings {
+        storage_root: path.map(|_| new.to_string_lossy().to_string()),
+    };
+    let settings_path = root_settings_path(app_handle)?;
+    if let Some(parent) = settings_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+    let save = || atomic_write(&settings_path, json.as_bytes());
+    let _guard = lock_index();
+    match current {
+        Some(current) if migrate && resolve_path(&current) != resolve_path(&new) => {
+            fs::create_dir_all(&new).map_err(|e| e.to_string())?;
+            migrate_storage_dirs(&current, &new, save)?;
+        }
+        _ => {
+            fs::create_dir_all(&new).map_err(|e| e.to_string())?;
+            save()?;
+        }
+    }
+    allow_asset_access(app_handle, &new)?;
+    init_storage(app_handle)?;
+    Ok(new.to_string_lossy().to_string())
+}
+
+/// Let the webview load images and attachments under `root` through the asset protocol. The scope in
+/// tauri.conf.json only covers the default location, so a custom root has to be added at runtime.
+pub fn allow_asset_access(app_handle: &tauri::AppHandle, root: &Path) -> Result<(), String> {
+    app_handle
+        .asset_protocol_scope()
+        .allow_directory(root, true)
+        .map_err(|e| e.to_string())
+}
+
+/// Total size in bytes of the files under `path` (0 if it does not exist).
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else { return 0 };
+    entries
+        .flatten()
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&e.path()),
+            Ok(_) => e.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Disk usage by category and per note. Attachment sizes come from ImageRef.size when recorded and
+/// from the file otherwise; version history is measured on disk.
+pub fn storage_usage(app_handle: &tauri::AppHandle) -> Result<StorageUsage, String> {
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let mut usage = StorageUsage {
+        notes_bytes: dir_size(&notes_dir(&root)),
+        versions_bytes: dir_size(&root.join("versions")),
+        ..Default::default()
+    };
+    for n in index.notes.iter().filter(|n| n.deleted_at.is_none()) {
+        let mut seen = HashSet::new();
+        let image_bytes: u64 = n
+            .images
+            .iter()
+            .filter(|img| seen.insert(img.path.as_str()))
+            .map(|img| {
+                img.size
+                    .or_else(|| fs::metadata(root.join(&img.path)).ok().map(|m| m.len()))
+                    .unwrap_or(0)
+            })
+            .sum();
+        let version_bytes = dir_size(&versions_dir(&root, &n.id));
+        usage.images_bytes += image_bytes;
+        usage.per_note.push(NoteStorageUsage {
+            note_id: n.id.clone(),
+            image_bytes,
+            version_bytes,
+        });
+    }
+    usage.per_note.sort_by_key(|u| std::cmp::Reverse(u.image_bytes + u.version_bytes));
+    usage.total_bytes = usage.notes_bytes
+        + usage.images_bytes
+        + usage.versions_bytes
+        + dir_size(&meta_dir(&root))
+        + dir_size(&root.join("trash"));
+    Ok(usage)
+}
+
+/// Names find_orphans never reports, so cleanup_orphans can't delete them: `.tmp` files of atomic
+/// writes that may be in flight, and `.conflict` copies (sync kept them next to the original before
+/// they moved to conflicts/).
+fn is_orphan_scan_exempt(name: &str) -> bool {
+    name.ends_with(".tmp") || name.ends_with(".conflict")
+}
+
+/// Files and folders under images/, attachments/ and versions/ that nothing in `notes` owns, as
+/// (storage-relative path, bytes). A folder for an unknown note id is reported whole; inside a known
+/// note's folder, files no ImageRef points at are reported. Thumbnail caches and anything
+/// is_orphan_scan_exempt matches are skipped.
+pub fn find_orphans(root: &Path, notes: &[NoteMeta]) -> Vec<(String, u64)> {
+    let owners: HashMap<String, &NoteMeta> = notes.iter().map(|n| (sanitize_filename(&n.id), n)).collect();
+    let mut orphans = vec![];
+    for top in ["images", "attachments", "versions"] {
+        let Ok(entries) = fs::read_dir(root.join(top)) else { continue };
+        let mut entries: Vec<fs::DirEntry> = entries.flatten().collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_orphan_scan_exempt(&name) {
+                continue;
+            }
+            let rel = format!("{}/{}", top, name);
+            if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                orphans.push((rel, entry.metadata().map(|m| m.len()).unwrap_or(0)));
+                continue;
+            }
+            let Some(owner) = owners.get(&name) else {
+                orphans.push((rel, dir_size(&entry.path())));
+                continue;
+            };
+            if top == "versions" {
+                continue;
+            }
+            let referenced: HashSet<&str> = owner.images.iter().map(|img| img.path.as_str()).collect();
+            let Ok(files) = fs::read_dir(entry.path()) else { continue };
+            let mut files: Vec<fs::DirEntry> = files.flatten().collect();
+            files.sort_by_key(|e| e.file_name());
+            for file in files {
+                let file_name = file.file_name().to_string_lossy().to_string();
+                let is_file = file.file_type().map(|t| t.is_file()).unwrap_or(false);
+                let file_rel = format!("{}/{}/{}", top, owner.id, file_name);
+                if is_file && !is_orphan_scan_exempt(&file_name) && !referenced.contains(file_rel.as_str()) {
+                    orphans.push((file_rel, file.metadata().map(|m| m.len()).unwrap_or(0)));
+                }
+            }
+        }
+    }
+    orphans
+}
+
+/// Report (dry_run) or delete files and folders left behind without an owning note or ImageRef,
+/// e.g. after a crash. Trashed notes still own their files; `.tmp` and `.conflict` files are never
+/// touched (see is_orphan_scan_exempt). The index lock is held from the scan through the deletes;
+/// commands that add attachment files hold it until the index lists them, so nothing in flight is
+/// mistaken for an orphan.
+pub fn cleanup_orphans(app_handle: &tauri::AppHandle, dry_run: bool) -> Result<OrphanReport, String> {
+    let root = storage_root(app_handle)?;
+    let _guard = lock_index();
+    let index = read_index(&root)?;
+    let orphans = find_orphans(&root, &index.notes);
+    if !dry_run {
+        for (rel, _) in &orphans {
+            let path = root.join(rel);
+            if path.is_dir() {
+                fs::remove_dir_all(&path).map_err(|e| e.to_string())?;
+            } else {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+                remove_thumbnails(&path, None);
+            }
+        }
+    }
+    Ok(OrphanReport {
+        total_bytes: orphans.iter().map(|(_, size)| size).sum(),
+        paths: orphans.into_iter().map(|(rel, _)| rel).collect(),
+    })
+}
+
+// --- App lock ---
+
+/// PBKDF2-HMAC-SHA256 rounds used to derive the lock key from the passphrase.
+const LOCK_KDF_ROUNDS: u32 = 100_000;
+
+/// Salt and verifier for the lock passphrase (meta/lock.json). The derived key itself is never stored;
+/// `verifier` is the SHA-256 of the key, which is enough to check a passphrase on unlock.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LockConfig {
+    salt: String,
+    verifier: String,
+}
+
+/// In-memory unlock state, managed by Tauri. Holds the derived key only while unlocked.
+#[derive(Default)]
+pub struct LockState {
+    inner: std::sync::Mutex<LockInner>,
+}
+
+#[derive(Default)]
+struct LockInner {
+    key: Option<[u8; 32]>,
+    last_activity: Option<std::time::Instant>,
+}
+
+impl LockInner {
+    fn clear(&mut self) {
+        if let Some(key) = self.key.as_mut() {
+            key.fill(0);
+        }
+        self.key = None;
+        self.last_activity = None;
+    }
+
+    /// Drop the key if it has been idle for longer than `auto_lock_minutes` (0 = never).
+    fn expire_if_idle(&mut self, auto_lock_minutes: u32) {
+        if auto_lock_minutes == 0 {
+            return;
+        }
+        let limit = std::time::Duration::from_secs(u64::from(auto_lock_minutes) * 60);
+        if self.last_activity.is_some_and(|t| t.elapsed() >= limit) {
+            self.clear();
+        }
+    }
+}
+
+fn lock_config_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("lock.json")
+}
+
+/// The lock passphrase config; Ok(None) only when lock.json doesn't exist. An unreadable or corrupt
+/// file is an error, so callers fail closed (locked) rather than treating it as "no passphrase".
+fn read_lock_config(root: &Path) -> Result<Option<LockConfig>, String> {
+    let path = lock_config_path(root);
+    let s = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Cannot read lock settings: {}", e)),
+    };
+    serde_json::from_str(&s).map(Some).map_err(|e| format!("Lock settings are corrupt: {}", e))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Derive the 32-byte lock key from a passphrase and salt.
+pub fn derive_lock_key(passphrase: &str, salt: &str) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt.as_bytes(), LOCK_KDF_ROUNDS, &mut key);
+    key
+}
+
+/// Hex SHA-256 of a derived key, stored to verify passphrases.
+pub fn lock_key_verifier(key: &[u8; 32]) -> String {
+    use sha2::{Digest, Sha256};
+    to_hex(&Sha256::digest(key))
+}
+
+/// Ok if no lock passphrase is set or the app is unlocked (and not idle past the auto-lock limit);
+/// otherwise Err("Locked"). Counts as activity for auto-lock. Commands that return or export note
+/// content (bodies, previews, snippets, versions, backups) call this first.
+pub fn ensure_unlocked(app_handle: &tauri::AppHandle, state: &LockState) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    match read_lock_config(&root) {
+        Ok(None) => return Ok(()),
+        Ok(Some(_)) => {}
+        Err(_) => return Err("Locked".into()),
     }
-    let new_name = sanitize_filename(new_name.trim());
-    if new_name.is_empty() {
-        return Err("Name cannot be empty".into());
+    let minutes = read_settings(&root)?.auto_lock_minutes;
+    let mut inner = state.inner.lock().map_err(|e| e.to_string())?;
+    inner.expire_if_idle(minutes);
+    if inner.key.is_none() {
+        return Err("Locked".into());
     }
+    inner.last_activity = Some(std::time::Instant::now());
+    Ok(())
+}
+
+/// Whether the app is currently locked (a passphrase is set and it hasn't been unlocked, or it idled out).
+pub fn is_locked(app_handle: &tauri::AppHandle, state: &LockState) -> Result<bool, String> {
     let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    if let Some(img) = n.images.iter_mut().find(|img| img.path == relative_path) {
-        img.name = new_name;
-    } else {
-        return Err("Attachment not found".into());
+    match read_lock_config(&root) {
+        Ok(None) => return Ok(false),
+        Ok(Some(_)) => {}
+        Err(_) => return Ok(true),
     }
-    n.updated_at = Utc::now().to_rfc3339();
-    let meta = n.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
+    let minutes = read_settings(&root)?.auto_lock_minutes;
+    let mut inner = state.inner.lock().map_err(|e| e.to_string())?;
+    inner.expire_if_idle(minutes);
+    Ok(inner.key.is_none())
 }
 
-/// GFM task list: detect lines like "- [ ]" or "- [x]" (or "*").
-fn body_has_task_lines(body: &str) -> (bool, bool) {
-    let mut has_unchecked = false;
-    let mut has_checked = false;
-    for line in body.lines() {
-        let t = line.trim();
-        if t.starts_with("- [ ]") || t.starts_with("* [ ]") {
-            has_unchecked = true;
-        }
-        if t.starts_with("- [x]") || t.starts_with("- [X]") || t.starts_with("* [x]") || t.starts_with("* [X]") {
-            has_checked = true;
-        }
-        if has_unchecked && has_checked {
-            break;
-        }
+/// Lock now: forget the derived key.
+pub fn lock(state: &LockState) -> Result<(), String> {
+    state.inner.lock().map_err(|e| e.to_string())?.clear();
+    Ok(())
+}
+
+/// Unlock with the passphrase. Succeeds trivially when no passphrase is set.
+pub fn unlock(app_handle: &tauri::AppHandle, state: &LockState, passphrase: &str) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let Some(config) = read_lock_config(&root)? else {
+        return Ok(());
+    };
+    let key = derive_lock_key(passphrase, &config.salt);
+    if lock_key_verifier(&key) != config.verifier {
+        return Err("Incorrect passphrase".into());
     }
-    (has_unchecked, has_checked)
+    let mut inner = state.inner.lock().map_err(|e| e.to_string())?;
+    inner.key = Some(key);
+    inner.last_activity = Some(std::time::Instant::now());
+    Ok(())
 }
 
-/// Search notes: full-text (title + body), operators tag: is:starred date:today|week|month has:attachments has:tasks is:completed is:uncompleted.
-pub fn search_notes(
+/// Set, change, or remove (new = None) the lock passphrase. If one is already set, `current` must match.
+/// Setting a passphrase leaves the app unlocked for this session.
+pub fn set_lock_passphrase(
     app_handle: &tauri::AppHandle,
-    query: &str,
-) -> Result<Vec<NoteMeta>, String> {
+    state: &LockState,
+    current: Option<&str>,
+    new: Option<&str>,
+) -> Result<(), String> {
     let root = storage_root(app_handle)?;
-    let index = read_index(&root)?;
-    let q = query.trim();
-    if q.is_empty() {
-        return Ok(index.notes);
+    if let Some(config) = read_lock_config(&root)? {
+        let key = derive_lock_key(current.unwrap_or(""), &config.salt);
+        if lock_key_verifier(&key) != config.verifier {
+            return Err("Incorrect passphrase".into());
+        }
     }
-    let now = Utc::now();
-    let today = now.format("%Y-%m-%d").to_string();
-    let week_start = (now - chrono::Duration::days(7)).format("%Y-%m-%d").to_string();
-    let month_start = (now - chrono::Duration::days(30)).format("%Y-%m-%d").to_string();
-    let mut tag_filter: Option<String> = None;
-    let mut starred_only = false;
-    let mut date_filter: Option<String> = None; // "today" | "week" | "month"
-    let mut has_attachments_only = false;
-    let mut has_tasks_only = false;
-    let mut task_filter: Option<bool> = None; // Some(true) = completed only, Some(false) = uncompleted only
-    let mut text_parts: Vec<String> = vec![];
-    for part in q.split_whitespace() {
-        let part_lower = part.to_lowercase();
-        if part_lower.starts_with("tag:") {
-            let tag = part_lower[4..].trim().to_string();
-            if !tag.is_empty() {
-                tag_filter = Some(tag);
+    let mut inner = state.inner.lock().map_err(|e| e.to_string())?;
+    inner.clear();
+    match new {
+        Some(passphrase) if !passphrase.is_empty() => {
+            let salt = Uuid::new_v4().simple().to_string();
+            let key = derive_lock_key(passphrase, &salt);
+            let config = LockConfig {
+                salt,
+                verifier: lock_key_verifier(&key),
+            };
+            let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+            atomic_write(&lock_config_path(&root), json.as_bytes())?;
+            inner.key = Some(key);
+            inner.last_activity = Some(std::time::Instant::now());
+        }
+        Some(_) => return Err("Passphrase cannot be empty".into()),
+        None => {
+            let path = lock_config_path(&root);
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
             }
-        } else if part_lower == "is:starred" {
-            starred_only = true;
-        } else if part_lower == "date:today" {
-            date_filter = Some("today".into());
-        } else if part_lower == "date:week" {
-            date_filter = Some("week".into());
-        } else if part_lower == "date:month" {
-            date_filter = Some("month".into());
-        } else if part_lower == "has:attachments" {
-            has_attachments_only = true;
-        } else if part_lower == "has:tasks" {
-            has_tasks_only = true;
-        } else if part_lower == "is:completed" {
-            task_filter = Some(true);
-        } else if part_lower == "is:uncompleted" {
-            task_filter = Some(false);
-        } else {
-            text_parts.push(part_lower);
         }
     }
-    let mut out: Vec<NoteMeta> = index.notes.into_iter().filter(|n| {
-        if let Some(ref tag) = tag_filter {
-            if !n.tags.iter().any(|t| t.to_lowercase() == *tag) {
-                return false;
+    Ok(())
+}
+
+/// Set how many idle minutes before the app locks again (0 = never).
+pub fn set_auto_lock_minutes(app_handle: &tauri::AppHandle, minutes: u32) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let mut settings = read_settings(&root)?;
+    settings.auto_lock_minutes = minutes;
+    write_settings(&root, &settings)
+}
+
+// --- Change events ---
+// Emitted by mutating commands so other windows can refresh. Payloads carry ids only; listeners
+// re-read whatever they display.
+
+/// A note's body or metadata changed, or a note was created. Payload: `NoteEvent`.
+pub const NOTE_UPDATED_EVENT: &str = "note-updated";
+/// A note was moved to the trash. Payload: `NoteEvent`.
+pub const NOTE_DELETED_EVENT: &str = "note-deleted";
+/// Many notes or the index as a whole changed; listeners should reload the note list. No payload.
+pub const INDEX_CHANGED_EVENT: &str = "index-changed";
+
+pub fn emit_note_updated(app_handle: &tauri::AppHandle, id: &str) {
+    let _ = app_handle.emit(NOTE_UPDATED_EVENT, NoteEvent { id: id.to_string() });
+}
+
+pub fn emit_note_deleted(app_handle: &tauri::AppHandle, id: &str) {
+    let _ = app_handle.emit(NOTE_DELETED_EVENT, NoteEvent { id: id.to_string() });
+}
+
+pub fn emit_index_changed(app_handle: &tauri::AppHandle) {
+    let _ = app_handle.emit(INDEX_CHANGED_EVENT, ());
+}
+
+// --- Sync & Backup ---
+
+fn sync_config_path(root: &Path) -> PathBuf {
+    meta_dir(root).join("sync_config.json")
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct SyncConfig {
+    #[serde(rename = "syncFolder")]
+    sync_folder: Option<String>,
+    /// When sync_now last completed (RFC3339).
+    #[serde(default, rename = "lastSync", skip_serializing_if = "Option::is_none")]
+    last_sync: Option<String>,
+}
+
+fn read_sync_config(root: &Path) -> SyncConfig {
+    let path = sync_config_path(root);
+    if !path.exists() {
+        return SyncConfig::default();
+    }
+    let s = fs::read_to_string(&path).unwrap_or_default();
+    serde_json::from_str(&s).unwrap_or_default()
+}
+
+fn write_sync_config(root: &Path, config: &SyncConfig) -> Result<(), String> {
+    let path = sync_config_path(root);
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Get the configured sync folder (e.g. iCloud Drive or Dropbox path).
+pub fn get_sync_folder(app_handle: &tauri::AppHandle) -> Result<Option<String>, String> {
+    let root = storage_root(app_handle)?;
+    Ok(read_sync_config(&root).sync_folder)
+}
+
+/// Set the sync folder. Pass None to clear. The folder watcher follows the new setting.
+pub fn set_sync_folder(app_handle: &tauri::AppHandle, path: Option<String>) -> Result<(), String> {
+    let root = storage_root(app_handle)?;
+    let mut config = read_sync_config(&root);
+    config.sync_folder = path;
+    write_sync_config(&root, &config)?;
+    // Watching is best-effort; a folder that can't be watched shouldn't block saving the setting.
+    let _ = watch_sync_folder(app_handle, config.sync_folder.as_deref());
+    Ok(())
+}
+
+/// meta/ files that are never synced: derived data or settings that belong to this device.
+const SYNC_EXCLUDED_META: [&str; 4] = ["sync_config.json", "search_index.json", "settings.json", "lock.json"];
+
+/// Relative paths ('/'-separated) of syncable files under notes/, meta/, images/, attachments/ of `base`.
+fn collect_sync_files(base: &Path, dir: &Path, out: &mut BTreeSet<String>) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        if entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+            // Thumbnail caches are rebuilt on demand on each device.
+            if entry.file_name() != THUMBS_DIR {
+                collect_sync_files(base, &path, out)?;
             }
+            continue;
         }
-        if starred_only && !n.important {
-            return false;
-        }
-        if let Some(ref date_kind) = date_filter {
-            let note_date: String = n.updated_at.chars().take(10).collect();
-            let ok = match date_kind.as_str() {
-                "today" => note_date == today,
-                "week" => note_date >= week_start,
-                "month" => note_date >= month_start,
-                _ => true,
-            };
-            if !ok {
-                return false;
-            }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".tmp") || name.ends_with(".conflict") {
+            continue;
         }
-        if has_attachments_only && n.images.is_empty() {
-            return false;
+        let rel = path.strip_prefix(base).map_err(|e| e.to_string())?;
+        let rel: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        let rel = rel.join("/");
+        if rel == "meta/index.json" || SYNC_EXCLUDED_META.iter().any(|m| rel == format!("meta/{}", m)) {
+            continue;
         }
-        if has_tasks_only || task_filter.is_some() {
-            let body_path = note_path(&root, &n.id);
-            let body = fs::read_to_string(&body_path).unwrap_or_default();
-            let (has_unchecked, has_checked) = body_has_task_lines(&body);
-            if has_tasks_only && !has_unchecked && !has_checked {
+        out.insert(rel);
+    }
+    Ok(())
+}
+
+/// Compare two files chunk by chunk, without reading either one whole.
+fn files_equal(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.len() == mb.len() => {
+            let (Ok(fa), Ok(fb)) = (fs::File::open(a), fs::File::open(b)) else {
                 return false;
-            }
-            if let Some(completed_only) = task_filter {
-                if completed_only && !has_checked {
-                    return false;
+            };
+            let (mut ra, mut rb) = (std::io::BufReader::new(fa), std::io::BufReader::new(fb));
+            let mut buf_a = vec![0u8; COPY_CHUNK_BYTES];
+            let mut buf_b = vec![0u8; COPY_CHUNK_BYTES];
+            loop {
+                let n = match ra.read(&mut buf_a) {
+                    Ok(n) => n,
+                    Err(_) => return false,
+                };
+                if n == 0 {
+                    return true;
                 }
-                if !completed_only && !has_unchecked {
+                if rb.read_exact(&mut buf_b[..n]).is_err() || buf_a[..n] != buf_b[..n] {
                     return false;
                 }
             }
         }
-        if text_parts.is_empty() {
-            return true;
-        }
-        let title_lower = n.title.to_lowercase();
-        let body_path = note_path(&root, &n.id);
-        let body = fs::read_to_string(&body_path).unwrap_or_default().to_lowercase();
-        text_parts.iter().all(|term| title_lower.contains(term) || body.contains(term))
-    }).collect();
-    out.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-    Ok(out)
+        _ => false,
+    }
 }
 
-fn validate_notebook_id(id: &str) -> Result<(), String> {
-    if id.is_empty() {
-        return Err("Notebook id cannot be empty".into());
+fn copy_file_creating_dirs(src: &Path, dest: &Path) -> Result<(), String> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
-    if id.contains('/') || id.contains('\\') || id == "." || id == ".." {
-        return Err("Invalid notebook id".into());
+    fs::copy(src, dest).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Where sync_dirs keeps the losing side of a conflict on `rel`: `conflicts/<rel>.conflict` under
+/// `root`, outside the folders that are synced, backed up or scanned for orphans.
+pub fn conflict_copy_path(root: &Path, rel: &str) -> PathBuf {
+    root.join("conflicts").join(format!("{}.conflict", rel))
+}
+
+/// Where a note stands in one index: live, in the trash, or deleted for good (a tombstone).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    Live,
+    Trashed,
+    Deleted,
+}
+
+/// Outcome of merge_sync_index for one note: the state it ends up in and whether that state (and
+/// with it the body) comes from the sync folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncedNote {
+    pub id: String,
+    pub state: SyncState,
+    pub from_remote: bool,
+    /// Both indexes have the same state and timestamp, so the index can't say whose body is newer
+    /// (e.g. a file edited outside the app); sync_dirs then compares the files themselves.
+    pub unchanged: bool,
+}
+
+/// Each note's state in `index` with the time it got there: updated_at for live notes, deleted_at
+/// for trashed ones and tombstones. A live or trashed entry hides a tombstone for the same id.
+fn note_sync_states(index: &IndexFile) -> HashMap<&str, (SyncState, &str)> {
+    let mut states = HashMap::new();
+    for n in &index.notes {
+        states.insert(n.id.as_str(), (SyncState::Live, n.updated_at.as_str()));
     }
-    if id.chars().any(|c| c.is_control()) {
-        return Err("Invalid notebook id".into());
+    for n in &index.trashed {
+        states.insert(n.id.as_str(), (SyncState::Trashed, n.deleted_at.as_deref().unwrap_or(&n.updated_at)));
     }
-    Ok(())
+    for t in &index.tombstones {
+        states.entry(t.id.as_str()).or_insert((SyncState::Deleted, t.deleted_at.as_str()));
+    }
+    states
 }
 
-/// List all notebooks (non-archived first, then archived), sorted by created_at.
-pub fn list_notebooks(app_handle: &tauri::AppHandle) -> Result<Vec<Notebook>, String> {
-    let root = storage_root(app_handle)?;
-    let index = read_index(&root)?;
-    let mut notebooks = index.notebooks.clone();
-    notebooks.sort_by(|a, b| {
-        let a_archived = a.archived as u8;
-        let b_archived = b.archived as u8;
-        a_archived.cmp(&b_archived).then_with(|| a.created_at.cmp(&b.created_at))
+/// Merge the sync folder's index into the local one, note by note: whichever side reached its
+/// state (edit, trash, delete) last wins, local on a tie. Notebooks are unioned by id (local wins).
+/// Returns the merged index and, per note, what sync_dirs has to do with its files.
+pub fn merge_sync_index(local: &IndexFile, remote: &IndexFile) -> (IndexFile, Vec<SyncedNote>) {
+    let (l, r) = (note_sync_states(local), note_sync_states(remote));
+    let mut merged = IndexFile {
+        notebooks: local.notebooks.clone(),
+        schema_version: local.schema_version,
+        ..Default::default()
+    };
+    for nb in &remote.notebooks {
+        if !merged.notebooks.iter().any(|n| n.id == nb.id) {
+            merged.notebooks.push(nb.clone());
+        }
+    }
+    let mut seen = HashSet::new();
+    let ids = [local, remote].into_iter().flat_map(|index| {
+        let live = index.notes.iter().chain(&index.trashed).map(|n| n.id.as_str());
+        live.chain(index.tombstones.iter().map(|t| t.id.as_str()))
     });
-    Ok(notebooks)
+    let mut synced = vec![];
+    for id in ids {
+        if !seen.insert(id) {
+            continue;
+        }
+        let (from_remote, unchanged) = match (l.get(id), r.get(id)) {
+            (Some(a), Some(b)) => (b.1 > a.1, a == b),
+            (None, Some(_)) => (true, false),
+            _ => (false, false),
+        };
+        let (index, state) = if from_remote { (remote, r[id].0) } else { (local, l[id].0) };
+        match state {
+            SyncState::Live => merged.notes.extend(index.notes.iter().find(|n| n.id == id).cloned()),
+            SyncState::Trashed => merged.trashed.extend(index.trashed.iter().find(|n| n.id == id).cloned()),
+            SyncState::Deleted => merged.tombstones.extend(index.tombstones.iter().find(|t| t.id == id).cloned()),
+        }
+        synced.push(SyncedNote {
+            id: id.to_string(),
+            state,
+            from_remote,
+            unchanged,
+        });
+    }
+    (merged, synced)
 }
 
-/// Create a new notebook. Name must be non-empty.
-pub fn create_notebook(app_handle: &tauri::AppHandle, name: &str) -> Result<Notebook, String> {
-    let name = name.trim();
-    if name.is_empty() {
-        return Err("Notebook name cannot be empty".into());
-    }
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let id = Uuid::new_v4().to_string();
-    validate_notebook_id(&id)?;
-    let now = Utc::now().to_rfc3339();
-    let notebook = Notebook {
-        id: id.clone(),
-        name: name.to_string(),
-        archived: false,
-        created_at: now.clone(),
+/// Remove everything a note has under `root`: body, images, attachments, versions and trash folder.
+fn remove_note_files(root: &Path, note_id: &str, ext: &str) -> Result<(), String> {
+    let ignore_missing = |r: std::io::Result<()>| match r {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
     };
-    index.notebooks.push(notebook.clone());
-    write_index(&root, &index)?;
-    Ok(notebook)
+    ignore_missing(fs::remove_file(note_path_with_ext(root, note_id, ext)))?;
+    for dir in [images_dir(root, note_id), attachments_dir(root, note_id), versions_dir(root, note_id), trash_dir(root, note_id)] {
+        ignore_missing(fs::remove_dir_all(dir))?;
+    }
+    Ok(())
 }
 
-/// Move a note into a notebook. Use None to move to unfiled.
-pub fn move_note_to_notebook(
-    app_handle: &tauri::AppHandle,
-    note_id: &str,
-    notebook_id: Option<&str>,
-) -> Result<NoteMeta, String> {
-    validate_note_id(note_id)?;
-    if let Some(nid) = notebook_id {
-        validate_notebook_id(nid)?;
+/// Two-way sync between a local storage root and a sync folder with the same layout.
+///
+/// Notes are merged one by one (see merge_sync_index), and a note's body comes from the same side as
+/// its index entry; only when both entries are identical is the body left to the file rules below.
+/// If the losing side's body was also edited since `last_sync`, it is kept
+/// locally (see conflict_copy_path). A note trashed on one side is moved to the trash on the other; a
+/// note deleted for good is removed from both. The merged index is written to both sides last.
+///
+/// Other files (attachments of live notes, files no index knows about) are copied to whichever side
+/// lacks them. When both sides have different content, a side counts as changed if its mtime is after
+/// `last_sync`. Only one side changed: it wins. Both changed (or no previous sync): local wins and the
+/// sync folder's copy is kept locally. Neither changed: the newer mtime wins.
+///
+/// settings.json isn't synced, so the note file extension is read from the sync folder's index
+/// entries; a sync folder written with a different extension is refused rather than half-merged.
+pub fn sync_dirs(local: &Path, remote: &Path, last_sync: Option<std::time::SystemTime>) -> Result<SyncReport, String> {
+    let mut report = SyncReport::default();
+    let _guard = lock_index();
+    let local_index = read_index(local)?;
+    let remote_index_path = index_path(remote);
+    let remote_index = if remote_index_path.exists() {
+        let s = fs::read_to_string(&remote_index_path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&s).map_err(|e| format!("Sync folder index is invalid: {}", e))?
+    } else {
+        IndexFile::default()
+    };
+    let ext = note_extension(local)?;
+    let remote_ext = remote_index
+        .notes
+        .iter()
+        .chain(&remote_index.trashed)
+        .filter_map(|n| Path::new(&n.filename).extension().and_then(|e| e.to_str()))
+        .find(|e| *e != ext);
+    if let Some(remote_ext) = remote_ext {
+        return Err(format!(
+            "The sync folder's notes are .{} files but this device uses .{}; set the same note file extension on both before syncing",
+            remote_ext, ext
+        ));
     }
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    if let Some(nid) = notebook_id {
-        if !index.notebooks.iter().any(|nb| nb.id == nid) {
-            return Err("Notebook not found".into());
+    let (merged, synced) = merge_sync_index(&local_index, &remote_index);
+    let mtime = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    let changed = |m: Option<std::time::SystemTime>| match (m, last_sync) {
+        (Some(m), Some(last)) => m > last,
+        _ => true,
+    };
+
+    // Per-note files the loop below must not copy on its own.
+    let mut handled: HashSet<String> = HashSet::new();
+    let mut not_live: Vec<String> = vec![];
+    for note in synced.iter().filter(|n| !n.unchanged || n.state != SyncState::Live) {
+        let rel = format!("notes/{}", note_filename(&sanitize_filename(&note.id), &ext));
+        handled.insert(rel.clone());
+        let was_local = local_index.notes.iter().chain(&local_index.trashed).any(|n| n.id == note.id);
+        let was_live = local_index.notes.iter().any(|n| n.id == note.id);
+        match note.state {
+            SyncState::Live => {
+                let (from, to) = if note.from_remote {
+                    (remote.join(&rel), local.join(&rel))
+                } else {
+                    (local.join(&rel), remote.join(&rel))
+                };
+                if !from.is_file() || files_equal(&from, &to) {
+                    continue;
+                }
+                if to.is_file() && changed(mtime(&to)) {
+                    copy_file_creating_dirs(&to, &conflict_copy_path(local, &rel))?;
+                    report.conflicts.push(rel);
+                }
+                copy_file_creating_dirs(&from, &to)?;
+                if note.from_remote {
+                    report.pulled += 1;
+                } else {
+                    report.pushed += 1;
+                }
+            }
+            SyncState::Trashed => {
+                not_live.push(sanitize_filename(&note.id));
+                for (side, other) in [(local, remote), (remote, local)] {
+                    if side.join(&rel).is_file() {
+                        move_note_files_to_trash(side, &note.id, &ext)?;
+                    }
+                    let (dir, other_dir) = (trash_dir(side, &note.id), trash_dir(other, &note.id));
+                    if !dir.exists() && other_dir.exists() {
+                        copy_dir_all(&other_dir, &dir, &mut |_| {})?;
+                    }
+                }
+                if was_live {
+                    report.deleted += 1;
+                }
+            }
+            SyncState::Deleted => {
+                not_live.push(sanitize_filename(&note.id));
+                remove_note_files(local, &note.id, &ext)?;
+                remove_note_files(remote, &note.id, &ext)?;
+                if was_local {
+                    report.deleted += 1;
+                }
+            }
+        }
+    }
+
+    let mut files: BTreeSet<String> = BTreeSet::new();
+    for dir in BACKUP_DIRS {
+        for base in [local, remote] {
+            let src = base.join(dir);
+            if src.is_dir() {
+                collect_sync_files(base, &src, &mut files)?;
+            }
+        }
+    }
+    let owned_by_removed = |rel: &str| {
+        not_live.iter().any(|id| {
+            ["images", "attachments"].iter().any(|top| rel.starts_with(&format!("{}/{}/", top, id)))
+        })
+    };
+    for rel in files {
+        if handled.contains(&rel) || owned_by_removed(&rel) {
+            continue;
+        }
+        let l = local.join(&rel);
+        let r = remote.join(&rel);
+        match (l.is_file(), r.is_file()) {
+            (true, false) => {
+                copy_file_creating_dirs(&l, &r)?;
+                report.pushed += 1;
+            }
+            (false, true) => {
+                copy_file_creating_dirs(&r, &l)?;
+                report.pulled += 1;
+            }
+            (true, true) if !files_equal(&l, &r) => {
+                let (lm, rm) = (mtime(&l), mtime(&r));
+                let (l_changed, r_changed) = (changed(lm), changed(rm));
+                if l_changed && r_changed {
+                    copy_file_creating_dirs(&r, &conflict_copy_path(local, &rel))?;
+                    copy_file_creating_dirs(&l, &r)?;
+                    report.pushed += 1;
+                    report.conflicts.push(rel);
+                } else if l_changed || (!r_changed && lm >= rm) {
+                    copy_file_creating_dirs(&l, &r)?;
+                    report.pushed += 1;
+                } else {
+                    copy_file_creating_dirs(&r, &l)?;
+                    report.pulled += 1;
+                }
+            }
+            _ => {}
         }
     }
-    let n = index.notes.iter_mut().find(|n| n.id == note_id).ok_or("Note not found")?;
-    n.notebook_id = notebook_id.map(String::from);
-    n.updated_at = Utc::now().to_rfc3339();
-    let meta = n.clone();
-    write_index(&root, &index)?;
-    Ok(meta)
-}
 
-/// Archive or unarchive a notebook.
-pub fn archive_notebook(
-    app_handle: &tauri::AppHandle,
-    notebook_id: &str,
-    archived: bool,
-) -> Result<Notebook, String> {
-    validate_notebook_id(notebook_id)?;
-    let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let nb = index.notebooks.iter_mut().find(|n| n.id == notebook_id).ok_or("Notebook not found")?;
-    nb.archived = archived;
-    let notebook = nb.clone();
-    write_index(&root, &index)?;
-    Ok(notebook)
+    if synced.iter().any(|n| n.from_remote) || merged.notebooks.len() != local_index.notebooks.len() {
+        write_index(local, &merged, false)?;
+        report.pulled += 1;
+    }
+    fs::create_dir_all(meta_dir(remote)).map_err(|e| e.to_string())?;
+    write_index(remote, &merged, false)?;
+    Ok(report)
 }
 
-/// Rename a notebook.
-pub fn update_notebook_name(
-    app_handle: &tauri::AppHandle,
-    notebook_id: &str,
-    new_name: &str,
-) -> Result<Notebook, String> {
-    let new_name = new_name.trim();
-    if new_name.is_empty() {
-        return Err("Notebook name cannot be empty".into());
-    }
-    validate_notebook_id(notebook_id)?;
+/// Sync notes/, meta/, images/, attachments/ with the configured sync folder and record the time in sync_config.json.
+pub fn sync_now(app_handle: &tauri::AppHandle) -> Result<SyncReport, String> {
     let root = storage_root(app_handle)?;
-    let mut index = read_index(&root)?;
-    let nb = index.notebooks.iter_mut().find(|n| n.id == notebook_id).ok_or("Notebook not found")?;
-    nb.name = new_name.to_string();
-    let notebook = nb.clone();
-    write_index(&root, &index)?;
-    Ok(notebook)
+    let mut config = read_sync_config(&root);
+    let folder = config.sync_folder.clone().ok_or("No sync folder configured")?;
+    let remote = PathBuf::from(&folder);
+    fs::create_dir_all(&remote).map_err(|e| e.to_string())?;
+    if remote.starts_with(&root) || root.starts_with(&remote) {
+        return Err("Sync folder cannot overlap the storage folder".into());
+    }
+    let last_sync = config
+        .last_sync
+        .as_deref()
+        .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+        .map(std::time::SystemTime::from);
+    let report = sync_dirs(&root, &remote, last_sync)?;
+    if report.pulled + report.deleted > 0 {
+        // Pulled bodies or index entries aren't in the local search index; rebuild on next search.
+        let _ = fs::remove_file(search_index_path(&root));
+    }
+    config.last_sync = Some(Utc::now().to_rfc3339());
+    write_sync_config(&root, &config)?;
+    Ok(report)
 }
 
-// --- Sync & Backup ---
+/// Quiet period after the last file event before `notes-changed` is emitted.
+const SYNC_WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
 
-fn sync_config_path(root: &Path) -> PathBuf {
-    meta_dir(root).join("sync_config.json")
+/// Holds the active sync folder watcher; dropping it stops the watch and its debounce thread.
+#[derive(Default)]
+pub struct SyncWatcher {
+    watcher: std::sync::Mutex<Option<notify::RecommendedWatcher>>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Default)]
-struct SyncConfig {
-    #[serde(rename = "syncFolder")]
-    sync_folder: Option<String>,
+/// Whether a changed path in the sync folder affects notes: the index or anything under notes/.
+pub fn is_sync_relevant_path(folder: &Path, path: &Path) -> bool {
+    let Ok(rel) = path.strip_prefix(folder) else {
+        return false;
+    };
+    rel.file_name().is_some_and(|n| n == "index.json") || rel.components().any(|c| c.as_os_str() == "notes")
 }
 
-fn read_sync_config(root: &Path) -> SyncConfig {
-    let path = sync_config_path(root);
-    if !path.exists() {
-        return SyncConfig::default();
-    }
-    let s = fs::read_to_string(&path).unwrap_or_default();
-    serde_json::from_str(&s).unwrap_or_default()
+/// Start watching the configured sync folder (called once from setup).
+pub fn start_sync_watcher(app_handle: &tauri::AppHandle) {
+    app_handle.manage(SyncWatcher::default());
+    let folder = get_sync_folder(app_handle).ok().flatten();
+    let _ = watch_sync_folder(app_handle, folder.as_deref());
 }
 
-fn write_sync_config(root: &Path, config: &SyncConfig) -> Result<(), String> {
-    let path = sync_config_path(root);
-    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
-    fs::write(&path, json).map_err(|e| e.to_string())?;
-    Ok(())
-}
+/// (Re)point the watcher at `folder`, or stop watching when None. External changes to the index or
+/// notes emit a debounced `notes-changed` event carrying the changed paths.
+fn watch_sync_folder(app_handle: &tauri::AppHandle, folder: Option<&str>) -> Result<(), String> {
+    use notify::Watcher;
 
-/// Get the configured sync folder (e.g. iCloud Drive or Dropbox path).
-pub fn get_sync_folder(app_handle: &tauri::AppHandle) -> Result<Option<String>, String> {
-    let root = storage_root(app_handle)?;
-    Ok(read_sync_config(&root).sync_folder)
+    let Some(state) = app_handle.try_state::<SyncWatcher>() else {
+        return Ok(());
+    };
+    let mut current = state.watcher.lock().map_err(|e| e.to_string())?;
+    *current = None;
+    let Some(folder) = folder.map(PathBuf::from).filter(|p| p.is_dir()) else {
+        return Ok(());
+    };
+    let (tx, rx) = std::sync::mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| e.to_string())?;
+    watcher
+        .watch(&folder, notify::RecursiveMode::Recursive)
+        .map_err(|e| e.to_string())?;
+    let app = app_handle.clone();
+    // Event paths may come back canonicalized (e.g. /private/var on macOS), so match against both forms.
+    let roots = [folder.clone(), folder.canonicalize().unwrap_or_else(|_| folder.clone())];
+    std::thread::spawn(move || {
+        use std::sync::mpsc::RecvTimeoutError;
+        let relevant = |event: notify::Result<notify::Event>| -> Vec<String> {
+            event
+                .map(|e| e.paths)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|p| roots.iter().any(|root| is_sync_relevant_path(root, p)))
+                .map(|p| p.to_string_lossy().to_string())
+                .collect()
+        };
+        // The loop ends when the watcher (and with it the sender) is dropped.
+        while let Ok(event) = rx.recv() {
+            let mut changed: BTreeSet<String> = relevant(event).into_iter().collect();
+            if changed.is_empty() {
+                continue;
+            }
+            loop {
+                match rx.recv_timeout(SYNC_WATCH_DEBOUNCE) {
+                    Ok(event) => changed.extend(relevant(event)),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            invalidate_index_cache();
+            let _ = app.emit("notes-changed", changed.into_iter().collect::<Vec<_>>());
+        }
+    });
+    *current = Some(watcher);
+    Ok(())
 }
 
-/// Set the sync folder. Pass None to clear.
-pub fn set_sync_folder(app_handle: &tauri::AppHandle, path: Option<String>) -> Result<(), String> {
-    let root = storage_root(app_handle)?;
-    let mut config = read_sync_config(&root);
-    config.sync_folder = path;
-    write_sync_config(&root, &config)
+/// Copy a directory recursively into dest (creates dest if needed).
+/// `on_progress` receives the cumulative number of bytes copied so far after each chunk (see stream_copy).
+fn copy_dir_all(src: &Path, dest: &Path, on_progress: &mut dyn FnMut(u64)) -> Result<(), String> {
+    let mut copied: u64 = 0;
+    copy_dir_recursive(src, dest, &mut copied, on_progress)
 }
 
-/// Copy a directory recursively into dest (creates dest if needed).
-fn copy_dir_all(src: &Path, dest: &Path) -> Result<(), String> {
+fn copy_dir_recursive(
+    src: &Path,
+    dest: &Path,
+    copied: &mut u64,
+    on_progress: &mut dyn FnMut(u64),
+) -> Result<(), String> {
     fs::create_dir_all(dest).map_err(|e| e.to_string())?;
     for entry in fs::read_dir(src).map_err(|e| e.to_string())? {
         let entry = entry.map_err(|e| e.to_string())?;
         let ty = entry.file_type().map_err(|e| e.to_string())?;
         let dest_path = dest.join(entry.file_name());
         if ty.is_dir() {
-            copy_dir_all(&entry.path(), &dest_path)?;
+            copy_dir_recursive(&entry.path(), &dest_path, copied, on_progress)?;
+        } else {
+            copy_file_streamed(&entry.path(), &dest_path, copied, on_progress)?;
+        }
+    }
+    Ok(())
+}
+
+/// Chunk size for streamed backup copies; progress is reported after each chunk.
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Copy `reader` to `writer` one chunk at a time, adding each chunk to `copied` and reporting it, so a
+/// large attachment is never held in memory and the progress bar keeps moving while it copies.
+/// Returns the bytes copied.
+fn stream_copy(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    copied: &mut u64,
+    on_progress: &mut dyn FnMut(u64),
+) -> Result<u64, String> {
+    let mut buf = vec![0u8; COPY_CHUNK_BYTES];
+    let mut total: u64 = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        writer.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        total += n as u64;
+        *copied += n as u64;
+        on_progress(*copied);
+    }
+    Ok(total)
+}
+
+/// Copy one file with stream_copy, overwriting `dest`.
+fn copy_file_streamed(src: &Path, dest: &Path, copied: &mut u64, on_progress: &mut dyn FnMut(u64)) -> Result<u64, String> {
+    let mut reader = fs::File::open(src).map_err(|e| e.to_string())?;
+    let mut writer = std::io::BufWriter::new(fs::File::create(dest).map_err(|e| e.to_string())?);
+    let n = stream_copy(&mut reader, &mut writer, copied, on_progress)?;
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(n)
+}
+
+/// Emit a `backup-progress` event so the UI can show how far an export/import has got.
+fn emit_backup_progress(app_handle: &tauri::AppHandle, operation: &str, bytes_copied: u64) {
+    let _ = app_handle.emit(
+        "backup-progress",
+        BackupProgress {
+            operation: operation.to_string(),
+            bytes_copied,
+        },
+    );
+}
+
+/// Streamed SHA-256 of a file, as lowercase hex, plus its size.
+pub fn hash_file(path: &Path) -> Result<(String, u64), String> {
+    use sha2::{Digest, Sha256};
+    let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    let hex: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    Ok((hex, size))
+}
+
+fn collect_manifest_entries(base: &Path, dir: &Path, out: &mut Vec<ManifestEntry>) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        if entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+            collect_manifest_entries(base, &path, out)?;
         } else {
-            fs::copy(entry.path(), &dest_path).map_err(|e| e.to_string())?;
+            let rel = path.strip_prefix(base).map_err(|e| e.to_string())?;
+            let rel: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            let (sha256, size) = hash_file(&path)?;
+            out.push(ManifestEntry {
+                path: rel.join("/"),
+                size,
+                sha256,
+            });
         }
     }
     Ok(())
 }
 
-/// Export full backup to target_dir (notes/, meta/, images/). Target dir is created if needed.
+/// Manifest of every file under the backup dirs (notes/, meta/, images/, attachments/) of `base`, sorted by path.
+pub fn build_backup_manifest(base: &Path) -> Result<BackupManifest, String> {
+    let mut files = vec![];
+    for dir in BACKUP_DIRS {
+        let src = base.join(dir);
+        if src.exists() {
+            collect_manifest_entries(base, &src, &mut files)?;
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(BackupManifest {
+        created_at: Utc::now().to_rfc3339(),
+        files,
+    })
+}
+
+/// Re-hash the files listed in `<base>/manifest.json` and report any that are missing or differ.
+pub fn verify_backup_dir(base: &Path) -> Result<BackupVerification, String> {
+    let s = fs::read_to_string(base.join("manifest.json")).map_err(|_| "Backup has no manifest.json".to_string())?;
+    let manifest: BackupManifest = serde_json::from_str(&s).map_err(|e| format!("Backup manifest is invalid: {}", e))?;
+    let mut result = BackupVerification::default();
+    for entry in manifest.files {
+        if entry.path.split('/').any(|part| part == ".." || part.is_empty()) {
+            result.corrupt.push(entry.path);
+            continue;
+        }
+        let path = base.join(&entry.path);
+        if !path.is_file() {
+            result.missing.push(entry.path);
+            continue;
+        }
+        match hash_file(&path) {
+            Ok((sha256, size)) if sha256 == entry.sha256 && size == entry.size => {}
+            _ => result.corrupt.push(entry.path),
+        }
+    }
+    Ok(result)
+}
+
+/// Check a backup folder (or .zip) against its manifest before importing.
+pub fn verify_backup(source_dir: &str) -> Result<BackupVerification, String> {
+    let source = Path::new(source_dir);
+    if source.is_file() {
+        let tmp = env::temp_dir().join(format!("local-notes-verify-{}", Uuid::new_v4()));
+        let result = extract_backup_zip(source, &tmp).and_then(|_| verify_backup_dir(&tmp));
+        let _ = fs::remove_dir_all(&tmp);
+        return result;
+    }
+    if !source.is_dir() {
+        return Err("Source backup directory does not exist".into());
+    }
+    verify_backup_dir(source)
+}
+
+/// Export full backup to target_dir (notes/, meta/, images/, attachments/). Target dir is created if needed.
 pub fn export_backup(app_handle: &tauri::AppHandle, target_dir: &str) -> Result<(), String> {
     let root = storage_root(app_handle)?;
     let target = Path::new(target_dir);
     if !root.exists() {
         return Err("App storage does not exist".into());
     }
-    let notes_src = notes_dir(&root);
-    let meta_src = meta_dir(&root);
-    let images_src = root.join("images");
-    let notes_dest = target.join("notes");
-    let meta_dest = target.join("meta");
-    let images_dest = target.join("images");
-    fs::create_dir_all(&notes_dest).map_err(|e| e.to_string())?;
-    fs::create_dir_all(&meta_dest).map_err(|e| e.to_string())?;
-    fs::create_dir_all(&images_dest).map_err(|e| e.to_string())?;
-    if notes_src.exists() {
-        copy_dir_all(&notes_src, &notes_dest)?;
+    let mut copied: u64 = 0;
+    for dir in BACKUP_DIRS {
+        let (src, dest) = (root.join(dir), target.join(dir));
+        fs::create_dir_all(&dest).map_err(|e| e.to_string())?;
+        if src.exists() {
+            let base = copied;
+            copy_dir_all(&src, &dest, &mut |bytes| {
+                copied = base + bytes;
+                emit_backup_progress(app_handle, "export", copied);
+            })?;
+        }
+    }
+    let manifest = build_backup_manifest(target)?;
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(target.join("manifest.json"), json).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Directories included in a backup, relative to the storage root.
+const BACKUP_DIRS: [&str; 4] = ["notes", "meta", "images", "attachments"];
+
+/// Forwards writes to `inner` and feeds the same bytes to a SHA-256 hasher.
+struct HashingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: sha2::Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        use sha2::Digest;
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Add every file under `dir` to the zip as `<prefix>/<relative path>`, streaming file contents.
+/// Each file is hashed from the bytes written into the zip, and its manifest entry pushed to `manifest`.
+fn zip_add_dir(
+    zip: &mut zip::ZipWriter<fs::File>,
+    dir: &Path,
+    prefix: &str,
+    manifest: &mut Vec<ManifestEntry>,
+    copied: &mut u64,
+    on_progress: &mut dyn FnMut(u64),
+) -> Result<(), String> {
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.add_directory(format!("{}/", prefix), options).map_err(|e| e.to_string())?;
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        let entry_name = format!("{}/{}", prefix, name);
+        if entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+            zip_add_dir(zip, &path, &entry_name, manifest, copied, on_progress)?;
+        } else {
+            use sha2::Digest;
+            zip.start_file(entry_name.as_str(), options).map_err(|e| e.to_string())?;
+            let mut file = fs::File::open(&path).map_err(|e| e.to_string())?;
+            let mut writer = HashingWriter {
+                inner: zip,
+                hasher: sha2::Sha256::new(),
+            };
+            let size = stream_copy(&mut file, &mut writer, copied, on_progress)?;
+            manifest.push(ManifestEntry {
+                path: entry_name,
+                size,
+                sha256: to_hex(&writer.hasher.finalize()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Write notes/, meta/ (index.json, templates.json, …), images/ and attachments/ under `root` into a zip at `target_file`,
+/// plus a manifest.json of their hashes, computed from the bytes as they go into the zip so the manifest
+/// always matches the archive even if a file changes during the export.
+/// The zip is written to a temp file first and renamed into place when complete.
+pub fn write_backup_zip(root: &Path, target_file: &Path, on_progress: &mut dyn FnMut(u64)) -> Result<(), String> {
+    if let Some(parent) = target_file.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
-    if meta_src.exists() {
-        copy_dir_all(&meta_src, &meta_dest)?;
+    let tmp = target_file.with_extension("zip.tmp");
+    let file = fs::File::create(&tmp).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipWriter::new(file);
+    let mut copied: u64 = 0;
+    let mut files = vec![];
+    for dir in BACKUP_DIRS {
+        let src = root.join(dir);
+        if src.exists() {
+            zip_add_dir(&mut zip, &src, dir, &mut files, &mut copied, on_progress)?;
+        }
     }
-    if images_src.exists() {
-        copy_dir_all(&images_src, &images_dest)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let manifest = BackupManifest {
+        created_at: Utc::now().to_rfc3339(),
+        files,
+    };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("manifest.json", options).map_err(|e| e.to_string())?;
+    zip.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+    let file = zip.finish().map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    drop(file);
+    fs::rename(&tmp, target_file).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Extract a backup zip into `dest`. Entries that would escape `dest` (absolute or `..` paths) are rejected.
+pub fn extract_backup_zip(zip_file: &Path, dest: &Path) -> Result<(), String> {
+    let file = fs::File::open(zip_file).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        let rel = entry.enclosed_name().ok_or("Invalid path in backup zip")?;
+        let out = dest.join(rel);
+        if entry.is_dir() {
+            fs::create_dir_all(&out).map_err(|e| e.to_string())?;
+            continue;
+        }
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut f = fs::File::create(&out).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut f).map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
-/// Import backup from source_dir (copies notes/, meta/, images/ into app storage; overwrites).
-pub fn import_backup(app_handle: &tauri::AppHandle, source_dir: &str) -> Result<(), String> {
+/// Export full backup as a single .zip file (notes/, meta/, images/, attachments/ with their relative paths).
+pub fn export_backup_zip(app_handle: &tauri::AppHandle, target_file: &str) -> Result<(), String> {
     let root = storage_root(app_handle)?;
+    if !root.exists() {
+        return Err("App storage does not exist".into());
+    }
+    write_backup_zip(&root, Path::new(target_file), &mut |bytes| {
+        emit_backup_progress(app_handle, "export", bytes);
+    })
+}
+
+/// Parse a backup's meta/index.json, failing if it is missing or doesn't deserialize.
+fn read_backup_index(source: &Path) -> Result<IndexFile, String> {
+    let path = source.join("meta").join("index.json");
+    let s = fs::read_to_string(&path).map_err(|_| "Backup has no readable meta/index.json".to_string())?;
+    serde_json::from_str(&s).map_err(|e| format!("Backup index is invalid: {}", e))
+}
+
+/// Merge a backup index into the local one: new notes are added, notes present in both take the
+/// newer updated_at, and notebooks are unioned by id (local wins). Incoming notes whose id is in the
+/// local trash are skipped. Returns the summary and the ids whose files should be copied in.
+pub fn merge_index(local: &mut IndexFile, incoming: IndexFile) -> (ImportSummary, Vec<String>) {
+    let mut summary = ImportSummary::default();
+    let mut to_copy = vec![];
+    for note in incoming.notes {
+        if local.trashed.iter().any(|t| t.id == note.id) {
+            summary.skipped += 1;
+            continue;
+        }
+        match local.notes.iter_mut().find(|n| n.id == note.id) {
+            None => {
+                to_copy.push(note.id.clone());
+                local.notes.push(note);
+                summary.added += 1;
+            }
+            Some(existing) if note.updated_at > existing.updated_at => {
+                to_copy.push(note.id.clone());
+                *existing = note;
+                summary.updated += 1;
+            }
+            Some(_) => summary.skipped += 1,
+        }
+    }
+    for nb in incoming.notebooks {
+        if !local.notebooks.iter().any(|n| n.id == nb.id) {
+            local.notebooks.push(nb);
+        }
+    }
+    (summary, to_copy)
+}
+
+/// Import a backup from source_dir (notes/, meta/, images/, attachments/), or from a .zip written by export_backup_zip.
+/// The backup's index is validated first. `Replace` overwrites current files; `Merge` combines by note id
+/// (see merge_index) and only adds or overwrites files for added/updated notes.
+pub fn import_backup(app_handle: &tauri::AppHandle, source_dir: &str, mode: ImportMode) -> Result<ImportSummary, String> {
     let source = Path::new(source_dir);
+    if source.is_file() {
+        let tmp = env::temp_dir().join(format!("local-notes-import-{}", Uuid::new_v4()));
+        let result = extract_backup_zip(source, &tmp)
+            .and_then(|_| import_backup(app_handle, &tmp.to_string_lossy(), mode));
+        let _ = fs::remove_dir_all(&tmp);
+        return result;
+    }
+    let root = storage_root(app_handle)?;
+    let ext = note_extension(&root)?;
     if !source.exists() || !source.is_dir() {
         return Err("Source backup directory does not exist".into());
     }
-    let notes_src = source.join("notes");
-    let meta_src = source.join("meta");
-    let images_src = source.join("images");
-    let notes_dest = notes_dir(&root);
-    let meta_dest = meta_dir(&root);
-    let images_dest = root.join("images");
-    fs::create_dir_all(&notes_dest).map_err(|e| e.to_string())?;
-    fs::create_dir_all(&meta_dest).map_err(|e| e.to_string())?;
-    fs::create_dir_all(&images_dest).map_err(|e| e.to_string())?;
-    if notes_src.exists() {
-        copy_dir_all(&notes_src, &notes_dest)?;
-    }
-    if meta_src.exists() {
-        copy_dir_all(&meta_src, &meta_dest)?;
-    }
-    if images_src.exists() {
-        copy_dir_all(&images_src, &images_dest)?;
+    let incoming = read_backup_index(source)?;
+    let _guard = lock_index();
+    let summary = match mode {
+        ImportMode::Replace => {
+            let local = read_index(&root)?;
+            let mut summary = ImportSummary::default();
+            for n in &incoming.notes {
+                if local.notes.iter().any(|l| l.id == n.id) {
+                    summary.updated += 1;
+                } else {
+                    summary.added += 1;
+                }
+            }
+            replace_from_backup(app_handle, &root, source)?;
+            summary
+        }
+        ImportMode::Merge => {
+            let mut local = read_index(&root)?;
+            let (summary, to_copy) = merge_index(&mut local, incoming);
+            // The backup's notes keep the extension from its own settings; they take ours when copied.
+            let (source_ext, local_ext) = (note_extension(source)?, note_extension(&root)?);
+            for n in local.notes.iter_mut().filter(|n| to_copy.contains(&n.id)) {
+                n.filename = note_filename(&n.id, &local_ext);
+            }
+            let mut copied: u64 = 0;
+            for id in &to_copy {
+                let body = source.join("notes").join(note_filename(&sanitize_filename(id), &source_ext));
+                if body.exists() {
+                    copy_file_streamed(&body, &note_path_with_ext(&root, id, &ext), &mut copied, &mut |bytes| {
+                        emit_backup_progress(app_handle, "import", bytes);
+                    })?;
+                }
+                for (src, dest) in [
+                    (source.join("images").join(sanitize_filename(id)), images_dir(&root, id)),
+                    (source.join("attachments").join(sanitize_filename(id)), attachments_dir(&root, id)),
+                ] {
+                    if src.exists() {
+                        let base = copied;
+                        copy_dir_all(&src, &dest, &mut |bytes| {
+                            copied = base + bytes;
+                            emit_backup_progress(app_handle, "import", copied);
+                        })?;
+                    }
+                }
+            }
+            write_index(&root, &local, false)?;
+            summary
+        }
+    };
+    // The imported index no longer matches the search index; it is rebuilt on the next search.
+    let _ = fs::remove_file(search_index_path(&root));
+    Ok(summary)
+}
+
+/// Copy notes/, meta/, images/, attachments/ from the backup over app storage (overwrites).
+fn replace_from_backup(app_handle: &tauri::AppHandle, root: &Path, source: &Path) -> Result<(), String> {
+    let mut copied: u64 = 0;
+    for dir in BACKUP_DIRS {
+        let (src, dest) = (source.join(dir), root.join(dir));
+        fs::create_dir_all(&dest).map_err(|e| e.to_string())?;
+        if src.exists() {
+            let base = copied;
+            copy_dir_all(&src, &dest, &mut |bytes| {
+                copied = base + bytes;
+                emit_backup_progress(app_handle, "import", copied);
+            })?;
+        }
     }
+    invalidate_index_cache();
     Ok(())
 }
 
@@ -1348,17 +8183,7 @@ pub fn list_note_versions(app_handle: &tauri::AppHandle, note_id: &str) -> Resul
         }
         let s = fs::read_to_string(&path).unwrap_or_default();
         if let Ok(snapshot) = serde_json::from_str::<VersionSnapshot>(&s) {
-            let preview_len = 150;
-            let body_preview = if snapshot.body.len() <= preview_len {
-                snapshot.body.clone()
-            } else {
-                format!("{}…", &snapshot.body[..preview_len])
-            };
-            items.push(NoteVersionItem {
-                saved_at: snapshot.saved_at,
-                title: snapshot.title,
-                body_preview,
-            });
+            items.push(version_item(snapshot));
         }
     }
     items.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
@@ -1388,6 +8213,147 @@ pub fn get_note_version(
     })
 }
 
+/// Largest LCS table diff_lines builds, in cells (8 MB of usize on 64-bit).
+pub const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// Line diff between two texts (LCS). Common leading/trailing lines are trimmed first so typical edits stay cheap.
+/// If the changed middle would need more than MAX_DIFF_CELLS table cells, it is reported coarsely instead:
+/// every old line removed, then every new line added.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let line = |kind: DiffKind, text: &str| DiffLine {
+        kind,
+        text: text.to_string(),
+    };
+    let mut out: Vec<DiffLine> = a[..prefix].iter().map(|t| line(DiffKind::Unchanged, t)).collect();
+    let (n, m) = (a_mid.len(), b_mid.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        out.extend(a_mid.iter().map(|t| line(DiffKind::Removed, t)));
+        out.extend(b_mid.iter().map(|t| line(DiffKind::Added, t)));
+        out.extend(a[a.len() - suffix..].iter().map(|t| line(DiffKind::Unchanged, t)));
+        return out;
+    }
+
+    // lcs[i][j] = LCS length of a_mid[i..] and b_mid[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a_mid[i] == b_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a_mid[i] == b_mid[j] {
+            out.push(line(DiffKind::Unchanged, a_mid[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(line(DiffKind::Removed, a_mid[i]));
+            i += 1;
+        } else {
+            out.push(line(DiffKind::Added, b_mid[j]));
+            j += 1;
+        }
+    }
+    out.extend(a_mid[i..].iter().map(|t| line(DiffKind::Removed, t)));
+    out.extend(b_mid[j..].iter().map(|t| line(DiffKind::Added, t)));
+    out.extend(a[a.len() - suffix..].iter().map(|t| line(DiffKind::Unchanged, t)));
+    out
+}
+
+/// Body of a version by saved_at, or the live on-disk body for "current".
+fn version_or_current_body(app_handle: &tauri::AppHandle, note_id: &str, saved_at: &str) -> Result<String, String> {
+    if saved_at == "current" {
+        let root = storage_root(app_handle)?;
+        let index = read_index(&root)?;
+        if !index.notes.iter().any(|n| n.id == note_id) {
+            return Err("Note not found".into());
+        }
+        return Ok(fs::read_to_string(note_path(&root, note_id)?).unwrap_or_default());
+    }
+    get_note_version(app_handle, note_id, saved_at).map(|v| v.body)
+}
+
+/// Line diff between two versions of a note. Either side may be "current" for the live body.
+pub fn diff_note_versions(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    from_saved_at: &str,
+    to_saved_at: &str,
+) -> Result<Vec<DiffLine>, String> {
+    validate_note_id(note_id)?;
+    let from = version_or_current_body(app_handle, note_id, from_saved_at)?;
+    let to = version_or_current_body(app_handle, note_id, to_saved_at)?;
+    Ok(diff_lines(&from, &to))
+}
+
+/// Protect (or unprotect) a version from pruning.
+pub fn pin_note_version(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    saved_at: &str,
+    pinned: bool,
+) -> Result<NoteVersionItem, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let v_dir = versions_dir(&root, note_id);
+    let v_path = v_dir.join(version_filename(saved_at));
+    if !v_path.exists() {
+        return Err("Version not found".into());
+    }
+    let s = fs::read_to_string(&v_path).map_err(|e| e.to_string())?;
+    let mut snapshot: VersionSnapshot = serde_json::from_str(&s).map_err(|e| e.to_string())?;
+    snapshot.pinned = pinned;
+    write_version_snapshot(&v_dir, &snapshot)?;
+    Ok(version_item(snapshot))
+}
+
+/// Capture the current on-disk body as a version right away, with an optional label.
+/// Manual snapshots count toward the per-note limit like any other unpinned version.
+pub fn create_note_snapshot(
+    app_handle: &tauri::AppHandle,
+    note_id: &str,
+    label: Option<String>,
+) -> Result<NoteVersionItem, String> {
+    validate_note_id(note_id)?;
+    let root = storage_root(app_handle)?;
+    let index = read_index(&root)?;
+    let meta = index
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or("Note not found")?;
+    let body = fs::read_to_string(note_path(&root, note_id)?).unwrap_or_default();
+    let label = label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    let snapshot = VersionSnapshot {
+        saved_at: Utc::now().to_rfc3339(),
+        title: meta.title.clone(),
+        body,
+        pinned: false,
+        label,
+    };
+    let v_dir = versions_dir(&root, note_id);
+    write_version_snapshot(&v_dir, &snapshot)?;
+    prune_versions(&v_dir, max_versions_per_note(&read_settings(&root)?));
+    Ok(version_item(snapshot))
+}
+
 /// Restore a note to a previous version (overwrites current content and saves).
 pub fn restore_note_version(
     app_handle: &tauri::AppHandle,
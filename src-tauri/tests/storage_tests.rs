@@ -1,6 +1,52 @@
-//! Unit tests for storage helpers (pure functions only).
+//! Tests for storage helpers: pure functions, plus filesystem-backed ones run against temp_dir roots.
 
-use local_private_notes_lib::storage::{sanitize_filename, validate_note_id};
+use local_private_notes_lib::models::{AttachmentKind, DiffKind, GraphEdgeKind, ImageRef, IndexFile, IndexSource, MergeOrder, NoteMeta, Notebook, NotebookDeleteStrategy, Period, SearchIndex, Settings, SortBy, TagMeta, Tombstone, VersionSnapshot};
+use local_private_notes_lib::storage::{
+    add_saved_search, add_tag, append_chunk, apply_template_placeholders, asset_url, atomic_write,
+    attachment_kind, build_backup_manifest, build_connections, build_link_graph, build_search_regex,
+    build_snippet, carry_over_tags, collapse_tag_variants, compare_notes_by, compare_sidebar_order,
+    compute_note_stats, conflict_copy_path, count_notes_by_notebook, count_tags, count_task_lines,
+    csv_escape, DateField, dedupe_filename, dedupe_note_attachments, derive_lock_key,
+    detect_image_mime, diff_lines, dir_size, duplicate_title_groups, extract_backup_zip,
+    extract_tags_from_body, file_url, filter_notes_by_notebook, find_identical_file, find_orphans,
+    find_unlinked_mentions, fuzzy_score, has_duplicate_title, hash_file, heading_anchor,
+    image_data_url, image_extension_for_mime, INDEX_SCHEMA_VERSION, invalidate_index_cache,
+    is_attachment_path, is_image_extension, is_sync_relevant_path, is_trash_expired, lock_index,
+    lock_key_verifier, markdown_pdf_blocks, MAX_DATA_URL_IMAGE_BYTES, MAX_DIFF_CELLS,
+    MAX_INDEX_SIZE_BYTES, MAX_INLINE_IMAGE_BYTES, max_versions_per_note, merge_index,
+    merge_note_bodies, merge_sync_index, migrate_index, migrate_storage_dirs, migrate_v0_to_v1,
+    most_recent_notes, normalize_note_color, normalize_tag, normalize_title, note_preview,
+    note_tags, note_to_markdown, notebook_creates_cycle, notebook_descendants, page_notes,
+    parse_date_range, parse_image_data_url, parse_markdown_import, parse_outline,
+    parse_search_query, parse_task_due, PdfBlock, PdfListMarker, periodic_note_body,
+    periodic_note_title, preview_text, prune_versions, read_index, read_saved_searches,
+    relevance_score, relink_title, remove_notebook_from_index, remove_saved_search,
+    rename_note_and_relink_in, rename_tag_in, render_note_html, render_note_pdf,
+    render_notebook_markdown, reorder_favorite_ids, repair_index, replace_inline_tag,
+    replace_link_target, replace_text, resolve_favorites, resolve_link_title, sanitize_filename,
+    search_index_add_note, search_index_lookup, search_index_remove_note, search_index_stale_notes,
+    search_index_stamp_notes, set_important, sort_merge_sources, sort_notebooks_for_tree,
+    stray_note_files, strip_note_frontmatter, summarize_tags, sync_dirs, SyncState, tag_details,
+    tag_key, tag_matches_prefix, tag_usage_counts, tags_after_edit, thumbnail_name, title_from_body,
+    toggle_task_line, tokenize_for_index, tokenize_query, validate_daily_title_format,
+    validate_note_extension, validate_note_id, validate_settings, validate_storage_root_change,
+    validate_tag_color, verify_backup_dir, wikilink_spans, wrap_text, write_backup_zip, write_index,
+    write_thumbnail,
+};
+
+/// Build a NoteMeta from the required fields; everything else uses serde defaults.
+fn note(id: &str, title: &str, updated_at: &str) -> NoteMeta {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "title": title,
+        "createdAt": updated_at,
+        "updatedAt": updated_at,
+        "important": false,
+        "filename": format!("{}.txt", id),
+        "images": [],
+    }))
+    .unwrap()
+}
 
 #[test]
 fn test_sanitize_filename_removes_path_separators() {
@@ -44,3 +90,2601 @@ fn test_validate_note_id_rejects_traversal() {
     assert!(validate_note_id("a/b").is_err());
     assert!(validate_note_id("a\\b").is_err());
 }
+
+#[test]
+fn test_normalize_title_trims_surrounding_whitespace() {
+    assert_eq!(normalize_title("  My note \n"), "My note");
+    assert_eq!(normalize_title("a  b"), "a  b");
+}
+
+#[test]
+fn test_normalize_title_space_only_falls_back_to_untitled() {
+    assert_eq!(normalize_title("   "), "Untitled");
+    assert_eq!(normalize_title(""), "Untitled");
+}
+
+#[test]
+fn test_normalize_title_tab_only_falls_back_to_untitled() {
+    assert_eq!(normalize_title("\t\t"), "Untitled");
+    assert_eq!(normalize_title(" \t \n"), "Untitled");
+}
+
+#[test]
+fn test_detect_image_mime_png() {
+    let data = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D];
+    assert_eq!(detect_image_mime(&data), Some("image/png"));
+}
+
+#[test]
+fn test_detect_image_mime_jpeg() {
+    let data = [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F'];
+    assert_eq!(detect_image_mime(&data), Some("image/jpeg"));
+}
+
+#[test]
+fn test_detect_image_mime_webp() {
+    let data = *b"RIFF\x24\x00\x00\x00WEBPVP8 ";
+    assert_eq!(detect_image_mime(&data), Some("image/webp"));
+    // A RIFF container that isn't WebP (e.g. WAV) must not match.
+    assert_eq!(detect_image_mime(b"RIFF\x24\x00\x00\x00WAVEfmt "), None);
+}
+
+#[test]
+fn test_detect_image_mime_gif() {
+    assert_eq!(detect_image_mime(b"GIF89a\x01\x00\x01\x00"), Some("image/gif"));
+    assert_eq!(detect_image_mime(b"GIF87a\x01\x00\x01\x00"), Some("image/gif"));
+}
+
+#[test]
+fn test_detect_image_mime_bmp() {
+    let data = *b"BM\x3a\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00";
+    assert_eq!(detect_image_mime(&data), Some("image/bmp"));
+}
+
+#[test]
+fn test_detect_image_mime_unknown() {
+    assert_eq!(detect_image_mime(b""), None);
+    assert_eq!(detect_image_mime(b"hello world"), None);
+}
+
+#[test]
+fn test_image_data_url_encodes_known_images_within_limit() {
+    let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    assert_eq!(image_data_url(&png).as_deref(), Some("data:image/png;base64,iVBORw0KGgo="));
+    assert_eq!(image_data_url(b"plain text"), None);
+    let mut big = png.to_vec();
+    big.resize(MAX_INLINE_IMAGE_BYTES + 1, 0);
+    assert_eq!(image_data_url(&big), None);
+}
+
+#[test]
+fn test_image_extension_for_mime() {
+    assert_eq!(image_extension_for_mime("image/webp"), Some("webp"));
+    assert_eq!(image_extension_for_mime("image/jpeg"), Some("jpg"));
+    assert_eq!(image_extension_for_mime("text/plain"), None);
+}
+
+#[test]
+fn test_is_image_extension() {
+    for ext in ["png", "jpg", "JPEG", "webp", "gif", "Bmp"] {
+        assert!(is_image_extension(ext), "{}", ext);
+    }
+    assert!(!is_image_extension("pdf"));
+    assert!(!is_image_extension(""));
+}
+
+#[test]
+fn test_csv_escape_plain_field_unchanged() {
+    assert_eq!(csv_escape("work"), "work");
+}
+
+#[test]
+fn test_csv_escape_quotes_commas_and_newlines() {
+    assert_eq!(csv_escape("a,b"), "\"a,b\"");
+    assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_escape("line\nbreak"), "\"line\nbreak\"");
+}
+
+#[test]
+fn test_compare_sidebar_order_pin_order_before_unordered() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    let mut b = note("b", "B", "2024-06-01T00:00:00Z");
+    let c = note("c", "C", "2024-12-01T00:00:00Z");
+    a.pin_order = Some(2);
+    b.pin_order = Some(1);
+    let mut notes = [c, a, b];
+    notes.sort_by(compare_sidebar_order);
+    let ids: Vec<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, vec!["b", "a", "c"]);
+}
+
+#[test]
+fn test_compare_sidebar_order_unordered_by_updated_desc() {
+    let older = note("old", "Old", "2024-01-01T00:00:00Z");
+    let newer = note("new", "New", "2024-02-01T00:00:00Z");
+    let mut notes = [older, newer];
+    notes.sort_by(compare_sidebar_order);
+    assert_eq!(notes[0].id, "new");
+}
+
+#[test]
+fn test_is_trash_expired_respects_retention() {
+    let now = chrono::DateTime::parse_from_rfc3339("2024-03-31T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+    assert!(is_trash_expired("2024-02-01T12:00:00Z", now, 30));
+    assert!(!is_trash_expired("2024-03-10T12:00:00Z", now, 30));
+    assert!(!is_trash_expired("2024-03-01T12:00:00Z", now, 30));
+}
+
+#[test]
+fn test_is_trash_expired_invalid_timestamp_never_expires() {
+    let now = chrono::Utc::now();
+    assert!(!is_trash_expired("not a date", now, 1));
+}
+
+#[test]
+fn test_replace_inline_tag_rewrites_exact_tag() {
+    assert_eq!(replace_inline_tag("todo #projct today", "projct", "project"), "todo #project today");
+    assert_eq!(replace_inline_tag("#projct", "projct", "project"), "#project");
+}
+
+#[test]
+fn test_replace_inline_tag_ignores_longer_tags() {
+    assert_eq!(replace_inline_tag("#projcts and #projct-x", "projct", "p"), "#projcts and #projct-x");
+}
+
+#[test]
+fn test_replace_inline_tag_leaves_headings_alone() {
+    assert_eq!(replace_inline_tag("# Heading\n#a", "a", "b"), "# Heading\n#b");
+}
+
+#[test]
+fn test_replace_inline_tag_ignores_case() {
+    assert_eq!(replace_inline_tag("#Projct and #PROJCT", "projct", "project"), "#project and #project");
+}
+
+#[test]
+fn test_normalize_tag_composes_and_lowercases() {
+    assert_eq!(normalize_tag("Cafe\u{301}", true), "caf\u{e9}");
+    assert_eq!(normalize_tag("CAF\u{c9}", true), "caf\u{e9}");
+    assert_eq!(normalize_tag("Cafe\u{301}", false), "Caf\u{e9}");
+    assert_eq!(tag_key("\u{c9}t\u{e9}"), tag_key("e\u{301}te\u{301}"));
+}
+
+#[test]
+fn test_collapse_tag_variants_keeps_most_used_spelling() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["Work".into(), "caf\u{e9}".into()];
+    let mut b = note("b", "B", "2024-01-01T00:00:00Z");
+    b.tags = vec!["work".into(), "Cafe\u{301}".into()];
+    let mut c = note("c", "C", "2024-01-01T00:00:00Z");
+    c.tags = vec!["work".into(), "\u{fc}ber".into()];
+    assert_eq!(
+        collapse_tag_variants(&[a, b, c]),
+        vec!["Caf\u{e9}".to_string(), "work".to_string(), "\u{fc}ber".to_string()]
+    );
+}
+
+#[test]
+fn test_extract_tags_from_body_keeps_nested_tags() {
+    assert_eq!(
+        extract_tags_from_body("#project/alpha and #project//beta/ plus #solo"),
+        vec!["project/alpha".to_string(), "project/beta".to_string(), "solo".to_string()]
+    );
+}
+
+#[test]
+fn test_tag_matches_prefix_matches_descendants_only() {
+    assert!(tag_matches_prefix("project", "project"));
+    assert!(tag_matches_prefix("project/alpha", "project"));
+    assert!(tag_matches_prefix("Project/Alpha/v2", "#project/alpha"));
+    assert!(!tag_matches_prefix("projectx", "project"));
+    assert!(!tag_matches_prefix("project", "project/alpha"));
+    assert!(!tag_matches_prefix("project", "/"));
+}
+
+#[test]
+fn test_count_tags_counts_each_note_once() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["Work".into(), "work".into()];
+    let mut b = note("b", "B", "2024-01-01T00:00:00Z");
+    b.tags = vec!["work".into()];
+    assert_eq!(count_tags(&[a, b]), vec![("work".to_string(), 2)]);
+}
+
+#[test]
+fn test_tag_usage_counts_sorts_by_count_then_name() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["beta".into(), "alpha".into(), "zeta".into()];
+    let mut b = note("b", "B", "2024-01-01T00:00:00Z");
+    b.tags = vec!["zeta".into()];
+    let counts: Vec<(String, usize)> = tag_usage_counts(&[a, b]).into_iter().map(|c| (c.tag, c.count)).collect();
+    assert_eq!(
+        counts,
+        vec![("zeta".to_string(), 2), ("alpha".to_string(), 1), ("beta".to_string(), 1)]
+    );
+}
+
+#[test]
+fn test_summarize_tags_tracks_latest_update_and_starred() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["Work".into(), "home".into()];
+    let mut b = note("b", "B", "2024-03-01T00:00:00Z");
+    b.tags = vec!["work".into()];
+    b.important = true;
+    let mut c = note("c", "C", "2024-02-01T00:00:00Z");
+    c.tags = vec!["work".into()];
+    let summary = summarize_tags(&[a, b, c]);
+    assert_eq!(summary.len(), 2);
+    assert_eq!(summary[0].tag, "home");
+    assert_eq!(summary[0].count, 1);
+    assert_eq!(summary[0].last_updated, "2024-01-01T00:00:00Z");
+    assert!(!summary[0].has_important);
+    assert_eq!(summary[1].tag, "work");
+    assert_eq!(summary[1].count, 3);
+    assert_eq!(summary[1].last_updated, "2024-03-01T00:00:00Z");
+    assert!(summary[1].has_important);
+}
+
+#[test]
+fn test_tag_details_attach_meta_by_normalized_tag() {
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["Caf\u{e9}".into(), "home".into()];
+    let mut meta = std::collections::HashMap::new();
+    meta.insert(
+        "caf\u{e9}".to_string(),
+        TagMeta { color: "#ff0000".into(), description: Some("Coffee".into()) },
+    );
+    meta.insert("home".to_string(), TagMeta { color: String::new(), description: Some("House".into()) });
+    let details = tag_details(&[a], &meta);
+    assert_eq!(details.len(), 2);
+    assert_eq!(details[0].tag, "Caf\u{e9}");
+    assert_eq!(details[0].count, 1);
+    assert_eq!(details[0].color.as_deref(), Some("#ff0000"));
+    assert_eq!(details[1].color, None);
+    assert_eq!(details[1].description.as_deref(), Some("House"));
+}
+
+#[test]
+fn test_validate_tag_color() {
+    assert!(validate_tag_color("#abc").is_ok());
+    assert!(validate_tag_color("#A0b1C2").is_ok());
+    assert!(validate_tag_color("abc").is_err());
+    assert!(validate_tag_color("#abcd").is_err());
+    assert!(validate_tag_color("#ggg").is_err());
+}
+
+#[test]
+fn test_parse_search_query_normalizes_tag_filter() {
+    let q = parse_search_query("tag:Cafe\u{301} -tag:\u{dc}ber");
+    assert_eq!(q.tag_filter.as_deref(), Some("caf\u{e9}"));
+    assert_eq!(q.excluded_tags, vec!["\u{fc}ber".to_string()]);
+}
+
+fn sorted_ids(sort: SortBy) -> Vec<String> {
+    let mut a = note("a", "banana", "2024-01-03T00:00:00Z");
+    a.created_at = "2024-01-01T00:00:00Z".into();
+    let mut b = note("b", "\u{c9}clair", "2024-01-01T00:00:00Z");
+    b.created_at = "2024-01-02T00:00:00Z".into();
+    b.important = true;
+    let mut c = note("c", "apple", "2024-01-02T00:00:00Z");
+    c.created_at = "2024-01-03T00:00:00Z".into();
+    let mut notes = vec![a, b, c];
+    notes.sort_by(|x, y| compare_notes_by(sort, x, y));
+    notes.into_iter().map(|n| n.id).collect()
+}
+
+#[test]
+fn test_compare_notes_by_updated_at() {
+    assert_eq!(sorted_ids(SortBy::UpdatedAt), vec!["a", "c", "b"]);
+}
+
+#[test]
+fn test_compare_notes_by_created_at() {
+    assert_eq!(sorted_ids(SortBy::CreatedAt), vec!["c", "b", "a"]);
+}
+
+#[test]
+fn test_compare_notes_by_title_ignores_case_and_accents() {
+    assert_eq!(sorted_ids(SortBy::TitleAsc), vec!["c", "a", "b"]);
+    assert_eq!(sorted_ids(SortBy::TitleDesc), vec!["b", "a", "c"]);
+    let upper = note("u", "Apple", "2024-01-01T00:00:00Z");
+    let lower = note("l", "banana", "2024-01-01T00:00:00Z");
+    assert_eq!(compare_notes_by(SortBy::TitleAsc, &upper, &lower), std::cmp::Ordering::Less);
+}
+
+#[test]
+fn test_compare_notes_by_important_then_updated_at() {
+    assert_eq!(sorted_ids(SortBy::Important), vec!["b", "a", "c"]);
+}
+
+#[test]
+fn test_append_chunk_separates_from_existing_content() {
+    assert_eq!(append_chunk(None, "idea", None), "idea\n");
+    assert_eq!(append_chunk(Some(b'\n'), "idea\n\n", None), "\nidea\n");
+    assert_eq!(append_chunk(Some(b'x'), "idea", Some("09:30")), "\n\n## 09:30\nidea\n");
+}
+
+#[test]
+fn test_merge_note_bodies_default_and_custom_separator() {
+    let parts = vec![("A".to_string(), "one".to_string()), ("B".to_string(), "two".to_string())];
+    assert_eq!(merge_note_bodies(&parts, None), "## A\n\none\n\n## B\n\ntwo");
+    assert_eq!(merge_note_bodies(&parts, Some("--- {{title}} ---\n")), "--- A ---\none\n\n--- B ---\ntwo");
+}
+
+#[test]
+fn test_sort_merge_sources_orders() {
+    let mut a = note("a", "A", "2024-01-03");
+    a.created_at = "2024-01-01".into();
+    let mut b = note("b", "B", "2024-01-02");
+    b.created_at = "2024-01-02".into();
+    let ids = |v: &[NoteMeta]| v.iter().map(|n| n.id.clone()).collect::<Vec<_>>();
+    let mut notes = vec![a.clone(), b.clone()];
+    sort_merge_sources(&mut notes, MergeOrder::ByUpdated);
+    assert_eq!(ids(&notes), vec!["b", "a"]);
+    sort_merge_sources(&mut notes, MergeOrder::ByCreated);
+    assert_eq!(ids(&notes), vec!["a", "b"]);
+    let mut notes = vec![b, a];
+    sort_merge_sources(&mut notes, MergeOrder::ByProvidedOrder);
+    assert_eq!(ids(&notes), vec!["b", "a"]);
+}
+
+#[test]
+fn test_page_notes_windows_after_sorting() {
+    let notes: Vec<NoteMeta> = (0..5).map(|i| note(&format!("n{}", i), "T", "2024-01-01T00:00:00Z")).collect();
+    let page = page_notes(notes.clone(), Some(1), Some(2));
+    assert_eq!(page.total, 5);
+    assert_eq!(page.items.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(), vec!["n1", "n2"]);
+    assert_eq!(page_notes(notes.clone(), None, None).items.len(), 5);
+    let past_end = page_notes(notes, Some(10), Some(3));
+    assert!(past_end.items.is_empty());
+    assert_eq!(past_end.total, 5);
+}
+
+#[test]
+fn test_relevance_score_title_match_outranks_body_match() {
+    let terms = vec!["rust".to_string()];
+    let title_hit = relevance_score(&terms, "Rust notes", "something else");
+    let body_hit = relevance_score(&terms, "Notes", "I like rust and RUST");
+    assert!(title_hit > body_hit);
+    assert_eq!(body_hit, 2);
+    assert_eq!(relevance_score(&terms, "Nothing", "here"), 0);
+}
+
+#[test]
+fn test_relevance_score_counts_repeated_terms_once() {
+    let terms = vec!["a".to_string(), "a".to_string()];
+    assert_eq!(relevance_score(&terms, "", "a"), 1);
+}
+
+#[test]
+fn test_preview_text_collapses_whitespace_and_counts_chars() {
+    assert_eq!(preview_text("  first line\n\n  second\tline ", 100), "first line second line");
+    assert_eq!(preview_text("héllo wörld", 7), "héllo w");
+    assert_eq!(preview_text("ab   cd", 3), "ab");
+    assert_eq!(preview_text("", 10), "");
+}
+
+#[test]
+fn test_note_preview_skips_leading_heading() {
+    assert_eq!(note_preview("# Title\nFirst paragraph\nmore", 100), "First paragraph more");
+    assert_eq!(note_preview("\n# Only heading", 100), "");
+    assert_eq!(note_preview("## Sub heading kept\ntext", 100), "## Sub heading kept text");
+    assert_eq!(note_preview("#tag at start\ntext", 100), "#tag at start text");
+}
+
+#[test]
+fn test_parse_outline_levels_and_code_fences() {
+    let body = "# Intro\ntext\n## Setup ##\n```sh\n# not a heading\n```\n#tag line\n####### too deep\n~~~~\n## hidden\n~~~\n## still hidden\n~~~~\n   ### Indented\n";
+    let outline: Vec<(u8, String, usize)> = parse_outline(body).into_iter().map(|h| (h.level, h.text, h.line)).collect();
+    assert_eq!(
+        outline,
+        vec![
+            (1, "Intro".to_string(), 1),
+            (2, "Setup".to_string(), 3),
+            (3, "Indented".to_string(), 14),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_task_due_markers() {
+    assert_eq!(parse_task_due("- [ ] pay rent @due(2024-06-01)").as_deref(), Some("2024-06-01"));
+    assert_eq!(parse_task_due("- [ ] file taxes \u{1F4C5} 2024-04-15 urgent").as_deref(), Some("2024-04-15"));
+    assert_eq!(parse_task_due("- [ ] tight \u{1F4C5}2024-04-15").as_deref(), Some("2024-04-15"));
+    assert_eq!(parse_task_due("- [ ] spaced @due( 2024-06-01 )").as_deref(), Some("2024-06-01"));
+    assert_eq!(parse_task_due("- [ ] bad @due(2024-02-30) then @due(2024-03-01)").as_deref(), Some("2024-03-01"));
+    assert_eq!(parse_task_due("- [ ] unclosed @due(2024-06-01"), None);
+    assert_eq!(parse_task_due("- [ ] \u{1F4C5} soon"), None);
+    assert_eq!(parse_task_due("- [ ] no date"), None);
+}
+
+#[test]
+fn test_replace_text() {
+    assert_eq!(replace_text("Foo foo FOO", "foo", "bar", true).unwrap(), ("Foo bar FOO".to_string(), 1));
+    assert_eq!(replace_text("Foo foo FOO", "foo", "bar", false).unwrap(), ("bar bar bar".to_string(), 3));
+    // The search text is literal and `$` in the replacement is not expanded.
+    assert_eq!(replace_text("a.b axb", "a.b", "$1", true).unwrap(), ("$1 axb".to_string(), 1));
+    assert_eq!(replace_text("Straße", "STRASSE", "x", false).unwrap().1, 0);
+    assert_eq!(replace_text("Élan élan", "élan", "zeal", false).unwrap(), ("zeal zeal".to_string(), 2));
+    assert!(replace_text("x", "", "y", true).is_err());
+}
+
+#[test]
+fn test_toggle_task_line_flips_checkbox() {
+    let body = "Plan\r\n- [ ] write\n  * [X] review\nnot a task\n";
+    let once = toggle_task_line(body, 1).unwrap();
+    assert_eq!(once, "Plan\r\n- [x] write\n  * [X] review\nnot a task\n");
+    assert_eq!(toggle_task_line(&once, 2).unwrap(), "Plan\r\n- [x] write\n  * [ ] review\nnot a task\n");
+    assert_eq!(toggle_task_line(&once, 1).unwrap(), body);
+    assert!(toggle_task_line(body, 3).is_err());
+    assert!(toggle_task_line(body, 10).is_err());
+}
+
+#[test]
+fn test_normalize_note_color_accepts_palette_and_hex() {
+    assert_eq!(normalize_note_color(Some(" Red ")).unwrap().as_deref(), Some("red"));
+    assert_eq!(normalize_note_color(Some("#A0B")).unwrap().as_deref(), Some("#a0b"));
+    assert_eq!(normalize_note_color(Some("#00ff88")).unwrap().as_deref(), Some("#00ff88"));
+    assert_eq!(normalize_note_color(Some("  ")).unwrap(), None);
+    assert_eq!(normalize_note_color(None).unwrap(), None);
+    assert!(normalize_note_color(Some("crimson")).is_err());
+    assert!(normalize_note_color(Some("#12345")).is_err());
+}
+
+#[test]
+fn test_resolve_favorites_drops_missing_and_repeats() {
+    let notes = vec![note("a", "A", "2024-01-01"), note("b", "B", "2024-01-01")];
+    let ids: Vec<String> = ["b", "gone", "a", "b"].iter().map(|s| s.to_string()).collect();
+    let resolved: Vec<String> = resolve_favorites(&ids, &notes).into_iter().map(|n| n.id).collect();
+    assert_eq!(resolved, vec!["b", "a"]);
+}
+
+#[test]
+fn test_reorder_favorite_ids_keeps_unlisted_after() {
+    let current: Vec<String> = ["a", "b", "c", "d"].iter().map(|s| s.to_string()).collect();
+    let ordered: Vec<String> = ["c", "x", "a"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(reorder_favorite_ids(&current, &ordered), vec!["c", "a", "b", "d"]);
+}
+
+#[test]
+fn test_most_recent_notes_keeps_newest_in_order() {
+    let notes = vec![
+        note("a", "A", "2024-01-03T00:00:00Z"),
+        note("b", "B", "2024-01-05T00:00:00Z"),
+        note("c", "C", "2024-01-01T00:00:00Z"),
+        note("d", "D", "2024-01-05T00:00:00Z"),
+        note("e", "E", "2024-01-04T00:00:00Z"),
+    ];
+    let ids: Vec<String> = most_recent_notes(&notes, 3).into_iter().map(|n| n.id).collect();
+    assert_eq!(ids, vec!["b", "d", "e"]);
+    assert_eq!(most_recent_notes(&notes, 10).len(), 5);
+    assert!(most_recent_notes(&notes, 0).is_empty());
+}
+
+#[test]
+fn test_fuzzy_score_matches_word_start_abbreviations() {
+    assert!(fuzzy_score("prjalp", "Project Alpha").is_some());
+    assert!(fuzzy_score("PRJ ALP", "Project Alpha").is_some());
+    assert!(fuzzy_score("alpj", "Project Alpha").is_none());
+    assert!(fuzzy_score("", "Project Alpha").is_none());
+}
+
+#[test]
+fn test_fuzzy_score_prefers_contiguous_and_word_starts() {
+    let contiguous = fuzzy_score("alp", "Alpha notes").unwrap();
+    let scattered = fuzzy_score("alp", "banal helper").unwrap();
+    assert!(contiguous > scattered);
+    let word_start = fuzzy_score("pa", "Project Alpha").unwrap();
+    let mid_word = fuzzy_score("pa", "Spam").unwrap();
+    assert!(word_start > mid_word);
+}
+
+#[test]
+fn test_parse_search_query_date_ranges() {
+    let q = parse_search_query("created:>=2024-01-01 updated:<2024-06-01 report");
+    assert_eq!(q.date_ranges.len(), 2);
+    assert_eq!(q.date_ranges[0].field, DateField::Created);
+    assert_eq!(q.date_ranges[0].op, ">=");
+    assert_eq!(q.date_ranges[1].field, DateField::Updated);
+    assert_eq!(q.date_ranges[1].date, "2024-06-01");
+    assert_eq!(q.text_groups, vec![vec!["report".to_string()]]);
+}
+
+#[test]
+fn test_parse_search_query_ignores_invalid_dates() {
+    let q = parse_search_query("created:>=2024-13-01 updated:soon created:<=2024-2-1 x");
+    assert!(q.date_ranges.is_empty());
+    assert_eq!(q.text_groups, vec![vec!["x".to_string()]]);
+}
+
+#[test]
+fn test_date_range_boundaries() {
+    let at = |value: &str| parse_date_range(DateField::Created, value).unwrap();
+    let ts = "2024-01-01T23:59:59+00:00";
+    assert!(at(">=2024-01-01").matches(ts));
+    assert!(!at(">2024-01-01").matches(ts));
+    assert!(at("<=2024-01-01").matches(ts));
+    assert!(!at("<2024-01-01").matches(ts));
+    assert!(at("=2024-01-01").matches(ts));
+    assert!(at("2024-01-01").matches(ts));
+    assert!(!at("2024-01-02").matches(ts));
+}
+
+#[test]
+fn test_tokenize_query_keeps_quoted_phrases() {
+    let tokens = tokenize_query(r#"tag:work "status report" OR urgent"#);
+    let texts: Vec<(&str, bool)> = tokens.iter().map(|t| (t.text.as_str(), t.quoted)).collect();
+    assert_eq!(
+        texts,
+        vec![("tag:work", false), ("status report", true), ("OR", false), ("urgent", false)]
+    );
+}
+
+#[test]
+fn test_tokenize_query_unclosed_quote_runs_to_end() {
+    let tokens = tokenize_query(r#"a "b c"#);
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[1].text, "b c");
+    assert!(tokens[1].quoted);
+}
+
+#[test]
+fn test_parse_search_query_mixed_operators_phrase_and_or() {
+    let q = parse_search_query(r#"tag:work "status report" OR urgent"#);
+    assert_eq!(q.tag_filter.as_deref(), Some("work"));
+    assert_eq!(
+        q.text_groups,
+        vec![vec!["status report".to_string()], vec!["urgent".to_string()]]
+    );
+    assert!(q.matches_text("weekly status report", ""));
+    assert!(q.matches_text("", "this is urgent"));
+    assert!(!q.matches_text("status", "report"));
+}
+
+#[test]
+fn test_parse_search_query_bare_terms_are_anded() {
+    let q = parse_search_query("rust zig");
+    assert_eq!(q.text_groups.len(), 1);
+    assert!(q.matches_text("rust", "and zig"));
+    assert!(!q.matches_text("rust", ""));
+}
+
+#[test]
+fn test_parse_search_query_or_between_terms() {
+    let q = parse_search_query("rust OR zig");
+    assert!(q.matches_text("zig notes", ""));
+    assert!(q.matches_text("", "rust"));
+    assert!(!q.matches_text("go", "c"));
+}
+
+#[test]
+fn test_parse_search_query_quoted_operator_is_text() {
+    let q = parse_search_query(r#""tag:work" "OR""#);
+    assert!(q.tag_filter.is_none());
+    assert_eq!(q.text_groups, vec![vec!["tag:work".to_string(), "or".to_string()]]);
+}
+
+#[test]
+fn test_parse_search_query_dangling_or_ignored() {
+    let q = parse_search_query("OR rust OR");
+    assert_eq!(q.text_groups, vec![vec!["rust".to_string()]]);
+}
+
+#[test]
+fn test_tokenize_query_negation() {
+    let tokens = tokenize_query(r#"-draft -"old plan" - x"#);
+    assert_eq!(tokens.len(), 4);
+    assert!(tokens[0].negated && !tokens[0].quoted);
+    assert!(tokens[1].negated && tokens[1].quoted);
+    assert_eq!(tokens[1].text, "old plan");
+    assert!(!tokens[2].negated);
+    assert_eq!(tokens[2].text, "-");
+    assert!(!tokens[3].negated);
+}
+
+#[test]
+fn test_parse_search_query_excluded_text_and_tags() {
+    let q = parse_search_query("report -draft -tag:archive -is:starred -has:attachments");
+    assert_eq!(q.excluded_text, vec!["draft".to_string()]);
+    assert_eq!(q.excluded_tags, vec!["archive".to_string()]);
+    assert!(q.exclude_starred);
+    assert!(q.exclude_attachments);
+    assert!(q.matches_text("weekly report", ""));
+    assert!(!q.matches_text("weekly report", "still a draft"));
+}
+
+#[test]
+fn test_parse_search_query_negated_task_forms() {
+    let q = parse_search_query("-has:tasks -is:completed");
+    assert!(q.exclude_tasks);
+    assert_eq!(q.excluded_task_filter, Some(true));
+    assert!(!q.has_text());
+}
+
+#[test]
+fn test_parse_search_query_positive_tag_wins_over_negated() {
+    let q = parse_search_query("tag:work -tag:work -tag:home");
+    assert_eq!(q.tag_filter.as_deref(), Some("work"));
+    assert_eq!(q.excluded_tags, vec!["home".to_string()]);
+}
+
+#[test]
+fn test_parse_search_query_only_exclusions_matches_everything_else() {
+    let q = parse_search_query("-draft");
+    assert!(q.matches_text("final", "done"));
+    assert!(!q.matches_text("draft", ""));
+}
+
+#[test]
+fn test_build_search_regex_valid_is_case_insensitive() {
+    let re = build_search_regex(r"todo:\s+\w+").unwrap();
+    assert!(re.is_match("TODO: ship it"));
+}
+
+#[test]
+fn test_build_search_regex_rejects_invalid_pattern() {
+    let err = build_search_regex("(unclosed").unwrap_err();
+    assert!(err.starts_with("Invalid regex"));
+    assert!(build_search_regex("   ").is_err());
+}
+
+#[test]
+fn test_build_search_regex_rejects_oversized_pattern() {
+    assert!(build_search_regex(r"(\w{100}){100}").is_err());
+}
+
+#[test]
+fn test_build_snippet_centers_on_first_match() {
+    let body = format!("{}needle{}", "a".repeat(500), "b".repeat(500));
+    let (snippet, ranges) = build_snippet(&body, &["needle".to_string()], 200);
+    assert_eq!(snippet.chars().count(), 200);
+    assert_eq!(ranges.len(), 1);
+    let (s, e) = ranges[0];
+    assert_eq!(&snippet[s..e], "needle");
+}
+
+#[test]
+fn test_build_snippet_falls_back_to_body_start() {
+    let (snippet, ranges) = build_snippet("hello world", &["absent".to_string()], 5);
+    assert_eq!(snippet, "hello");
+    assert!(ranges.is_empty());
+    let (snippet, _) = build_snippet("hello", &[], 200);
+    assert_eq!(snippet, "hello");
+}
+
+#[test]
+fn test_build_snippet_multibyte_utf8_is_safe() {
+    let body = format!("{}Café naïve 🎉 ÉTÉ{}", "日本語".repeat(80), "ü🎉".repeat(80));
+    let (snippet, ranges) = build_snippet(&body, &["été".to_string(), "🎉".to_string()], 30);
+    assert!(!ranges.is_empty());
+    for (s, e) in ranges {
+        assert!(snippet.is_char_boundary(s) && snippet.is_char_boundary(e));
+        let m = &snippet[s..e];
+        assert!(m == "🎉" || m.to_lowercase() == "été");
+    }
+}
+
+#[test]
+fn test_build_snippet_reports_all_matches_in_window() {
+    let (snippet, ranges) = build_snippet("Rust and rust and RUST", &["rust".to_string()], 200);
+    assert_eq!(ranges.len(), 3);
+    assert_eq!(&snippet[ranges[2].0..ranges[2].1], "RUST");
+}
+
+#[test]
+fn test_tokenize_for_index_lowercases_words() {
+    let tokens = tokenize_for_index("Hello, WORLD! foo-bar #tag_x 42");
+    let v: Vec<&str> = tokens.iter().map(|s| s.as_str()).collect();
+    assert_eq!(v, vec!["42", "bar", "foo", "hello", "tag", "world", "x"]);
+}
+
+#[test]
+fn test_search_index_add_replace_and_remove() {
+    let mut sidx = SearchIndex::default();
+    search_index_add_note(&mut sidx, "n1", "Rust notes", "ownership and borrowing");
+    search_index_add_note(&mut sidx, "n2", "Zig", "comptime");
+    assert!(search_index_lookup(&sidx, "borrow").unwrap().contains("n1"));
+    // Re-adding replaces the old postings.
+    search_index_add_note(&mut sidx, "n1", "Rust notes", "lifetimes");
+    assert!(search_index_lookup(&sidx, "borrow").unwrap().is_empty());
+    search_index_remove_note(&mut sidx, "n2");
+    assert!(search_index_lookup(&sidx, "comptime").unwrap().is_empty());
+    assert!(!sidx.tokens.contains_key("zig"));
+}
+
+#[test]
+fn test_search_index_lookup_only_answers_word_terms() {
+    let sidx = SearchIndex::default();
+    assert!(search_index_lookup(&sidx, "status report").is_none());
+    assert!(search_index_lookup(&sidx, "c++").is_none());
+    assert!(search_index_lookup(&sidx, "status").is_some());
+}
+
+#[test]
+fn test_search_query_matches_text_indexed() {
+    let mut sidx = SearchIndex::default();
+    search_index_add_note(&mut sidx, "a", "Rust", "draft");
+    search_index_add_note(&mut sidx, "b", "Zig", "final");
+    let q = parse_search_query("rust OR zig -draft");
+    let lookups: std::collections::HashMap<_, _> = ["rust", "zig", "draft"]
+        .iter()
+        .map(|t| (t.to_string(), search_index_lookup(&sidx, t).unwrap()))
+        .collect();
+    assert_eq!(q.matches_text_indexed("a", &lookups), Some(false));
+    assert_eq!(q.matches_text_indexed("b", &lookups), Some(true));
+    let phrase = parse_search_query(r#""status report""#);
+    assert_eq!(phrase.matches_text_indexed("a", &lookups), None);
+}
+
+#[test]
+fn test_search_index_500_notes_lookups() {
+    let sidx = search_index_500_notes();
+    let hits = search_index_lookup(&sidx, "topic3").unwrap();
+    let expected: std::collections::HashSet<String> = (0..500).filter(|i| i % 10 == 3).map(|i| format!("note-{:03}", i)).collect();
+    assert_eq!(hits, expected);
+    assert_eq!(search_index_lookup(&sidx, "lorem").unwrap().len(), 500);
+    let one = search_index_lookup(&sidx, "title").unwrap();
+    assert_eq!(one.len(), 500);
+    assert!(search_index_lookup(&sidx, "topic10").unwrap_or_default().is_empty());
+    let exact = search_index_lookup(&sidx, "123").unwrap();
+    assert_eq!(exact, std::collections::HashSet::from(["note-123".to_string()]));
+}
+
+#[test]
+fn test_search_index_stale_notes_after_external_edit() {
+    let root = temp_dir("search-stale");
+    write_file(&root, "notes/a.txt", "alpha");
+    write_file(&root, "notes/b.txt", "beta");
+    let notes = vec![note("a", "A", "2024-01-01"), note("b", "B", "2024-01-01")];
+    let mut sidx = SearchIndex::default();
+    search_index_add_note(&mut sidx, "a", "A", "alpha");
+    search_index_add_note(&mut sidx, "b", "B", "beta");
+    search_index_stamp_notes(&root, "txt", &mut sidx, &notes);
+    assert!(search_index_stale_notes(&root, "txt", &sidx, &notes).is_empty());
+
+    // Edited behind the index's back: its postings ("beta") can no longer be trusted.
+    write_file(&root, "notes/b.txt", "gamma");
+    let stale = search_index_stale_notes(&root, "txt", &sidx, &notes);
+    assert_eq!(stale, std::collections::HashSet::from(["b".to_string()]));
+
+    // Re-indexing clears the stamp; stamping afterwards makes the entry current again.
+    search_index_add_note(&mut sidx, "b", "B", "gamma");
+    assert!(search_index_stale_notes(&root, "txt", &sidx, &notes).contains("b"));
+    search_index_stamp_notes(&root, "txt", &mut sidx, &notes);
+    assert!(search_index_stale_notes(&root, "txt", &sidx, &notes).is_empty());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+fn search_index_500_notes() -> SearchIndex {
+    let mut sidx = SearchIndex::default();
+    for i in 0..500 {
+        let body = format!("Note number {} about topic{} with shared words lorem ipsum dolor", i, i % 10);
+        search_index_add_note(&mut sidx, &format!("note-{:03}", i), &format!("Title {}", i), &body);
+    }
+    sidx
+}
+
+/// Timing only; run with `cargo test -- --ignored` when touching the search index.
+#[test]
+#[ignore]
+fn bench_search_index_500_notes() {
+    let start = std::time::Instant::now();
+    let sidx = search_index_500_notes();
+    let built = start.elapsed();
+    let start = std::time::Instant::now();
+    search_index_lookup(&sidx, "topic3").unwrap();
+    search_index_lookup(&sidx, "lorem").unwrap();
+    println!("search index: built 500 notes in {:?}, lookups in {:?}", built, start.elapsed());
+}
+
+#[test]
+fn test_note_meta_without_pinned_field_deserializes() {
+    let n = note("a", "A", "2024-01-01T00:00:00Z");
+    assert!(!n.pinned);
+}
+
+#[test]
+fn test_compare_sidebar_order_pinned_then_updated() {
+    let old_pinned = {
+        let mut n = note("p", "P", "2023-01-01T00:00:00Z");
+        n.pinned = true;
+        n
+    };
+    let recent = note("r", "R", "2024-06-01T00:00:00Z");
+    let older = note("o", "O", "2024-01-01T00:00:00Z");
+    let mut notes = [older, recent, old_pinned];
+    notes.sort_by(compare_sidebar_order);
+    let ids: Vec<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, vec!["p", "r", "o"]);
+}
+
+#[test]
+fn test_compare_sidebar_order_manual_order_before_updated() {
+    let mut first = note("first", "F", "2020-01-01T00:00:00Z");
+    first.order = Some(1);
+    let mut second = note("second", "S", "2021-01-01T00:00:00Z");
+    second.order = Some(2);
+    let unordered = note("new", "N", "2024-01-01T00:00:00Z");
+    let mut pinned = note("pin", "P", "2019-01-01T00:00:00Z");
+    pinned.pinned = true;
+    let mut notes = [unordered, second, pinned, first];
+    notes.sort_by(compare_sidebar_order);
+    let ids: Vec<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, vec!["pin", "first", "second", "new"]);
+}
+
+#[test]
+fn test_compare_sidebar_order_precedence() {
+    // pinned -> pin_order -> order -> updated_at, each level only breaking ties of the one before.
+    let mut pinned = note("pinned", "P", "2019-01-01T00:00:00Z");
+    pinned.pinned = true;
+    pinned.order = Some(9);
+    let mut pin_ordered = note("pin-order", "PO", "2020-01-01T00:00:00Z");
+    pin_ordered.pin_order = Some(5);
+    let mut ordered = note("order", "O", "2021-01-01T00:00:00Z");
+    ordered.order = Some(1);
+    let newest = note("newest", "N", "2024-01-01T00:00:00Z");
+    let mut notes = [newest, ordered, pin_ordered, pinned];
+    notes.sort_by(compare_sidebar_order);
+    let ids: Vec<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, vec!["pinned", "pin-order", "order", "newest"]);
+}
+
+#[test]
+fn test_count_task_lines() {
+    let body = "- [ ] a\n- [x] b\n  * [X] c\n* [ ] d\n- not a task\n-[ ] nope";
+    assert_eq!(count_task_lines(body), (4, 2));
+    assert_eq!(count_task_lines(""), (0, 0));
+}
+
+#[test]
+fn test_compute_note_stats() {
+    let stats = compute_note_stats("Hello  world\n- [x] done\n- [ ] todo");
+    assert_eq!(stats.words, 9);
+    assert_eq!(stats.chars, 34);
+    assert_eq!(stats.reading_minutes, 1);
+    assert_eq!(stats.task_total, 2);
+    assert_eq!(stats.task_done, 1);
+}
+
+#[test]
+fn test_compute_note_stats_reading_time_rounds_up() {
+    assert_eq!(compute_note_stats("").reading_minutes, 0);
+    assert_eq!(compute_note_stats(&"word ".repeat(200)).reading_minutes, 1);
+    assert_eq!(compute_note_stats(&"word ".repeat(201)).reading_minutes, 2);
+}
+
+#[test]
+fn test_compute_note_stats_counts_unicode_chars() {
+    let stats = compute_note_stats("café 🎉");
+    assert_eq!(stats.chars, 6);
+    assert_eq!(stats.words, 2);
+}
+
+fn diff_summary(old: &str, new: &str) -> Vec<(DiffKind, String)> {
+    diff_lines(old, new).into_iter().map(|l| (l.kind, l.text)).collect()
+}
+
+#[test]
+fn test_diff_lines_insert() {
+    assert_eq!(
+        diff_summary("a\nc", "a\nb\nc"),
+        vec![
+            (DiffKind::Unchanged, "a".to_string()),
+            (DiffKind::Added, "b".to_string()),
+            (DiffKind::Unchanged, "c".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_lines_delete() {
+    assert_eq!(
+        diff_summary("a\nb\nc", "a\nc"),
+        vec![
+            (DiffKind::Unchanged, "a".to_string()),
+            (DiffKind::Removed, "b".to_string()),
+            (DiffKind::Unchanged, "c".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_lines_replace_and_empty_sides() {
+    assert_eq!(
+        diff_summary("x", "y"),
+        vec![(DiffKind::Removed, "x".to_string()), (DiffKind::Added, "y".to_string())]
+    );
+    assert_eq!(diff_summary("", "new"), vec![(DiffKind::Added, "new".to_string())]);
+    assert_eq!(diff_summary("old", ""), vec![(DiffKind::Removed, "old".to_string())]);
+    assert!(diff_summary("", "").is_empty());
+}
+
+/// "head", then `lines` lines unique to `side` with "common" in the middle, then "tail".
+fn diff_input(side: &str, lines: usize) -> String {
+    let mut out = vec!["head".to_string()];
+    out.extend((0..lines).map(|i| if i == lines / 2 { "common".to_string() } else { format!("{}{}", side, i) }));
+    out.push("tail".into());
+    out.join("\n")
+}
+
+#[test]
+fn test_diff_lines_falls_back_to_coarse_diff_above_cell_cap() {
+    let unchanged = |d: &[(DiffKind, String)]| d.iter().filter(|(k, _)| *k == DiffKind::Unchanged).map(|(_, t)| t.clone()).collect::<Vec<_>>();
+    // 1000 x 1000 changed lines is exactly the cap: still a real LCS, which finds "common".
+    assert_eq!(MAX_DIFF_CELLS, 1000 * 1000);
+    let d = diff_summary(&diff_input("old", 1000), &diff_input("new", 1000));
+    assert_eq!(unchanged(&d), ["head", "common", "tail"]);
+    // One more old line is over the cap: all old lines removed, then all new lines added.
+    let d = diff_summary(&diff_input("old", 1001), &diff_input("new", 1000));
+    assert_eq!(unchanged(&d), ["head", "tail"]);
+    assert_eq!(d.len(), 2 + 1001 + 1000);
+    assert!(d[1..1002].iter().all(|(k, _)| *k == DiffKind::Removed));
+    assert!(d[1002..2002].iter().all(|(k, _)| *k == DiffKind::Added));
+}
+
+#[test]
+fn test_diff_lines_identical_is_all_unchanged() {
+    let d = diff_lines("a\nb", "a\nb");
+    assert!(d.iter().all(|l| l.kind == DiffKind::Unchanged));
+    assert_eq!(d.len(), 2);
+}
+
+/// Fresh empty directory under the system temp dir.
+fn temp_dir(prefix: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_snapshot(dir: &std::path::Path, saved_at: &str, pinned: bool) {
+    let snapshot = VersionSnapshot {
+        saved_at: saved_at.to_string(),
+        title: "T".into(),
+        body: "B".into(),
+        pinned,
+        label: None,
+    };
+    let name = format!("{}.json", saved_at.replace(':', "-"));
+    std::fs::write(dir.join(name), serde_json::to_string(&snapshot).unwrap()).unwrap();
+}
+
+#[test]
+fn test_prune_versions_keeps_pinned() {
+    let dir = temp_dir("lpn-versions");
+    for i in 0..35 {
+        let saved_at = format!("2024-01-01T00:00:{:02}+00:00", i);
+        // Pin the two oldest versions, which would otherwise be pruned first.
+        write_snapshot(&dir, &saved_at, i < 2);
+    }
+    prune_versions(&dir, 30);
+    let remaining: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(remaining.len(), 32);
+    assert!(remaining.contains(&"2024-01-01T00-00-00+00-00.json".to_string()));
+    assert!(remaining.contains(&"2024-01-01T00-00-01+00-00.json".to_string()));
+    assert!(!remaining.contains(&"2024-01-01T00-00-02+00-00.json".to_string()));
+    assert!(remaining.contains(&"2024-01-01T00-00-34+00-00.json".to_string()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_version_snapshot_without_pinned_field_deserializes() {
+    let v: VersionSnapshot = serde_json::from_str(r#"{"savedAt":"x","title":"t","body":"b"}"#).unwrap();
+    assert!(!v.pinned);
+    assert!(v.label.is_none());
+}
+
+#[test]
+fn test_max_versions_per_note_defaults_and_clamps() {
+    let mut settings = Settings::default();
+    assert_eq!(max_versions_per_note(&settings), 30);
+    settings.max_versions_per_note = 5;
+    assert_eq!(max_versions_per_note(&settings), 5);
+    settings.max_versions_per_note = 0;
+    assert_eq!(max_versions_per_note(&settings), 30);
+    settings.max_versions_per_note = -3;
+    assert_eq!(max_versions_per_note(&settings), 30);
+    settings.max_versions_per_note = 50_000;
+    assert_eq!(max_versions_per_note(&settings), 1000);
+
+    let s: Settings = serde_json::from_str(r#"{"trashRetentionDays":7}"#).unwrap();
+    assert_eq!(s.max_versions_per_note, 30);
+}
+
+#[test]
+fn test_validate_settings_rejects_non_positive_version_limit() {
+    let mut settings = Settings {
+        max_versions_per_note: 0,
+        ..Default::default()
+    };
+    assert!(validate_settings(settings.clone()).is_err());
+    settings.max_versions_per_note = -1;
+    assert!(validate_settings(settings.clone()).is_err());
+    settings.max_versions_per_note = 2000;
+    assert_eq!(validate_settings(settings).unwrap().max_versions_per_note, 1000);
+}
+
+fn notebook(id: &str, parent: Option<&str>, created_at: &str) -> Notebook {
+    Notebook {
+        id: id.to_string(),
+        name: id.to_uppercase(),
+        archived: false,
+        created_at: created_at.to_string(),
+        parent_id: parent.map(String::from),
+    }
+}
+
+#[test]
+fn test_sort_notebooks_for_tree_parents_before_children() {
+    let notebooks = vec![
+        notebook("child", Some("root"), "2024-01-03"),
+        notebook("grandchild", Some("child"), "2024-01-01"),
+        notebook("root", None, "2024-01-02"),
+        notebook("other", None, "2024-01-04"),
+        notebook("orphan", Some("missing"), "2024-01-05"),
+    ];
+    let ids: Vec<String> = sort_notebooks_for_tree(notebooks).into_iter().map(|nb| nb.id).collect();
+    assert_eq!(ids, ["root", "child", "grandchild", "other", "orphan"]);
+}
+
+#[test]
+fn test_notebook_creates_cycle() {
+    let notebooks = [
+        notebook("a", None, "1"),
+        notebook("b", Some("a"), "2"),
+        notebook("c", Some("b"), "3"),
+        notebook("d", None, "4"),
+    ];
+    assert!(notebook_creates_cycle(&notebooks, "a", "a"));
+    assert!(notebook_creates_cycle(&notebooks, "a", "c"));
+    assert!(!notebook_creates_cycle(&notebooks, "c", "a"));
+    assert!(!notebook_creates_cycle(&notebooks, "a", "d"));
+}
+
+#[test]
+fn test_notebook_descendants() {
+    let notebooks = [
+        notebook("a", None, "1"),
+        notebook("b", Some("a"), "2"),
+        notebook("c", Some("b"), "3"),
+        notebook("d", None, "4"),
+    ];
+    let mut ids = notebook_descendants(&notebooks, "a");
+    ids.sort();
+    assert_eq!(ids, ["b", "c"]);
+    assert!(notebook_descendants(&notebooks, "d").is_empty());
+}
+
+#[test]
+fn test_count_notes_by_notebook() {
+    let notebooks = [notebook("a", None, "1"), notebook("b", None, "2")];
+    let mut n1 = note("1", "One", "2024-01-01");
+    n1.notebook_id = Some("a".into());
+    n1.important = true;
+    let mut n2 = note("2", "Two", "2024-01-02");
+    n2.notebook_id = Some("a".into());
+    let n3 = note("3", "Three", "2024-01-03");
+    let mut n4 = note("4", "Four", "2024-01-04");
+    n4.notebook_id = Some("gone".into());
+    n4.important = true;
+    let counts = count_notes_by_notebook(&notebooks, &[n1, n2, n3, n4]);
+    let summary: Vec<(Option<&str>, usize, usize)> = counts
+        .iter()
+        .map(|c| (c.notebook_id.as_deref(), c.total, c.important))
+        .collect();
+    assert_eq!(summary, [(None, 2, 1), (Some("a"), 2, 1), (Some("b"), 0, 0)]);
+}
+
+#[test]
+fn test_filter_notes_by_notebook() {
+    let mut notebooks = vec![notebook("a", None, "1")];
+    notebooks[0].archived = true;
+    let mut n1 = note("1", "One", "2024-01-01");
+    n1.notebook_id = Some("a".into());
+    let mut n2 = note("2", "Two", "2024-01-02");
+    n2.notebook_id = Some("a".into());
+    let n3 = note("3", "Three", "2024-01-03");
+    let mut n4 = note("4", "Four", "2024-01-04");
+    n4.notebook_id = Some("gone".into());
+    let notes = vec![n1, n2, n3, n4];
+    let ids = |v: Vec<NoteMeta>| v.into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert_eq!(ids(filter_notes_by_notebook(&notebooks, notes.clone(), Some("a"))), vec!["2", "1"]);
+    assert_eq!(ids(filter_notes_by_notebook(&notebooks, notes, None)), vec!["4", "3"]);
+}
+
+fn notebook_index() -> IndexFile {
+    let mut n1 = note("1", "One", "2024-01-01");
+    n1.notebook_id = Some("a".into());
+    let mut n2 = note("2", "Two", "2024-01-02");
+    n2.notebook_id = Some("a".into());
+    let n3 = note("3", "Three", "2024-01-03");
+    IndexFile {
+        notes: vec![n1, n2, n3],
+        notebooks: vec![notebook("a", None, "1"), notebook("b", Some("a"), "2")],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_remove_notebook_move_to_unfiled() {
+    let mut index = notebook_index();
+    let affected = remove_notebook_from_index(&mut index, "a", NotebookDeleteStrategy::MoveToUnfiled).unwrap();
+    assert_eq!(affected, ["1", "2"]);
+    assert_eq!(index.notes.len(), 3);
+    assert!(index.notes.iter().all(|n| n.notebook_id.is_none()));
+    assert_eq!(index.notebooks.len(), 1);
+    assert_eq!(index.notebooks[0].parent_id, None);
+    assert!(index.trashed.is_empty());
+}
+
+#[test]
+fn test_remove_notebook_delete_notes() {
+    let mut index = notebook_index();
+    let affected = remove_notebook_from_index(&mut index, "a", NotebookDeleteStrategy::DeleteNotes).unwrap();
+    assert_eq!(affected.len(), 2);
+    assert_eq!(index.notes.len(), 1);
+    assert_eq!(index.notes[0].id, "3");
+    assert_eq!(index.trashed.len(), 2);
+    assert!(index.trashed.iter().all(|n| n.deleted_at.is_some()));
+    assert!(remove_notebook_from_index(&mut index, "missing", NotebookDeleteStrategy::DeleteNotes).is_err());
+}
+
+#[test]
+fn test_wikilink_spans() {
+    let spans = wikilink_spans("a [[One]] b [[x]y]] c [[open");
+    let inner: Vec<(&str, bool)> = spans.iter().map(|s| (s.inner, s.closed)).collect();
+    assert_eq!(inner, [("One", true), ("x]y", true), ("open", false)]);
+    assert_eq!(&"a [[One]] b"[spans[0].start..spans[0].end], "[[One]]");
+    // A trailing single bracket used to panic the link parser.
+    assert_eq!(wikilink_spans("[[abc]")[0].inner, "abc]");
+}
+
+#[test]
+fn test_relink_title() {
+    let body = "See [[Old Title]] and [[ old title ]], not [[Old Titles]] or [[Old Title";
+    assert_eq!(
+        relink_title(body, "Old Title", "New"),
+        "See [[New]] and [[New]], not [[Old Titles]] or [[Old Title"
+    );
+    assert_eq!(relink_title("no links", "Old", "New"), "no links");
+}
+
+#[test]
+fn test_find_unlinked_mentions_word_boundaries() {
+    assert_eq!(find_unlinked_mentions("My cat sat.", "Cat"), [3]);
+    assert!(find_unlinked_mentions("Category and concat", "Cat").is_empty());
+    assert_eq!(find_unlinked_mentions("CAT, cat_ and (cat)", "cat"), [0, 15]);
+    assert!(find_unlinked_mentions("anything", "  ").is_empty());
+}
+
+#[test]
+fn test_find_unlinked_mentions_skips_linked() {
+    let body = "[[Cat]] is linked, [[The Cat Book]] too, but Cat is not";
+    assert_eq!(find_unlinked_mentions(body, "cat"), [45]);
+    assert_eq!(find_unlinked_mentions("Big Cat and [[big cat]]", "Big Cat"), [0]);
+}
+
+#[test]
+fn test_build_link_graph() {
+    let mut a = note("a", "A", "2024-01-01");
+    a.links_to = vec!["b".into(), "deleted".into()];
+    a.tags = vec!["x".into(), "y".into()];
+    let mut b = note("b", "B", "2024-01-02");
+    b.tags = vec!["x".into(), "y".into()];
+    let c = note("c", "C", "2024-01-03");
+    let notes = [a, b, c];
+
+    let graph = build_link_graph(&notes, false);
+    assert_eq!(graph.nodes.len(), 3);
+    assert_eq!(graph.edges.len(), 1);
+    assert_eq!((graph.edges[0].from.as_str(), graph.edges[0].to.as_str()), ("a", "b"));
+    assert_eq!(graph.edges[0].kind, GraphEdgeKind::Link);
+
+    let graph = build_link_graph(&notes, true);
+    let tag_edges: Vec<_> = graph.edges.iter().filter(|e| e.kind == GraphEdgeKind::Tag).collect();
+    // Two shared tags still give a single edge.
+    assert_eq!(tag_edges.len(), 1);
+    assert_eq!((tag_edges[0].from.as_str(), tag_edges[0].to.as_str()), ("a", "b"));
+}
+
+#[test]
+fn test_build_connections() {
+    let mut me = note("me", "Me", "2024-01-01");
+    me.links_to = vec!["zeta".into(), "alpha".into(), "mutual".into(), "deleted".into()];
+    let zeta = note("zeta", "zeta", "2024-01-01");
+    let alpha = note("alpha", "Alpha", "2024-01-01");
+    let mut mutual = note("mutual", "Mutual", "2024-01-05");
+    mutual.links_to = vec!["me".into()];
+    let mut old_fan = note("old", "Old fan", "2024-01-02");
+    old_fan.links_to = vec!["me".into()];
+    let mut new_fan = note("new", "New fan", "2024-01-03");
+    new_fan.links_to = vec!["me".into()];
+    let notes = [me, zeta, alpha, mutual, old_fan, new_fan];
+
+    let c = build_connections(&notes, "me").unwrap();
+    let out: Vec<&str> = c.outgoing.iter().map(|n| n.id.as_str()).collect();
+    let inc: Vec<&str> = c.incoming.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(out, ["alpha", "mutual", "zeta"]);
+    assert_eq!(inc, ["new", "old"]);
+    assert!(build_connections(&notes, "missing").is_err());
+}
+
+#[test]
+fn test_resolve_link_title_with_aliases() {
+    let mut js = note("js", "JavaScript Notes", "2024-01-01");
+    js.aliases = vec!["JS".into()];
+    js.created_at = "2024-01-02".into();
+    let mut older = note("older", "Other", "2024-01-01");
+    older.aliases = vec!["js".into()];
+    older.created_at = "2024-01-01".into();
+    let exact = note("exact", "Exact", "2024-01-01");
+    let mut claims_exact = note("claims", "Claims", "2024-01-01");
+    claims_exact.aliases = vec!["exact".into()];
+    let notes = [js, older, exact, claims_exact];
+
+    assert_eq!(resolve_link_title(&notes, "javascript notes", ""), Some("js"));
+    // Collision: the note created first wins.
+    assert_eq!(resolve_link_title(&notes, " Js ", ""), Some("older"));
+    assert_eq!(resolve_link_title(&notes, "JS", "older"), Some("js"));
+    // A title match beats an alias.
+    assert_eq!(resolve_link_title(&notes, "EXACT", ""), Some("exact"));
+    assert_eq!(resolve_link_title(&notes, "nope", ""), None);
+    assert_eq!(resolve_link_title(&notes, " ", ""), None);
+}
+
+#[test]
+fn test_duplicate_title_groups() {
+    let notes = [
+        note("b", "Plans", "2024-02-01"),
+        note("a", "plans", "2024-01-01"),
+        note("c", "Ideas", "2024-01-01"),
+        note("d", "Zoo", "2024-01-01"),
+        note("e", "ZOO", "2024-01-01"),
+    ];
+    let groups = duplicate_title_groups(&notes);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].title, "plans");
+    let ids: Vec<&str> = groups[0].notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, ["a", "b"]);
+    let ids: Vec<&str> = groups[1].notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, ["d", "e"]);
+
+    assert!(has_duplicate_title(&notes, "IDEAS", ""));
+    assert!(!has_duplicate_title(&notes, "Ideas", "c"));
+    assert!(has_duplicate_title(&notes, "plans", "a"));
+    assert!(!has_duplicate_title(&notes, "New", ""));
+}
+
+#[test]
+fn test_render_note_html() {
+    let body = "- [x] done\n- [ ] todo\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```rust\nfn main() {}\n```\n\nSee [[Other Note]].\n\n![pic](images/n1/p.png) ![web](https://x.test/a.png)\n";
+    let html = render_note_html("A <b> & c", body, |rel| Some(format!("file:///root/{}", rel)));
+    assert!(html.contains("<title>A &lt;b&gt; &amp; c</title>"));
+    assert!(html.contains("<h1>A &lt;b&gt; &amp; c</h1>"));
+    assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+    assert!(html.contains("<table>"));
+    assert!(html.contains("<code class=\"language-rust\">"));
+    assert!(html.contains("See Other Note."));
+    assert!(html.contains("src=\"file:///root/images/n1/p.png\""));
+    assert!(html.contains("src=\"https://x.test/a.png\""));
+}
+
+#[test]
+fn test_file_url() {
+    assert_eq!(file_url(std::path::Path::new("/a b/c#1.png")), "file:///a%20b/c%231.png");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_asset_url_matches_convert_file_src() {
+    let path = std::path::Path::new("/Users/me/Notes data/images/n1/café (1).png");
+    assert_eq!(
+        asset_url(path),
+        "asset://localhost/%2FUsers%2Fme%2FNotes%20data%2Fimages%2Fn1%2Fcaf%C3%A9%20(1).png"
+    );
+}
+
+#[test]
+fn test_parse_image_data_url() {
+    assert_eq!(parse_image_data_url("data:image/png;base64,iVBORw0KGgo=").unwrap(), ("png", "iVBORw0KGgo="));
+    assert_eq!(parse_image_data_url(" DATA:Image/JPEG;charset=x;Base64,/9j/ ").unwrap(), ("jpg", "/9j/"));
+    assert!(parse_image_data_url("data:text/plain;base64,aGk=").is_err());
+    assert!(parse_image_data_url("data:image/svg+xml;base64,PHN2Zz4=").is_err());
+    assert!(parse_image_data_url("data:image/png,rawbytes").is_err());
+    assert!(parse_image_data_url("https://example.com/a.png").is_err());
+    assert!(parse_image_data_url("data:image/png;base64").is_err());
+    let huge = format!("data:image/png;base64,{}", "A".repeat(MAX_DATA_URL_IMAGE_BYTES / 3 * 4 + 8));
+    assert_eq!(parse_image_data_url(&huge).unwrap_err(), "Image is too large");
+}
+
+#[test]
+fn test_dedupe_filename() {
+    let taken = ["Note.md", "Note (2).md"];
+    assert_eq!(dedupe_filename("Other", ".md", |n| taken.contains(&n)), "Other.md");
+    assert_eq!(dedupe_filename("Note", ".md", |n| taken.contains(&n)), "Note (3).md");
+}
+
+#[test]
+fn test_note_to_markdown() {
+    let mut meta = note("1", "Title", "2024-01-02");
+    meta.created_at = "2024-01-01".into();
+    meta.tags = vec!["a".into()];
+    assert_eq!(
+        note_to_markdown(&meta, "Body"),
+        "---\ntags:\n  - a\ncreated: 2024-01-01\nupdated: 2024-01-02\n---\n\n# Title\n\nBody\n"
+    );
+    meta.tags.clear();
+    meta.created_at = meta.updated_at.clone();
+    assert_eq!(note_to_markdown(&meta, "Body\n"), "# Title\n\nBody\n");
+}
+
+#[test]
+fn test_heading_anchor() {
+    let mut used = std::collections::HashMap::new();
+    assert_eq!(heading_anchor("Hello, World!", &mut used), "hello-world");
+    assert_eq!(heading_anchor("Hello World", &mut used), "hello-world-1");
+    assert_eq!(heading_anchor("hello world", &mut used), "hello-world-2");
+    assert_eq!(heading_anchor("Über_cool - v2", &mut used), "über_cool---v2");
+}
+
+#[test]
+fn test_render_notebook_markdown() {
+    let nb = notebook("nb", None, "2024-01-01");
+    let mut a = note("a", "Plan", "2024-02-01");
+    a.tags = vec!["work".into()];
+    let b = note("b", "Contents", "2024-03-01");
+    let notes = [(a, "First".to_string()), (b, "Second\n".to_string())];
+    let md = render_notebook_markdown(&nb, &notes, false);
+    assert_eq!(
+        md,
+        "# NB\n\n## Contents\n\n- [Plan](#plan)\n- [Contents](#contents-1)\n\n## Plan\n\nFirst\n\n---\n\n## Contents\n\nSecond\n"
+    );
+    let md = render_notebook_markdown(&nb, &notes, true);
+    assert!(md.starts_with("---\ntags:\n  - work\ncreated: 2024-01-01\nupdated: 2024-03-01\n---\n\n# NB\n"));
+    assert_eq!(render_notebook_markdown(&nb, &[], false), "# NB\n\n");
+}
+
+#[test]
+fn test_backup_zip_round_trip() {
+    let root = temp_dir("lpn-zip-src");
+    std::fs::create_dir_all(root.join("notes")).unwrap();
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    std::fs::create_dir_all(root.join("images").join("n1")).unwrap();
+    std::fs::write(root.join("notes").join("n1.txt"), "hello").unwrap();
+    std::fs::write(root.join("meta").join("index.json"), r#"{"notes":[]}"#).unwrap();
+    std::fs::write(root.join("meta").join("templates.json"), "[]").unwrap();
+    std::fs::write(root.join("images").join("n1").join("a.png"), [1u8, 2, 3]).unwrap();
+
+    let out = temp_dir("lpn-zip-out");
+    let zip_file = out.join("backup.zip");
+    let mut last = 0;
+    write_backup_zip(&root, &zip_file, &mut |bytes| last = bytes).unwrap();
+    assert!(zip_file.exists());
+    assert!(!out.join("backup.zip.tmp").exists());
+    assert_eq!(last, 5 + 12 + 2 + 3);
+
+    let restored = out.join("restored");
+    extract_backup_zip(&zip_file, &restored).unwrap();
+    assert_eq!(std::fs::read_to_string(restored.join("notes").join("n1.txt")).unwrap(), "hello");
+    assert_eq!(std::fs::read_to_string(restored.join("meta").join("templates.json")).unwrap(), "[]");
+    assert_eq!(std::fs::read(restored.join("images").join("n1").join("a.png")).unwrap(), [1u8, 2, 3]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_dir_all(&out).unwrap();
+}
+
+#[test]
+fn test_backup_zip_manifest_matches_archived_bytes() {
+    let root = temp_dir("lpn-zip-hash-src");
+    std::fs::create_dir_all(root.join("notes")).unwrap();
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    std::fs::write(root.join("notes").join("n1.txt"), "hello").unwrap();
+    std::fs::write(root.join("meta").join("index.json"), r#"{"notes":[]}"#).unwrap();
+
+    // Edit a note right after it was streamed into the zip; the manifest must describe what was archived.
+    let out = temp_dir("lpn-zip-hash-out");
+    let zip_file = out.join("backup.zip");
+    let note_file = root.join("notes").join("n1.txt");
+    let mut edited = false;
+    write_backup_zip(&root, &zip_file, &mut |_| {
+        if !edited {
+            std::fs::write(&note_file, "changed during export").unwrap();
+            edited = true;
+        }
+    })
+    .unwrap();
+
+    let restored = out.join("restored");
+    extract_backup_zip(&zip_file, &restored).unwrap();
+    let archived = std::fs::read_to_string(restored.join("notes").join("n1.txt")).unwrap();
+    assert_ne!(archived, std::fs::read_to_string(&note_file).unwrap());
+    assert_eq!(verify_backup_dir(&restored).unwrap(), Default::default());
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_dir_all(&out).unwrap();
+}
+
+#[test]
+fn test_merge_index() {
+    let mut local = IndexFile {
+        notes: vec![note("same", "Local", "2024-01-05"), note("older", "Local old", "2024-01-01")],
+        notebooks: vec![notebook("nb1", None, "1")],
+        trashed: vec![note("binned", "Binned", "2024-01-01")],
+        ..Default::default()
+    };
+    let mut nb1_renamed = notebook("nb1", None, "1");
+    nb1_renamed.name = "Renamed".into();
+    let incoming = IndexFile {
+        notes: vec![
+            note("same", "Backup", "2024-01-03"),
+            note("older", "Backup newer", "2024-01-02"),
+            note("new", "New", "2024-01-01"),
+            note("binned", "Binned", "2024-02-01"),
+        ],
+        notebooks: vec![nb1_renamed, notebook("nb2", None, "2")],
+        ..Default::default()
+    };
+    let (summary, to_copy) = merge_index(&mut local, incoming);
+    assert_eq!((summary.added, summary.updated, summary.skipped), (1, 1, 2));
+    assert_eq!(to_copy, ["older", "new"]);
+    assert_eq!(local.notes.len(), 3);
+    assert_eq!(local.notes.iter().find(|n| n.id == "same").unwrap().title, "Local");
+    assert_eq!(local.notes.iter().find(|n| n.id == "older").unwrap().title, "Backup newer");
+    assert_eq!(local.notebooks.len(), 2);
+    assert_eq!(local.notebooks[0].name, "NB1");
+}
+
+#[test]
+fn test_backup_manifest_verification() {
+    let base = temp_dir("lpn-manifest");
+    std::fs::create_dir_all(base.join("notes")).unwrap();
+    std::fs::create_dir_all(base.join("images").join("n1")).unwrap();
+    std::fs::write(base.join("notes").join("a.txt"), "abc").unwrap();
+    std::fs::write(base.join("notes").join("b.txt"), "bbb").unwrap();
+    std::fs::write(base.join("images").join("n1").join("p.png"), "png").unwrap();
+    let manifest = build_backup_manifest(&base).unwrap();
+    let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, ["images/n1/p.png", "notes/a.txt", "notes/b.txt"]);
+    assert_eq!(
+        manifest.files[1].sha256,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(manifest.files[1].size, 3);
+
+    assert!(verify_backup_dir(&base).is_err());
+    std::fs::write(base.join("manifest.json"), serde_json::to_string(&manifest).unwrap()).unwrap();
+    assert_eq!(verify_backup_dir(&base).unwrap(), Default::default());
+
+    std::fs::write(base.join("notes").join("a.txt"), "abd").unwrap();
+    std::fs::remove_file(base.join("images").join("n1").join("p.png")).unwrap();
+    let result = verify_backup_dir(&base).unwrap();
+    assert_eq!(result.missing, ["images/n1/p.png"]);
+    assert_eq!(result.corrupt, ["notes/a.txt"]);
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_derive_lock_key() {
+    let key = derive_lock_key("hunter2", "salt-a");
+    assert_eq!(key, derive_lock_key("hunter2", "salt-a"));
+    assert_ne!(key, derive_lock_key("hunter2", "salt-b"));
+    assert_ne!(key, derive_lock_key("hunter3", "salt-a"));
+    let verifier = lock_key_verifier(&key);
+    assert_eq!(verifier.len(), 64);
+    assert_eq!(verifier, lock_key_verifier(&derive_lock_key("hunter2", "salt-a")));
+}
+
+#[test]
+fn test_validate_storage_root_change() {
+    let current = std::path::Path::new("/data/notes-root");
+    assert!(validate_storage_root_change(current, std::path::Path::new("/elsewhere")).is_ok());
+    assert!(validate_storage_root_change(current, current).is_ok());
+    assert!(validate_storage_root_change(current, std::path::Path::new("/data")).is_ok());
+    assert!(validate_storage_root_change(current, &current.join("sub")).is_err());
+    assert!(validate_storage_root_change(current, std::path::Path::new("relative/dir")).is_err());
+}
+
+#[test]
+fn test_validate_storage_root_change_resolves_links_and_dots() {
+    let base = temp_dir("lpn-root-nesting");
+    let current = base.join("current");
+    std::fs::create_dir_all(&current).unwrap();
+    assert!(validate_storage_root_change(&current, &base.join("other/../current/sub")).is_err());
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&current, base.join("link")).unwrap();
+        assert!(validate_storage_root_change(&current, &base.join("link/sub")).is_err());
+    }
+    assert!(validate_storage_root_change(&current, &base.join("current-2")).is_ok());
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_migrate_storage_dirs_is_all_or_nothing() {
+    let base = temp_dir("lpn-migrate");
+    let (current, new) = (base.join("current"), base.join("new"));
+    write_file(&current, "notes/a.txt", "a");
+    write_file(&current, "meta/index.json", "{}");
+    std::fs::create_dir_all(&new).unwrap();
+
+    // The commit step fails: everything is back where it was.
+    assert!(migrate_storage_dirs(&current, &new, || Err("disk full".into())).is_err());
+    assert_eq!(std::fs::read_to_string(current.join("notes/a.txt")).unwrap(), "a");
+    assert!(current.join("meta/index.json").exists());
+    assert!(!new.join("notes").exists() && !new.join("meta").exists());
+
+    migrate_storage_dirs(&current, &new, || Ok(())).unwrap();
+    assert_eq!(std::fs::read_to_string(new.join("notes/a.txt")).unwrap(), "a");
+    assert!(!current.join("notes").exists() && !current.join("meta").exists());
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_is_sync_relevant_path() {
+    use std::path::Path;
+    let folder = Path::new("/home/me/notes/sync");
+    assert!(is_sync_relevant_path(folder, &folder.join("meta/index.json")));
+    assert!(is_sync_relevant_path(folder, &folder.join("notes/abc.txt")));
+    assert!(!is_sync_relevant_path(folder, &folder.join("meta/templates.json")));
+    assert!(!is_sync_relevant_path(folder, &folder.join("images/abc/p.png")));
+    assert!(!is_sync_relevant_path(folder, Path::new("/elsewhere/notes/x.txt")));
+}
+
+fn write_file(base: &std::path::Path, rel: &str, content: &str) {
+    let path = base.join(rel);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn test_read_index_sees_external_changes_after_cached_write() {
+    let root = temp_dir("lpn-index-cache");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let index = IndexFile {
+        notes: vec![note("a", "A", "2024-01-01")],
+        ..Default::default()
+    };
+    write_index(&root, &index, false).unwrap();
+    assert_eq!(read_index(&root).unwrap().notes.len(), 1);
+    let external = IndexFile {
+        notes: vec![note("a", "A", "2024-01-01"), note("b", "B", "2024-01-02")],
+        ..Default::default()
+    };
+    write_file(&root, "meta/index.json", &serde_json::to_string(&external).unwrap());
+    assert_eq!(read_index(&root).unwrap().notes.len(), 2);
+    invalidate_index_cache();
+    assert_eq!(read_index(&root).unwrap().notes.len(), 2);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_title_from_body_uses_first_non_empty_line() {
+    assert_eq!(title_from_body("\n\n## Meeting notes \nrest", "x"), "Meeting notes");
+    assert_eq!(title_from_body("plain first line\nsecond", "x"), "plain first line");
+    assert_eq!(title_from_body(" \n#\n", "fallback"), "fallback");
+    assert_eq!(title_from_body(&"a".repeat(300), "x").len(), 100);
+}
+
+#[test]
+fn test_repair_index_rebuilds_from_note_files() {
+    let root = temp_dir("lpn-index-repair");
+    write_file(&root, "meta/index.json", "{\"notes\": [{\"id\": \"a\", \"ti");
+    write_file(&root, "notes/a.txt", "first");
+    write_file(&root, "notes/b.txt", "second");
+    write_file(&root, "notes/readme.md", "not a note");
+    let report = repair_index(&root).unwrap();
+    assert_eq!(report.source, IndexSource::Rebuilt);
+    assert_eq!(report.notes_recovered, 2);
+    let backup = report.corrupt_backup.unwrap();
+    assert!(backup.starts_with("meta/index.json.corrupt-"));
+    assert!(std::fs::read_to_string(root.join(&backup)).unwrap().contains("\"ti"));
+    let index = read_index(&root).unwrap();
+    let ids: Vec<&str> = index.notes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "b"]);
+    assert_eq!(index.notes[0].filename, "a.txt");
+    assert_eq!(repair_index(&root).unwrap().source, IndexSource::Intact);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// An index.json as written before schemaVersion and tag normalization existed.
+const V0_INDEX_JSON: &str = r#"{
+  "notes": [
+    {
+      "id": "a",
+      "title": "Work log",
+      "createdAt": "2023-05-01T09:00:00Z",
+      "updatedAt": "2023-05-02T09:00:00Z",
+      "important": true,
+      "filename": "a.txt",
+      "images": [],
+      "tags": ["Work", "work", "cafe\u0301", "Zed"],
+      "linksTo": ["b"]
+    },
+    {
+      "id": "b",
+      "title": "Other",
+      "createdAt": "2023-05-01T09:00:00Z",
+      "updatedAt": "2023-05-01T09:00:00Z",
+      "important": false,
+      "filename": "b.txt",
+      "images": []
+    }
+  ],
+  "trashed": [
+    {
+      "id": "c",
+      "title": "Gone",
+      "createdAt": "2023-05-01T09:00:00Z",
+      "updatedAt": "2023-05-01T09:00:00Z",
+      "important": false,
+      "filename": "c.txt",
+      "images": [],
+      "tags": ["OLD", "old"]
+    }
+  ]
+}"#;
+
+#[test]
+fn test_migrate_v0_to_v1_normalizes_tags() {
+    let old: IndexFile = serde_json::from_str(V0_INDEX_JSON).unwrap();
+    assert_eq!(old.schema_version, 0);
+
+    let index = migrate_v0_to_v1(old.clone(), true);
+    assert_eq!(index.schema_version, 1);
+    assert_eq!(index.notes[0].tags, ["café", "work", "zed"]);
+    assert!(index.notes[1].tags.is_empty());
+    assert_eq!(index.trashed[0].tags, ["old"]);
+    // Everything else is carried over.
+    assert_eq!(index.notes[0].links_to, ["b"]);
+    assert!(index.notes[0].important);
+
+    let index = migrate_v0_to_v1(old, false);
+    assert_eq!(index.notes[0].tags, ["Work", "Zed", "café"]);
+    assert_eq!(index.trashed[0].tags, ["OLD"]);
+}
+
+#[test]
+fn test_migrate_index_skips_current_and_newer() {
+    let mut index: IndexFile = serde_json::from_str(V0_INDEX_JSON).unwrap();
+    assert_eq!(migrate_index(index.clone(), true).schema_version, INDEX_SCHEMA_VERSION);
+    for version in [INDEX_SCHEMA_VERSION, INDEX_SCHEMA_VERSION + 1] {
+        index.schema_version = version;
+        let migrated = migrate_index(index.clone(), true);
+        assert_eq!(migrated.schema_version, version);
+        assert_eq!(migrated.notes[0].tags, index.notes[0].tags);
+    }
+}
+
+#[test]
+fn test_read_index_migrates_old_index_in_memory() {
+    let root = temp_dir("lpn-index-migrate");
+    write_file(&root, "meta/index.json", V0_INDEX_JSON);
+    invalidate_index_cache();
+    let index = read_index(&root).unwrap();
+    assert_eq!(index.schema_version, INDEX_SCHEMA_VERSION);
+    assert_eq!(index.notes[0].tags, ["café", "work", "zed"]);
+    let on_disk = std::fs::read_to_string(root.join("meta/index.json")).unwrap();
+    assert!(!on_disk.contains("schemaVersion"));
+    invalidate_index_cache();
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_repair_index_prefers_temp_file() {
+    let root = temp_dir("lpn-index-repair-tmp");
+    let saved = IndexFile {
+        notes: vec![note("a", "Kept title", "2024-01-01T00:00:00Z")],
+        ..Default::default()
+    };
+    write_file(&root, "meta/index.json", "");
+    write_file(&root, "meta/index.json.tmp", &serde_json::to_string(&saved).unwrap());
+    write_file(&root, "notes/a.txt", "body");
+    let report = repair_index(&root).unwrap();
+    assert_eq!(report.source, IndexSource::TempFile);
+    assert_eq!(read_index(&root).unwrap().notes[0].title, "Kept title");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_atomic_write_replaces_file_without_leaving_temp() {
+    let dir = temp_dir("lpn-atomic-write");
+    let path = dir.join("note.txt");
+    atomic_write(&path, b"first").unwrap();
+    atomic_write(&path, b"second").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+    assert!(!dir.join("note.txt.tmp").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_lock_index_serializes_concurrent_updates() {
+    let root = temp_dir("lpn-index-lock");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let notes: Vec<NoteMeta> = (0..20).map(|i| note(&format!("n{}", i), "N", "2024-01-01")).collect();
+    let ids: Vec<String> = notes.iter().map(|n| n.id.clone()).collect();
+    write_index(&root, &IndexFile { notes, ..Default::default() }, false).unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let root = root.clone();
+            let ids = ids.clone();
+            std::thread::spawn(move || {
+                for (i, id) in ids.iter().enumerate() {
+                    if t % 2 == 0 {
+                        if i % 4 == t / 2 {
+                            // Nested guards on the same thread must not deadlock.
+                            let _guard = lock_index();
+                            set_important(&root, id, true).unwrap();
+                        }
+                    } else {
+                        add_tag(&root, std::slice::from_ref(id), &format!("t{}", t)).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let index = read_index(&root).unwrap();
+    assert_eq!(index.notes.len(), 20);
+    for n in &index.notes {
+        assert!(n.important, "{} lost its star", n.id);
+        let mut tags = n.tags.clone();
+        tags.sort();
+        assert_eq!(tags, ["t1", "t3", "t5", "t7"], "{} lost a tag", n.id);
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_sync_dirs() {
+    let local = temp_dir("lpn-sync-local");
+    let remote = temp_dir("lpn-sync-remote");
+    let index = |notes: Vec<NoteMeta>| serde_json::to_string(&IndexFile { notes, ..Default::default() }).unwrap();
+    write_file(&local, "meta/index.json", &index(vec![note("a", "A", "2024-01-01")]));
+    write_file(&remote, "meta/index.json", &index(vec![note("b", "B", "2024-01-01")]));
+    write_file(&local, "notes/a.txt", "local a");
+    write_file(&remote, "notes/b.txt", "remote b");
+    write_file(&local, "notes/both.txt", "local edit");
+    write_file(&remote, "notes/both.txt", "remote edit");
+    write_file(&local, "meta/settings.json", "{}");
+
+    let report = sync_dirs(&local, &remote, None).unwrap();
+    assert_eq!(report.conflicts, ["notes/both.txt"]);
+    assert_eq!(std::fs::read_to_string(remote.join("notes/a.txt")).unwrap(), "local a");
+    assert_eq!(std::fs::read_to_string(local.join("notes/b.txt")).unwrap(), "remote b");
+    assert_eq!(std::fs::read_to_string(remote.join("notes/both.txt")).unwrap(), "local edit");
+    assert_eq!(std::fs::read_to_string(local.join("conflicts/notes/both.txt.conflict")).unwrap(), "remote edit");
+    assert!(!local.join("notes/both.txt.conflict").exists());
+    assert!(!remote.join("meta/settings.json").exists());
+    for base in [&local, &remote] {
+        let s = std::fs::read_to_string(base.join("meta/index.json")).unwrap();
+        let idx: IndexFile = serde_json::from_str(&s).unwrap();
+        assert_eq!(idx.notes.len(), 2);
+    }
+
+    // Neither side changed since the last sync: the newer file wins.
+    write_file(&remote, "notes/b.txt", "remote b v2");
+    let future = std::time::SystemTime::now() + std::time::Duration::from_secs(3600);
+    let report = sync_dirs(&local, &remote, Some(future)).unwrap();
+    assert!(report.conflicts.is_empty());
+    assert_eq!(std::fs::read_to_string(local.join("notes/b.txt")).unwrap(), "remote b v2");
+
+    std::fs::remove_dir_all(&local).unwrap();
+    std::fs::remove_dir_all(&remote).unwrap();
+}
+
+#[test]
+fn test_parse_markdown_import_reads_export_frontmatter() {
+    let mut meta = note("a", "Trip", "2024-03-02T10:00:00+00:00");
+    meta.created_at = "2024-03-01T09:00:00+00:00".into();
+    meta.tags = vec!["travel".into(), "work".into()];
+    let md = note_to_markdown(&meta, "Packing list #todo");
+    let parsed = parse_markdown_import(&md, "Trip");
+    assert_eq!(parsed.title, "Trip");
+    assert_eq!(parsed.tags, ["travel", "work"]);
+    assert_eq!(parsed.created_at.as_deref(), Some("2024-03-01T09:00:00+00:00"));
+    assert_eq!(parsed.body, "Packing list #todo\n");
+}
+
+#[test]
+fn test_parse_markdown_import_title_inline_tags_and_fallback() {
+    let parsed = parse_markdown_import("---\ntitle: \"Ideas\"\ntags: [a, '#b', not valid]\ncreated: yesterday\n---\nBody", "file");
+    assert_eq!(parsed.title, "Ideas");
+    assert_eq!(parsed.tags, ["a", "b"]);
+    assert_eq!(parsed.created_at, None);
+    assert_eq!(parsed.body, "Body");
+
+    let parsed = parse_markdown_import("Just text\n---\n", "file");
+    assert_eq!(parsed.title, "file");
+    assert!(parsed.tags.is_empty());
+    assert_eq!(parsed.body, "Just text\n---\n");
+}
+
+#[test]
+fn test_parse_markdown_import_round_trips_export_without_frontmatter() {
+    let meta = note("a", "Plain note", "2024-03-02T10:00:00+00:00");
+    let md = note_to_markdown(&meta, "Line one\n\n## Section\n");
+    let parsed = parse_markdown_import(&md, "exported-file");
+    assert_eq!(parsed.title, "Plain note");
+    assert_eq!(parsed.body, "Line one\n\n## Section\n");
+    assert!(parsed.tags.is_empty());
+    assert_eq!(parsed.created_at, None);
+}
+
+#[test]
+fn test_strip_note_frontmatter_round_trips_export() {
+    let mut meta = note("a", "Trip", "2024-03-02T10:00:00+00:00");
+    meta.created_at = "2024-03-01T09:00:00+00:00".into();
+    meta.tags = vec!["travel".into()];
+    let md = note_to_markdown(&meta, "Packing list");
+    let (fm, body) = strip_note_frontmatter(&md);
+    assert_eq!(fm.tags, ["travel"]);
+    assert_eq!(fm.created_at.as_deref(), Some("2024-03-01T09:00:00+00:00"));
+    assert_eq!(body, "# Trip\n\nPacking list\n");
+    // Stripping again finds nothing, so saves never stack frontmatter.
+    assert_eq!(strip_note_frontmatter(&body).1, body);
+}
+
+#[test]
+fn test_strip_note_frontmatter_keeps_text_between_rules() {
+    for body in ["---\nJust a paragraph\n---\nmore", "---\nnote: to self\n---\n", "---\nno closing"] {
+        let (fm, rest) = strip_note_frontmatter(body);
+        assert_eq!(rest, body);
+        assert_eq!(fm, Default::default());
+    }
+}
+
+#[test]
+fn test_strip_note_frontmatter_keeps_unknown_keys_in_body() {
+    let md = "---\ntitle: Trip\nauthor: Sam\ntags:\n  - travel\naliases:\n  - Journey\ncreated: 2024-03-01T09:00:00+00:00\n---\n\nBody\n";
+    let (fm, body) = strip_note_frontmatter(md);
+    assert_eq!(fm.tags, ["travel"]);
+    assert_eq!(body, "---\nauthor: Sam\naliases:\n  - Journey\n---\nBody\n");
+    // The leftover block has no key we read, so the next save stores it unchanged.
+    let (fm, again) = strip_note_frontmatter(&body);
+    assert_eq!(fm, Default::default());
+    assert_eq!(again, body);
+}
+
+#[test]
+fn test_parse_markdown_import_title_from_first_heading() {
+    let parsed = parse_markdown_import("Intro #tag\n\n## Real Title\nText", "file");
+    assert_eq!(parsed.title, "Real Title");
+    assert_eq!(parsed.body, "Intro #tag\n\n## Real Title\nText");
+
+    // With frontmatter the leading heading is only taken when it agrees with title:.
+    let parsed = parse_markdown_import("---\ntitle: A\n---\n# B\nText", "file");
+    assert_eq!(parsed.title, "A");
+    assert_eq!(parsed.body, "# B\nText");
+}
+
+#[test]
+fn test_markdown_pdf_blocks() {
+    let body = "# Plan\n\nSee [[Other Note]] and `code`.\n\n- one\n  - nested\n- [x] done\n- [ ] todo\n\n1. first\n2. second\n\n> quoted\n\n```\n  indented\n```\n\n---\n\n![diagram](images/n/d.png)\n";
+    let blocks = markdown_pdf_blocks(body);
+    assert_eq!(
+        blocks,
+        [
+            PdfBlock::Heading { level: 1, text: "Plan".into() },
+            PdfBlock::Paragraph { text: "See Other Note and code.".into(), indent: 0 },
+            PdfBlock::ListItem { depth: 0, marker: PdfListMarker::Bullet, text: "one".into() },
+            PdfBlock::ListItem { depth: 1, marker: PdfListMarker::Bullet, text: "nested".into() },
+            PdfBlock::ListItem { depth: 0, marker: PdfListMarker::Task(true), text: "done".into() },
+            PdfBlock::ListItem { depth: 0, marker: PdfListMarker::Task(false), text: "todo".into() },
+            PdfBlock::ListItem { depth: 0, marker: PdfListMarker::Number(1), text: "first".into() },
+            PdfBlock::ListItem { depth: 0, marker: PdfListMarker::Number(2), text: "second".into() },
+            PdfBlock::Paragraph { text: "quoted".into(), indent: 1 },
+            PdfBlock::Code("  indented\n".into()),
+            PdfBlock::Rule,
+            PdfBlock::Image { url: "images/n/d.png".into(), alt: "diagram".into() },
+        ]
+    );
+}
+
+#[test]
+fn test_wrap_text() {
+    assert_eq!(wrap_text("the quick brown fox", 10), ["the quick", "brown fox"]);
+    assert_eq!(wrap_text("abcdefghij xy", 4), ["abcd", "efgh", "ij", "xy"]);
+    assert_eq!(wrap_text("a\n\nb", 10), ["a", "", "b"]);
+}
+
+#[test]
+fn test_render_note_pdf_produces_pdf() {
+    let body = "## Section\n\nSome text.\n\n- [x] task\n\n![missing](images/n/x.png)\n";
+    let pdf = render_note_pdf("Title", &body.repeat(60), |_| None).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+    // Long enough to spill onto more pages.
+    let text = String::from_utf8_lossy(&pdf);
+    let pages = text
+        .split("/Count ")
+        .skip(1)
+        .filter_map(|s| s.split(|c: char| !c.is_ascii_digit()).next()?.parse::<usize>().ok())
+        .max()
+        .unwrap();
+    assert!(pages > 1);
+}
+
+#[test]
+fn test_attachment_kind_by_extension() {
+    assert_eq!(attachment_kind("PNG"), AttachmentKind::Image);
+    assert_eq!(attachment_kind("mp3"), AttachmentKind::Audio);
+    assert_eq!(attachment_kind("pdf"), AttachmentKind::Pdf);
+    assert_eq!(attachment_kind("zip"), AttachmentKind::Other);
+    assert_eq!(attachment_kind(""), AttachmentKind::Other);
+}
+
+#[test]
+fn test_is_attachment_path() {
+    assert!(is_attachment_path("images/n1/a.png"));
+    assert!(is_attachment_path("attachments/n1/report.pdf"));
+    assert!(!is_attachment_path("notes/n1.txt"));
+    assert!(!is_attachment_path("attachments/../meta/index.json"));
+    assert!(!is_attachment_path("/images/n1/a.png"));
+}
+
+#[test]
+fn test_image_ref_without_kind_is_image() {
+    let img: ImageRef = serde_json::from_str(r#"{"name":"a.png","path":"images/n/a.png","addedAt":"2024-01-01"}"#).unwrap();
+    assert_eq!(img.kind, AttachmentKind::Image);
+}
+
+#[test]
+fn test_thumbnail_name_keys_on_mtime_and_size() {
+    assert_eq!(thumbnail_name("1-shot.png", 1700, 256), "1-shot.png.1700.256.png");
+    assert_eq!(thumbnail_name("1-photo.JPG", 1700, 256), "1-photo.JPG.1700.256.jpg");
+    assert_ne!(thumbnail_name("a.png", 1, 256), thumbnail_name("a.png", 2, 256));
+}
+
+#[test]
+fn test_write_thumbnail() {
+    let dir = temp_dir("thumb");
+    let src = dir.join("big.png");
+    image::RgbaImage::from_pixel(400, 200, image::Rgba([10, 20, 30, 128])).save(&src).unwrap();
+    let dest = dir.join("big.png.1.100.png");
+    assert!(write_thumbnail(&src, &dest, 100).unwrap());
+    assert_eq!(image::image_dimensions(&dest).unwrap(), (100, 50));
+
+    let jpg = dir.join("big.png.1.100.jpg");
+    assert!(write_thumbnail(&src, &jpg, 100).unwrap());
+    assert_eq!(image::image_dimensions(&jpg).unwrap(), (100, 50));
+
+    let not_image = dir.join("doc.png");
+    std::fs::write(&not_image, "not really a png").unwrap();
+    assert!(!write_thumbnail(&not_image, &dir.join("doc.thumb.png"), 100).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dir_size_sums_nested_files() {
+    let dir = temp_dir("dirsize");
+    write_file(&dir, "a.txt", "12345");
+    write_file(&dir, "sub/b.txt", "123");
+    write_file(&dir, "sub/deeper/c.txt", "1");
+    assert_eq!(dir_size(&dir), 9);
+    assert_eq!(dir_size(&dir.join("missing")), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_find_orphans() {
+    let dir = temp_dir("orphans");
+    let mut owner = note("n1", "Owner", "2024-01-01");
+    owner.images = serde_json::from_str(r#"[{"name":"a.png","path":"images/n1/a.png","addedAt":"2024-01-01"}]"#).unwrap();
+    write_file(&dir, "images/n1/a.png", "keep");
+    write_file(&dir, "images/n1/stray.png", "12345");
+    write_file(&dir, "images/n1/b.png.tmp", "in flight");
+    write_file(&dir, "images/n1/a.png.conflict", "their copy");
+    write_file(&dir, "images/upload.tmp", "in flight");
+    write_file(&dir, "attachments/old.pdf.conflict", "their copy");
+    write_file(&dir, "images/n1/.thumbs/a.png.1.64.png", "t");
+    write_file(&dir, "images/gone/x.png", "123");
+    write_file(&dir, "attachments/gone/y.pdf", "12");
+    write_file(&dir, "versions/n1/1.json", "{}");
+    write_file(&dir, "versions/gone/1.json", "{}");
+
+    let orphans = find_orphans(&dir, &[owner]);
+    assert_eq!(
+        orphans,
+        [
+            ("images/gone".to_string(), 3),
+            ("images/n1/stray.png".to_string(), 5),
+            ("attachments/gone".to_string(), 2),
+            ("versions/gone".to_string(), 2),
+        ]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn ymd(y: i32, m: u32, d: u32) -> chrono::NaiveDate {
+    chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn test_periodic_note_title() {
+    assert_eq!(periodic_note_title(Period::Daily, ymd(2024, 1, 5)), "2024-01-05");
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2024, 1, 17)), "2024-W03");
+    assert_eq!(periodic_note_title(Period::Monthly, ymd(2024, 1, 17)), "2024-01");
+}
+
+#[test]
+fn test_periodic_note_title_iso_week_year_boundaries() {
+    // Early January can still belong to the last ISO week of the previous year…
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2021, 1, 3)), "2020-W53");
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2027, 1, 1)), "2026-W53");
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2023, 1, 1)), "2022-W52");
+    // …and late December to week 1 of the next.
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2024, 12, 30)), "2025-W01");
+    assert_eq!(periodic_note_title(Period::Weekly, ymd(2026, 1, 1)), "2026-W01");
+    // Monthly titles follow the calendar year.
+    assert_eq!(periodic_note_title(Period::Monthly, ymd(2024, 12, 30)), "2024-12");
+}
+
+#[test]
+fn test_periodic_note_body_weekly_range() {
+    let body = periodic_note_body(Period::Weekly, ymd(2024, 12, 31));
+    assert!(body.starts_with("# weekly\n"));
+    assert!(body.contains("2024-12-30 – 2025-01-05"));
+    assert_eq!(periodic_note_body(Period::Daily, ymd(2024, 1, 1)), "# daily\n");
+}
+
+#[test]
+fn test_apply_template_placeholders_uses_given_offset_date() {
+    // 02:00 UTC on Jan 1 is still Dec 31 in Pacific time.
+    let utc = chrono::DateTime::parse_from_rfc3339("2024-01-01T02:00:00+00:00").unwrap();
+    let pacific = utc.with_timezone(&chrono::FixedOffset::west_opt(8 * 3600).unwrap());
+    let (body, title) = apply_template_placeholders("Log {{date}}", "Day {{date}}", pacific, &std::collections::HashMap::new());
+    assert_eq!(title, "Day 2023-12-31");
+    assert_eq!(body, "Log 2023-12-31");
+    let (body, _) = apply_template_placeholders("{{date}}", "T", utc, &std::collections::HashMap::new());
+    assert_eq!(body, "2024-01-01");
+}
+
+#[test]
+fn test_settings_use_utc_dates_defaults_off() {
+    let settings: Settings = serde_json::from_str(r#"{"trashRetentionDays": 7}"#).unwrap();
+    assert!(!settings.use_utc_dates);
+}
+
+#[test]
+fn test_validate_daily_title_format() {
+    assert!(validate_daily_title_format("%Y-%m-%d").is_ok());
+    assert!(validate_daily_title_format("%A, %B %e %Y").is_ok());
+    assert!(validate_daily_title_format("Journal %Y.%m.%d").is_ok());
+    assert!(validate_daily_title_format("%Y/%m/%d").is_err());
+    assert!(validate_daily_title_format("%H:%M").is_err());
+    assert!(validate_daily_title_format("%Q").is_err());
+    assert!(validate_daily_title_format("   ").is_err());
+}
+
+#[test]
+fn test_settings_note_file_extension_defaults_to_txt() {
+    let settings: Settings = serde_json::from_str(r#"{"trashRetentionDays": 7}"#).unwrap();
+    assert_eq!(settings.note_file_extension, "txt");
+}
+
+#[test]
+fn test_validate_note_extension() {
+    assert_eq!(validate_note_extension("md").unwrap(), "md");
+    assert_eq!(validate_note_extension(" .MD ").unwrap(), "md");
+    assert_eq!(validate_note_extension("markdown").unwrap(), "markdown");
+    assert!(validate_note_extension("").is_err());
+    assert!(validate_note_extension(".").is_err());
+    assert!(validate_note_extension("../md").is_err());
+    assert!(validate_note_extension("tar.gz").is_err());
+    assert!(validate_note_extension("m d").is_err());
+    assert!(validate_note_extension("tmp").is_err());
+    assert!(validate_note_extension("averyveryverylongext").is_err());
+}
+
+#[test]
+fn test_validate_settings_daily_note_fields() {
+    let settings = Settings {
+        daily_note_title_format: Some("%Y/%m".into()),
+        ..Settings::default()
+    };
+    assert!(validate_settings(settings).is_err());
+    let settings = Settings {
+        daily_note_title_format: Some(" ".into()),
+        daily_note_template_id: Some("".into()),
+        ..Settings::default()
+    };
+    let settings = validate_settings(settings).unwrap();
+    assert_eq!(settings.daily_note_title_format, None);
+    assert_eq!(settings.daily_note_template_id, None);
+}
+
+#[test]
+fn test_validate_settings_clipboard_images_folder() {
+    let settings: Settings = serde_json::from_str(r#"{"trashRetentionDays": 7}"#).unwrap();
+    assert!(!settings.save_clipboard_images_to_folder);
+    assert_eq!(settings.clipboard_images_folder, None);
+    let settings = Settings {
+        clipboard_images_folder: Some("Pictures/pastes".into()),
+        ..Settings::default()
+    };
+    assert!(validate_settings(settings).is_err());
+    let settings = Settings {
+        clipboard_images_folder: Some("  ".into()),
+        ..Settings::default()
+    };
+    assert_eq!(validate_settings(settings).unwrap().clipboard_images_folder, None);
+    let folder = std::env::temp_dir().join("pastes").to_string_lossy().to_string();
+    let settings = Settings {
+        save_clipboard_images_to_folder: true,
+        clipboard_images_folder: Some(format!(" {} ", folder)),
+        ..Settings::default()
+    };
+    assert_eq!(validate_settings(settings).unwrap().clipboard_images_folder, Some(folder));
+}
+
+fn template_now() -> chrono::DateTime<chrono::FixedOffset> {
+    chrono::DateTime::parse_from_rfc3339("2024-03-08T09:05:00+01:00").unwrap()
+}
+
+fn fill(body: &str) -> String {
+    apply_template_placeholders(body, "T", template_now(), &std::collections::HashMap::new()).0
+}
+
+#[test]
+fn test_template_placeholder_time() {
+    assert_eq!(fill("{{time}}"), "09:05");
+}
+
+#[test]
+fn test_template_placeholder_datetime() {
+    assert_eq!(fill("{{datetime}}"), "2024-03-08 09:05");
+}
+
+#[test]
+fn test_template_placeholder_weekday() {
+    assert_eq!(fill("{{weekday}}"), "Friday");
+}
+
+#[test]
+fn test_template_placeholder_year() {
+    assert_eq!(fill("{{ year }}"), "2024");
+}
+
+#[test]
+fn test_template_placeholder_uuid_shared_by_title_and_body() {
+    let (body, title) = apply_template_placeholders("{{uuid}}", "{{uuid}}", template_now(), &std::collections::HashMap::new());
+    assert_eq!(body.len(), 36);
+    assert_eq!(body, title);
+    assert_ne!(fill("{{uuid}}"), fill("{{uuid}}"));
+}
+
+#[test]
+fn test_template_placeholders_custom_vars_and_unknown_tokens() {
+    let vars: std::collections::HashMap<String, String> = [("client".to_string(), "Acme".to_string())].into_iter().collect();
+    let (body, title) = apply_template_placeholders(
+        "# {{title}}\n{{client}} on {{weekday}} {{date}} at {{time}} — {{missing}} {{unclosed",
+        "Call with {{client}}",
+        template_now(),
+        &vars,
+    );
+    assert_eq!(title, "Call with Acme");
+    assert_eq!(body, "# Call with Acme\nAcme on Friday 2024-03-08 at 09:05 — {{missing}} {{unclosed");
+}
+
+#[test]
+fn test_stray_note_files_skips_indexed_and_non_canonical_stems() {
+    let root = temp_dir("lpn-stray");
+    write_file(&root, "notes/known.txt", "Known");
+    write_file(&root, "notes/new.txt", "New");
+    write_file(&root, "notes/ padded.txt", "Padded");
+    write_file(&root, "notes/a:b.txt", "Colon");
+    let index = IndexFile { notes: vec![note("known", "Known", "2024-01-01")], ..Default::default() };
+    let ids: Vec<String> = stray_note_files(&root, &index).unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, ["new"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_read_index_sees_same_length_external_edit() {
+    let root = temp_dir("lpn-index-cache-same-len");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let index = IndexFile {
+        notes: vec![note("a", "Alpha", "2024-01-01")],
+        ..Default::default()
+    };
+    write_index(&root, &index, false).unwrap();
+    assert_eq!(read_index(&root).unwrap().notes[0].title, "Alpha");
+    let path = root.join("meta/index.json");
+    let before = std::fs::metadata(&path).unwrap();
+    let edited = std::fs::read_to_string(&path).unwrap().replace("\"Alpha\"", "\"Omega\"");
+    std::fs::write(&path, edited).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), before.len());
+    assert_eq!(read_index(&root).unwrap().notes[0].title, "Omega");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Set a file's mtime to 2024-01-01 00:00 local time.
+#[cfg(unix)]
+fn backdate(path: &std::path::Path) {
+    let status = std::process::Command::new("touch").arg("-t").arg("202401010000").arg(path).status().unwrap();
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_index_serves_settled_cache_without_reading() {
+    let root = temp_dir("lpn-index-cache-hit");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let path = root.join("meta/index.json");
+    let index = IndexFile {
+        notes: vec![note("a", "Alpha", "2024-01-01")],
+        ..Default::default()
+    };
+    // The cache holds one index for the whole process, so a test running alongside can evict it between
+    // reads; retry until a round runs undisturbed.
+    let mut hit = false;
+    for _ in 0..20 {
+        write_index(&root, &index, false).unwrap();
+        backdate(&path);
+        // The stamp changed, so this read re-hashes the file and records its (now old) mtime.
+        assert_eq!(read_index(&root).unwrap().notes[0].title, "Alpha");
+        // Same length, same mtime: only a read that skips the file still sees Alpha.
+        let edited = std::fs::read_to_string(&path).unwrap().replace("\"Alpha\"", "\"Omega\"");
+        std::fs::write(&path, edited).unwrap();
+        backdate(&path);
+        if read_index(&root).unwrap().notes[0].title == "Alpha" {
+            hit = true;
+            break;
+        }
+    }
+    assert!(hit);
+    invalidate_index_cache();
+    assert_eq!(read_index(&root).unwrap().notes[0].title, "Omega");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_replace_link_target_matches_whole_targets_only() {
+    let body = "![a](images/n/a.png) [b](images/n/a.png.bak) <images/n/a.png> ![t](images/n/a.png \"t\") images/n/a.png";
+    assert_eq!(
+        replace_link_target(body, "images/n/a.png", "images/n/k.png"),
+        "![a](images/n/k.png) [b](images/n/a.png.bak) <images/n/k.png> ![t](images/n/k.png \"t\") images/n/a.png"
+    );
+}
+
+#[test]
+fn test_find_identical_file_matches_size_and_hash() {
+    let dir = temp_dir("lpn-identical");
+    write_file(&dir, "1-a.png", "same");
+    write_file(&dir, "2-b.png", "same");
+    write_file(&dir, "3-c.png", "diff");
+    let (hash, size) = hash_file(&dir.join("2-b.png")).unwrap();
+    assert_eq!(find_identical_file(&dir, &hash, size).as_deref(), Some("1-a.png"));
+    assert_eq!(find_identical_file(&dir, &hash, size + 1), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dedupe_note_attachments_rewrites_refs_and_keeps_prefix_paths() {
+    let root = temp_dir("lpn-dedupe");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    write_file(&root, "images/n/1-a.png", "pixels");
+    write_file(&root, "images/n/2-a.png", "pixels");
+    write_file(&root, "images/n/2-a.png.bak", "backup");
+    write_file(&root, "notes/n.txt", "![x](images/n/1-a.png)\n![y](images/n/2-a.png)\n[bak](images/n/2-a.png.bak)\n");
+    let image = |path: &str| ImageRef {
+        name: path.rsplit('/').next().unwrap().to_string(),
+        path: path.to_string(),
+        added_at: "2024-01-01".into(),
+        size: None,
+        kind: AttachmentKind::Image,
+    };
+    let mut n = note("n", "N", "2024-01-01");
+    n.images = vec![image("images/n/1-a.png"), image("images/n/2-a.png"), image("images/n/2-a.png.bak")];
+    write_index(&root, &IndexFile { notes: vec![n], ..Default::default() }, false).unwrap();
+
+    let summary = dedupe_note_attachments(&root, "n").unwrap();
+    assert_eq!(summary.files_removed, 1);
+    assert_eq!(summary.bytes_saved, 6);
+    assert!(!root.join("images/n/2-a.png").exists());
+    assert!(root.join("images/n/2-a.png.bak").exists());
+    assert_eq!(
+        std::fs::read_to_string(root.join("notes/n.txt")).unwrap(),
+        "![x](images/n/1-a.png)\n![y](images/n/1-a.png)\n[bak](images/n/2-a.png.bak)\n"
+    );
+    let paths: Vec<String> = read_index(&root).unwrap().notes[0].images.iter().map(|i| i.path.clone()).collect();
+    assert_eq!(paths, ["images/n/1-a.png", "images/n/2-a.png.bak"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_frontmatter_tags_survive_later_saves() {
+    let mut meta = note("a", "Trip", "2024-03-02T10:00:00+00:00");
+    meta.tags = vec!["travel".into()];
+    // First save: an export pasted back in, so the tag only exists in the frontmatter.
+    let (fm, body) = strip_note_frontmatter(&note_to_markdown(&meta, "Packing list"));
+    let mut tags = note_tags("Trip", &body, false);
+    tags.extend(fm.tags);
+    let first = carry_over_tags(&[], &[], tags);
+    assert!(first.contains(&"travel".to_string()));
+    // Second and third saves: plain edits, no frontmatter, one inline tag added then removed.
+    let second_body = format!("{}#gear\n", body);
+    let second = carry_over_tags(&first, &note_tags("Trip", &body, false), note_tags("Trip", &second_body, false));
+    assert!(second.contains(&"travel".to_string()) && second.contains(&"gear".to_string()));
+    let third = carry_over_tags(&second, &note_tags("Trip", &second_body, false), note_tags("Trip", &body, false));
+    assert!(third.contains(&"travel".to_string()));
+    assert!(!third.contains(&"gear".to_string()));
+}
+
+#[test]
+fn test_sync_conflict_copies_are_not_orphans() {
+    let local = temp_dir("lpn-sync-conflict-local");
+    let remote = temp_dir("lpn-sync-conflict-remote");
+    let mut owner = note("n1", "Owner", "2024-01-01");
+    owner.images = serde_json::from_str(r#"[{"name":"a.png","path":"images/n1/a.png","addedAt":"2024-01-01"}]"#).unwrap();
+    write_file(&local, "images/n1/a.png", "local pixels");
+    write_file(&remote, "images/n1/a.png", "remote pixels");
+
+    let report = sync_dirs(&local, &remote, None).unwrap();
+    assert_eq!(report.conflicts, ["images/n1/a.png"]);
+    let copy = conflict_copy_path(&local, "images/n1/a.png");
+    assert_eq!(copy, local.join("conflicts/images/n1/a.png.conflict"));
+    assert_eq!(std::fs::read_to_string(&copy).unwrap(), "remote pixels");
+    assert!(find_orphans(&local, &[owner]).is_empty());
+
+    std::fs::remove_dir_all(&local).unwrap();
+    std::fs::remove_dir_all(&remote).unwrap();
+}
+
+#[test]
+fn test_saved_search_create_list_delete() {
+    let root = temp_dir("lpn-saved-search");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    assert!(read_saved_searches(&root).unwrap().is_empty());
+    assert!(add_saved_search(&root, "  ", "tag:work").is_err());
+    assert!(add_saved_search(&root, "Work", " ").is_err());
+    let work = add_saved_search(&root, " Work ", " tag:work ").unwrap();
+    let todo = add_saved_search(&root, "Todo", "has:tasks").unwrap();
+    assert_eq!((work.name.as_str(), work.query.as_str()), ("Work", "tag:work"));
+    let names: Vec<String> = read_saved_searches(&root).unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names, ["Work", "Todo"]);
+    remove_saved_search(&root, &work.id).unwrap();
+    assert_eq!(remove_saved_search(&root, &work.id).unwrap_err(), "Saved search not found");
+    let ids: Vec<String> = read_saved_searches(&root).unwrap().into_iter().map(|s| s.id).collect();
+    assert_eq!(ids, [todo.id]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_concurrent_saved_search_creates_all_land() {
+    let root = temp_dir("lpn-saved-search-race");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let root = root.clone();
+            std::thread::spawn(move || {
+                for i in 0..5 {
+                    add_saved_search(&root, &format!("s{}-{}", t, i), "q").unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(read_saved_searches(&root).unwrap().len(), 40);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Index whose pretty-printed JSON (what write_index writes) is exactly `len` bytes.
+fn index_of_json_len(len: usize) -> IndexFile {
+    let mut index = IndexFile { notes: vec![note("big", "", "2024-01-01")], ..Default::default() };
+    let base = serde_json::to_string_pretty(&index).unwrap().len();
+    index.notes[0].title = "x".repeat(len - base);
+    index
+}
+
+#[test]
+fn test_write_index_size_threshold_boundaries() {
+    let root = temp_dir("lpn-index-size");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let path = root.join("meta/index.json");
+    write_index(&root, &index_of_json_len(MAX_INDEX_SIZE_BYTES - 1), false).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), (MAX_INDEX_SIZE_BYTES - 1) as u64);
+    write_index(&root, &index_of_json_len(MAX_INDEX_SIZE_BYTES), false).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), MAX_INDEX_SIZE_BYTES as u64);
+
+    let over = index_of_json_len(MAX_INDEX_SIZE_BYTES + 1);
+    assert_eq!(write_index(&root, &over, false).unwrap_err(), "Index file would exceed maximum allowed size");
+    // The rejected write leaves the previous index in place.
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), MAX_INDEX_SIZE_BYTES as u64);
+    write_index(&root, &over, true).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), (MAX_INDEX_SIZE_BYTES + 1) as u64);
+    invalidate_index_cache();
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+fn trashed(id: &str, deleted_at: &str) -> NoteMeta {
+    let mut n = note(id, id, "2024-01-01");
+    n.deleted_at = Some(deleted_at.into());
+    n
+}
+
+fn tombstone(id: &str, deleted_at: &str) -> Tombstone {
+    Tombstone { id: id.into(), deleted_at: deleted_at.into() }
+}
+
+#[test]
+fn test_merge_sync_index_latest_state_wins() {
+    let local = IndexFile {
+        notes: vec![note("edited", "Local", "2024-02-01"), note("stale", "Stale", "2024-01-01"), note("revived", "Back", "2024-03-01")],
+        trashed: vec![trashed("binned", "2024-02-01")],
+        ..Default::default()
+    };
+    let remote = IndexFile {
+        notes: vec![note("edited", "Remote", "2024-01-15"), note("binned", "Old", "2024-01-01"), note("new", "New", "2024-01-01")],
+        tombstones: vec![tombstone("stale", "2024-02-01"), tombstone("revived", "2024-02-01")],
+        ..Default::default()
+    };
+    let (merged, synced) = merge_sync_index(&local, &remote);
+    let ids = |notes: &[NoteMeta]| notes.iter().map(|n| n.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&merged.notes), ["edited", "revived", "new"]);
+    assert_eq!(merged.notes[0].title, "Local");
+    assert_eq!(ids(&merged.trashed), ["binned"]);
+    assert_eq!(merged.tombstones, [tombstone("stale", "2024-02-01")]);
+    let state = |id: &str| synced.iter().find(|n| n.id == id).map(|n| (n.state, n.from_remote)).unwrap();
+    assert_eq!(state("stale"), (SyncState::Deleted, true));
+    assert_eq!(state("binned"), (SyncState::Trashed, false));
+    assert_eq!(state("new"), (SyncState::Live, true));
+}
+
+#[test]
+fn test_sync_dirs_propagates_trash_and_deletions() {
+    let local = temp_dir("lpn-sync-del-local");
+    let remote = temp_dir("lpn-sync-del-remote");
+    let index = |idx: IndexFile| serde_json::to_string(&idx).unwrap();
+    // Local trashed "t" after the last sync; the sync folder emptied its trash of "d".
+    write_file(&local, "meta/index.json", &index(IndexFile {
+        notes: vec![note("d", "D", "2024-01-01")],
+        trashed: vec![trashed("t", "2024-02-01")],
+        ..Default::default()
+    }));
+    write_file(&local, "trash/t/note.txt", "t body");
+    write_file(&local, "notes/d.txt", "d body");
+    write_file(&local, "images/d/x.png", "px");
+    write_file(&remote, "meta/index.json", &index(IndexFile {
+        notes: vec![note("t", "T", "2024-01-01")],
+        tombstones: vec![tombstone("d", "2024-02-01")],
+        ..Default::default()
+    }));
+    write_file(&remote, "notes/t.txt", "t body");
+
+    let report = sync_dirs(&local, &remote, None).unwrap();
+    assert_eq!(report.deleted, 1);
+    assert!(!local.join("notes/d.txt").exists() && !local.join("images/d").exists());
+    assert!(!remote.join("notes/d.txt").exists() && !remote.join("images/d").exists());
+    assert!(!remote.join("notes/t.txt").exists());
+    assert_eq!(std::fs::read_to_string(remote.join("trash/t/note.txt")).unwrap(), "t body");
+    for base in [&local, &remote] {
+        let idx: IndexFile = serde_json::from_str(&std::fs::read_to_string(base.join("meta/index.json")).unwrap()).unwrap();
+        assert!(idx.notes.is_empty());
+        assert_eq!(idx.trashed.len(), 1);
+        assert_eq!(idx.tombstones, [tombstone("d", "2024-02-01")]);
+    }
+    // A second sync doesn't bring either note back.
+    sync_dirs(&local, &remote, None).unwrap();
+    assert!(!local.join("notes/d.txt").exists() && !remote.join("notes/t.txt").exists());
+    invalidate_index_cache();
+    std::fs::remove_dir_all(&local).unwrap();
+    std::fs::remove_dir_all(&remote).unwrap();
+}
+
+#[test]
+fn test_sync_dirs_takes_body_from_the_side_whose_index_entry_wins() {
+    let local = temp_dir("lpn-sync-pair-local");
+    let remote = temp_dir("lpn-sync-pair-remote");
+    let index = |notes: Vec<NoteMeta>| serde_json::to_string(&IndexFile { notes, ..Default::default() }).unwrap();
+    write_file(&remote, "meta/index.json", &index(vec![note("a", "Remote title", "2024-02-01")]));
+    write_file(&remote, "notes/a.txt", "remote body");
+    // Written after the remote copy, so its mtime is newer, but its index entry is older.
+    write_file(&local, "meta/index.json", &index(vec![note("a", "Local title", "2024-01-01")]));
+    write_file(&local, "notes/a.txt", "local body");
+
+    let report = sync_dirs(&local, &remote, None).unwrap();
+    assert_eq!(read_index(&local).unwrap().notes[0].title, "Remote title");
+    assert_eq!(std::fs::read_to_string(local.join("notes/a.txt")).unwrap(), "remote body");
+    assert_eq!(std::fs::read_to_string(remote.join("notes/a.txt")).unwrap(), "remote body");
+    // The losing body had changes of its own, so it is kept as a conflict copy.
+    assert_eq!(report.conflicts, ["notes/a.txt"]);
+    assert_eq!(std::fs::read_to_string(local.join("conflicts/notes/a.txt.conflict")).unwrap(), "local body");
+    invalidate_index_cache();
+    std::fs::remove_dir_all(&local).unwrap();
+    std::fs::remove_dir_all(&remote).unwrap();
+}
+
+#[test]
+fn test_tags_after_edit_keeps_tags_not_from_the_body() {
+    // "travel" came from frontmatter, "todo" was added by hand; "trip" is in the body.
+    let tags: Vec<String> = vec!["todo".into(), "travel".into(), "trip".into()];
+    let body = "- [ ] pack #trip\n";
+    let keep = |new_body: &str| tags_after_edit("T", &tags, body, new_body, false);
+
+    let appended = format!("{}{}", body, append_chunk(body.as_bytes().last().copied(), "more #gear", None));
+    assert_eq!(keep(&appended), ["gear", "todo", "travel", "trip"]);
+    assert_eq!(keep(&toggle_task_line(body, 0).unwrap()), ["todo", "travel", "trip"]);
+    // A replace that removes the body tag drops it, and only it.
+    assert_eq!(keep(&body.replace("#trip", "")), ["todo", "travel"]);
+}
+
+#[test]
+fn test_sync_dirs_refuses_a_sync_folder_with_another_note_extension() {
+    let local = temp_dir("lpn-sync-ext-local");
+    let remote = temp_dir("lpn-sync-ext-remote");
+    write_file(&local, "meta/index.json", &serde_json::to_string(&IndexFile::default()).unwrap());
+    let mut md = note("a", "A", "2024-01-01");
+    md.filename = "a.md".into();
+    write_file(&remote, "meta/index.json", &serde_json::to_string(&IndexFile { notes: vec![md], ..Default::default() }).unwrap());
+    write_file(&remote, "notes/a.md", "body");
+
+    let err = sync_dirs(&local, &remote, None).unwrap_err();
+    assert!(err.contains(".md") && err.contains(".txt"), "{}", err);
+    assert!(!local.join("notes/a.md").exists());
+    std::fs::remove_dir_all(&local).unwrap();
+    std::fs::remove_dir_all(&remote).unwrap();
+}
+
+#[test]
+fn test_rename_tag_in_snapshots_bodies_and_fails_on_an_unreadable_one() {
+    let root = temp_dir("lpn-rename-tag");
+    let mut a = note("a", "A", "2024-01-01T00:00:00Z");
+    a.tags = vec!["old".into()];
+    let mut b = note("b", "B", "2024-01-01T00:00:00Z");
+    b.tags = vec!["old".into()];
+    write_file(&root, "notes/a.txt", "x #old");
+    std::fs::create_dir_all(root.join("notes/b.txt")).unwrap();
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    write_index(&root, &IndexFile { notes: vec![a, b], ..Default::default() }, false).unwrap();
+
+    // b's body can't be read: nothing is rewritten and the index keeps the old tag.
+    assert!(rename_tag_in(&root, "old", "new").is_err());
+    assert_eq!(std::fs::read_to_string(root.join("notes/a.txt")).unwrap(), "x #old");
+    invalidate_index_cache();
+    assert!(read_index(&root).unwrap().notes.iter().all(|n| n.tags == ["old"]));
+
+    std::fs::remove_dir(root.join("notes/b.txt")).unwrap();
+    let updated = rename_tag_in(&root, "old", "new").unwrap();
+    assert_eq!(updated.len(), 2);
+    assert_eq!(std::fs::read_to_string(root.join("notes/a.txt")).unwrap(), "x #new");
+    let versions: Vec<_> = std::fs::read_dir(root.join("versions/a")).unwrap().flatten().collect();
+    assert_eq!(versions.len(), 1);
+    let snapshot = std::fs::read_to_string(versions[0].path()).unwrap();
+    assert!(snapshot.contains("x #old") && snapshot.contains("Before renaming #old to #new"), "{}", snapshot);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_rename_note_and_relink_in_only_relinks_links_that_resolve_to_the_note() {
+    let root = temp_dir("lpn-relink");
+    std::fs::create_dir_all(root.join("meta")).unwrap();
+    let mut old = note("a", "Plan", "2024-01-01T00:00:00Z");
+    old.created_at = "2023-01-01T00:00:00Z".into();
+    let dup = note("b", "Plan", "2024-01-01T00:00:00Z");
+    let linker = note("c", "C", "2024-01-01T00:00:00Z");
+    write_file(&root, "notes/a.txt", "a");
+    write_file(&root, "notes/b.txt", "b");
+    write_file(&root, "notes/c.txt", "see [[Plan]]");
+    write_index(&root, &IndexFile { notes: vec![old, dup, linker], ..Default::default() }, false).unwrap();
+
+    // [[Plan]] resolves to "a" (first exact title match), so renaming "b" leaves the link alone.
+    assert_eq!(rename_note_and_relink_in(&root, "b", "Plan B").unwrap(), 0);
+    assert_eq!(std::fs::read_to_string(root.join("notes/c.txt")).unwrap(), "see [[Plan]]");
+
+    assert_eq!(rename_note_and_relink_in(&root, "a", "Plan A").unwrap(), 1);
+    assert_eq!(std::fs::read_to_string(root.join("notes/c.txt")).unwrap(), "see [[Plan A]]");
+    let versions: Vec<_> = std::fs::read_dir(root.join("versions/c")).unwrap().flatten().collect();
+    assert_eq!(versions.len(), 1);
+    assert!(std::fs::read_to_string(versions[0].path()).unwrap().contains("see [[Plan]]"));
+    invalidate_index_cache();
+    let index = read_index(&root).unwrap();
+    assert_eq!(index.notes.iter().find(|n| n.id == "c").unwrap().links_to, ["a"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -1,12 +1,12 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { NoteMeta, NoteContent, Notebook, NoteTemplate, NoteVersionItem, NoteVersionContent } from "./types";
+import type { NoteMeta, NoteContent, Notebook, NoteTemplate, NoteVersionItem, NoteVersionContent, MergeOptions, DuplicateResult, IndexRepairReport } from "./types";
 
-export async function initStorage(): Promise<void> {
-  await invoke("init_storage");
+export async function initStorage(): Promise<IndexRepairReport> {
+  return invoke("init_storage");
 }
 
-export async function listNotes(): Promise<NoteMeta[]> {
-  return invoke("list_notes");
+export async function listNotes(includeArchived?: boolean): Promise<NoteMeta[]> {
+  return invoke("list_notes", { includeArchived: includeArchived ?? undefined });
 }
 
 export async function readNote(noteId: string): Promise<NoteContent> {
@@ -51,6 +51,18 @@ export async function attachImageFromClipboard(
   });
 }
 
+export async function attachImageFromDataUrl(
+  noteId: string,
+  dataUrl: string,
+  suggestedName: string
+): Promise<NoteMeta> {
+  return invoke("attach_image_from_data_url", {
+    noteId,
+    dataUrl,
+    suggestedName,
+  });
+}
+
 export async function deleteNote(noteId: string): Promise<void> {
   return invoke("delete_note", { noteId });
 }
@@ -59,6 +71,10 @@ export async function resolveImagePath(relativePath: string): Promise<string> {
   return invoke("resolve_image_path", { relativePath });
 }
 
+export async function resolveImageAssetUrl(relativePath: string): Promise<string> {
+  return invoke("resolve_image_asset_url", { relativePath });
+}
+
 export async function updateNoteTitle(noteId: string, newTitle: string): Promise<NoteMeta> {
   return invoke("update_note_title", { noteId, newTitle });
 }
@@ -87,12 +103,12 @@ export async function batchToggleImportant(noteIds: string[], important: boolean
   return invoke("batch_toggle_important", { noteIds, important });
 }
 
-export async function duplicateNote(noteId: string): Promise<NoteMeta> {
+export async function duplicateNote(noteId: string): Promise<DuplicateResult> {
   return invoke("duplicate_note", { noteId });
 }
 
-export async function mergeNotes(noteIds: string[]): Promise<NoteMeta> {
-  return invoke("merge_notes", { noteIds });
+export async function mergeNotes(noteIds: string[], options?: MergeOptions): Promise<NoteMeta> {
+  return invoke("merge_notes", { noteIds, options: options ?? undefined });
 }
 
 export async function exportNote(noteId: string): Promise<string> {
@@ -119,8 +135,8 @@ export async function renameAttachment(
   return invoke("rename_attachment", { noteId, relativePath, newName });
 }
 
-export async function searchNotes(query: string): Promise<NoteMeta[]> {
-  return invoke("search_notes", { query });
+export async function searchNotes(query: string, includeArchived?: boolean): Promise<NoteMeta[]> {
+  return invoke("search_notes", { query, includeArchived: includeArchived ?? undefined });
 }
 
 export async function listNoteVersions(noteId: string): Promise<NoteVersionItem[]> {
@@ -141,11 +157,13 @@ export async function listTemplates(): Promise<NoteTemplate[]> {
 
 export async function createNoteFromTemplate(
   templateId: string,
-  titleOverride?: string
+  titleOverride?: string,
+  vars?: Record<string, string>
 ): Promise<NoteMeta> {
   return invoke("create_note_from_template", {
     template_id: templateId,
     title_override: titleOverride ?? undefined,
+    vars: vars ?? undefined,
   });
 }
 
@@ -164,16 +182,31 @@ export async function listNotebooks(): Promise<Notebook[]> {
   return invoke("list_notebooks");
 }
 
-export async function createNotebook(name: string): Promise<Notebook> {
-  return invoke("create_notebook", { name });
+export async function createNotebook(name: string, parentId: string | null = null): Promise<Notebook> {
+  return invoke("create_notebook", { name, parentId: parentId ?? undefined });
+}
+
+export async function moveNotebook(notebookId: string, newParent: string | null): Promise<Notebook> {
+  return invoke("move_notebook", { notebookId, newParent: newParent ?? undefined });
 }
 
 export async function moveNoteToNotebook(noteId: string, notebookId: string | null): Promise<NoteMeta> {
   return invoke("move_note_to_notebook", { noteId, notebookId: notebookId ?? undefined });
 }
 
-export async function archiveNotebook(notebookId: string, archived: boolean): Promise<Notebook> {
-  return invoke("archive_notebook", { notebookId, archived });
+export async function archiveNotebook(
+  notebookId: string,
+  archived: boolean,
+  cascade = false
+): Promise<Notebook> {
+  return invoke("archive_notebook", { notebookId, archived, cascade });
+}
+
+export async function deleteNotebook(
+  notebookId: string,
+  strategy: "moveToUnfiled" | "deleteNotes"
+): Promise<number> {
+  return invoke("delete_notebook", { notebookId, strategy });
 }
 
 export async function updateNotebookName(notebookId: string, newName: string): Promise<Notebook> {
@@ -182,8 +215,8 @@ export async function updateNotebookName(notebookId: string, newName: string): P
 
 // --- Export & Sync ---
 
-export async function exportNoteAsMarkdown(noteId: string): Promise<string> {
-  return invoke("export_note_as_markdown", { noteId });
+export async function exportNoteAsMarkdown(noteId: string, inlineImages?: boolean): Promise<string> {
+  return invoke("export_note_as_markdown", { noteId, inlineImages: inlineImages ?? undefined });
 }
 
 export async function writeTextFile(path: string, content: string): Promise<void> {
@@ -202,6 +235,15 @@ export async function exportBackup(targetDir: string): Promise<void> {
   return invoke("export_backup", { targetDir });
 }
 
-export async function importBackup(sourceDir: string): Promise<void> {
-  return invoke("import_backup", { sourceDir });
+export interface ImportSummary {
+  added: number;
+  updated: number;
+  skipped: number;
+}
+
+export async function importBackup(
+  sourceDir: string,
+  mode: "replace" | "merge" = "replace"
+): Promise<ImportSummary> {
+  return invoke("import_backup", { sourceDir, mode });
 }
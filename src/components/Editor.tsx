@@ -4,7 +4,6 @@ import remarkGfm from "remark-gfm";
 import rehypeRaw from "rehype-raw";
 import { open } from "@tauri-apps/plugin-dialog";
 import { openPath } from "@tauri-apps/plugin-opener";
-import { convertFileSrc } from "@tauri-apps/api/core";
 import {
   readNote,
   saveNote,
@@ -13,6 +12,7 @@ import {
   attachImageFromClipboard,
   deleteNote,
   resolveImagePath,
+  resolveImageAssetUrl,
   removeAttachment,
   renameAttachment,
   listTags,
@@ -128,9 +128,9 @@ function ImageThumbnail({ path }: { path: string }) {
   useEffect(() => {
     let cancelled = false;
     setLoadFailed(false);
-    resolveImagePath(path)
-      .then((p) => {
-        if (!cancelled) setSrc(convertFileSrc(p));
+    resolveImageAssetUrl(path)
+      .then((url) => {
+        if (!cancelled) setSrc(url);
       })
       .catch(() => {
         if (!cancelled) setLoadFailed(true);
@@ -427,17 +427,17 @@ export function Editor({
     async (img: ImageRef) => {
       if (isImagePath(img.path)) {
         try {
-          const fullPath = await resolveImagePath(img.path);
+          const url = await resolveImageAssetUrl(img.path);
           setPreviewAttachment({ path: img.path, type: "image" });
-          setPreviewUrl(convertFileSrc(fullPath));
+          setPreviewUrl(url);
         } catch (e) {
           setError(e instanceof Error ? e.message : String(e));
         }
       } else if (isPdfPath(img.path)) {
         try {
-          const fullPath = await resolveImagePath(img.path);
+          const url = await resolveImageAssetUrl(img.path);
           setPreviewAttachment({ path: img.path, type: "pdf" });
-          setPreviewUrl(convertFileSrc(fullPath));
+          setPreviewUrl(url);
         } catch (e) {
           setError(e instanceof Error ? e.message : String(e));
         }
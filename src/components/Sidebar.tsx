@@ -851,7 +851,7 @@ export function Sidebar({
             {contextMenu.noteIds.length === 1 && (
               <button
                 type="button"
-                onClick={() => runBatch(() => duplicateNote(contextMenu.noteIds[0]).then((m) => onSelect(m.id)))}
+                onClick={() => runBatch(() => duplicateNote(contextMenu.noteIds[0]).then((r) => onSelect(r.meta.id)))}
                 className="w-full text-left px-3 py-2 text-sm text-stone-700 hover:bg-stone-100"
               >
                 Duplicate
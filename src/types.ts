@@ -4,6 +4,8 @@ export interface ImageRef {
   addedAt: string;
   /** File size in bytes, if known. */
   size?: number;
+  /** Missing on entries saved before non-image attachments; treat as "image". */
+  kind?: "image" | "audio" | "pdf" | "other";
 }
 
 export interface NoteMeta {
@@ -18,6 +20,14 @@ export interface NoteMeta {
   linksTo?: string[];
   isDaily?: boolean;
   notebookId?: string | null;
+  period?: "daily" | "weekly" | "monthly";
+  archived?: boolean;
+  /** Last star/pin/archive/tag/alias/notebook change; updatedAt only follows these when the setting allows. */
+  metadataUpdatedAt?: string;
+  /** Start of the body for list previews; missing on notes not saved since previews were added. */
+  preview?: string;
+  /** Palette name ("red", "blue", …) or "#rgb"/"#rrggbb". */
+  color?: string;
 }
 
 export interface Notebook {
@@ -25,6 +35,7 @@ export interface Notebook {
   name: string;
   archived?: boolean;
   createdAt: string;
+  parentId?: string | null;
 }
 
 export interface NoteContent {
@@ -51,3 +62,24 @@ export interface NoteTemplate {
   defaultTitlePattern?: string;
   isCustom?: boolean;
 }
+
+export interface DuplicateResult {
+  meta: NoteMeta;
+  /** Attachments that could not be copied, as "name: error". */
+  failed: string[];
+}
+
+export interface IndexRepairReport {
+  /** "intact" unless index.json was corrupt and had to be recovered. */
+  source: "intact" | "tempFile" | "rebuilt";
+  /** Where the corrupt index.json was copied, relative to the storage root. */
+  corruptBackup: string | null;
+  notesRecovered: number;
+}
+
+export interface MergeOptions {
+  order?: "byUpdated" | "byCreated" | "byProvidedOrder";
+  /** Written before each note's body; {{title}} is replaced. Defaults to "## {{title}}\n\n". */
+  separator?: string | null;
+  keepSources?: boolean;
+}